@@ -0,0 +1,397 @@
+//! Benchmarks for the cages and the reclaimers behind them.
+//!
+//! criterion isn't available here, so this is a small std-only harness:
+//! each benchmark is run for a handful of samples and the median time per
+//! operation is reported.  Pass a substring to run only the benchmarks whose
+//! names contain it, e.g. `cargo bench -- mixed`.
+
+use epoch_playground::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use epoch_playground::{ArcCage, BirdCage, Cage, Canary, FlushPolicy, LockCage, MemoryOrder};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::hint::black_box;
+use crossbeam::epoch::{self, Collector, LocalHandle};
+use std::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SLOTS: usize = 64;
+const OPS: u64 = 200_000;
+const SAMPLES: usize = 7;
+const THREADS: usize = 4;
+
+fn canary(n: usize) -> Canary {
+    Canary::silent(&format!("Canary {}", n))
+}
+
+fn birdcage<R: Reclaimer>(flush: FlushPolicy) -> BirdCage<Canary, R> {
+    BirdCage::from_fn(SLOTS, canary).with_flush_policy(flush)
+}
+
+// The same, but with the weakest orderings that are still correct.
+fn acqrel<R: Reclaimer>() -> BirdCage<Canary, R> {
+    birdcage::<R>(FlushPolicy::Never).with_memory_order(MemoryOrder::AcqRel)
+}
+
+struct Bench {
+    filter: Vec<String>,
+}
+
+impl Bench {
+    fn wants(&self, name: &str) -> bool {
+        self.filter.is_empty() || self.filter.iter().any(|s| name.contains(s.as_str()))
+    }
+
+    /// Time `f`, which does `ops` operations per call, and print the median
+    /// time per operation.
+    fn run<F: FnMut()>(&self, name: &str, ops: u64, mut f: F) {
+        if !self.wants(name) {
+            return;
+        }
+        // One untimed run to warm things up.
+        f();
+        let mut samples: Vec<Duration> = (0..SAMPLES)
+            .map(|_| {
+                let start = Instant::now();
+                f();
+                start.elapsed()
+            })
+            .collect();
+        samples.sort();
+        let median = samples[SAMPLES / 2];
+        let per_op = median.as_nanos() as f64 / ops as f64;
+        println!("{:32} {:>10.1} ns/op", name, per_op);
+    }
+}
+
+fn access<C: Cage<Canary>>(cage: &C) {
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..OPS {
+        let pick = rng.gen_range(0, SLOTS);
+        black_box(cage.with_slot(pick, |c| c.name().len()));
+    }
+}
+
+fn replace<C: Cage<Canary>>(cage: &C) {
+    let mut rng = StdRng::seed_from_u64(2);
+    for ii in 0..OPS {
+        let pick = rng.gen_range(0, SLOTS);
+        cage.put(pick, canary(ii as usize));
+        if ii % 64 == 0 {
+            cage.quiescent();
+        }
+    }
+}
+
+// These do the same work as `access` and `replace`, but pin once for every
+// SLOTS operations instead of once per operation.
+fn access_batched<R: Reclaimer>(cage: &BirdCage<Canary, R>) {
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..OPS / SLOTS as u64 {
+        let guard = &cage.pin();
+        for _ in 0..SLOTS {
+            let pick = rng.gen_range(0, SLOTS);
+            black_box(cage.get(pick, guard).ok().map(|c| c.name().len()));
+        }
+    }
+}
+
+fn replace_batched<R: Reclaimer>(cage: &BirdCage<Canary, R>) {
+    let mut rng = StdRng::seed_from_u64(2);
+    for batch in 0..OPS as usize / SLOTS {
+        let values = (0..SLOTS).map(|ii| (rng.gen_range(0, SLOTS), canary(batch * SLOTS + ii)));
+        black_box(cage.replace_many(values).unwrap());
+        R::quiescent();
+    }
+}
+
+// Every thread does OPS operations, `writes` percent of which are replaces.
+fn mixed<C: Cage<Canary> + 'static>(cage: &Arc<C>, writes: u32) {
+    let handles: Vec<_> = (0..THREADS)
+        .map(|id| {
+            let cage = cage.clone();
+            thread::spawn(move || {
+                let mut rng = StdRng::seed_from_u64(id as u64);
+                for ii in 0..OPS {
+                    let pick = rng.gen_range(0, SLOTS);
+                    if rng.gen_range(0, 100) < writes {
+                        cage.put(pick, canary(ii as usize));
+                    } else {
+                        black_box(cage.with_slot(pick, |c| c.name().len()));
+                    }
+                    if ii % 64 == 0 {
+                        cage.quiescent();
+                    }
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+// These run on their own thread, because a thread that has used QSBR and
+// then sits in `join` would block reclamation for every later benchmark.
+// Run `f` while another thread keeps track of the most garbage waiting on
+// the reclaimer.
+fn peak_pending<F: FnOnce()>(f: F) -> usize {
+    let done = AtomicBool::new(false);
+    let peak = AtomicUsize::new(0);
+    thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(Ordering::Relaxed) {
+                peak.fetch_max(reclaim::pending(), Ordering::Relaxed);
+                thread::sleep(Duration::from_micros(100));
+            }
+        });
+        f();
+        done.store(true, Ordering::Relaxed);
+    });
+    peak.into_inner()
+}
+
+fn single<C: Cage<Canary>>(bench: &Bench, backend: &str, cage: C) {
+    thread::scope(|s| {
+        s.spawn(|| {
+            bench.run(&format!("access/{}", backend), OPS, || access(&cage));
+            bench.run(&format!("replace/{}", backend), OPS, || replace(&cage));
+        });
+    });
+}
+
+// Each thread sticks to its own slot, so the only thing the threads share
+// is whatever cache lines their slots happen to sit on.
+fn neighbours(bench: &Bench, padded: bool) {
+    let cage = Arc::new(BirdCage::<usize>::from_fn(THREADS, |n| n).with_padded_slots(padded));
+    let layout = if padded { "padded" } else { "packed" };
+    bench.run(&format!("neighbours/{}", layout), OPS * THREADS as u64, || {
+        let handles: Vec<_> = (0..THREADS)
+            .map(|id| {
+                let cage = cage.clone();
+                thread::spawn(move || {
+                    for ii in 0..OPS as usize {
+                        if ii % 4 == 0 {
+                            cage.put(id, ii);
+                        } else {
+                            black_box(cage.with_slot(id, |n| *n).ok());
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+    });
+}
+
+fn batched<R: Reclaimer>(bench: &Bench) {
+    let cage = birdcage::<R>(FlushPolicy::Never);
+    thread::scope(|s| {
+        s.spawn(|| {
+            bench.run(&format!("access/batched/{}", R::NAME), OPS, || access_batched(&cage));
+            bench.run(&format!("replace/batched/{}", R::NAME), OPS, || replace_batched(&cage));
+        });
+    });
+}
+
+// Replace every slot once per round, either slot by slot or with one
+// `replace_all`, and report the most garbage waiting at once.
+fn whole_cage<R: Reclaimer>(bench: &Bench) {
+    let cage = birdcage::<R>(FlushPolicy::Never);
+    let rounds = OPS as usize / SLOTS;
+    let individual = || {
+        for round in 0..rounds {
+            for n in 0..SLOTS {
+                cage.put(n, canary(round * SLOTS + n));
+            }
+            R::quiescent();
+        }
+    };
+    let all = || {
+        for round in 0..rounds {
+            cage.replace_all((0..SLOTS).map(|n| canary(round * SLOTS + n)));
+            R::quiescent();
+        }
+    };
+    for (how, f) in [("replace", &individual as &(dyn Fn() + Sync)), ("replace-all", &all)] {
+        let name = format!("whole-cage/{}/{}", how, R::NAME);
+        if !bench.wants(&name) {
+            continue;
+        }
+        // On its own thread, for the same reason as `single`.  Anything
+        // left over from earlier benchmarks isn't ours to count.
+        let before = reclaim::pending();
+        let peak = thread::scope(|s| {
+            s.spawn(|| {
+                let peak = peak_pending(|| bench.run(&name, OPS, f));
+                reclaim::force_reclaim::<R>();
+                peak
+            })
+            .join()
+            .unwrap()
+        });
+        println!("{:32} {:>10} peak pending", "", peak.saturating_sub(before));
+    }
+}
+
+// A collector of our own, and this thread's handle to it, looked up once.
+fn collector() -> &'static Collector {
+    static COLLECTOR: OnceLock<Collector> = OnceLock::new();
+    COLLECTOR.get_or_init(Collector::new)
+}
+
+thread_local! {
+    static HANDLE: LocalHandle = collector().register();
+}
+
+// What a pin costs on its own, however it's done.
+fn pins(bench: &Bench) {
+    bench.run("pin/default", OPS, || {
+        for _ in 0..OPS {
+            black_box(epoch::pin());
+        }
+    });
+    bench.run("pin/nested", OPS, || {
+        let _outer = epoch::pin();
+        for _ in 0..OPS {
+            black_box(epoch::pin());
+        }
+    });
+    bench.run("pin/thread-local-handle", OPS, || {
+        for _ in 0..OPS {
+            black_box(HANDLE.with(|h| h.pin()));
+        }
+    });
+    bench.run("pin/local-handle", OPS, || {
+        let handle = collector().register();
+        for _ in 0..OPS {
+            black_box(handle.pin());
+        }
+    });
+
+    // The same loads, with a pin for each one or one for every batch.
+    let value = Box::into_raw(Box::new(7_u64));
+    let slot = AtomicPtr::new(value);
+    let read = || black_box(unsafe{*slot.load(Ordering::Acquire)});
+    for &batch in &[1, 8, 64, 512] {
+        bench.run(&format!("pin/every-{}-loads", batch), OPS, || {
+            for _ in 0..OPS / batch {
+                let _guard = epoch::pin();
+                for _ in 0..batch {
+                    read();
+                }
+            }
+        });
+    }
+    drop(unsafe{Box::from_raw(value)});
+}
+
+// The read path, taken apart: a pin (which issues a SeqCst fence of its
+// own) and a load, with the ordering on the load or in a separate fence.
+// This is a raw pointer rather than a cage, so nothing else gets timed.
+fn fences(bench: &Bench) {
+    let value = Box::into_raw(Box::new(7_u64));
+    let slot = AtomicPtr::new(value);
+    let read = |p: *mut u64| black_box(unsafe{*p});
+    let loads: [(&str, &dyn Fn() -> *mut u64); 4] = [
+        ("seqcst-load", &|| slot.load(Ordering::SeqCst)),
+        ("acquire-load", &|| slot.load(Ordering::Acquire)),
+        ("relaxed+acquire-fence", &|| {
+            let p = slot.load(Ordering::Relaxed);
+            atomic::fence(Ordering::Acquire);
+            p
+        }),
+        ("relaxed+seqcst-fence", &|| {
+            let p = slot.load(Ordering::Relaxed);
+            atomic::fence(Ordering::SeqCst);
+            p
+        }),
+    ];
+    for (name, load) in &loads {
+        bench.run(&format!("fence/pin+{}", name), OPS, || {
+            for _ in 0..OPS {
+                let _guard = epoch::pin();
+                read(load());
+            }
+        });
+        // Pinned once, so this is just the load and whatever fence it has.
+        bench.run(&format!("fence/{}", name), OPS, || {
+            let _guard = epoch::pin();
+            for _ in 0..OPS {
+                read(load());
+            }
+        });
+    }
+    drop(unsafe{Box::from_raw(value)});
+}
+
+fn multi<C: Cage<Canary> + 'static>(bench: &Bench, backend: &str, cage: C) {
+    let cage = Arc::new(cage);
+    for &writes in &[5, 50] {
+        let name = format!("mixed/{}%/{}", writes, backend);
+        bench.run(&name, OPS * THREADS as u64, || mixed(&cage, writes));
+    }
+}
+
+fn main() {
+    // cargo passes `--bench`; anything else is a name filter.
+    let filter = std::env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with("--"))
+        .collect();
+    let bench = Bench { filter };
+
+    single(&bench, Epoch::NAME, birdcage::<Epoch>(FlushPolicy::Never));
+    single(&bench, HazardPointers::NAME, birdcage::<HazardPointers>(FlushPolicy::Never));
+    single(&bench, Qsbr::NAME, birdcage::<Qsbr>(FlushPolicy::Never));
+    single(&bench, "epoch-acqrel", acqrel::<Epoch>());
+    single(&bench, "qsbr-acqrel", acqrel::<Qsbr>());
+    single(&bench, "arc", ArcCage::from_fn(SLOTS, canary));
+    single(&bench, "rwlock", LockCage::from_fn(SLOTS, canary));
+
+    batched::<Epoch>(&bench);
+    batched::<HazardPointers>(&bench);
+    batched::<Qsbr>(&bench);
+
+    pins(&bench);
+    fences(&bench);
+
+    whole_cage::<Epoch>(&bench);
+    whole_cage::<HazardPointers>(&bench);
+    whole_cage::<Qsbr>(&bench);
+
+    multi(&bench, Epoch::NAME, birdcage::<Epoch>(FlushPolicy::Never));
+    multi(&bench, HazardPointers::NAME, birdcage::<HazardPointers>(FlushPolicy::Never));
+    multi(&bench, Qsbr::NAME, birdcage::<Qsbr>(FlushPolicy::Never));
+    multi(&bench, "epoch-acqrel", acqrel::<Epoch>());
+    multi(&bench, "qsbr-acqrel", acqrel::<Qsbr>());
+    multi(&bench, "arc", ArcCage::from_fn(SLOTS, canary));
+    multi(&bench, "rwlock", LockCage::from_fn(SLOTS, canary));
+
+    neighbours(&bench, false);
+    neighbours(&bench, true);
+
+    // Each flush policy, with the most garbage seen while it ran, since the
+    // point of flushing more often is to trade throughput for less garbage.
+    let policies = [
+        FlushPolicy::Never,
+        FlushPolicy::EveryN(64),
+        FlushPolicy::EveryN(8),
+        FlushPolicy::EveryDuration(Duration::from_millis(1)),
+        FlushPolicy::EveryOp,
+    ];
+    for &policy in &policies {
+        let name = format!("flush/{}", policy);
+        if !bench.wants(&name) {
+            continue;
+        }
+        let cage = Arc::new(birdcage::<Epoch>(policy));
+        let peak = peak_pending(|| {
+            bench.run(&name, OPS * THREADS as u64, || mixed(&cage, 50));
+        });
+        println!("{:32} {:>10} peak pending", "", peak);
+    }
+}
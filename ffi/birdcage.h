@@ -0,0 +1,29 @@
+/* The C interface to epoch_playground's BirdCage; see src/ffi.rs. */
+
+#ifndef BIRDCAGE_H
+#define BIRDCAGE_H
+
+#include <stddef.h>
+
+#define BIRDCAGE_OK 0
+#define BIRDCAGE_EMPTY 1
+#define BIRDCAGE_BAD_ARGUMENT (-1)
+#define BIRDCAGE_WRONG_THREAD (-2)
+
+typedef struct birdcage birdcage;
+typedef struct birdcage_guard birdcage_guard;
+
+birdcage *birdcage_new(size_t size);
+int birdcage_access(const birdcage *cage, size_t n);
+int birdcage_replace(const birdcage *cage, size_t n, const char *name);
+int birdcage_take(const birdcage *cage, size_t n);
+void birdcage_destroy(birdcage *cage);
+
+void birdcage_flush(void);
+int birdcage_reclaim(void);
+size_t birdcage_alive(void);
+
+birdcage_guard *birdcage_pin(void);
+int birdcage_unpin(birdcage_guard *guard);
+
+#endif
@@ -0,0 +1,69 @@
+/*
+ * Drive a BirdCage from C threads that Rust knows nothing about.
+ *
+ *   cargo rustc --lib --release --features ffi --crate-type cdylib
+ *   cc -o harness ffi/harness.c -Ltarget/release -lepoch_playground -lpthread
+ *   LD_LIBRARY_PATH=target/release ./harness
+ */
+
+#include "birdcage.h"
+
+#include <assert.h>
+#include <pthread.h>
+#include <stdio.h>
+
+#define SLOTS 4
+#define THREADS 4
+#define ROUNDS 100
+
+static birdcage *cage;
+static birdcage_guard *held;
+
+static void *hammer(void *arg)
+{
+    char name[32];
+    for (int ii = 0; ii < ROUNDS; ii++) {
+        snprintf(name, sizeof name, "thread %ld Cuckoo %d", (long)arg, ii);
+        birdcage_replace(cage, ii % SLOTS, name);
+        birdcage_access(cage, (ii + 1) % SLOTS);
+    }
+    /* No flush: this thread's leftovers go to the global queue when it exits. */
+    return NULL;
+}
+
+static void *pin_and_leave(void *arg)
+{
+    (void)arg;
+    held = birdcage_pin();
+    return NULL;
+}
+
+int main(void)
+{
+    pthread_t threads[THREADS];
+
+    cage = birdcage_new(SLOTS);
+    assert(birdcage_access(cage, SLOTS) == BIRDCAGE_BAD_ARGUMENT);
+
+    for (long ii = 0; ii < THREADS; ii++)
+        pthread_create(&threads[ii], NULL, hammer, (void *)ii);
+    for (int ii = 0; ii < THREADS; ii++)
+        pthread_join(threads[ii], NULL);
+    assert(birdcage_reclaim());
+    printf("after the hammering: %zu canaries alive\n", birdcage_alive());
+
+    assert(birdcage_take(cage, 0) == BIRDCAGE_OK);
+    assert(birdcage_take(cage, 0) == BIRDCAGE_EMPTY);
+
+    /* A thread that exits while pinned never unpins, and nobody else may. */
+    pthread_create(&threads[0], NULL, pin_and_leave, NULL);
+    pthread_join(threads[0], NULL);
+    assert(birdcage_unpin(held) == BIRDCAGE_WRONG_THREAD);
+    birdcage_replace(cage, 1, "Stuck");
+    if (!birdcage_reclaim())
+        printf("a dead thread's pin is holding up reclamation, forever\n");
+
+    birdcage_destroy(cage);
+    printf("at exit: %zu canaries alive\n", birdcage_alive());
+    return 0;
+}
@@ -0,0 +1,9 @@
+//! Run arbitrary bytes as cage operations; see `epoch_playground::fuzz_ops`
+//! for how they're decoded and what's checked.  Build with
+//! `cargo fuzz run ops`, which turns on ASAN.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| epoch_playground::fuzz_ops::run(data));
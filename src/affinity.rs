@@ -0,0 +1,49 @@
+//! Pinning threads to CPU cores, so the scheduler can't move them around
+//! in the middle of a measurement.
+//!
+//! Only Linux is supported; elsewhere `pin_current` always fails and runs
+//! just aren't pinned.
+
+use std::thread;
+
+/// The cores this process is allowed to run on, in order.
+///
+/// Off Linux, or if the kernel won't say, it's `0..n` for however many
+/// threads the machine can run at once.
+pub fn cores() -> Vec<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut set = unsafe{std::mem::zeroed::<libc::cpu_set_t>()};
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        if unsafe{libc::sched_getaffinity(0, size, &mut set)} == 0 {
+            let cores: Vec<usize> = (0..libc::CPU_SETSIZE as usize)
+                .filter(|&core| unsafe{libc::CPU_ISSET(core, &set)})
+                .collect();
+            if !cores.is_empty() {
+                return cores;
+            }
+        }
+    }
+    let n = thread::available_parallelism().map_or(1, |n| n.get());
+    (0..n).collect()
+}
+
+/// Pin the calling thread to `core`, returning whether that worked.
+pub fn pin_current(core: usize) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        if core >= libc::CPU_SETSIZE as usize {
+            return false;
+        }
+        let mut set = unsafe{std::mem::zeroed::<libc::cpu_set_t>()};
+        unsafe{libc::CPU_SET(core, &mut set)};
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        let pinned = unsafe{libc::sched_setaffinity(0, size, &set)};
+        pinned == 0
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = core;
+        false
+    }
+}
@@ -0,0 +1,95 @@
+//! A reference-counted baseline for the `BirdCage`.
+//!
+//! Every slot holds an `Arc<T>`.  Readers clone the `Arc` and read through
+//! their own reference, so there's no deferred work at all: a replaced value
+//! is freed the instant the last reader lets go of it.  The price is an
+//! atomic increment and decrement on a shared counter for every read.
+//!
+//! The `arc-swap` crate can swap `Arc`s without any locking; it isn't
+//! available here, so each slot's pointer is guarded by a `Mutex` that's
+//! only held long enough to clone or swap the `Arc`.  Nobody ever reads a
+//! value while holding the lock.
+
+use crate::cage::{Cage, CageError};
+use std::sync::{Arc, Mutex};
+
+/// A fixed-size collection of reference-counted slots.
+pub struct ArcCage<T> {
+    c: Vec<Mutex<Arc<T>>>,
+}
+
+impl<T> ArcCage<T> {
+    /// Create a cage with `size` slots, filling slot `n` with `f(n)`.
+    pub fn from_fn<F>(size: usize, mut f: F) -> ArcCage<T>
+    where
+        F: FnMut(usize) -> T,
+    {
+        ArcCage {
+            c: (0..size).map(|ii| Mutex::new(Arc::new(f(ii)))).collect(),
+        }
+    }
+
+    /// The number of slots in the cage.
+    pub fn len(&self) -> usize {
+        self.c.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.c.is_empty()
+    }
+
+    // Slot `n`, if there is one.  Slots are never empty.
+    fn slot(&self, n: usize) -> Result<&Mutex<Arc<T>>, CageError> {
+        self.c.get(n).ok_or(CageError::IndexOutOfBounds {
+            index: n,
+            len: self.c.len(),
+        })
+    }
+
+    /// Get our own reference to the value in slot `n`.
+    ///
+    /// Unlike a `BirdCage` reference, this can be kept as long as we like.
+    pub fn load(&self, n: usize) -> Result<Arc<T>, CageError> {
+        Ok(self.slot(n)?.lock().unwrap().clone())
+    }
+
+    /// Hand the value in slot `n` to `f`, returning whatever `f` returns.
+    pub fn with_slot<F, R>(&self, n: usize, f: F) -> Result<R, CageError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.load(n).map(|c| f(&c))
+    }
+
+    /// Put `new_c` into slot `n`, returning the old value.
+    ///
+    /// If nobody else is holding the old value, it's freed as soon as the
+    /// returned `Arc` is dropped.
+    pub fn swap(&self, n: usize, new_c: T) -> Result<Arc<T>, CageError> {
+        let slot = self.slot(n)?;
+        Ok(std::mem::replace(&mut *slot.lock().unwrap(), Arc::new(new_c)))
+    }
+}
+
+impl<T: Send + Sync> Cage<T> for ArcCage<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn with_slot<F, R>(&self, n: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.with_slot(n, f).ok()
+    }
+
+    // The trait can't report a bad slot, so this panics on one, like
+    // indexing a slice would.
+    fn put_with<F>(&self, n: usize, value: T, removed: F)
+    where
+        F: FnOnce(&T),
+    {
+        let old = self.swap(n, value).unwrap_or_else(|e| panic!("{}", e));
+        removed(&old);
+    }
+}
@@ -0,0 +1,154 @@
+//! The ABA problem, acted out step by step on a tiny Treiber stack.
+//!
+//! Thread A starts a pop: it reads the head (node `a`) and the node after it
+//! (`b`), and then gets delayed just before its compare-and-swap.  While it
+//! waits, thread B pops `a` and `b` and pushes two new nodes.  If `a` was
+//! freed right away, the allocator is likely to hand its address to one of
+//! the new nodes, so the head *looks* unchanged to A: same address, different
+//! node.  A's CAS succeeds, and it installs `b`, which it never should have.
+//!
+//! With epochs, A is pinned the whole time, so `a` can't be freed, its
+//! address can't be reused, and A's CAS fails the way it should.
+//!
+//! Build with `--features aba-bug` to free popped nodes immediately instead
+//! of deferring them, and watch the stack get corrupted.  That build has
+//! undefined behavior on purpose; it exists only to show the bug.
+
+use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared};
+use std::sync::atomic::Ordering;
+use std::thread;
+
+struct Node {
+    value: u64,
+    next: Atomic<Node>,
+}
+
+struct Stack {
+    head: Atomic<Node>,
+}
+
+impl Stack {
+    // Returns the new node's address.
+    fn push(&self, value: u64) -> usize {
+        let guard = &epoch::pin();
+        let mut node = Owned::new(Node {
+            value,
+            next: Atomic::null(),
+        });
+        loop {
+            let head = self.head.load(Ordering::SeqCst, guard);
+            node.next.store(head, Ordering::SeqCst);
+            match self.head.compare_and_set(head, node, Ordering::SeqCst, guard) {
+                Ok(new) => return new.as_raw() as usize,
+                Err(e) => node = e.new,
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<u64> {
+        let guard = &epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::SeqCst, guard);
+            let next = unsafe{head.as_ref()}?.next.load(Ordering::SeqCst, guard);
+            if self.head.compare_and_set(head, next, Ordering::SeqCst, guard).is_ok() {
+                return Some(unsafe{free(head, guard)});
+            }
+        }
+    }
+
+    fn values(&self) -> Vec<u64> {
+        let guard = &epoch::pin();
+        let mut values = Vec::new();
+        let mut p = self.head.load(Ordering::SeqCst, guard);
+        while let Some(node) = unsafe{p.as_ref()} {
+            values.push(node.value);
+            p = node.next.load(Ordering::SeqCst, guard);
+        }
+        values
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+// Take the value out of a node we just unlinked, and get rid of the node.
+#[cfg(not(feature = "aba-bug"))]
+unsafe fn free(node: Shared<Node>, guard: &Guard) -> u64 {
+    let value = node.deref().value;
+    // Anyone still pinned might be looking at it, so it has to wait.
+    guard.defer_destroy(node);
+    value
+}
+
+#[cfg(feature = "aba-bug")]
+unsafe fn free(node: Shared<Node>, _guard: &Guard) -> u64 {
+    // Wrong: some other thread may still hold this pointer.
+    node.into_owned().value
+}
+
+fn main() {
+    let stack = Stack {
+        head: Atomic::null(),
+    };
+    stack.push(3);
+    stack.push(2);
+    stack.push(1);
+    println!("stack:  {:?}", stack.values());
+
+    // Thread A: the first half of a pop.
+    let guard = &epoch::pin();
+    let a = stack.head.load(Ordering::SeqCst, guard);
+    let b = unsafe{a.deref()}.next.load(Ordering::SeqCst, guard);
+    let (a_value, b_value) = unsafe{(a.deref().value, b.deref().value)};
+    println!(
+        "A: head is node {} at {:p}, next is node {} at {:p}",
+        a_value,
+        a.as_raw(),
+        b_value,
+        b.as_raw()
+    );
+
+    // Thread B runs to completion while A is stalled.
+    let addresses = thread::scope(|s| {
+        s.spawn(|| {
+            println!("B: popped {:?} and {:?}", stack.pop(), stack.pop());
+            let addresses = [stack.push(4), stack.push(5)];
+            println!("B: pushed 4 at {:#x} and 5 at {:#x}", addresses[0], addresses[1]);
+            addresses
+        })
+        .join()
+        .unwrap()
+    });
+    println!("stack:  {:?}", stack.values());
+    let reused = |p: Shared<Node>| addresses.contains(&(p.as_raw() as usize));
+    if reused(a) {
+        println!("node {}'s old address has been reused by a new node", a_value);
+    } else {
+        println!("node {}'s old address hasn't been reused", a_value);
+    }
+
+    // Thread A: the second half.
+    match stack.head.compare_and_set(a, b, Ordering::SeqCst, guard) {
+        Ok(_) => {
+            println!("A: CAS succeeded, though the head had changed: ABA!");
+            // Node `b` was popped and freed by B, and it's the head now.
+            if reused(b) {
+                // Its address went to one of B's nodes, so that's what the
+                // head is now, and whatever was above it is gone.
+                println!("stack:  {:?}", stack.values());
+            } else {
+                println!("the head now points at freed memory");
+            }
+            // Nothing about this stack can be trusted any more.
+            std::mem::forget(stack);
+        }
+        Err(_) => {
+            println!("A: CAS failed, so A starts over");
+            println!("A: popped {:?}", stack.pop());
+            println!("stack:  {:?}", stack.values());
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! A quick side-by-side of every cage and reclaimer, running the stress
+//! workload for a second each and printing one line apiece.
+//!
+//! This is for getting a feel for the differences; `cargo bench` has the
+//! more careful measurements.  Pass a duration to run each one for longer,
+//! e.g. `cargo run --release --bin bench -- 5s`.
+
+use epoch_playground::cli::parse_duration;
+use epoch_playground::stress::{self, CageKind, ReclaimerKind, StressConfig};
+use std::env;
+use std::process;
+use std::time::Duration;
+
+fn main() {
+    let duration = match env::args().nth(1) {
+        Some(arg) => parse_duration(&arg).unwrap_or_else(|e| {
+            eprintln!("{}\nusage: bench [DURATION]", e);
+            process::exit(2);
+        }),
+        None => Duration::from_secs(1),
+    };
+    let backends = [
+        (CageKind::BirdCage, ReclaimerKind::Epoch),
+        (CageKind::BirdCage, ReclaimerKind::Hazard),
+        (CageKind::BirdCage, ReclaimerKind::Qsbr),
+        (CageKind::Arc, ReclaimerKind::Epoch),
+        (CageKind::Lock, ReclaimerKind::Epoch),
+    ];
+
+    println!(
+        "{:<10}{:>14}{:>14}{:>14}{:>14}",
+        "backend", "reads/s", "writes/s", "peak garbage", "read p99"
+    );
+    for &(cage, reclaimer) in &backends {
+        let report = stress::run(&StressConfig {
+            cage,
+            reclaimer,
+            duration,
+            ..StressConfig::default()
+        });
+        let secs = report.elapsed.as_secs_f64();
+        println!(
+            "{:<10}{:>14.0}{:>14.0}{:>14}{:>14?}",
+            report.backend(),
+            report.reads as f64 / secs,
+            report.writes as f64 / secs,
+            report.peak_garbage,
+            Duration::from_nanos(report.read_latency.quantile(0.99))
+        );
+    }
+}
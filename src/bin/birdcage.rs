@@ -0,0 +1,57 @@
+//! The birdcage demo on its own, without the command line: a few threads
+//! that each access and replace random slots, printing every access,
+//! replace and drop.  `epoch_playground demo` is the same thing with knobs.
+
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::{BirdCage, Canary};
+use rand::Rng;
+use std::sync::Arc;
+use std::thread;
+
+// Increase these to see how much deferred work gets buffered before
+// items start getting dropped.
+const CAGE_SIZE: usize = 10;
+const ITERATIONS: usize = 20;
+const NUM_THREADS: usize = 4;
+
+fn worker(birdcage: &BirdCage<Canary>, id: usize) {
+    let my_name = format!("thread {}", id);
+    let mut rng = rand::thread_rng();
+
+    for n in 0..ITERATIONS {
+        let pick1 = rng.gen_range(0, birdcage.len());
+        if let Err(e) = birdcage.access(pick1, &my_name) {
+            println!("[{}] {}", my_name, e);
+        }
+
+        let c = Canary::new(&format!("{} Cuckoo {}", my_name, n));
+        let pick2 = rng.gen_range(0, birdcage.len());
+        if let Err(e) = birdcage.replace(pick2, &my_name, c) {
+            println!("[{}] {}", my_name, e);
+        }
+    }
+    println!("{} exiting", my_name);
+}
+
+fn main() {
+    let birdcage = Arc::new(BirdCage::new(CAGE_SIZE));
+    let mut thread_handles = Vec::new();
+
+    for thread_id in 0..NUM_THREADS {
+        let local_birdcage = birdcage.clone();
+        let handle = thread::spawn(move || worker(local_birdcage.as_ref(), thread_id));
+        thread_handles.push(handle);
+    }
+
+    for handle in thread_handles {
+        handle.join().unwrap();
+    }
+
+    println!("dropping the cage");
+    drop(birdcage);
+
+    // Whatever was replaced but not yet dropped is still in the global
+    // garbage.
+    force_reclaim::<Epoch>();
+    println!("{} canaries created, {} still alive", Canary::created(), Canary::alive());
+}
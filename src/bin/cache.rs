@@ -0,0 +1,46 @@
+use epoch_playground::clock_cache::ClockCache;
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use rand::Rng;
+use std::sync::Arc;
+use std::thread;
+
+// A small cache and a bigger key space, so there's plenty of eviction.
+const CAPACITY: usize = 64;
+const KEY_SPACE: u32 = 256;
+const ITERATIONS: usize = 100_000;
+const NUM_THREADS: usize = 4;
+
+fn worker(cache: &ClockCache<u32, String>) {
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..ITERATIONS {
+        // Low keys are much more popular, so CLOCK should keep them around.
+        let key = rng.gen_range(0, KEY_SPACE).min(rng.gen_range(0, KEY_SPACE));
+        if cache.get(&key).is_none() {
+            cache.insert(key, format!("value {}", key));
+        }
+    }
+}
+
+fn main() {
+    let cache = Arc::new(ClockCache::new(CAPACITY));
+    let mut thread_handles = Vec::new();
+
+    for _ in 0..NUM_THREADS {
+        let local_cache = cache.clone();
+        thread_handles.push(thread::spawn(move || worker(&local_cache)));
+    }
+    for handle in thread_handles {
+        handle.join().unwrap();
+    }
+
+    let stats = cache.linger_stats();
+    println!("evictions: {}", cache.evictions());
+    println!("destroyed: {} (before final flush)", stats.count());
+
+    force_reclaim::<Epoch>();
+
+    println!("destroyed: {} (after final flush)", stats.count());
+    println!("mean linger: {:?}", stats.mean());
+    println!("max linger:  {:?}", stats.max());
+}
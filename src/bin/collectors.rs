@@ -0,0 +1,195 @@
+//! Should a data structure own its collector?  The same workload, once on a
+//! `BirdCage`, which shares crossbeam's default collector with everything
+//! else in the process, and once on a `PrivateBirdCage`, which has a
+//! `Collector` of its own.
+//!
+//! Each runs twice: alone, and next to an unrelated neighbour that also
+//! uses the default collector, churning garbage of its own and every so
+//! often staying pinned for a millisecond.  A pinned thread holds back
+//! everything retired through its collector, so the neighbour's stalls
+//! show up in the shared cage's reclamation delays, and not at all in the
+//! private one's.  The price of a private collector is a `LocalHandle` to
+//! carry around, and a registry of its own to keep the epoch moving.
+//!
+//! Pass a number of operations per thread to change how long it runs, e.g.
+//! `cargo run --release --bin collectors -- 1000000`.
+
+use crossbeam::epoch::{self, LocalHandle};
+use epoch_playground::histogram::Histogram;
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::{BirdCage, Cage, Canary, PrivateBirdCage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::env;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SLOTS: usize = 64;
+const THREADS: usize = 4;
+const WRITE_PERCENT: u32 = 20;
+const DEFAULT_OPS: u64 = 200_000;
+// The neighbour stays pinned this long, once every `NEIGHBOUR_STALL_EVERY`
+// pieces of garbage.
+const NEIGHBOUR_STALL: Duration = Duration::from_millis(1);
+const NEIGHBOUR_STALL_EVERY: usize = 1000;
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+
+fn canary(n: u64) -> Canary {
+    Canary::silent(&format!("Canary {}", n))
+}
+
+// What the workload needs from a cage, whichever collector is behind it.
+trait Target: Sync {
+    type Handle;
+    const NAME: &'static str;
+
+    fn register(&self) -> Self::Handle;
+    fn read(&self, handle: &Self::Handle, n: usize) -> usize;
+    fn write(&self, handle: &Self::Handle, n: usize, c: Canary);
+    fn flush(&self, handle: &Self::Handle);
+}
+
+impl Target for BirdCage<Canary, Epoch> {
+    // The default collector keeps a handle per thread for us.
+    type Handle = ();
+    const NAME: &'static str = "default";
+
+    fn register(&self) {}
+
+    fn read(&self, _: &(), n: usize) -> usize {
+        self.with_slot(n, |c| c.name().len()).unwrap_or(0)
+    }
+
+    fn write(&self, _: &(), n: usize, c: Canary) {
+        self.put_with(n, c, Canary::mark_retired);
+    }
+
+    fn flush(&self, _: &()) {
+        Cage::flush(self);
+    }
+}
+
+impl Target for PrivateBirdCage<Canary> {
+    type Handle = LocalHandle;
+    const NAME: &'static str = "private";
+
+    fn register(&self) -> LocalHandle {
+        PrivateBirdCage::register(self)
+    }
+
+    fn read(&self, handle: &LocalHandle, n: usize) -> usize {
+        self.with_slot(handle, n, |c| c.name().len()).unwrap_or(0)
+    }
+
+    fn write(&self, handle: &LocalHandle, n: usize, c: Canary) {
+        self.put_with(handle, n, c, Canary::mark_retired).unwrap();
+    }
+
+    fn flush(&self, handle: &LocalHandle) {
+        handle.pin().flush();
+    }
+}
+
+fn worker<C: Target>(cage: &C, id: u64, ops: u64) {
+    let handle = cage.register();
+    let mut rng = StdRng::seed_from_u64(id);
+    for ii in 0..ops {
+        let n = rng.gen_range(0, SLOTS);
+        if rng.gen_range(0, 100) < WRITE_PERCENT {
+            cage.write(&handle, n, canary(ii));
+        } else {
+            black_box(cage.read(&handle, n));
+        }
+    }
+    // Otherwise our leftovers might not be handed over until after the
+    // scope ends.
+    cage.flush(&handle);
+}
+
+// Keep the default collector busy until `stop` is set.
+fn neighbour(stop: &AtomicBool) {
+    let mut count = 0;
+    while !stop.load(Ordering::Relaxed) {
+        let guard = epoch::pin();
+        let junk = Box::new([0_u8; 64]);
+        guard.defer(move || drop(junk));
+        count += 1;
+        if count % NEIGHBOUR_STALL_EVERY == 0 {
+            thread::sleep(NEIGHBOUR_STALL);
+        }
+    }
+    epoch::pin().flush();
+}
+
+struct Outcome {
+    elapsed: Duration,
+    peak_garbage: usize,
+    delays: Histogram,
+}
+
+fn run<C: Target>(cage: &C, ops: u64, with_neighbour: bool) -> Outcome {
+    Canary::reset_reclaim_delays();
+    let alive_before = Canary::alive();
+    let stop = AtomicBool::new(false);
+    let start = Instant::now();
+    let mut peak_garbage = 0;
+    thread::scope(|s| {
+        let neighbour = with_neighbour.then(|| s.spawn(|| neighbour(&stop)));
+        let workers: Vec<_> = (0..THREADS as u64)
+            .map(|id| s.spawn(move || worker(cage, id, ops)))
+            .collect();
+        while workers.iter().any(|w| !w.is_finished()) {
+            thread::sleep(SAMPLE_INTERVAL);
+            peak_garbage = peak_garbage.max(Canary::alive().saturating_sub(alive_before));
+        }
+        stop.store(true, Ordering::Relaxed);
+        if let Some(neighbour) = neighbour {
+            neighbour.join().unwrap();
+        }
+    });
+    Outcome {
+        elapsed: start.elapsed(),
+        peak_garbage,
+        // Only what was reclaimed while the workload ran; whatever is left
+        // goes when the cage does, which isn't what we're measuring.
+        delays: Canary::reclaim_delays(),
+    }
+}
+
+fn report<C: Target>(cage: C, ops: u64, with_neighbour: bool) {
+    let outcome = run(&cage, ops, with_neighbour);
+    drop(cage);
+    force_reclaim::<Epoch>();
+    let secs = outcome.elapsed.as_secs_f64();
+    let delay = |q| Duration::from_nanos(outcome.delays.quantile(q));
+    println!(
+        "{:<10}{:<11}{:>14.0}{:>14}{:>14?}{:>14?}",
+        C::NAME,
+        if with_neighbour { "yes" } else { "no" },
+        (ops * THREADS as u64) as f64 / secs,
+        outcome.peak_garbage,
+        delay(0.5),
+        delay(0.99),
+    );
+}
+
+fn main() {
+    let ops = match env::args().nth(1) {
+        Some(arg) => arg.parse().unwrap_or_else(|_| {
+            eprintln!("bad operation count {:?}\nusage: collectors [OPS]", arg);
+            std::process::exit(2);
+        }),
+        None => DEFAULT_OPS,
+    };
+
+    println!(
+        "{:<10}{:<11}{:>14}{:>14}{:>14}{:>14}",
+        "collector", "neighbour", "ops/s", "peak garbage", "reclaim p50", "reclaim p99"
+    );
+    for &with_neighbour in &[false, true] {
+        report(BirdCage::<Canary, Epoch>::from_fn(SLOTS, |n| canary(n as u64)), ops, with_neighbour);
+        report(PrivateBirdCage::from_fn(SLOTS, |n| canary(n as u64)), ops, with_neighbour);
+    }
+}
@@ -0,0 +1,211 @@
+//! The three ways to hand work to the epoch collector, side by side on the
+//! same workload: a few threads replacing the value in one slot, over and
+//! over, while reading it in between.
+//!
+//! - `defer_destroy(shared)` drops the value behind a `Shared` later.  It's
+//!   `unsafe`: the caller promises nobody else will destroy it, and nothing
+//!   checks that the value can be dropped on another thread.
+//! - `defer(f)` is safe, so `f` has to be `Send + 'static`: it may run on
+//!   any thread, long after this stack frame is gone.  A `Shared` is
+//!   neither, so getting a pointer in means smuggling it out as a raw
+//!   pointer, and the `unsafe` just moves into the closure.  Where `defer`
+//!   shines is work that isn't freeing memory at all.
+//! - `defer_unchecked(f)` has no bounds on `f`, so it can capture the
+//!   `Shared` directly, or borrow from the stack.  It's on the caller to
+//!   make sure that's fine wherever and whenever `f` ends up running.
+//!
+//! After the three, two closures that release something other than memory:
+//! a connection handed back to a pool once no reader can be using it, and
+//! a borrowed, single-threaded log that `defer_unchecked` can only get away
+//! with because the collector is private to this thread and dropped first.
+
+use crossbeam::epoch::{self, Atomic, Collector, Guard, Owned, Shared};
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::Canary;
+use std::cell::RefCell;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+const NUM_THREADS: usize = 4;
+const REPLACES: usize = 20_000;
+const CONNECTIONS: usize = 16;
+
+#[derive(Clone, Copy, Debug)]
+enum Api {
+    DeferDestroy,
+    Defer,
+    DeferUnchecked,
+}
+
+// A raw pointer that can be moved into a `defer` closure.  Sending it is
+// only sound because the value it points to is `Send`, and we're the only
+// ones who will ever free it.
+struct SendPtr<T>(*mut T);
+
+unsafe impl<T: Send> Send for SendPtr<T> {}
+
+// Schedule destruction of `old`, which we just unlinked.
+fn retire(api: Api, old: Shared<'_, Canary>, guard: &Guard) {
+    match api {
+        Api::DeferDestroy => unsafe {
+            // No closure at all: crossbeam stores the pointer and a function
+            // that drops it.
+            guard.defer_destroy(old);
+        },
+        Api::Defer => {
+            // `old` borrows from `guard` (its lifetime is `'g`), and isn't
+            // `Send`, so the closure can't capture it.  A raw pointer has
+            // no lifetime, and the wrapper promises it can be sent.
+            let ptr = SendPtr(old.as_raw() as *mut Canary);
+            guard.defer(move || {
+                // The compiler can't tell that nobody else frees this
+                // pointer, so this part is still `unsafe`.
+                drop(unsafe{Box::from_raw(ptr.0)});
+            });
+        }
+        Api::DeferUnchecked => unsafe {
+            // The `Shared` goes straight in, lifetime and all.  Once the
+            // epoch has moved on nobody can be using it, and `into_owned`
+            // doesn't need the guard, so this is the same as
+            // `defer_destroy`, spelled out.
+            guard.defer_unchecked(move || drop(old.into_owned()));
+        },
+    }
+}
+
+fn worker(slot: &Atomic<Canary>, api: Api, id: usize) {
+    for ii in 0..REPLACES {
+        let guard = &epoch::pin();
+        let c = Canary::silent(&format!("thread {} Cuckoo {}", id, ii));
+        let old = slot.swap(Owned::new(c), Ordering::SeqCst, guard);
+        retire(api, old, guard);
+        let now = unsafe{slot.load(Ordering::SeqCst, guard).deref()};
+        assert!(!now.name().is_empty());
+    }
+}
+
+fn run(api: Api) {
+    let slot = Atomic::new(Canary::silent("Canary 0"));
+    let alive_before = Canary::alive();
+    let start = Instant::now();
+    thread::scope(|s| {
+        for id in 0..NUM_THREADS {
+            let slot = &slot;
+            s.spawn(move || {
+                worker(slot, api, id);
+                // Hand our leftovers over before the scope ends.
+                epoch::pin().flush();
+            });
+        }
+    });
+    let elapsed = start.elapsed();
+    force_reclaim::<Epoch>();
+    println!(
+        "{:<16} {:>8.1}ns per replace, {} canaries left over",
+        format!("{:?}:", api),
+        elapsed.as_nanos() as f64 / (NUM_THREADS * REPLACES) as f64,
+        Canary::alive() - alive_before
+    );
+    unsafe {
+        drop(slot.into_owned());
+    }
+}
+
+// A pool of connections, each just a number here.  A connection taken out
+// of a session mustn't go back to the pool while a reader might still be
+// using the session, or two sessions would share it.
+struct Pool {
+    free: Mutex<Vec<usize>>,
+}
+
+impl Pool {
+    // Wait for a free connection.  Ours may be stuck in this thread's own
+    // deferred work, so push that along while we wait.  We mustn't be
+    // pinned here, or the ones we're waiting for could never come back.
+    fn take(&self) -> usize {
+        loop {
+            if let Some(conn) = self.free.lock().unwrap().pop() {
+                return conn;
+            }
+            epoch::pin().flush();
+            thread::yield_now();
+        }
+    }
+
+    fn give_back(&self, conn: usize) {
+        self.free.lock().unwrap().push(conn);
+    }
+}
+
+struct Session {
+    conn: usize,
+}
+
+fn pooled_connections() {
+    let pool = Arc::new(Pool {
+        free: Mutex::new((0..CONNECTIONS).collect()),
+    });
+    let slot = Atomic::new(Session { conn: pool.take() });
+    thread::scope(|s| {
+        for _ in 0..NUM_THREADS {
+            let (slot, pool) = (&slot, &pool);
+            s.spawn(move || {
+                for _ in 0..REPLACES / 10 {
+                    let new = Owned::new(Session { conn: pool.take() });
+                    let guard = &epoch::pin();
+                    let old = slot.swap(new, Ordering::SeqCst, guard);
+                    let conn = unsafe{old.deref()}.conn;
+                    // The closure owns an `Arc` and a number: `Send` and
+                    // `'static`, so the safe `defer` takes it as is.
+                    let pool = pool.clone();
+                    guard.defer(move || pool.give_back(conn));
+                    unsafe {
+                        guard.defer_destroy(old);
+                    }
+                }
+                epoch::pin().flush();
+            });
+        }
+    });
+    force_reclaim::<Epoch>();
+    let session = unsafe{slot.into_owned()};
+    pool.give_back(session.conn);
+    println!(
+        "pooled connections: {} of {} back in the pool",
+        pool.free.lock().unwrap().len(),
+        CONNECTIONS
+    );
+}
+
+fn borrowed_log() {
+    // Neither `Send` nor `'static`, so `defer` won't take a closure that
+    // touches it.
+    let log = RefCell::new(Vec::new());
+    {
+        // This collector is only ever used from this thread, and it's
+        // dropped at the end of this block, which runs whatever is still
+        // deferred.  So every closure runs on this thread, before `log`
+        // goes away, and `defer_unchecked` is sound.
+        let collector = Collector::new();
+        let handle = collector.register();
+        for ii in 0..5 {
+            let guard = handle.pin();
+            let log = &log;
+            unsafe {
+                guard.defer_unchecked(move || log.borrow_mut().push(ii));
+            }
+        }
+        println!("borrowed log: {} entries before the collector is dropped", log.borrow().len());
+    }
+    println!("borrowed log: {:?} after", log.borrow());
+}
+
+fn main() {
+    for &api in &[Api::DeferDestroy, Api::Defer, Api::DeferUnchecked] {
+        run(api);
+    }
+    pooled_connections();
+    borrowed_log();
+}
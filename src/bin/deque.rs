@@ -0,0 +1,78 @@
+use epoch_playground::chase_lev::{Steal, Stealer, Worker};
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::Canary;
+use rand::Rng;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Thread 0 gets all the work, so everyone else has to steal it.
+const NUM_THREADS: usize = 4;
+const NUM_TASKS: usize = 40;
+
+fn worker(
+    id: usize,
+    deque: Worker<Canary>,
+    stealers: Vec<Stealer<Canary>>,
+    remaining: &AtomicUsize,
+) {
+    let my_name = format!("worker {}", id);
+    let mut rng = rand::thread_rng();
+
+    if id == 0 {
+        for n in 0..NUM_TASKS {
+            deque.push(Canary::new(&format!("Task {}", n)));
+        }
+    }
+
+    while remaining.load(Ordering::SeqCst) > 0 {
+        let task = match deque.pop() {
+            Some(task) => {
+                println!("[{}] popped {}", my_name, task.name());
+                task
+            }
+            None => {
+                // Pick a random victim, and if it has anything, take it.
+                let victim = rng.gen_range(0, stealers.len());
+                match stealers[victim].steal() {
+                    Steal::Success(task) => {
+                        println!("[{}] stole {} from worker {}", my_name, task.name(), victim);
+                        task
+                    }
+                    Steal::Empty | Steal::Retry => {
+                        thread::yield_now();
+                        continue;
+                    }
+                }
+            }
+        };
+
+        // "Running" a task takes a little while, which gives the other
+        // workers a chance to steal.
+        thread::sleep(Duration::from_millis(1));
+        remaining.fetch_sub(1, Ordering::SeqCst);
+        drop(task);
+    }
+    println!("{} exiting", my_name);
+}
+
+fn main() {
+    let deques: Vec<Worker<Canary>> = (0..NUM_THREADS).map(|_| Worker::new()).collect();
+    let stealers: Vec<Stealer<Canary>> = deques.iter().map(|d| d.stealer()).collect();
+    let remaining = Arc::new(AtomicUsize::new(NUM_TASKS));
+    let mut thread_handles = Vec::new();
+
+    for (id, deque) in deques.into_iter().enumerate() {
+        let stealers = stealers.clone();
+        let remaining = remaining.clone();
+        thread_handles.push(thread::spawn(move || worker(id, deque, stealers, &remaining)));
+    }
+
+    for handle in thread_handles {
+        handle.join().unwrap();
+    }
+
+    // The buffers that were outgrown are waiting in the global garbage.
+    force_reclaim::<Epoch>();
+}
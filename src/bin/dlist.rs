@@ -0,0 +1,139 @@
+//! Threads inserting into and removing from one doubly linked list, while
+//! another walks it backwards and forwards.  Every key carries a canary, so
+//! the removed nodes can be seen being reclaimed, and only once neither
+//! neighbour links to them.
+
+use epoch_playground::doubly_linked_list::DoublyLinkedList;
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::Canary;
+use std::cmp::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::thread;
+
+// Increase these to see more contention on the same keys.
+const KEYS: u32 = 8;
+const ROUNDS: u32 = 5;
+const NUM_THREADS: u32 = 3;
+
+// A key, ordered by number, carrying a canary that announces when the
+// list's node for it is finally freed.
+struct Key {
+    n: u32,
+    // Never read; it's only here to be dropped along with the key.
+    _canary: Canary,
+}
+
+impl Key {
+    fn new(n: u32, owner: &str) -> Key {
+        Key {
+            n,
+            _canary: Canary::new(&format!("{}'s key {}", owner, n)),
+        }
+    }
+
+    // A key to look things up with, or to copy a key out as.  Its canary
+    // keeps quiet, so only the list's own keys are heard from.
+    fn probe(n: u32) -> Key {
+        Key {
+            n,
+            _canary: Canary::silent("probe"),
+        }
+    }
+}
+
+// Copies are only for looking at, so they get a quiet canary.
+impl Clone for Key {
+    fn clone(&self) -> Key {
+        Key::probe(self.n)
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.n == other.n
+    }
+}
+
+impl Eq for Key {}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        self.n.cmp(&other.n)
+    }
+}
+
+fn numbers(keys: Vec<Key>) -> Vec<u32> {
+    keys.iter().map(|k| k.n).collect()
+}
+
+fn worker(list: &DoublyLinkedList<Key>, id: u32) {
+    let my_name = format!("thread {}", id);
+
+    for round in 0..ROUNDS {
+        // Each thread starts at a different key, so they collide now and
+        // then rather than all the time.
+        for ii in 0..KEYS {
+            let n = (ii + id * 3 + round) % KEYS;
+            if ii % 2 == 0 {
+                // A key that's already there isn't inserted, and its canary
+                // is dropped right here.
+                let inserted = list.insert(Key::new(n, &my_name));
+                println!("[{}] insert {}: {}", my_name, n, inserted);
+            } else {
+                let removed = list.remove(&Key::probe(n));
+                println!("[{}] remove {}: {}", my_name, n, removed);
+            }
+        }
+    }
+    println!("{} exiting", my_name);
+}
+
+// Walk the list both ways until told to stop.  The two walks needn't agree,
+// since writers get in between them, but each has to come out in order.
+fn walker(list: &DoublyLinkedList<Key>, stop: &AtomicBool) {
+    let mut walks = 0;
+    while !stop.load(AtomicOrdering::Relaxed) {
+        let forwards = numbers(list.to_vec());
+        let backwards = numbers(list.to_vec_rev());
+        assert!(forwards.windows(2).all(|w| w[0] < w[1]), "{:?}", forwards);
+        assert!(backwards.windows(2).all(|w| w[0] > w[1]), "{:?}", backwards);
+        walks += 1;
+        thread::yield_now();
+    }
+    println!("walker: walked the list both ways {} times", walks);
+}
+
+fn main() {
+    let list = DoublyLinkedList::new();
+    let stop = AtomicBool::new(false);
+
+    thread::scope(|s| {
+        let walker = s.spawn(|| walker(&list, &stop));
+        let workers: Vec<_> = (0..NUM_THREADS)
+            .map(|id| {
+                let list = &list;
+                s.spawn(move || worker(list, id))
+            })
+            .collect();
+        for w in workers {
+            w.join().unwrap();
+        }
+        stop.store(true, AtomicOrdering::Relaxed);
+        walker.join().unwrap();
+    });
+
+    println!("keys forwards:  {:?}", numbers(list.to_vec()));
+    println!("keys backwards: {:?}", numbers(list.to_vec_rev()));
+
+    println!("dropping the list");
+    drop(list);
+
+    // The removed nodes, and their canaries, are in the global garbage.
+    force_reclaim::<Epoch>();
+}
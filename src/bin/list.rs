@@ -0,0 +1,108 @@
+//! Threads inserting into and removing from one Harris list, with a canary
+//! in every key so the removals can be seen being reclaimed.
+
+use epoch_playground::harris_list::HarrisList;
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::Canary;
+use std::cmp::Ordering;
+use std::sync::Arc;
+use std::thread;
+
+// Increase these to see more contention on the same keys.
+const KEYS: u32 = 8;
+const ROUNDS: u32 = 5;
+const NUM_THREADS: u32 = 3;
+
+// A key, ordered by number, carrying a canary that announces when the
+// list's node for it is finally freed.
+struct Key {
+    n: u32,
+    // Never read; it's only here to be dropped along with the key.
+    _canary: Canary,
+}
+
+impl Key {
+    fn new(n: u32, owner: &str) -> Key {
+        Key {
+            n,
+            _canary: Canary::new(&format!("{}'s key {}", owner, n)),
+        }
+    }
+
+    // A key to look things up with.  Its canary keeps quiet, so only the
+    // list's own keys are heard from.
+    fn probe(n: u32) -> Key {
+        Key {
+            n,
+            _canary: Canary::silent("probe"),
+        }
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.n == other.n
+    }
+}
+
+impl Eq for Key {}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        self.n.cmp(&other.n)
+    }
+}
+
+fn worker(list: &HarrisList<Key>, id: u32) {
+    let my_name = format!("thread {}", id);
+
+    for round in 0..ROUNDS {
+        // Each thread starts at a different key, so they collide now and
+        // then rather than all the time.
+        for ii in 0..KEYS {
+            let n = (ii + id * 3 + round) % KEYS;
+            if ii % 2 == 0 {
+                // A key that's already there isn't inserted, and its canary
+                // is dropped right here.
+                let inserted = list.insert(Key::new(n, &my_name));
+                println!("[{}] insert {}: {}", my_name, n, inserted);
+            } else {
+                let removed = list.remove(&Key::probe(n));
+                println!("[{}] remove {}: {}", my_name, n, removed);
+            }
+        }
+    }
+    println!("{} exiting", my_name);
+}
+
+fn main() {
+    let list = Arc::new(HarrisList::new());
+    let mut thread_handles = Vec::new();
+
+    for thread_id in 0..NUM_THREADS {
+        let local_list = list.clone();
+        let handle = thread::spawn(move || worker(local_list.as_ref(), thread_id));
+        thread_handles.push(handle);
+    }
+
+    for handle in thread_handles {
+        handle.join().unwrap();
+    }
+
+    let left: Vec<u32> = (0..KEYS)
+        .filter(|&n| list.contains(&Key::probe(n)))
+        .collect();
+    println!("keys left in the list: {:?}", left);
+
+    println!("dropping the list");
+    drop(list);
+
+    // The removed nodes, and their canaries, are in the global garbage.
+    force_reclaim::<Epoch>();
+}
@@ -0,0 +1,68 @@
+use epoch_playground::ms_queue::MsQueue;
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::Canary;
+use std::sync::Arc;
+use std::thread;
+
+// Increase these to see more contention between producers and consumers.
+const ITERATIONS: usize = 20;
+const NUM_PRODUCERS: usize = 2;
+const NUM_CONSUMERS: usize = 2;
+
+fn producer(queue: &MsQueue<Canary>, id: usize) {
+    let my_name = format!("producer {}", id);
+
+    for n in 0..ITERATIONS {
+        let c = Canary::new(&format!("{} Canary {}", my_name, n));
+        println!("[{}] push {}", my_name, c.name());
+        queue.push(c);
+    }
+    println!("{} exiting", my_name);
+}
+
+fn consumer(queue: &MsQueue<Canary>, id: usize) {
+    let my_name = format!("consumer {}", id);
+
+    // Consumers each pop half of what's produced, before giving up.
+    let mut remaining = ITERATIONS * NUM_PRODUCERS / NUM_CONSUMERS;
+    let mut misses = 0;
+    while remaining > 0 && misses < 1000 {
+        match queue.pop() {
+            Some(c) => {
+                println!("[{}] popped {}", my_name, c.name());
+                remaining -= 1;
+            }
+            None => {
+                misses += 1;
+                thread::yield_now();
+            }
+        }
+    }
+    println!("{} exiting", my_name);
+}
+
+fn main() {
+    let queue = Arc::new(MsQueue::new());
+    let mut thread_handles = Vec::new();
+
+    for id in 0..NUM_PRODUCERS {
+        let local_queue = queue.clone();
+        thread_handles.push(thread::spawn(move || producer(&local_queue, id)));
+    }
+    for id in 0..NUM_CONSUMERS {
+        let local_queue = queue.clone();
+        thread_handles.push(thread::spawn(move || consumer(&local_queue, id)));
+    }
+
+    for handle in thread_handles {
+        handle.join().unwrap();
+    }
+
+    // Anything the consumers didn't get to is dropped along with the queue.
+    println!("dropping the queue");
+    drop(queue);
+
+    // Old sentinels may be waiting in the global garbage; their canaries
+    // were already moved out, so this only frees node memory.
+    force_reclaim::<Epoch>();
+}
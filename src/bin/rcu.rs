@@ -0,0 +1,67 @@
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::{BirdCage, Canary};
+use rand::Rng;
+use std::sync::Arc;
+use std::thread;
+
+// Increase these to see more conflicts between updaters.
+const ITERATIONS: u64 = 10_000;
+const NUM_THREADS: usize = 4;
+const SLOTS: usize = 4;
+
+// One version of a slot's value.  Every update makes a new one; the old one
+// is left for readers that might still be looking at it.
+struct Counter {
+    count: u64,
+    _canary: Canary,
+}
+
+impl Counter {
+    fn new(count: u64) -> Counter {
+        Counter {
+            count,
+            _canary: Canary::silent(&format!("version {}", count)),
+        }
+    }
+}
+
+fn worker(cage: &BirdCage<Counter>) -> usize {
+    let mut rng = rand::thread_rng();
+    let mut retries = 0;
+
+    for _ in 0..ITERATIONS {
+        // Read, copy with one more, and update, retrying if another thread
+        // got its update in first.
+        let n = rng.gen_range(0, SLOTS);
+        retries += cage.update(n, |old| Counter::new(old.count + 1)).unwrap();
+    }
+    retries
+}
+
+fn main() {
+    let cage = Arc::new(BirdCage::from_fn(SLOTS, |_| Counter::new(0)));
+    let created_before = Canary::created();
+
+    let handles: Vec<_> = (0..NUM_THREADS)
+        .map(|_| {
+            let cage = cage.clone();
+            thread::spawn(move || worker(&cage))
+        })
+        .collect();
+    let retries: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+
+    let counts: Vec<u64> = cage.iter(&cage.pin()).map(|c| c.count).collect();
+    let total: u64 = counts.iter().sum();
+    println!("counts: {:?}", counts);
+    println!("total:  {} (expected {})", total, ITERATIONS * NUM_THREADS as u64);
+    println!("conflicts retried: {}", retries);
+    assert_eq!(total, ITERATIONS * NUM_THREADS as u64, "an update was lost");
+
+    let versions = Canary::created() - created_before;
+    force_reclaim::<Epoch>();
+    println!(
+        "old versions still waiting: {} of {}",
+        Canary::alive() - SLOTS,
+        versions
+    );
+}
@@ -0,0 +1,137 @@
+//! Slots that hold a whole array of canaries, with a different length every
+//! time, replaced and destroyed as a unit.
+//!
+//! Newer crossbeam-epoch versions can point straight at a slice, as
+//! `Atomic<[T]>`, through their `Pointable` trait.  The crossbeam 0.7 we
+//! build against doesn't have that yet, and `Atomic<T>` needs `T: Sized`,
+//! so here each slot points at a `Box<[Canary]>` instead.  That costs an
+//! extra allocation and an extra hop per read, but the epoch side works the
+//! same: swap the whole array out, and defer destroying it, so readers that
+//! are still walking the old array never see it go away.
+
+use crossbeam::epoch::{self, Atomic, Owned};
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::Canary;
+use rand::Rng;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const MAX_LEN: usize = 16;
+const NUM_READERS: usize = 2;
+const RUN_TIME: Duration = Duration::from_millis(200);
+const SLOTS: usize = 4;
+
+struct SliceCage {
+    c: Vec<Atomic<Box<[Canary]>>>,
+}
+
+// Every canary in one array shares a generation, so a reader can tell if it
+// ever sees parts of two different arrays.
+fn flock(generation: usize, len: usize) -> Box<[Canary]> {
+    (0..len)
+        .map(|ii| Canary::silent(&format!("generation {} bird {}", generation, ii)))
+        .collect()
+}
+
+fn generation(c: &Canary) -> &str {
+    c.name().split(" bird ").next().unwrap()
+}
+
+impl SliceCage {
+    fn new(size: usize) -> SliceCage {
+        SliceCage {
+            c: (0..size).map(|_| Atomic::new(flock(0, 1))).collect(),
+        }
+    }
+
+    // Swap in a new array; the old one, and every canary in it, goes away
+    // together once no reader can still be looking at it.
+    fn replace(&self, n: usize, new: Box<[Canary]>) {
+        let guard = &epoch::pin();
+        let old = self.c[n].swap(Owned::new(new), Ordering::SeqCst, guard);
+        unsafe {
+            guard.defer_destroy(old);
+        }
+    }
+
+    // Walk the array in slot `n`, returning its length and whether it all
+    // came from one generation.
+    fn inspect(&self, n: usize) -> (usize, bool) {
+        let guard = &epoch::pin();
+        let birds: &[Canary] = unsafe{self.c[n].load(Ordering::SeqCst, guard).deref()};
+        let first = generation(&birds[0]);
+        let whole = birds.iter().all(|c| generation(c) == first);
+        (birds.len(), whole)
+    }
+}
+
+impl Drop for SliceCage {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            for slot in &self.c {
+                drop(slot.load(Ordering::Relaxed, guard).into_owned());
+            }
+        }
+    }
+}
+
+fn writer(cage: &SliceCage, stop: &AtomicBool) -> usize {
+    let mut rng = rand::thread_rng();
+    let mut generation = 0;
+    while !stop.load(Ordering::Relaxed) {
+        generation += 1;
+        let len = rng.gen_range(1, MAX_LEN + 1);
+        cage.replace(rng.gen_range(0, SLOTS), flock(generation, len));
+    }
+    generation
+}
+
+fn reader(cage: &SliceCage, stop: &AtomicBool) -> (usize, usize) {
+    let mut rng = rand::thread_rng();
+    let (mut reads, mut birds) = (0, 0);
+    while !stop.load(Ordering::Relaxed) {
+        let (len, whole) = cage.inspect(rng.gen_range(0, SLOTS));
+        assert!(whole, "saw two arrays at once");
+        reads += 1;
+        birds += len;
+    }
+    (reads, birds)
+}
+
+fn main() {
+    let cage = Arc::new(SliceCage::new(SLOTS));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..NUM_READERS)
+        .map(|_| {
+            let (cage, stop) = (cage.clone(), stop.clone());
+            thread::spawn(move || reader(&cage, &stop))
+        })
+        .collect();
+    let writer = {
+        let (cage, stop) = (cage.clone(), stop.clone());
+        thread::spawn(move || writer(&cage, &stop))
+    };
+
+    thread::sleep(RUN_TIME);
+    stop.store(true, Ordering::Relaxed);
+    let replaced = writer.join().unwrap();
+    let (mut reads, mut birds) = (0, 0);
+    for h in readers {
+        let (r, b) = h.join().unwrap();
+        reads += r;
+        birds += b;
+    }
+    println!("replaced {} arrays", replaced);
+    println!("read {} arrays, {} canaries in all, none torn", reads, birds);
+
+    let lengths: Vec<usize> = (0..SLOTS).map(|n| cage.inspect(n).0).collect();
+    println!("lengths now: {:?}", lengths);
+
+    drop(cage);
+    force_reclaim::<Epoch>();
+    println!("canaries alive: {}", Canary::alive());
+}
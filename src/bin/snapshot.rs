@@ -0,0 +1,59 @@
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::BirdCage;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+// Increase these to see more torn snapshots.
+const SNAPSHOTS: usize = 100_000;
+const NUM_READERS: usize = 2;
+const SLOTS: usize = 8;
+
+// The writer stamps every slot with the same generation, one slot at a
+// time.  A snapshot is consistent if it saw a single generation everywhere.
+fn writer(cage: &BirdCage<u64>, stop: &AtomicBool) -> u64 {
+    let mut generation = 0;
+    while !stop.load(Ordering::Relaxed) {
+        generation += 1;
+        for n in 0..SLOTS {
+            cage.update(n, |_| generation).unwrap();
+        }
+    }
+    generation
+}
+
+// Returns how many of this reader's snapshots were torn.
+fn reader(cage: &BirdCage<u64>) -> usize {
+    let mut torn = 0;
+    for _ in 0..SNAPSHOTS {
+        let snapshot: Vec<u64> = cage.snapshot().into_iter().flatten().collect();
+        if snapshot.iter().any(|&g| g != snapshot[0]) {
+            torn += 1;
+        }
+    }
+    torn
+}
+
+fn main() {
+    let cage = Arc::new(BirdCage::from_fn(SLOTS, |_| 0));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let writer = {
+        let (cage, stop) = (cage.clone(), stop.clone());
+        thread::spawn(move || writer(&cage, &stop))
+    };
+    let readers: Vec<_> = (0..NUM_READERS)
+        .map(|_| {
+            let cage = cage.clone();
+            thread::spawn(move || reader(&cage))
+        })
+        .collect();
+
+    let torn: usize = readers.into_iter().map(|h| h.join().unwrap()).sum();
+    stop.store(true, Ordering::Relaxed);
+    let generations = writer.join().unwrap();
+
+    println!("writer generations: {}", generations);
+    println!("torn snapshots:     {} of {}", torn, SNAPSHOTS * NUM_READERS);
+    force_reclaim::<Epoch>();
+}
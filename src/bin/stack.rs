@@ -0,0 +1,52 @@
+use epoch_playground::treiber_stack::TreiberStack;
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::Canary;
+use std::sync::Arc;
+use std::thread;
+
+// Increase these to see more contention between pushers and poppers.
+const ITERATIONS: usize = 20;
+const NUM_THREADS: usize = 4;
+
+fn worker(stack: &TreiberStack<Canary>, id: usize) {
+    let my_name = format!("thread {}", id);
+
+    for n in 0..ITERATIONS {
+        let c = Canary::new(&format!("{} Canary {}", my_name, n));
+        println!("[{}] push {}", my_name, c.name());
+        stack.push(c);
+
+        // Every other time around, pop something.  It might be one of ours,
+        // or it might belong to another thread.
+        if n % 2 == 1 {
+            match stack.pop() {
+                Some(c) => println!("[{}] popped {}", my_name, c.name()),
+                None => println!("[{}] stack was empty", my_name),
+            }
+        }
+    }
+    println!("{} exiting", my_name);
+}
+
+fn main() {
+    let stack = Arc::new(TreiberStack::new());
+    let mut thread_handles = Vec::new();
+
+    for thread_id in 0..NUM_THREADS {
+        let local_stack = stack.clone();
+        let handle = thread::spawn(move || worker(local_stack.as_ref(), thread_id));
+        thread_handles.push(handle);
+    }
+
+    for handle in thread_handles {
+        handle.join().unwrap();
+    }
+
+    // The canaries that are still on the stack are dropped along with it.
+    println!("dropping the stack");
+    drop(stack);
+
+    // Some popped nodes may be waiting in the global garbage; the canaries
+    // were already moved out of them, so this only frees node memory.
+    force_reclaim::<Epoch>();
+}
@@ -0,0 +1,263 @@
+//! A stress test meant to be left running for hours.
+//!
+//! Worker threads do a random mix of reads, replaces, takes and updates on
+//! one epoch `BirdCage`, with canary validation turned on, so reading a
+//! dropped canary panics on the spot.  Every so often the main thread
+//! stops the world, reclaims everything, and checks the books: every
+//! canary still alive must be sitting in a slot, and every one of those
+//! must be valid.  Stats are printed as it goes.
+//!
+//! Unlike `epoch_playground stress`, which measures a short run, this one
+//! is for finding bugs: if anything goes wrong it prints the seed and how
+//! far it got, and exits with an error.  The seed replays each thread's
+//! operations, though not how the threads interleave.
+//!
+//! With `--metrics-addr` it also serves Prometheus metrics over HTTP, and
+//! with `--metrics-file` it rewrites them into a file after every check,
+//! for node_exporter's textfile collector.
+//!
+//! usage: stress [--duration T] [--seed N] [--threads N] [--size N]
+//!               [--check-every T] [--report-every T]
+//!               [--metrics-addr ADDR] [--metrics-file FILE]
+
+use epoch_playground::cli::parse_duration;
+use epoch_playground::metrics::Metrics;
+use epoch_playground::reclaim::{self, force_reclaim, Epoch};
+use epoch_playground::workload::{self, ThreadRng};
+use epoch_playground::{BirdCage, Cage, Canary};
+use rand::Rng;
+use std::env;
+use std::panic;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How many operations a worker does between chances for the checker to
+// stop the world.
+const BATCH: usize = 1000;
+
+// Each worker times one of every this many operations, for the guard-hold
+// metric.
+const TIME_EVERY: usize = 16;
+
+struct Config {
+    // `None` means run until killed.
+    duration: Option<Duration>,
+    seed: u64,
+    threads: usize,
+    size: usize,
+    check_every: Duration,
+    report_every: Duration,
+    metrics_addr: Option<String>,
+    metrics_file: Option<PathBuf>,
+}
+
+fn value<T: FromStr>(flag: &str, args: &mut impl Iterator<Item = String>) -> T {
+    let arg = args.next().unwrap_or_else(|| usage(&format!("{} needs a value", flag)));
+    arg.parse()
+        .unwrap_or_else(|_| usage(&format!("bad value for {}: {:?}", flag, arg)))
+}
+
+fn duration(flag: &str, args: &mut impl Iterator<Item = String>) -> Duration {
+    parse_duration(&value::<String>(flag, args)).unwrap_or_else(|e| usage(&e))
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("{}", error);
+    eprintln!(
+        "usage: stress [--duration T] [--seed N] [--threads N] [--size N] \
+         [--check-every T] [--report-every T] [--metrics-addr ADDR] [--metrics-file FILE]"
+    );
+    process::exit(2);
+}
+
+fn parse_args() -> Config {
+    let mut config = Config {
+        duration: None,
+        seed: workload::random_seed(),
+        threads: 4,
+        size: 16,
+        check_every: Duration::from_secs(1),
+        report_every: Duration::from_secs(10),
+        metrics_addr: None,
+        metrics_file: None,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--duration" => config.duration = Some(duration(&arg, &mut args)),
+            "--seed" => config.seed = value(&arg, &mut args),
+            "--threads" => config.threads = value(&arg, &mut args),
+            "--size" => config.size = value(&arg, &mut args),
+            "--check-every" => config.check_every = duration(&arg, &mut args),
+            "--report-every" => config.report_every = duration(&arg, &mut args),
+            "--metrics-addr" => config.metrics_addr = Some(value(&arg, &mut args)),
+            "--metrics-file" => config.metrics_file = Some(value(&arg, &mut args)),
+            _ => usage(&format!("unknown argument: {:?}", arg)),
+        }
+    }
+    if config.size == 0 || config.threads == 0 {
+        usage("--size and --threads must be at least 1");
+    }
+    config
+}
+
+struct Shared {
+    cage: BirdCage<Canary>,
+    // Workers hold this for reading while they run a batch; the checker
+    // takes it for writing when it wants everyone to stand still.
+    world: RwLock<()>,
+    stop: AtomicBool,
+    metrics: Arc<Metrics>,
+}
+
+fn one_op(cage: &BirdCage<Canary>, rng: &mut ThreadRng, id: usize) {
+    let n = rng.gen_range(0, cage.len());
+    match rng.gen_range(0, 10) {
+        0..=5 => {
+            let _ = cage.with_slot(n, |c| c.validate());
+        }
+        6 | 7 => cage.put(n, Canary::silent(&format!("worker {} put", id))),
+        8 => {
+            // A slot that was taken stays empty, and fails the update.
+            let _ = cage.update(n, |old| {
+                old.validate();
+                Canary::silent(&format!("worker {} update", id))
+            });
+        }
+        // Leave the slot empty until a later put fills it again.
+        _ => drop(cage.take(n)),
+    }
+}
+
+fn worker(shared: &Shared, mut rng: ThreadRng, id: usize) {
+    while !shared.stop.load(Ordering::Relaxed) {
+        let _running = shared.world.read().unwrap();
+        for ii in 0..BATCH {
+            // Every operation pins for exactly as long as it runs.
+            if ii % TIME_EVERY == 0 {
+                let start = Instant::now();
+                one_op(&shared.cage, &mut rng, id);
+                shared.metrics.record_guard_hold(start.elapsed());
+            } else {
+                one_op(&shared.cage, &mut rng, id);
+            }
+        }
+        // Hand our garbage over, so a check can reclaim all of it.
+        shared.cage.flush();
+        shared.metrics.add_ops(BATCH as u64);
+    }
+}
+
+// With the workers stopped, everything that was replaced or taken should
+// be reclaimable, leaving exactly the canaries in the cage.
+fn check(shared: &Shared) {
+    let _stopped = shared.world.write().unwrap();
+    assert!(force_reclaim::<Epoch>(), "reclamation stalled with every worker stopped");
+    assert_eq!(reclaim::pending(), 0, "retired values were never destroyed");
+
+    let guard = &shared.cage.pin();
+    let mut occupied = 0;
+    for c in shared.cage.iter(guard) {
+        c.validate();
+        assert!(c.generation() < Canary::created() as u64, "impossible generation");
+        occupied += 1;
+    }
+    assert_eq!(
+        Canary::alive(),
+        occupied,
+        "drop accounting: {} canaries alive, but {} in the cage",
+        Canary::alive(),
+        occupied
+    );
+}
+
+fn main() {
+    let config = parse_args();
+    Canary::set_validation(true);
+    let start = Instant::now();
+    let shared = Arc::new(Shared {
+        cage: BirdCage::from_fn(config.size, |ii| Canary::silent(&format!("Canary {}", ii))),
+        world: RwLock::new(()),
+        stop: AtomicBool::new(false),
+        metrics: Arc::new(Metrics::new()),
+    });
+    if let Some(addr) = &config.metrics_addr {
+        match shared.metrics.serve(addr) {
+            Ok(local) => println!("serving metrics on http://{}/metrics", local),
+            Err(e) => usage(&format!("can't serve metrics on {}: {}", addr, e)),
+        }
+    }
+
+    // Whichever thread finds a problem, report what's needed to chase it.
+    let default_hook = panic::take_hook();
+    let (seed, ops) = (config.seed, shared.clone());
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        eprintln!(
+            "FAILED after {:?} and about {} ops; rerun with --seed {}",
+            start.elapsed(),
+            ops.metrics.ops(),
+            seed
+        );
+        process::exit(1);
+    }));
+
+    println!(
+        "seed {}, {} threads, {} slots, checking every {:?}",
+        config.seed, config.threads, config.size, config.check_every
+    );
+    let workers: Vec<_> = (0..config.threads)
+        .map(|id| {
+            let shared = shared.clone();
+            let rng = workload::thread_rng(config.seed, id as u64);
+            thread::spawn(move || worker(&shared, rng, id))
+        })
+        .collect();
+
+    let mut checks = 0;
+    let mut last_report = (Instant::now(), 0);
+    while config.duration.is_none_or(|d| start.elapsed() < d) {
+        thread::sleep(config.check_every);
+        check(&shared);
+        checks += 1;
+        shared.metrics.probe::<Epoch>();
+        if let Some(path) = &config.metrics_file {
+            if let Err(e) = shared.metrics.write_textfile(path) {
+                eprintln!("can't write metrics to {}: {}", path.display(), e);
+            }
+        }
+
+        if last_report.0.elapsed() >= config.report_every {
+            let ops = shared.metrics.ops();
+            let rate = (ops - last_report.1) as f64 / last_report.0.elapsed().as_secs_f64();
+            println!(
+                "{:>8.0}s: {} ops ({:.0}/s), {} checks, {} canaries made, {} alive, {} pending",
+                start.elapsed().as_secs_f64(),
+                ops,
+                rate,
+                checks,
+                Canary::created(),
+                Canary::alive(),
+                reclaim::pending()
+            );
+            last_report = (Instant::now(), ops);
+        }
+    }
+
+    shared.stop.store(true, Ordering::Relaxed);
+    for h in workers {
+        h.join().unwrap();
+    }
+    check(&shared);
+    println!(
+        "passed: {} ops and {} checks in {:?}",
+        shared.metrics.ops(),
+        checks + 1,
+        start.elapsed()
+    );
+}
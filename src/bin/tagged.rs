@@ -0,0 +1,141 @@
+use crossbeam::epoch::{self, Atomic, Owned, Shared};
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::Canary;
+use rand::Rng;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// How long the writers run before, between, and after the seals.
+const PAUSE: Duration = Duration::from_millis(10);
+const NUM_WRITERS: usize = 3;
+const SLOTS: usize = 8;
+
+// A Canary is at least 8-byte aligned, so the low bits of a pointer to one
+// are always zero, and crossbeam lets us keep a small tag in them.  We use
+// the lowest bit to mean "sealed: no more replacing".
+const SEALED: usize = 1;
+
+struct SealableCage {
+    c: Vec<Atomic<Canary>>,
+}
+
+impl SealableCage {
+    fn new(size: usize) -> SealableCage {
+        SealableCage {
+            c: (0..size)
+                .map(|ii| Atomic::new(Canary::silent(&format!("Canary {}", ii))))
+                .collect(),
+        }
+    }
+
+    // Replace slot `n`, unless it's sealed.  Returns whether it worked.
+    fn replace(&self, n: usize, new_c: Canary) -> bool {
+        let guard = &epoch::pin();
+        let mut new = Owned::new(new_c);
+        loop {
+            let current = self.c[n].load(Ordering::SeqCst, guard);
+            if current.tag() == SEALED {
+                return false;
+            }
+            // The CAS compares the whole word, tag included, so if someone
+            // seals the slot after our load, it fails and we look again.
+            match self.c[n].compare_and_set(current, new, Ordering::SeqCst, guard) {
+                Ok(_) => {
+                    unsafe {
+                        guard.defer_destroy(current);
+                    }
+                    return true;
+                }
+                Err(e) => new = e.new,
+            }
+        }
+    }
+
+    // Set the tag on slot `n` without changing the pointer, and return the
+    // name of whoever is sealed in.
+    fn seal(&self, n: usize) -> String {
+        let guard = &epoch::pin();
+        let before = self.c[n].fetch_or(SEALED, Ordering::SeqCst, guard);
+        unsafe{before.deref()}.name().to_owned()
+    }
+
+    // Look at slot `n`, returning its canary's name and whether it's sealed.
+    fn peek(&self, n: usize) -> (String, bool) {
+        let guard = &epoch::pin();
+        let shared = self.c[n].load(Ordering::SeqCst, guard);
+        // The tag has to be masked off before the pointer can be used, which
+        // `deref` does for us.
+        let name = unsafe{shared.deref()}.name().to_owned();
+        (name, shared.tag() == SEALED)
+    }
+}
+
+impl Drop for SealableCage {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            for slot in &self.c {
+                let shared: Shared<Canary> = slot.load(Ordering::Relaxed, guard);
+                // `into_owned` ignores the tag, so sealed slots are fine too.
+                drop(shared.into_owned());
+            }
+        }
+    }
+}
+
+fn writer(cage: &SealableCage, id: usize, stop: &AtomicBool) -> (usize, usize) {
+    let mut rng = rand::thread_rng();
+    let (mut done, mut refused) = (0, 0);
+    while !stop.load(Ordering::Relaxed) {
+        let c = Canary::silent(&format!("writer {} Cuckoo {}", id, done + refused));
+        if cage.replace(rng.gen_range(0, SLOTS), c) {
+            done += 1;
+        } else {
+            refused += 1;
+        }
+    }
+    (done, refused)
+}
+
+fn main() {
+    let cage = Arc::new(SealableCage::new(SLOTS));
+    let stop = Arc::new(AtomicBool::new(false));
+    let writers: Vec<_> = (0..NUM_WRITERS)
+        .map(|id| {
+            let (cage, stop) = (cage.clone(), stop.clone());
+            thread::spawn(move || writer(&cage, id, &stop))
+        })
+        .collect();
+
+    // Seal the even slots while the writers are going.
+    let sealed: Vec<(usize, String)> = (0..SLOTS)
+        .step_by(2)
+        .map(|n| {
+            thread::sleep(PAUSE);
+            (n, cage.seal(n))
+        })
+        .collect();
+    thread::sleep(PAUSE);
+    stop.store(true, Ordering::Relaxed);
+
+    let (mut done, mut refused) = (0, 0);
+    for h in writers {
+        let (d, r) = h.join().unwrap();
+        done += d;
+        refused += r;
+    }
+    println!("replaced {} times, refused {} times", done, refused);
+
+    // Nothing got past a seal.
+    for (n, name) in &sealed {
+        let (now, is_sealed) = cage.peek(*n);
+        println!("slot {}: sealed with {}, now {}", n, name, now);
+        assert!(is_sealed && &now == name, "a sealed slot changed");
+    }
+
+    drop(cage);
+    force_reclaim::<Epoch>();
+    println!("canaries alive: {}", Canary::alive());
+}
@@ -0,0 +1,119 @@
+//! Async tasks sharing a birdcage, and what goes wrong when one of them
+//! holds a guard across an `.await`.
+//!
+//! The tasks run on a tiny single-threaded executor, which is exactly where
+//! it's easy to get wrong: crossbeam's `Guard` isn't `Send`, so a
+//! work-stealing runtime would refuse to compile the careless task, but
+//! here nothing stops it.  The well-behaved tasks only ever pin inside
+//! `with_pinned`, so their guards can't reach an `.await`.  The careless
+//! one pins with `pin_watched` and then sleeps, and nothing the writer
+//! retires meanwhile can be freed; its guard warns when it's finally
+//! dropped.  Pass a duration to change the warning limit, e.g.
+//! `cargo run --bin tasks -- 20ms`.
+
+use epoch_playground::cli::parse_duration;
+use epoch_playground::reclaim::{self, force_reclaim, Epoch};
+use epoch_playground::{guard_watch, BirdCage, Canary};
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::process;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SLOTS: usize = 4;
+const ROUNDS: usize = 10;
+
+type Task<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+// A future that's ready once `deadline` has passed.
+struct Sleep {
+    deadline: Instant,
+}
+
+fn sleep(d: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + d,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+// Poll every task in turn until they're all done.  Nothing here ever
+// needs waking, because every task is polled every millisecond anyway.
+fn run(mut tasks: Vec<Task<'_>>) {
+    let mut cx = Context::from_waker(Waker::noop());
+    while !tasks.is_empty() {
+        tasks.retain_mut(|t| t.as_mut().poll(&mut cx).is_pending());
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+async fn reader(cage: &BirdCage<Canary>, id: usize) {
+    let ctx = format!("reader {}", id);
+    for ii in 0..ROUNDS {
+        if let Err(e) = cage.with_pinned(|guard| cage.access_with((id + ii) % SLOTS, &ctx, guard)) {
+            println!("[{}] {}", ctx, e);
+        }
+        sleep(Duration::from_millis(5)).await;
+    }
+}
+
+async fn writer(cage: &BirdCage<Canary>) {
+    for ii in 0..ROUNDS {
+        let n = ii % SLOTS;
+        cage.replace(n, "writer", Canary::new(&format!("Cuckoo {}", ii))).unwrap();
+        sleep(Duration::from_millis(8)).await;
+    }
+}
+
+async fn careless(cage: &BirdCage<Canary>) {
+    let guard = cage.pin_watched();
+    if let Err(e) = cage.access_with(0, "careless", &guard) {
+        println!("[careless] {}", e);
+    }
+    // Everything the writer retires from here on is stuck until we wake.
+    sleep(Duration::from_millis(50)).await;
+    println!("[careless] waking up with {} values pending", reclaim::pending());
+    drop(guard);
+}
+
+fn main() {
+    if let Some(arg) = env::args().nth(1) {
+        let limit = parse_duration(&arg).unwrap_or_else(|e| {
+            eprintln!("{}\nusage: tasks [GUARD_LIMIT]", e);
+            process::exit(2);
+        });
+        guard_watch::set_limit(limit);
+    } else {
+        guard_watch::set_limit(Duration::from_millis(20));
+    }
+
+    let cage = BirdCage::new(SLOTS).with_flush(true);
+    let mut tasks: Vec<Task<'_>> = vec![Box::pin(writer(&cage)), Box::pin(careless(&cage))];
+    for id in 0..2 {
+        tasks.push(Box::pin(reader(&cage, id)));
+    }
+    run(tasks);
+
+    println!("dropping the cage");
+    drop(cage);
+    force_reclaim::<Epoch>();
+    println!(
+        "{} canaries created, {} still alive, {} guard warnings",
+        Canary::created(),
+        Canary::alive(),
+        guard_watch::warnings()
+    );
+}
@@ -0,0 +1,1010 @@
+use crate::cage::{Cage, CageError, Contention};
+use crate::flush_policy::FlushPolicy;
+use crate::guard_watch::Watched;
+use crate::json::{self, Object, Raw, Value};
+use crate::memory_order::MemoryOrder;
+use crate::reclaim::{self, Epoch, Reclaimer};
+use crate::slots::Slots;
+use crate::yield_points::{self, Point};
+use crate::Canary;
+use crossbeam::utils::Backoff;
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// A fixed-size collection of slots, with removed values reclaimed by `R`.
+///
+/// All of the `unsafe` needed to deal with shared pointers stays inside
+/// this type; callers only see `&self` methods.
+///
+/// Values that are removed from the cage are destroyed later, possibly on
+/// another thread, which is why `T` must be `Send + 'static`.
+///
+/// By default the cage uses `crossbeam::epoch`, through the `Epoch`
+/// reclaimer, but any other `Reclaimer` can be swapped in.
+///
+/// Every method that takes a slot number reports one that's out of range
+/// as a `CageError`, and lookups report an empty slot the same way.  Only
+/// the `Cage` trait's `put`, which has no way to say so, panics instead.
+pub struct BirdCage<T, R: Reclaimer = Epoch> {
+    c: Slots<T>,
+    flush: FlushPolicy,
+    order: MemoryOrder,
+    // We own the boxed values in the slots.
+    _marker: PhantomData<(Box<T>, R)>,
+}
+
+impl BirdCage<Canary> {
+    /// Create a cage full of canaries named "Canary 0", "Canary 1", ...
+    pub fn new(size: usize) -> BirdCage<Canary> {
+        BirdCage::from_fn(size, |ii| Canary::new(&format!("Canary {}", ii)))
+    }
+}
+
+impl<R: Reclaimer> BirdCage<Canary, R> {
+    /// Write down every slot's canary as JSON, all under one pin, so the
+    /// cage can be rebuilt later with `restore_from_json`.
+    ///
+    /// Only names and silence are kept.  Restored canaries are new ones,
+    /// with generations of their own.
+    pub fn snapshot_to_json(&self) -> String {
+        let guard = &R::pin();
+        let slots: Vec<String> = (0..self.len())
+            .map(|n| match self.get(n, guard) {
+                Ok(c) => Object::new()
+                    .field("name", c.name())
+                    .field("silent", &c.is_silent())
+                    .finish(),
+                Err(_) => "null".to_owned(),
+            })
+            .collect();
+        Object::new()
+            .field("reclaimer", R::NAME)
+            .field("slots", &Raw(format!("[{}]", slots.join(","))))
+            .finish()
+    }
+
+    /// Build a new cage from the output of `snapshot_to_json`.
+    ///
+    /// The snapshot can come from a cage with any reclaimer.
+    pub fn restore_from_json(json: &str) -> Result<BirdCage<Canary, R>, String> {
+        let snapshot = json::parse(json)?;
+        let slots = match snapshot.get("slots") {
+            Some(Value::Array(slots)) => slots,
+            _ => return Err("a snapshot needs a \"slots\" array".to_owned()),
+        };
+        // Check everything before making any canaries.
+        let canaries = slots
+            .iter()
+            .enumerate()
+            .map(|(n, slot)| match slot {
+                Value::Null => Ok(None),
+                Value::Object(_) => {
+                    let name = match slot.get("name") {
+                        Some(Value::String(name)) => name,
+                        _ => return Err(format!("slot {} needs a \"name\" string", n)),
+                    };
+                    match slot.get("silent") {
+                        None | Some(Value::Bool(false)) => Ok(Some((name, false))),
+                        Some(Value::Bool(true)) => Ok(Some((name, true))),
+                        Some(_) => Err(format!("slot {}: \"silent\" should be true or false", n)),
+                    }
+                }
+                _ => Err(format!("slot {} should be an object or null", n)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut cage = BirdCage::empty(canaries.len());
+        for (n, canary) in canaries.into_iter().enumerate() {
+            if let Some((name, silent)) = canary {
+                let c = if silent { Canary::silent(name) } else { Canary::new(name) };
+                cage.replace_mut(n, c).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(cage)
+    }
+}
+
+impl<T: Send + 'static, R: Reclaimer> BirdCage<T, R> {
+    /// Create a cage with `size` slots, filling slot `n` with `f(n)`.
+    pub fn from_fn<F>(size: usize, mut f: F) -> BirdCage<T, R>
+    where
+        F: FnMut(usize) -> T,
+    {
+        BirdCage {
+            c: Slots::new((0..size).map(|ii| Box::into_raw(Box::new(f(ii)))), false),
+            flush: FlushPolicy::Never,
+            order: MemoryOrder::SeqCst,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Create a cage with `size` empty slots.
+    pub fn empty(size: usize) -> BirdCage<T, R> {
+        BirdCage {
+            c: Slots::new((0..size).map(|_| ptr::null_mut()), false),
+            flush: FlushPolicy::Never,
+            order: MemoryOrder::SeqCst,
+            _marker: PhantomData,
+        }
+    }
+
+    /// If `flush` is set, every replace will ask the reclaimer to get rid of
+    /// its garbage right away, so the deferred destruction runs much sooner.
+    ///
+    /// This is shorthand for `FlushPolicy::EveryOp` or `FlushPolicy::Never`.
+    pub fn with_flush(self, flush: bool) -> BirdCage<T, R> {
+        self.with_flush_policy(if flush {
+            FlushPolicy::EveryOp
+        } else {
+            FlushPolicy::Never
+        })
+    }
+
+    /// Decide how often replaces ask the reclaimer to flush.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> BirdCage<T, R> {
+        self.flush = policy;
+        self
+    }
+
+    /// Choose the atomic orderings for every operation on the slots.
+    pub fn with_memory_order(mut self, order: MemoryOrder) -> BirdCage<T, R> {
+        self.order = order;
+        self
+    }
+
+    /// If `padded` is set, give each slot a cache line of its own, so that
+    /// threads using neighbouring slots don't slow each other down through
+    /// false sharing.  This costs a cache line per slot instead of a word.
+    pub fn with_padded_slots(mut self, padded: bool) -> BirdCage<T, R> {
+        if padded != self.c.is_padded() {
+            // The old layout holds nothing but copies of the pointers, and
+            // we own the only `BirdCage` that refers to it.
+            self.c = self.c.relayout(padded);
+        }
+        self
+    }
+
+    /// Whether each slot has its own cache line.
+    pub fn is_padded(&self) -> bool {
+        self.c.is_padded()
+    }
+
+    /// The number of slots in the cage.
+    pub fn len(&self) -> usize {
+        self.c.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.c.is_empty()
+    }
+
+    /// Start a protected section, for use with `get` and `iter`.
+    pub fn pin(&self) -> R::Guard {
+        R::pin()
+    }
+
+    /// Like `pin`, but the guard warns when it's dropped if it was held
+    /// longer than `guard_watch::limit()`.
+    #[track_caller]
+    pub fn pin_watched(&self) -> Watched<R::Guard> {
+        Watched::new(R::pin())
+    }
+
+    /// Pin, and hand the guard to `f`, returning whatever `f` returns.
+    ///
+    /// `f` isn't async, so the guard can't be held across an `.await`.
+    #[track_caller]
+    pub fn with_pinned<F, R2>(&self, f: F) -> R2
+    where
+        F: FnOnce(&R::Guard) -> R2,
+    {
+        f(&self.pin_watched())
+    }
+
+    /// Print the value in slot `n`.
+    pub fn access(&self, n: usize, ctx: &str) -> Result<(), CageError>
+    where
+        T: Display,
+    {
+        self.with_slot(n, |c| println!("[{}] accessing {}", ctx, c))
+    }
+
+    /// Like `access`, but under a guard the caller already has, so a run of
+    /// accesses can share one pin.
+    pub fn access_with(&self, n: usize, ctx: &str, guard: &R::Guard) -> Result<(), CageError>
+    where
+        T: Display,
+    {
+        let c = self.get(n, guard)?;
+        println!("[{}] accessing {}", ctx, c);
+        Ok(())
+    }
+
+    /// Print every slot, with the address of what's in it, all under one
+    /// pin, followed by how much garbage is waiting.
+    ///
+    /// Unlike a run of `access`es, everything printed was in the cage at
+    /// the same moment (as far as this thread could tell).
+    pub fn dump(&self)
+    where
+        T: Display,
+    {
+        let guard = &R::pin();
+        for n in 0..self.len() {
+            let p = R::protect_ordered(&self.c[n], guard, self.order.load());
+            match unsafe{p.as_ref()} {
+                Some(c) => println!("    slot {}: {} at {:p}", n, c, p),
+                None => println!("    slot {}: empty", n),
+            }
+        }
+        println!("    pending: {}", reclaim::pending());
+    }
+
+    /// Get a reference to the value in slot `n`.
+    ///
+    /// The reference is valid for as long as `guard` is alive.
+    pub fn get<'g>(&self, n: usize, guard: &'g R::Guard) -> Result<&'g T, CageError> {
+        let p = R::protect_ordered(self.slot(n)?, guard, self.order.load());
+        yield_points::hit(Point::AfterLoad);
+        unsafe{p.as_ref()}.ok_or(CageError::SlotEmpty { index: n })
+    }
+
+    /// Clone the value in slot `n`.
+    ///
+    /// The clone is made under a pin of its own and is ours to keep, so it
+    /// can outlive the pin (or be sent to another thread) where a
+    /// reference from `get` couldn't.
+    pub fn get_cloned(&self, n: usize) -> Result<T, CageError>
+    where
+        T: Clone,
+    {
+        self.with_slot(n, T::clone)
+    }
+
+    /// Put `value` into slot `n`, but only if the slot is empty.
+    ///
+    /// If there's no such slot, or it's already occupied, `value` is handed
+    /// back with the reason.
+    pub fn insert(&self, n: usize, value: T) -> Result<(), (CageError, T)> {
+        let slot = match self.slot(n) {
+            Ok(slot) => slot,
+            Err(e) => return Err((e, value)),
+        };
+        let new = Box::into_raw(Box::new(value));
+        yield_points::hit(Point::BeforeCas);
+        match slot.compare_exchange(ptr::null_mut(), new, self.order.swap(), self.order.failure()) {
+            Ok(_) => Ok(()),
+            // Nobody else ever saw our pointer, so we can take it back.
+            Err(_) => Err((CageError::SlotOccupied { index: n }, *unsafe{Box::from_raw(new)})),
+        }
+    }
+
+    /// Empty slot `n`, scheduling whatever was there for destruction.
+    ///
+    /// Fails if there's no such slot, or it was already empty.
+    pub fn remove(&self, n: usize) -> Result<(), CageError> {
+        let slot = self.slot(n)?;
+        let guard = &R::pin();
+        let stolen_c = slot.swap(ptr::null_mut(), self.order.swap());
+        if stolen_c.is_null() {
+            return Err(CageError::SlotEmpty { index: n });
+        }
+        self.destroy_replaced(stolen_c, guard);
+        Ok(())
+    }
+
+    pub fn replace(&self, n: usize, ctx: &str, new_c: T) -> Result<(), CageError>
+    where
+        T: Display,
+    {
+        self.replace_with(n, ctx, new_c, &R::pin())
+    }
+
+    /// Like `replace`, but under a guard the caller already has.
+    pub fn replace_with(
+        &self,
+        n: usize,
+        ctx: &str,
+        new_c: T,
+        guard: &R::Guard,
+    ) -> Result<(), CageError>
+    where
+        T: Display,
+    {
+        self.slot(n)?;
+        println!("[{}] put {} into slot {}", ctx, new_c, n);
+        self.swap_and_destroy_with(
+            n,
+            new_c,
+            |c| println!("[{}] removed {} ({} pending)", ctx, c, reclaim::pending()),
+            guard,
+        )
+    }
+
+    /// Put each `(slot, value)` pair into the cage, all under one pin, and
+    /// return how many old values were scheduled for destruction.
+    ///
+    /// Pinning isn't free, so a writer with a batch of updates can save a
+    /// little by paying for it once.  The flip side is that nothing retired
+    /// during the batch can be freed until it's over.
+    ///
+    /// Stops at the first pair whose slot is out of range; the pairs before
+    /// it have already gone in.
+    pub fn replace_many<I>(&self, values: I) -> Result<usize, CageError>
+    where
+        I: IntoIterator<Item = (usize, T)>,
+    {
+        let guard = &R::pin();
+        let mut replaced = 0;
+        for (n, new_c) in values {
+            self.swap_and_destroy_with(n, new_c, |_| replaced += 1, guard)?;
+        }
+        Ok(replaced)
+    }
+
+    /// Put the `i`th of `values` into slot `i`, all under one pin, and
+    /// retire everything that was replaced as a single batch.  Returns how
+    /// many old values were retired.
+    ///
+    /// Unlike `replace_many`, the reclaimer gets to treat the old values as
+    /// one piece of garbage (if it can), instead of one per slot.
+    ///
+    /// # Panics
+    ///
+    /// If there are more values than slots.
+    pub fn replace_all<I>(&self, values: I) -> usize
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let guard = &R::pin();
+        let mut stolen = Vec::with_capacity(self.len());
+        for (n, new_c) in values.into_iter().enumerate() {
+            assert!(n < self.len(), "more values than slots");
+            let old = self.c[n].swap(Box::into_raw(Box::new(new_c)), self.order.swap());
+            if !old.is_null() {
+                stolen.push(old);
+            }
+        }
+        let retired = stolen.len();
+        if retired > 0 {
+            // We unlinked all of these, so we're the only ones retiring them.
+            unsafe {
+                R::retire_batch_with(guard, stolen, reclaim::counted_each(retired, drop));
+            }
+            yield_points::hit(Point::AfterDefer);
+            if self.flush.should_flush() {
+                R::flush(guard);
+            }
+        }
+        retired
+    }
+
+    /// Empty every slot, retiring everything that was in the cage as a
+    /// single batch, and return how many values were retired.
+    ///
+    /// Readers that already have a value keep it until their guard is
+    /// gone; readers that come later find the slots empty.  Other threads
+    /// can fill slots again while this goes, so the cage isn't necessarily
+    /// empty afterwards.
+    pub fn clear(&self) -> usize {
+        let guard = &R::pin();
+        let stolen = self.steal_all();
+        let retired = stolen.len();
+        if retired > 0 {
+            // We unlinked all of these, so we're the only ones retiring them.
+            unsafe {
+                R::retire_batch_with(guard, stolen, reclaim::counted_each(retired, drop));
+            }
+            yield_points::hit(Point::AfterDefer);
+            if self.flush.should_flush() {
+                R::flush(guard);
+            }
+        }
+        retired
+    }
+
+    /// Empty every slot, like `clear`, but hand the values over instead of
+    /// dropping them.
+    ///
+    /// Like `take`, the values only come out of the returned `Drain` once
+    /// the reclaimer decides nobody else can be looking at them.
+    pub fn drain(&self) -> Drain<T, R> {
+        let guard = &R::pin();
+        let stolen = self.steal_all();
+        let remaining = stolen.len();
+        let (tx, rx) = mpsc::channel();
+        if remaining > 0 {
+            // Nobody can find these through the cage any more.  The sender
+            // goes away along with the deferred function, once every value
+            // has been delivered.
+            unsafe {
+                let deliver = move |owned| {
+                    // If the Drain was dropped, the value is dropped here instead.
+                    let _ = tx.send(owned);
+                };
+                R::retire_batch_with(guard, stolen, reclaim::counted_each(remaining, deliver));
+            }
+            yield_points::hit(Point::AfterDefer);
+        }
+        Drain {
+            rx,
+            remaining,
+            _marker: PhantomData,
+        }
+    }
+
+    // Swap every slot to null, and return whatever was in them.
+    fn steal_all(&self) -> Vec<*mut T> {
+        (0..self.len())
+            .map(|n| self.c[n].swap(ptr::null_mut(), self.order.swap()))
+            .filter(|p| !p.is_null())
+            .collect()
+    }
+
+    /// Exchange the values in slots `i` and `j`.
+    ///
+    /// Two separate atomics can't be exchanged in one step, so this isn't
+    /// atomic, and other threads can see it half done.  It takes `i`'s
+    /// value out, swaps it into `j`, and then puts `j`'s old value into
+    /// `i`.  In between, slot `i` is empty, and `j`'s old value isn't in the
+    /// cage at all; a reader can find either slot's value missing, or (by
+    /// reading `i` before and `j` after) the same value twice.  What it
+    /// never does is leave one value in two slots, which would have two
+    /// owners retiring it.
+    ///
+    /// A write to `i` that lands in the gap is overwritten, and retired like
+    /// any other replaced value.  A write to `j` just replaces `i`'s old
+    /// value, which is retired by the writer.  Either way every value still
+    /// has exactly one owner.
+    pub fn swap_slots(&self, i: usize, j: usize) -> Result<(), CageError> {
+        let (slot_i, slot_j) = (self.slot(i)?, self.slot(j)?);
+        if i == j {
+            return Ok(());
+        }
+        let guard = &R::pin();
+        let a = slot_i.swap(ptr::null_mut(), self.order.swap());
+        let b = slot_j.swap(a, self.order.swap());
+        let displaced = slot_i.swap(b, self.order.swap());
+        self.destroy_replaced(displaced, guard);
+        Ok(())
+    }
+
+    /// Iterate over every occupied slot, all under one `guard`.
+    ///
+    /// The references stay valid for as long as the guard is alive, even if
+    /// other threads replace the values in the meantime.  Empty slots are
+    /// skipped.
+    pub fn iter<'g>(&'g self, guard: &'g R::Guard) -> Iter<'g, T, R> {
+        Iter {
+            order: self.order.load(),
+            slots: &self.c,
+            next: 0,
+            guard,
+        }
+    }
+
+    /// Clone every slot's value, all under one guard.  Empty slots come
+    /// back as `None`.
+    ///
+    /// One guard keeps every value we load alive, but it doesn't stop other
+    /// threads from replacing slots while we go.  The snapshot can mix slot
+    /// 0 from before some write with slot 1 from after it.  Epochs make
+    /// reading safe, not consistent.
+    pub fn snapshot(&self) -> Vec<Option<T>>
+    where
+        T: Clone,
+    {
+        let guard = &R::pin();
+        (0..self.len()).map(|n| self.get(n, guard).ok().cloned()).collect()
+    }
+
+    /// Remove the value from slot `n`, leaving the slot empty.
+    ///
+    /// Other threads may still be reading the old value, so we can't hand
+    /// it over right away.  Instead, it is delivered through the returned
+    /// `Taken` once the reclaimer decides that nobody else can be looking
+    /// at it.
+    ///
+    /// Fails only if there's no such slot.  Taking from an empty slot gives
+    /// a `Taken` that never produces anything.
+    pub fn take(&self, n: usize) -> Result<Taken<T, R>, CageError> {
+        let slot = self.slot(n)?;
+        let guard = &R::pin();
+        let stolen_c = slot.swap(ptr::null_mut(), self.order.swap());
+        let (tx, rx) = mpsc::channel();
+        if !stolen_c.is_null() {
+            // Nobody can find this value through the cage any more, and the
+            // deferred function won't run until all current readers are done.
+            unsafe {
+                let deliver = move |owned| {
+                    // If the Taken was dropped, the value is dropped here instead.
+                    let _ = tx.send(owned);
+                };
+                R::retire_with(guard, stolen_c, reclaim::counted(deliver));
+            }
+            yield_points::hit(Point::AfterDefer);
+        }
+        Ok(Taken {
+            rx,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Put `new_c` into slot `n` and hand back the old value right away,
+    /// without going through the reclaimer.
+    ///
+    /// Having `&mut self` means no other thread can be looking at the old
+    /// value, so single-threaded setup and teardown can use this and leave
+    /// no garbage behind.
+    pub fn replace_mut(&mut self, n: usize, new_c: T) -> Result<Option<T>, CageError> {
+        let slot = self.slot(n)?;
+        let old = slot.swap(Box::into_raw(Box::new(new_c)), Ordering::Relaxed);
+        if old.is_null() {
+            Ok(None)
+        } else {
+            Ok(Some(*unsafe{Box::from_raw(old)}))
+        }
+    }
+
+    /// Get the identity of whatever is currently in slot `n`, for use with
+    /// `replace_if`.
+    pub fn current_id(&self, n: usize) -> Result<SlotId, CageError> {
+        // We never dereference this, so there's nothing to protect.
+        Ok(SlotId(self.slot(n)?.load(self.order.peek()) as usize))
+    }
+
+    /// Put `new_c` into slot `n`, but only if the slot still holds the value
+    /// identified by `expected`.
+    ///
+    /// This is a single `compare_exchange`, which never fails spuriously, so
+    /// `retries` will always be zero.
+    pub fn replace_if(
+        &self,
+        n: usize,
+        expected: SlotId,
+        new_c: T,
+    ) -> Result<CasOutcome<T>, CageError> {
+        let slot = self.slot(n)?;
+        let guard = &R::pin();
+        let current = expected.0 as *mut T;
+        let new = Box::into_raw(Box::new(new_c));
+        yield_points::hit(Point::BeforeCas);
+        match slot.compare_exchange(current, new, self.order.swap(), self.order.failure()) {
+            Ok(_) => {
+                self.destroy_replaced(current, guard);
+                Ok(CasOutcome { retries: 0, rejected: None })
+            }
+            Err(_) => Ok(CasOutcome {
+                retries: 0,
+                rejected: Some(*unsafe{Box::from_raw(new)}),
+            }),
+        }
+    }
+
+    /// Like `replace_if`, but built on `compare_exchange_weak`.
+    ///
+    /// A weak CAS is allowed to fail even when the slot matches, so it's
+    /// retried until it either succeeds or sees a different value.  Each of
+    /// those spurious failures is counted in `retries`.
+    pub fn replace_if_weak(
+        &self,
+        n: usize,
+        expected: SlotId,
+        new_c: T,
+    ) -> Result<CasOutcome<T>, CageError> {
+        let slot = self.slot(n)?;
+        let guard = &R::pin();
+        let current = expected.0 as *mut T;
+        let new = Box::into_raw(Box::new(new_c));
+        let mut retries = 0;
+        loop {
+            yield_points::hit(Point::BeforeCas);
+            match slot.compare_exchange_weak(current, new, self.order.swap(), self.order.failure()) {
+                Ok(_) => {
+                    self.destroy_replaced(current, guard);
+                    return Ok(CasOutcome { retries, rejected: None });
+                }
+                Err(actual) if actual == current => retries += 1,
+                Err(_) => {
+                    return Ok(CasOutcome {
+                        retries,
+                        rejected: Some(*unsafe{Box::from_raw(new)}),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Read-copy-update slot `n`: build a new value from the current one
+    /// with `f`, and swap it in if nobody else replaced the slot meanwhile.
+    /// If somebody did, `f` is called again on their value.  The old value
+    /// is retired like any other replaced value.
+    ///
+    /// Returns how many times it had to retry.  Fails if there's no such
+    /// slot, or it was (or became) empty, in which case nothing is put in.
+    pub fn update<F>(&self, n: usize, mut f: F) -> Result<usize, CageError>
+    where
+        F: FnMut(&T) -> T,
+    {
+        let slot = self.slot(n)?;
+        let guard = &R::pin();
+        let mut retries = 0;
+        loop {
+            // While we hold this protected, it can't be freed and its
+            // address reused, so the CAS can't be fooled by ABA.
+            let current = R::protect_ordered(slot, guard, self.order.load());
+            yield_points::hit(Point::AfterLoad);
+            let old = unsafe{current.as_ref()}.ok_or(CageError::SlotEmpty { index: n })?;
+            let new = Box::into_raw(Box::new(f(old)));
+            yield_points::hit(Point::BeforeCas);
+            match slot.compare_exchange(current, new, self.order.swap(), self.order.failure()) {
+                Ok(_) => {
+                    self.destroy_replaced(current, guard);
+                    return Ok(retries);
+                }
+                // Nobody else ever saw our value, so we can drop it now.
+                Err(_) => {
+                    drop(unsafe{Box::from_raw(new)});
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    /// Put `new_c` into slot `n` with a `compare_exchange_weak` loop rather
+    /// than a swap, show the old value to `removed`, and retire it.
+    ///
+    /// When another thread gets its write in first, the loop backs off with
+    /// a `Backoff` before trying again; spurious failures are retried right
+    /// away.  Both are counted in the returned `Contention`.
+    pub fn replace_backoff<F>(
+        &self,
+        n: usize,
+        new_c: T,
+        removed: F,
+    ) -> Result<Contention, CageError>
+    where
+        F: FnOnce(&T),
+    {
+        let slot = self.slot(n)?;
+        let guard = &R::pin();
+        let backoff = Backoff::new();
+        let mut contention = Contention::default();
+        let new = Box::into_raw(Box::new(new_c));
+        let mut current = R::protect_ordered(slot, guard, self.order.load());
+        yield_points::hit(Point::AfterLoad);
+        loop {
+            yield_points::hit(Point::BeforeCas);
+            match slot.compare_exchange_weak(current, new, self.order.swap(), self.order.failure()) {
+                Ok(_) => break,
+                Err(actual) if actual == current => contention.spurious += 1,
+                Err(_) => {
+                    contention.retries += 1;
+                    backoff.spin();
+                    current = R::protect_ordered(slot, guard, self.order.load());
+                    yield_points::hit(Point::AfterLoad);
+                }
+            }
+        }
+        // `current` is still protected, and now it's ours to retire.
+        if let Some(c) = unsafe{current.as_ref()} {
+            removed(c);
+        }
+        self.destroy_replaced(current, guard);
+        Ok(contention)
+    }
+
+    // Schedule destruction of a value that we just unlinked from a slot.
+    // We unlinked it, so we're the only ones who will retire it.
+    fn destroy_replaced(&self, old: *mut T, guard: &R::Guard) {
+        if !old.is_null() {
+            unsafe {
+                R::retire_with(guard, old, reclaim::counted(drop));
+            }
+            yield_points::hit(Point::AfterDefer);
+            if self.flush.should_flush() {
+                R::flush(guard);
+            }
+        }
+    }
+
+    /// Pin, load slot `n`, and hand the value to `f`, returning whatever
+    /// `f` returns.
+    ///
+    /// The reference can't escape the closure, so it can't outlive the
+    /// guard.
+    pub fn with_slot<F, R2>(&self, n: usize, f: F) -> Result<R2, CageError>
+    where
+        F: FnOnce(&T) -> R2,
+    {
+        let guard = &R::pin();
+        self.get(n, guard).map(f)
+    }
+
+    // Slot `n`, if there is one.
+    fn slot(&self, n: usize) -> Result<&AtomicPtr<T>, CageError> {
+        if n < self.len() {
+            Ok(&self.c[n])
+        } else {
+            Err(CageError::IndexOutOfBounds {
+                index: n,
+                len: self.len(),
+            })
+        }
+    }
+
+    /// Put `new_c` into slot `n`, show the old value to `removed`, and then
+    /// schedule the old value for destruction.
+    pub(crate) fn swap_and_destroy<F>(
+        &self,
+        n: usize,
+        new_c: T,
+        removed: F,
+    ) -> Result<(), CageError>
+    where
+        F: FnOnce(&T),
+    {
+        self.swap_and_destroy_with(n, new_c, removed, &R::pin())
+    }
+
+    fn swap_and_destroy_with<F>(
+        &self,
+        n: usize,
+        new_c: T,
+        removed: F,
+        guard: &R::Guard,
+    ) -> Result<(), CageError>
+    where
+        F: FnOnce(&T),
+    {
+        // We are stealing whatever value happens to be present in this
+        // location, and substituting a new one.
+        let stolen_c = self.slot(n)?.swap(Box::into_raw(Box::new(new_c)), self.order.swap());
+
+        // Until we retire it, nobody else will destroy the stolen value.
+        let c: &T = match unsafe{stolen_c.as_ref()} {
+            Some(c) => c,
+            // The slot was empty, so there's nothing to clean up.
+            None => return Ok(()),
+        };
+        // If this panics, the stolen value leaks.  Retiring it first would
+        // be worse: nothing protects it from a hazard pointer scan, so it
+        // could be freed while `removed` is still looking at it.
+        removed(c);
+
+        // Now schedule the stolen value for deallocation.
+        self.destroy_replaced(stolen_c, guard);
+        Ok(())
+    }
+}
+
+impl<T: Send + 'static + fmt::Debug, R: Reclaimer> fmt::Debug for BirdCage<T, R> {
+    /// Every slot, with the address of what's in it, all under one pin.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let guard = &R::pin();
+        let slots = (0..self.len()).map(|n| {
+            let p = R::protect_ordered(&self.c[n], guard, self.order.load());
+            (n, DebugSlot(p, unsafe{p.as_ref()}))
+        });
+        f.debug_struct("BirdCage")
+            .field("reclaimer", &R::NAME)
+            .field("order", &self.order)
+            .field("flush", &self.flush)
+            .field("padded", &self.is_padded())
+            .field("slots", &DebugMap(slots.collect()))
+            .finish()
+    }
+}
+
+// What `Debug` shows for one slot: its value and where it lives, or that
+// it's empty.
+struct DebugSlot<'g, T>(*mut T, Option<&'g T>);
+
+impl<T: fmt::Debug> fmt::Debug for DebugSlot<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            Some(value) if f.alternate() => write!(f, "{:#?} at {:p}", value, self.0),
+            Some(value) => write!(f, "{:?} at {:p}", value, self.0),
+            None => f.write_str("empty"),
+        }
+    }
+}
+
+struct DebugMap<'g, T>(Vec<(usize, DebugSlot<'g, T>)>);
+
+impl<T: fmt::Debug> fmt::Debug for DebugMap<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.0.iter().map(|(n, s)| (n, s))).finish()
+    }
+}
+
+impl<T: Send + Sync + 'static, R: Reclaimer> Cage<T> for BirdCage<T, R> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn with_slot<F, R2>(&self, n: usize, f: F) -> Option<R2>
+    where
+        F: FnOnce(&T) -> R2,
+    {
+        self.with_slot(n, f).ok()
+    }
+
+    // The trait can't report a bad slot, so these panic on one, like
+    // indexing a slice would.
+    fn put_with<F>(&self, n: usize, value: T, removed: F)
+    where
+        F: FnOnce(&T),
+    {
+        if let Err(e) = self.swap_and_destroy(n, value, removed) {
+            panic!("{}", e);
+        }
+    }
+
+    fn put_cas_with<F>(&self, n: usize, value: T, removed: F) -> Contention
+    where
+        F: FnOnce(&T),
+    {
+        self.replace_backoff(n, value, removed).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn quiescent(&self) {
+        R::quiescent();
+    }
+
+    fn flush(&self) {
+        R::quiescent();
+        R::flush(&R::pin());
+    }
+
+    fn force_reclaim(&self) -> bool {
+        reclaim::force_reclaim::<R>()
+    }
+}
+
+impl<T, R: Reclaimer> Drop for BirdCage<T, R> {
+    fn drop(&mut self) {
+        // We have `&mut self`, so nobody else can be looking at the slots.
+        // The values that are still in the cage can be destroyed right away.
+        // Values that were replaced earlier are up to the reclaimer.
+        for n in 0..self.c.len() {
+            let p = self.c[n].load(Ordering::Relaxed);
+            if !p.is_null() {
+                drop(unsafe{Box::from_raw(p)});
+            }
+        }
+    }
+}
+
+/// An iterator over the values in a `BirdCage`, created by `BirdCage::iter`.
+pub struct Iter<'g, T, R: Reclaimer = Epoch> {
+    slots: &'g Slots<T>,
+    next: usize,
+    guard: &'g R::Guard,
+    order: Ordering,
+}
+
+impl<'g, T, R: Reclaimer> Iterator for Iter<'g, T, R> {
+    type Item = &'g T;
+
+    fn next(&mut self) -> Option<&'g T> {
+        while self.next < self.slots.len() {
+            let slot = &self.slots[self.next];
+            self.next += 1;
+            // Anything we protect can't be destroyed until the guard is gone.
+            let p = R::protect_ordered(slot, self.guard, self.order);
+            if let Some(c) = unsafe{p.as_ref()} {
+                return Some(c);
+            }
+        }
+        None
+    }
+}
+
+/// A value removed from a `BirdCage` by `take`, which becomes available
+/// once it's safe to own.
+///
+/// If no value was present in the slot, the `Taken` will never produce one.
+pub struct Taken<T, R: Reclaimer = Epoch> {
+    rx: Receiver<Box<T>>,
+    _marker: PhantomData<R>,
+}
+
+impl<T, R: Reclaimer> Taken<T, R> {
+    /// Get the value, if the deferred handoff has already happened.
+    pub fn try_get(&self) -> Option<Box<T>> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Keep flushing the reclaimer's garbage until the value is handed over.
+    ///
+    /// Returns `None` if the slot was empty.  This will spin forever if some
+    /// thread (including this one!) stays pinned, because then the value
+    /// can never become safe.
+    pub fn wait(self) -> Option<Box<T>> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(value) => return Some(value),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            R::flush(&R::pin());
+            // QSBR frees nothing until this thread says it's quiescent.
+            R::quiescent();
+            thread::yield_now();
+        }
+    }
+}
+
+/// The values removed from a `BirdCage` by `drain`, which come out once
+/// they're safe to own.
+///
+/// Under epochs they come out in slot order; other reclaimers hand them
+/// over in whatever order they free them.
+pub struct Drain<T, R: Reclaimer = Epoch> {
+    rx: Receiver<Box<T>>,
+    remaining: usize,
+    _marker: PhantomData<R>,
+}
+
+impl<T, R: Reclaimer> Iterator for Drain<T, R> {
+    type Item = Box<T>;
+
+    /// Keep flushing the reclaimer's garbage until the next value is handed
+    /// over.
+    ///
+    /// Like `Taken::wait`, this will spin forever if some thread (including
+    /// this one!) stays pinned.
+    fn next(&mut self) -> Option<Box<T>> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(value) => {
+                    self.remaining -= 1;
+                    return Some(value);
+                }
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            R::flush(&R::pin());
+            R::quiescent();
+            thread::yield_now();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, R: Reclaimer> ExactSizeIterator for Drain<T, R> {}
+
+/// The identity (address) of a value that was in a slot at some point.
+///
+/// Two ids compare equal if they refer to the same allocation.  If a value
+/// is freed and its memory reused, a new value can end up with an old id;
+/// that's the ABA problem, and `replace_if` can't tell the difference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SlotId(usize);
+
+impl SlotId {
+    /// The id of an empty slot.
+    pub const EMPTY: SlotId = SlotId(0);
+}
+
+/// The result of `replace_if` or `replace_if_weak`.
+#[derive(Debug)]
+pub struct CasOutcome<T> {
+    /// How many times the CAS failed spuriously and had to be retried.
+    pub retries: usize,
+    /// If the slot didn't match, the new value is handed back here.
+    pub rejected: Option<T>,
+}
+
+impl<T> CasOutcome<T> {
+    pub fn succeeded(&self) -> bool {
+        self.rejected.is_none()
+    }
+}
@@ -0,0 +1,165 @@
+//! A lock-free hash map, built on the same idea as the `BirdCage`: a fixed
+//! array of `Atomic` slots, one per bucket.
+//!
+//! Each bucket holds an immutable list of entries.  Writers never modify a
+//! bucket in place; they build a new copy with the change applied and
+//! `compare_and_set` it into the slot, retrying if another writer got there
+//! first.  The old copy (including any entry that was removed or
+//! overwritten) is handed to the epoch collector, since readers may still
+//! be looking at it.
+
+use crossbeam::epoch::{self, pin, Atomic, Guard, Owned, Shared};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::Ordering;
+
+struct Bucket<K, V> {
+    entries: Vec<(K, V)>,
+}
+
+/// A fixed-size, lock-free hash map with copy-on-write buckets.
+pub struct BucketMap<K, V> {
+    buckets: Vec<Atomic<Bucket<K, V>>>,
+    hasher: RandomState,
+}
+
+impl<K, V> BucketMap<K, V>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    pub fn new(num_buckets: usize) -> BucketMap<K, V> {
+        assert!(num_buckets > 0, "BucketMap needs at least one bucket");
+        BucketMap {
+            buckets: (0..num_buckets).map(|_| Atomic::null()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn bucket(&self, key: &K) -> &Atomic<Bucket<K, V>> {
+        let h = self.hasher.hash_one(key);
+        &self.buckets[h as usize % self.buckets.len()]
+    }
+
+    // Keep trying to swap in `update(old entries)` until no other writer
+    // interferes.  `update` returns the new entries (or `None` to leave the
+    // bucket alone) along with a result for the caller.
+    fn modify<F, R>(&self, key: &K, guard: &Guard, mut update: F) -> R
+    where
+        F: FnMut(&[(K, V)]) -> (Option<Vec<(K, V)>>, R),
+    {
+        let slot = self.bucket(key);
+        loop {
+            let current = slot.load(Ordering::SeqCst, guard);
+            let entries = match unsafe{current.as_ref()} {
+                Some(b) => &b.entries[..],
+                None => &[],
+            };
+            let (new_entries, result) = update(entries);
+            let new_entries = match new_entries {
+                Some(e) => e,
+                None => return result,
+            };
+
+            // An empty bucket goes back to being a null pointer.
+            let new: Shared<'_, Bucket<K, V>> = if new_entries.is_empty() {
+                Shared::null()
+            } else {
+                Owned::new(Bucket { entries: new_entries }).into_shared(guard)
+            };
+
+            match slot.compare_and_set(current, new, Ordering::SeqCst, guard) {
+                Ok(_) => {
+                    if !current.is_null() {
+                        unsafe {
+                            guard.defer_destroy(current);
+                        }
+                    }
+                    return result;
+                }
+                Err(_) => {
+                    // Nobody else ever saw our copy, so free it now and retry.
+                    if !new.is_null() {
+                        drop(unsafe{new.into_owned()});
+                    }
+                }
+            }
+        }
+    }
+
+    /// Insert a key/value pair, returning the previous value for this key.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let guard = &pin();
+        self.modify(&key, guard, |entries| {
+            let mut new_entries = entries.to_vec();
+            let old = match new_entries.iter_mut().find(|(k, _)| *k == key) {
+                Some(entry) => Some(std::mem::replace(&mut entry.1, value.clone())),
+                None => {
+                    new_entries.push((key.clone(), value.clone()));
+                    None
+                }
+            };
+            (Some(new_entries), old)
+        })
+    }
+
+    /// Remove a key, returning its value if it was present.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let guard = &pin();
+        self.modify(key, guard, |entries| {
+            match entries.iter().position(|(k, _)| k == key) {
+                Some(pos) => {
+                    let mut new_entries = entries.to_vec();
+                    let (_, v) = new_entries.remove(pos);
+                    (Some(new_entries), Some(v))
+                }
+                None => (None, None),
+            }
+        })
+    }
+
+    /// Get a copy of the value for `key`.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let guard = &pin();
+        let current = self.bucket(key).load(Ordering::SeqCst, guard);
+        let bucket = unsafe{current.as_ref()}?;
+        bucket
+            .entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// The number of entries, counted under one guard.  Other threads may
+    /// change the map while we're counting.
+    pub fn len(&self) -> usize {
+        let guard = &pin();
+        self.buckets
+            .iter()
+            .filter_map(|slot| unsafe{slot.load(Ordering::SeqCst, guard).as_ref()})
+            .map(|b| b.entries.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V> Drop for BucketMap<K, V> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            for slot in &self.buckets {
+                let shared = slot.load(Ordering::Relaxed, guard);
+                if !shared.is_null() {
+                    drop(shared.into_owned());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! The interface shared by all the cage variants, so the same workload can
+//! be pointed at any of them.
+
+use core::fmt;
+
+/// A fixed-size collection of slots that can be read and replaced
+/// concurrently.
+pub trait Cage<T>: Send + Sync {
+    /// The number of slots.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hand the value in slot `n` to `f`, or return `None` if the slot is
+    /// empty.
+    fn with_slot<F, R>(&self, n: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R;
+
+    /// Put `value` into slot `n`, handing the old value (if there was one)
+    /// to `removed` before getting rid of it however this cage does that.
+    ///
+    /// If `removed` panics, `value` stays in the slot and the old value is
+    /// leaked: it's already been unlinked, and nothing will retire it.
+    fn put_with<F>(&self, n: usize, value: T, removed: F)
+    where
+        F: FnOnce(&T);
+
+    /// Put `value` into slot `n`, getting rid of the old value.
+    fn put(&self, n: usize, value: T) {
+        self.put_with(n, value, |_| {});
+    }
+
+    /// Like `put_with`, but with a compare-and-swap loop instead of a
+    /// plain swap, returning how much the CAS had to retry.  Cages that
+    /// have no CAS to retry just call `put_with`.
+    fn put_cas_with<F>(&self, n: usize, value: T, removed: F) -> Contention
+    where
+        F: FnOnce(&T),
+    {
+        self.put_with(n, value, removed);
+        Contention::default()
+    }
+
+    /// Called by each worker thread every so often, at a point where it
+    /// isn't holding any references into the cage.
+    fn quiescent(&self) {}
+
+    /// Try to get rid of any old values that are still waiting around.
+    fn flush(&self) {}
+
+    /// Keep flushing until nothing more gets destroyed, and say whether
+    /// every old value is gone.  Cages that don't defer anything are always
+    /// done.
+    fn force_reclaim(&self) -> bool {
+        true
+    }
+}
+
+/// How many times a compare-and-swap loop went around again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Contention {
+    /// Failures because another thread changed the slot first.  Each one
+    /// is followed by a `Backoff` step.
+    pub retries: u64,
+    /// Failures of a weak CAS that found the slot unchanged.  These are
+    /// retried straight away.
+    pub spurious: u64,
+}
+
+impl Contention {
+    /// Add `other`'s counts to these.
+    pub fn merge(&mut self, other: &Contention) {
+        self.retries += other.retries;
+        self.spurious += other.spurious;
+    }
+}
+
+/// Why a cage couldn't hand over, or take, the value in a slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CageError {
+    /// The cage has no slot with this index.
+    IndexOutOfBounds { index: usize, len: usize },
+    /// The slot exists, but there's nothing in it.
+    SlotEmpty { index: usize },
+    /// The slot exists, but something is already in it.
+    SlotOccupied { index: usize },
+}
+
+impl fmt::Display for CageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CageError::IndexOutOfBounds { index, len } => {
+                write!(f, "slot {} is out of range for a cage of {}", index, len)
+            }
+            CageError::SlotEmpty { index } => write!(f, "slot {} is empty", index),
+            CageError::SlotOccupied { index } => write!(f, "slot {} is already occupied", index),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CageError {}
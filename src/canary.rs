@@ -0,0 +1,246 @@
+use crate::histogram::{AtomicHistogram, Histogram};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+static RECLAIM_DELAY: AtomicHistogram = AtomicHistogram::new();
+static VALIDATE: AtomicBool = AtomicBool::new(false);
+static PAYLOAD_BYTES: AtomicUsize = AtomicUsize::new(0);
+// What payloads are filled with, so that their pages really are in use.
+const PAYLOAD_FILL: u8 = 0xCA;
+
+// Values for `Canary::state`.  These are unlikely bit patterns, so freed
+// memory that has been reused for something else probably won't pass for
+// a live canary either.
+const ALIVE: u32 = 0xC0FF_EE11;
+const FREED: u32 = 0xDEAD_BEEF;
+
+// Nanoseconds since the first time anybody asked, but never zero, because
+// zero means "not retired".
+fn now_nanos() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    let start = *START.get_or_init(Instant::now);
+    (start.elapsed().as_nanos() as u64).max(1)
+}
+
+/// Told when a canary is finally dropped, for programs that want to know
+/// more than the `dropped` line on stdout says.
+///
+/// Any `Fn(&Canary, Option<Duration>)` will do.
+pub trait ReclaimObserver: Send + Sync {
+    /// `canary` is about to be dropped, `delay` after it was marked
+    /// retired, or `None` if it never was (like a canary still in its cage
+    /// when the cage goes away).
+    ///
+    /// This runs wherever the deferred destructor does, which is often
+    /// some other thread, in the middle of a flush.
+    fn reclaimed(&self, canary: &Canary, delay: Option<Duration>);
+}
+
+impl<F> ReclaimObserver for F
+where
+    F: Fn(&Canary, Option<Duration>) + Send + Sync,
+{
+    fn reclaimed(&self, canary: &Canary, delay: Option<Duration>) {
+        self(canary, delay)
+    }
+}
+
+// A `ReclaimObserver` in a form `Canary` can derive `Debug` with.
+struct Observer(Arc<dyn ReclaimObserver>);
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReclaimObserver")
+    }
+}
+
+/// An object that announces its destruction to stdout.
+///
+/// Every `Canary` also bumps a global counter when it's created and
+/// dropped, so we can tell how many are still waiting to be reclaimed.
+///
+/// Each one gets a unique generation number, and marks itself freed when
+/// it's dropped.  With `set_validation(true)`, reading a canary that has
+/// already been dropped panics instead of quietly returning garbage.  This
+/// is best-effort, since the memory could have been reused by then, but it
+/// turns most use-after-free bugs into an immediate failure that names the
+/// canary involved.
+///
+/// On its own a canary is only a few dozen bytes, which makes piles of
+/// garbage look cheaper than they'd really be.  `set_payload_bytes` gives
+/// each new one a buffer to carry around.
+#[derive(Debug)]
+pub struct Canary {
+    name: String,
+    verbose: bool,
+    generation: u64,
+    state: AtomicU32,
+    // When this canary was taken out of its cage, from `now_nanos`.
+    retired_at: AtomicU64,
+    observer: Option<Observer>,
+    payload: Payload,
+}
+
+// Dead weight, to make a canary as big as a real value would be.
+struct Payload(Box<[u8]>);
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes", self.0.len())
+    }
+}
+
+impl Canary {
+    pub fn new(name: &str) -> Canary {
+        let generation = CREATED.fetch_add(1, Ordering::Relaxed) as u64;
+        Canary {
+            name: name.to_owned(),
+            verbose: true,
+            generation,
+            state: AtomicU32::new(ALIVE),
+            retired_at: AtomicU64::new(0),
+            observer: None,
+            payload: Payload(vec![PAYLOAD_FILL; Canary::payload_bytes()].into_boxed_slice()),
+        }
+    }
+
+    /// Have `observer` told when this canary is dropped.
+    pub fn with_observer(mut self, observer: Arc<dyn ReclaimObserver>) -> Canary {
+        self.observer = Some(Observer(observer));
+        self
+    }
+
+    /// Create a `Canary` that doesn't print anything when it's dropped.
+    ///
+    /// Useful when there are going to be millions of them.
+    pub fn silent(name: &str) -> Canary {
+        let mut c = Canary::new(name);
+        c.verbose = false;
+        c
+    }
+
+    /// Whether this canary was made with `silent`.
+    pub fn is_silent(&self) -> bool {
+        !self.verbose
+    }
+
+    pub fn name(&self) -> &str {
+        self.validate();
+        &self.name
+    }
+
+    /// This canary's generation number: no two canaries get the same one,
+    /// so seeing a different generation in a slot means it was replaced,
+    /// even if the new canary lives at the same address.
+    pub fn generation(&self) -> u64 {
+        self.validate();
+        self.generation
+    }
+
+    /// The size of the buffer this canary carries.
+    pub fn payload_len(&self) -> usize {
+        self.payload.0.len()
+    }
+
+    /// Give every canary made from now on a buffer of `bytes` bytes, so
+    /// that garbage takes up as much memory as real values would.  The
+    /// default is none.
+    pub fn set_payload_bytes(bytes: usize) {
+        PAYLOAD_BYTES.store(bytes, Ordering::Relaxed);
+    }
+
+    /// The size of the buffer new canaries get.
+    pub fn payload_bytes() -> usize {
+        PAYLOAD_BYTES.load(Ordering::Relaxed)
+    }
+
+    /// Turn use-after-free checking on or off for every canary.
+    pub fn set_validation(on: bool) {
+        VALIDATE.store(on, Ordering::Relaxed);
+    }
+
+    /// Panic if this canary has already been dropped (and validation is
+    /// turned on).  `name` and `Display` call this for you.
+    pub fn validate(&self) {
+        if !VALIDATE.load(Ordering::Relaxed) {
+            return;
+        }
+        let state = self.state.load(Ordering::Acquire);
+        if state != ALIVE {
+            // Don't touch the name; it's already been freed.
+            panic!(
+                "use after free: canary at {:p} (generation {}) read in state {:#x}",
+                self, self.generation, state
+            );
+        }
+    }
+
+    /// Note that this canary has just been removed from its data structure,
+    /// so that when it's finally dropped the delay is recorded in
+    /// `reclaim_delays`.
+    pub fn mark_retired(&self) {
+        self.retired_at.store(now_nanos(), Ordering::Relaxed);
+    }
+
+    /// How long retired canaries have waited between `mark_retired` and
+    /// being dropped, in nanoseconds.  Canaries that haven't been dropped
+    /// yet aren't included.
+    pub fn reclaim_delays() -> Histogram {
+        RECLAIM_DELAY.snapshot()
+    }
+
+    /// Forget the delays recorded so far.
+    pub fn reset_reclaim_delays() {
+        RECLAIM_DELAY.reset();
+    }
+
+    /// The number of canaries created so far, by all threads.
+    pub fn created() -> usize {
+        CREATED.load(Ordering::Relaxed)
+    }
+
+    /// The number of canaries dropped so far, by all threads.
+    pub fn dropped() -> usize {
+        DROPPED.load(Ordering::Relaxed)
+    }
+
+    /// The number of canaries that have been created but not dropped.
+    ///
+    /// This is only exact when no other thread is creating or dropping
+    /// canaries at the same time.
+    pub fn alive() -> usize {
+        // Read DROPPED first, so a drop racing with us can't make it look
+        // bigger than CREATED.
+        let dropped = Canary::dropped();
+        Canary::created().saturating_sub(dropped)
+    }
+}
+
+impl fmt::Display for Canary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Drop for Canary {
+    fn drop(&mut self) {
+        let retired_at = *self.retired_at.get_mut();
+        let delay = (retired_at != 0).then(|| now_nanos() - retired_at);
+        // The observer gets to look before we're marked freed.
+        if let Some(Observer(observer)) = self.observer.take() {
+            observer.reclaimed(self, delay.map(Duration::from_nanos));
+        }
+        self.state.store(FREED, Ordering::Release);
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        if let Some(delay) = delay {
+            RECLAIM_DELAY.record(delay);
+        }
+        if self.verbose {
+            println!("{}: dropped", self.name);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Random misbehaviour for stress runs, to find the worst cases a clean
+//! benchmark never hits.
+//!
+//! A well-behaved stress thread pins for a moment, swaps, retires and moves
+//! on, so the epoch always advances promptly and garbage never piles up
+//! far.  Real threads get descheduled while pinned, take a page fault
+//! between unlinking a value and retiring it, or take a different path
+//! that doesn't flush.  With `--chaos`, every stress thread does those
+//! things now and then, for a random length of time:
+//!
+//! - holds a guard (by sitting inside `with_slot`) before an operation,
+//! - sleeps after swapping a value out and before it's retired,
+//! - skips a flush that its cage's `FlushPolicy` says is due.
+//!
+//! Each thread's chaos comes from a random number stream of its own,
+//! seeded from the run's seed, so it doesn't change which operations the
+//! threads do.  When it happens relative to the other threads is still up
+//! to the scheduler, which is rather the point.
+
+use crate::flush_policy;
+use crate::workload::ThreadRng;
+use crate::{Cage, Canary};
+use rand::Rng;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+/// How often, and for how long, threads misbehave.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Chaos {
+    /// The chance, before each operation, of holding a guard first.
+    pub hold_chance: f64,
+    /// The longest a guard is held; each hold is uniform up to this.
+    pub max_hold: Duration,
+    /// The chance, on each replace, of sleeping between the swap and the
+    /// retire.
+    pub delay_chance: f64,
+    /// The longest of those sleeps.
+    pub max_delay: Duration,
+    /// The chance, on each replace, of skipping the flush that follows it,
+    /// if one is due.  Only matters with a `FlushPolicy` other than
+    /// `Never`.
+    pub skip_flush_chance: f64,
+}
+
+impl Default for Chaos {
+    fn default() -> Self {
+        Chaos {
+            hold_chance: 0.0001,
+            max_hold: Duration::from_millis(20),
+            delay_chance: 0.001,
+            max_delay: Duration::from_millis(1),
+            skip_flush_chance: 0.25,
+        }
+    }
+}
+
+/// What the chaos did to a run, added up over every thread.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChaosStats {
+    /// How many times a guard was held.
+    pub holds: u64,
+    /// How long guards were held for, in all.
+    pub held: Duration,
+    /// How many times a replace slept between its swap and its retire.
+    pub delays: u64,
+    /// How long those sleeps were, in all.
+    pub delayed: Duration,
+    /// How many flushes were due but skipped.
+    pub skipped_flushes: u64,
+}
+
+impl ChaosStats {
+    /// Add `other`'s counts to these.
+    pub fn merge(&mut self, other: &ChaosStats) {
+        self.holds += other.holds;
+        self.held += other.held;
+        self.delays += other.delays;
+        self.delayed += other.delayed;
+        self.skipped_flushes += other.skipped_flushes;
+    }
+}
+
+impl fmt::Display for ChaosStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "held a guard {} times ({:?} in all), slept before retiring {} times ({:?}), \
+             skipped {} flushes",
+            self.holds, self.held, self.delays, self.delayed, self.skipped_flushes
+        )
+    }
+}
+
+/// One thread's source of chaos.
+pub struct Gremlin {
+    chaos: Chaos,
+    rng: ThreadRng,
+    stats: ChaosStats,
+}
+
+impl Gremlin {
+    /// A gremlin that draws from `rng`, which should be a stream of its
+    /// own.
+    pub fn new(chaos: Chaos, rng: ThreadRng) -> Gremlin {
+        Gremlin {
+            chaos,
+            rng,
+            stats: ChaosStats::default(),
+        }
+    }
+
+    fn maybe(&mut self, chance: f64, max: Duration) -> Option<Duration> {
+        if max == Duration::ZERO || !self.rng.gen_bool(chance.clamp(0.0, 1.0)) {
+            return None;
+        }
+        Some(Duration::from_nanos(self.rng.gen_range(0, max.as_nanos() as u64)))
+    }
+
+    /// Maybe stay pinned inside one of `cage`'s slots for a while.  Call
+    /// this between operations, when the thread isn't holding anything.
+    pub fn before_op<C: Cage<Canary>>(&mut self, cage: &C) {
+        if cage.is_empty() {
+            return;
+        }
+        if let Some(hold) = self.maybe(self.chaos.hold_chance, self.chaos.max_hold) {
+            let pick = self.rng.gen_range(0, cage.len());
+            cage.with_slot(pick, |_| thread::sleep(hold));
+            self.stats.holds += 1;
+            self.stats.held += hold;
+        }
+    }
+
+    /// Decide what the next replace will suffer: maybe skip the flush after
+    /// it, and maybe return how long to sleep once the old value has been
+    /// swapped out, which the caller does in its `removed` callback.
+    pub fn before_replace(&mut self) -> Option<Duration> {
+        if self.rng.gen_bool(self.chaos.skip_flush_chance.clamp(0.0, 1.0)) {
+            flush_policy::skip_next_flush();
+        }
+        let delay = self.maybe(self.chaos.delay_chance, self.chaos.max_delay);
+        if let Some(delay) = delay {
+            self.stats.delays += 1;
+            self.stats.delayed += delay;
+        }
+        delay
+    }
+
+    /// What this thread's gremlin did.  The thread should be done with it:
+    /// skipped flushes are counted per thread, so this has to be called on
+    /// the thread that did the replaces.
+    pub fn finish(self) -> ChaosStats {
+        ChaosStats {
+            skipped_flushes: flush_policy::skipped_flushes(),
+            ..self.stats
+        }
+    }
+}
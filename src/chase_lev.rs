@@ -0,0 +1,257 @@
+//! A Chase–Lev work-stealing deque.
+//!
+//! The owning `Worker` pushes and pops at the bottom; any number of
+//! `Stealer`s take from the top.  When the buffer fills up, the owner copies
+//! everything into a bigger one and swaps it in.  A stealer may have loaded
+//! the old buffer just before that and still be reading from it, so the old
+//! buffer is destroyed through the epoch collector rather than right away.
+
+use crossbeam::epoch::{self, pin, Atomic, Owned};
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem::{self, MaybeUninit};
+use std::sync::atomic::{self, AtomicIsize, Ordering};
+use std::sync::Arc;
+
+const MIN_CAPACITY: usize = 4;
+
+// A ring buffer of possibly-uninitialized values.  Dropping a buffer never
+// drops the values in it, because they're copied (bitwise) into the next
+// buffer when it grows.
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+unsafe impl<T: Send> Send for Buffer<T> {}
+unsafe impl<T: Send> Sync for Buffer<T> {}
+
+impl<T> Buffer<T> {
+    fn new(cap: usize) -> Buffer<T> {
+        debug_assert!(cap.is_power_of_two());
+        Buffer {
+            slots: (0..cap).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+        }
+    }
+
+    fn cap(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn at(&self, index: isize) -> *mut MaybeUninit<T> {
+        self.slots[index as usize & (self.cap() - 1)].get()
+    }
+
+    unsafe fn write(&self, index: isize, value: T) {
+        (*self.at(index)).as_mut_ptr().write(value)
+    }
+
+    unsafe fn read(&self, index: isize) -> T {
+        (*self.at(index)).as_ptr().read()
+    }
+}
+
+struct Inner<T> {
+    bottom: AtomicIsize,
+    top: AtomicIsize,
+    buffer: Atomic<Buffer<T>>,
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        // The last Worker or Stealer is gone, so nobody else can touch this.
+        unsafe {
+            let guard = epoch::unprotected();
+            let buffer = self.buffer.load(Ordering::Relaxed, guard);
+            let b = buffer.deref();
+            let bottom = self.bottom.load(Ordering::Relaxed);
+            let mut i = self.top.load(Ordering::Relaxed);
+            while i != bottom {
+                drop(b.read(i));
+                i = i.wrapping_add(1);
+            }
+            drop(buffer.into_owned());
+        }
+    }
+}
+
+/// The owner's end of a work-stealing deque.
+///
+/// It can be sent to another thread, but not shared: `push` and `pop`
+/// assume nobody else is moving `bottom` or writing to the buffer.
+///
+/// ```compile_fail
+/// fn is_sync<T: Sync>() {}
+/// is_sync::<epoch_playground::chase_lev::Worker<String>>();
+/// ```
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    // Not `Sync`, whatever `Inner` is.
+    _not_sync: PhantomData<*mut ()>,
+}
+
+// Only one thread at a time uses the owner's end, and the values it hands
+// out can go anywhere a `T` can.
+unsafe impl<T: Send> Send for Worker<T> {}
+
+/// A handle that can steal values from the top of someone else's deque.
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// The result of `Stealer::steal`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+    Empty,
+    Success(T),
+    /// We lost a race with the owner or another stealer.
+    Retry,
+}
+
+impl<T: Send> Default for Worker<T> {
+    fn default() -> Self {
+        Worker::new()
+    }
+}
+
+impl<T: Send> Worker<T> {
+    pub fn new() -> Worker<T> {
+        Worker {
+            inner: Arc::new(Inner {
+                bottom: AtomicIsize::new(0),
+                top: AtomicIsize::new(0),
+                buffer: Atomic::new(Buffer::new(MIN_CAPACITY)),
+            }),
+            _not_sync: PhantomData,
+        }
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let b = self.inner.bottom.load(Ordering::SeqCst);
+        let t = self.inner.top.load(Ordering::SeqCst);
+        b.wrapping_sub(t).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&self, value: T) {
+        let inner = &*self.inner;
+        let guard = &pin();
+        let b = inner.bottom.load(Ordering::Relaxed);
+        let t = inner.top.load(Ordering::Acquire);
+        let mut buffer = inner.buffer.load(Ordering::Relaxed, guard);
+
+        // Only the owner ever replaces the buffer, so this is stable.
+        let cap = unsafe{buffer.deref()}.cap();
+        if b.wrapping_sub(t) >= cap as isize - 1 {
+            let old = unsafe{buffer.deref()};
+            let new = Buffer::new(cap * 2);
+            let mut i = t;
+            while i != b {
+                unsafe {
+                    new.write(i, old.read(i));
+                }
+                i = i.wrapping_add(1);
+            }
+            let new = Owned::new(new).into_shared(guard);
+            inner.buffer.store(new, Ordering::Release);
+
+            // Stealers may still be reading from the old buffer.
+            unsafe {
+                guard.defer_destroy(buffer);
+            }
+            buffer = new;
+        }
+
+        unsafe {
+            buffer.deref().write(b, value);
+        }
+        atomic::fence(Ordering::Release);
+        inner.bottom.store(b.wrapping_add(1), Ordering::Relaxed);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let inner = &*self.inner;
+        let b = inner.bottom.load(Ordering::Relaxed).wrapping_sub(1);
+        inner.bottom.store(b, Ordering::Relaxed);
+        atomic::fence(Ordering::SeqCst);
+        let t = inner.top.load(Ordering::Relaxed);
+
+        if b.wrapping_sub(t) < 0 {
+            // It was already empty.
+            inner.bottom.store(b.wrapping_add(1), Ordering::Relaxed);
+            return None;
+        }
+
+        let guard = &pin();
+        let buffer = inner.buffer.load(Ordering::Relaxed, guard);
+        let value = unsafe{buffer.deref().read(b)};
+
+        if b == t {
+            // This was the last value, so we have to race the stealers for it.
+            let won = inner
+                .top
+                .compare_exchange(t, t.wrapping_add(1), Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            inner.bottom.store(b.wrapping_add(1), Ordering::Relaxed);
+            if !won {
+                // A stealer has it; our copy must not be dropped.
+                mem::forget(value);
+                return None;
+            }
+        }
+        Some(value)
+    }
+}
+
+impl<T: Send> Stealer<T> {
+    pub fn is_empty(&self) -> bool {
+        let t = self.inner.top.load(Ordering::SeqCst);
+        let b = self.inner.bottom.load(Ordering::SeqCst);
+        b.wrapping_sub(t) <= 0
+    }
+
+    /// Try to take a value from the top of the deque.
+    pub fn steal(&self) -> Steal<T> {
+        let inner = &*self.inner;
+        let t = inner.top.load(Ordering::Acquire);
+        atomic::fence(Ordering::SeqCst);
+        let b = inner.bottom.load(Ordering::Acquire);
+
+        if b.wrapping_sub(t) <= 0 {
+            return Steal::Empty;
+        }
+
+        // Pinning keeps the buffer alive even if the owner swaps in a new
+        // one right after we load it.
+        let guard = &pin();
+        let buffer = inner.buffer.load(Ordering::Acquire, guard);
+        let value = unsafe{buffer.deref().read(t)};
+
+        if inner
+            .top
+            .compare_exchange(t, t.wrapping_add(1), Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            // Someone else took it first; our copy must not be dropped.
+            mem::forget(value);
+            return Steal::Retry;
+        }
+        Steal::Success(value)
+    }
+}
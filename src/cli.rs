@@ -0,0 +1,462 @@
+//! Hand-rolled command line parsing, so experiments don't need a recompile.
+
+use crate::chaos::Chaos;
+use crate::numa::{NumaConfig, Placement};
+use crate::stall::StallConfig;
+use crate::stress::{CageKind, ReclaimerKind, StressConfig};
+use crate::tui::TuiConfig;
+use crate::workload::SlotDistribution;
+use crate::{FlushPolicy, MemoryOrder};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+usage: epoch_playground [MODE] [OPTIONS]
+
+modes:
+    demo        threads that each access and replace random slots (default)
+    private     like demo, but the cage has its own Collector
+    step        like demo, but the threads take turns one operation at a
+                time, and the cage is printed after each step
+    stress      separate reader and writer threads, for a fixed duration
+    stall       writers keep going while one reader stays pinned
+    tui         a live dashboard of the stall scenario (also --tui)
+    repl        type commands at a birdcage and watch the drops happen
+    replay      play back a stress run recorded with --trace
+    numa        the cost of reading and dropping canaries from another NUMA
+                node than the one they were allocated on
+    probe       count how many deferrals pile up before crossbeam runs any
+
+options:
+    --size N        number of slots in the birdcage
+    --threads N     number of demo threads
+    --iterations N  number of access/replace pairs per demo thread
+    --readers N     number of stress reader threads
+    --writers N     number of stress writer threads
+    --mixed N       number of stress threads that both access and replace
+    --write-percent P
+                    percentage of a mixed thread's ops that are replaces
+    --slots D       how stress threads pick slots: uniform, zipf or zipf:S
+    --step-delay T  step runs wait T between steps, instead of for Enter
+    --duration T    how long to run, e.g. 30s, 500ms or 2m (a bare number is
+                    seconds); demo and private runs use this instead of
+                    --iterations when it's given
+    --padded        give each birdcage slot its own cache line in stress runs
+    --cas-writes    stress writers use a compare-and-swap loop with backoff
+                    instead of a plain swap
+    --memory-order O
+                    atomic orderings for birdcage slots in stress runs:
+                    seqcst (the default) or acqrel
+    --pin-cores     pin each stress thread to a core, and report ops per core
+    --chaos         stress threads now and then hold a guard, sleep between
+                    swap and retire, or skip a flush, each for a random time
+    --cage C        cage for stress runs: birdcage, arc or rwlock
+    --reclaimer R   reclamation scheme for stress and stall runs: epoch,
+                    hazard or qsbr
+    --stall T       how long the stall reader stays pinned
+    --placement P   where numa runs allocate: bind (each node in turn) or
+                    interleave (spread over every node)
+    --shards N      stall runs use an epoch cage split into N shards, each
+                    with its own collector
+    --quiescent-every N
+                    ops between quiescent states, for qsbr (0 = never)
+    --forgetful     one qsbr reader never announces a quiescent state
+    --seed N        seed for the random number generators, to replay a run
+    --output F      how to print stress results: text or json
+    --csv FILE      append the stress results to FILE as a CSV row
+    --timeline FILE write the stress or scenario run's garbage samples to
+                    FILE as CSV
+    --scenario FILE run the workload described in FILE (see scenarios/);
+                    --duration and --seed override the file's
+    --trace FILE    stress runs record every operation to FILE, and replay
+                    runs play FILE back (with --reclaimer, if given)
+    --watchdog N    during stress runs, force a flush whenever more than N
+                    values are waiting to be reclaimed
+    --background-reclaim T
+                    during stress runs, flush from a separate thread every T
+                    instead of leaving it to the workers
+    --observe T     print reclamation progress to stderr every T (e.g. 100ms)
+    --malloc-stats T
+                    print malloc's allocated, resident and free memory to
+                    stderr every T, to see how much deferred frees fragment
+                    the heap (glibc only)
+    --validate      panic if a canary is read after it has been dropped
+    --payload-bytes N
+                    give every canary an N-byte buffer, so garbage weighs
+                    what real values would
+    --explain       say what the epoch reclaimer does with every pin, defer
+                    and flush, and why memory is or isn't freed yet (try
+                    demo --threads 2 --iterations 5)
+    --check-leaks   exit with an error if any canary is never dropped
+    --flush         flush the thread-local garbage after every replace
+    --flush-policy P
+                    when to flush after a replace: never, every-op,
+                    every:N (replaces) or every:T (e.g. every:5ms)
+    --help          print this message
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Demo,
+    Private,
+    Step,
+    Stress,
+    Stall,
+    Tui,
+    Repl,
+    Replay,
+    Scenario,
+    Numa,
+    Probe,
+    Help,
+}
+
+/// How stress results are printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format: {:?}", s)),
+        }
+    }
+}
+
+/// Everything that can be set from the command line.
+#[derive(Clone, Debug)]
+pub struct Args {
+    pub mode: Mode,
+    pub cage_size: usize,
+    pub threads: usize,
+    pub iterations: usize,
+    pub readers: usize,
+    pub writers: usize,
+    pub mixed: usize,
+    pub write_percent: f64,
+    pub slots: SlotDistribution,
+    /// `None` means demo and private runs go by `iterations`, and stress
+    /// runs use their default duration.
+    pub duration: Option<Duration>,
+    pub flush: FlushPolicy,
+    /// How long step runs wait between steps (`None` means for Enter).
+    pub step_delay: Option<Duration>,
+    pub cage: CageKind,
+    pub reclaimer: ReclaimerKind,
+    pub quiescent_every: u64,
+    pub forgetful: bool,
+    pub seed: Option<u64>,
+    pub output: OutputFormat,
+    /// Where to append stress results as CSV.
+    pub csv: Option<PathBuf>,
+    /// Where to write the stress run's garbage samples.
+    pub timeline: Option<PathBuf>,
+    /// Where stress runs record their operations, and replay runs read
+    /// them back.
+    pub trace: Option<PathBuf>,
+    /// The scenario file to run.
+    pub scenario: Option<PathBuf>,
+    /// Whether leftover canaries at exit are an error.
+    pub check_leaks: bool,
+    /// Whether to check every canary read for use-after-free.
+    pub validate: bool,
+    /// How big a buffer each canary carries.
+    pub payload_bytes: usize,
+    /// Whether the epoch reclaimer explains itself as it goes.
+    pub explain: bool,
+    /// How often the observer thread reports, if it's running.
+    pub observe: Option<Duration>,
+    /// How often malloc's statistics are printed, if at all.
+    pub malloc_stats: Option<Duration>,
+    /// How long the stall reader stays pinned.
+    pub stall: Duration,
+    /// How many shards the stall run's cage has (0 = not sharded).
+    pub shards: usize,
+    pub watchdog: Option<usize>,
+    /// How often the stress run's background reclaimer flushes, if at all.
+    pub background_reclaim: Option<Duration>,
+    pub padded: bool,
+    pub cas_writes: bool,
+    pub pin_cores: bool,
+    pub memory_order: MemoryOrder,
+    /// Whether stress threads misbehave at random.
+    pub chaos: bool,
+    /// Where numa runs allocate their canaries.
+    pub placement: Placement,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        let stress = StressConfig::default();
+        Args {
+            mode: Mode::Demo,
+            cage_size: 10,
+            threads: 10,
+            iterations: 100,
+            readers: stress.readers,
+            writers: stress.writers,
+            mixed: stress.mixed,
+            write_percent: stress.write_percent,
+            slots: stress.slots,
+            duration: None,
+            flush: FlushPolicy::Never,
+            step_delay: None,
+            cage: stress.cage,
+            reclaimer: stress.reclaimer,
+            quiescent_every: stress.quiescent_every,
+            forgetful: stress.forgetful,
+            seed: None,
+            output: OutputFormat::Text,
+            csv: None,
+            timeline: None,
+            trace: None,
+            scenario: None,
+            check_leaks: false,
+            validate: false,
+            payload_bytes: 0,
+            explain: false,
+            observe: None,
+            malloc_stats: None,
+            stall: StallConfig::default().stall,
+            shards: 0,
+            watchdog: None,
+            background_reclaim: None,
+            padded: stress.padded,
+            cas_writes: stress.cas_writes,
+            pin_cores: stress.pin_cores,
+            memory_order: stress.memory_order,
+            chaos: stress.chaos.is_some(),
+            placement: NumaConfig::default().placement,
+        }
+    }
+}
+
+fn value<T, I>(flag: &str, args: &mut I) -> Result<T, String>
+where
+    T: FromStr,
+    I: Iterator<Item = String>,
+{
+    let arg = args
+        .next()
+        .ok_or_else(|| format!("{} needs a value", flag))?;
+    arg.parse()
+        .map_err(|_| format!("bad value for {}: {:?}", flag, arg))
+}
+
+/// Parse a duration like `30s`, `1.5s`, `500ms` or `2m`.  A bare number is
+/// in seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, scale) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = s.strip_suffix('s') {
+        (n, 1.0)
+    } else if let Some(n) = s.strip_suffix('m') {
+        (n, 60.0)
+    } else {
+        (s, 1.0)
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok(Duration::from_secs_f64(n * scale)),
+        _ => Err(format!("bad duration: {:?}", s)),
+    }
+}
+
+impl Args {
+    /// Parse command line arguments (not including the program name).
+    pub fn parse<I>(args: I) -> Result<Args, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+
+        // The mode is optional, and must come first.
+        match args.peek().map(String::as_str) {
+            Some("demo") => {
+                args.next();
+            }
+            Some("private") => {
+                parsed.mode = Mode::Private;
+                args.next();
+            }
+            Some("step") => {
+                parsed.mode = Mode::Step;
+                args.next();
+            }
+            Some("stress") => {
+                parsed.mode = Mode::Stress;
+                args.next();
+            }
+            Some("stall") => {
+                parsed.mode = Mode::Stall;
+                args.next();
+            }
+            Some("tui") => {
+                parsed.mode = Mode::Tui;
+                args.next();
+            }
+            Some("repl") => {
+                parsed.mode = Mode::Repl;
+                args.next();
+            }
+            Some("replay") => {
+                parsed.mode = Mode::Replay;
+                args.next();
+            }
+            Some("numa") => {
+                parsed.mode = Mode::Numa;
+                args.next();
+            }
+            Some("probe") => {
+                parsed.mode = Mode::Probe;
+                args.next();
+            }
+            _ => {}
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--size" => parsed.cage_size = value(&arg, &mut args)?,
+                "--threads" => parsed.threads = value(&arg, &mut args)?,
+                "--iterations" => parsed.iterations = value(&arg, &mut args)?,
+                "--readers" => parsed.readers = value(&arg, &mut args)?,
+                "--writers" => parsed.writers = value(&arg, &mut args)?,
+                "--mixed" => parsed.mixed = value(&arg, &mut args)?,
+                "--write-percent" => parsed.write_percent = value(&arg, &mut args)?,
+                "--slots" => parsed.slots = value(&arg, &mut args)?,
+                "--duration" => {
+                    let arg = value::<String, _>(&arg, &mut args)?;
+                    parsed.duration = Some(parse_duration(&arg)?);
+                }
+                "--step-delay" => {
+                    let arg = value::<String, _>(&arg, &mut args)?;
+                    parsed.step_delay = Some(parse_duration(&arg)?);
+                }
+                "--cage" => parsed.cage = value(&arg, &mut args)?,
+                "--reclaimer" => parsed.reclaimer = value(&arg, &mut args)?,
+                "--quiescent-every" => parsed.quiescent_every = value(&arg, &mut args)?,
+                "--output" => parsed.output = value(&arg, &mut args)?,
+                "--csv" => parsed.csv = Some(value(&arg, &mut args)?),
+                "--timeline" => parsed.timeline = Some(value(&arg, &mut args)?),
+                "--trace" => parsed.trace = Some(value(&arg, &mut args)?),
+                "--scenario" => {
+                    parsed.scenario = Some(value(&arg, &mut args)?);
+                    parsed.mode = Mode::Scenario;
+                }
+                "--seed" => parsed.seed = Some(value(&arg, &mut args)?),
+                "--forgetful" => parsed.forgetful = true,
+                "--padded" => parsed.padded = true,
+                "--cas-writes" => parsed.cas_writes = true,
+                "--pin-cores" => parsed.pin_cores = true,
+                "--chaos" => parsed.chaos = true,
+                "--memory-order" => parsed.memory_order = value(&arg, &mut args)?,
+                "--shards" => parsed.shards = value(&arg, &mut args)?,
+                "--placement" => parsed.placement = value(&arg, &mut args)?,
+                "--check-leaks" => parsed.check_leaks = true,
+                "--validate" => parsed.validate = true,
+                "--payload-bytes" => parsed.payload_bytes = value(&arg, &mut args)?,
+                "--explain" => parsed.explain = true,
+                "--watchdog" => parsed.watchdog = Some(value(&arg, &mut args)?),
+                "--stall" => {
+                    let arg = value::<String, _>(&arg, &mut args)?;
+                    parsed.stall = parse_duration(&arg)?;
+                }
+                "--observe" => {
+                    let arg = value::<String, _>(&arg, &mut args)?;
+                    parsed.observe = Some(parse_duration(&arg)?);
+                }
+                "--malloc-stats" => {
+                    let arg = value::<String, _>(&arg, &mut args)?;
+                    parsed.malloc_stats = Some(parse_duration(&arg)?);
+                }
+                "--background-reclaim" => {
+                    let arg = value::<String, _>(&arg, &mut args)?;
+                    parsed.background_reclaim = Some(parse_duration(&arg)?);
+                }
+                "--flush" => parsed.flush = FlushPolicy::EveryOp,
+                "--flush-policy" => parsed.flush = value(&arg, &mut args)?,
+                "--tui" => parsed.mode = Mode::Tui,
+                "--help" | "-h" => parsed.mode = Mode::Help,
+                _ => return Err(format!("unknown argument: {:?}", arg)),
+            }
+        }
+
+        if parsed.cage_size == 0 {
+            return Err("--size must be at least 1".to_owned());
+        }
+        if parsed.mode == Mode::Replay && parsed.trace.is_none() {
+            return Err("replay needs --trace FILE".to_owned());
+        }
+        if !(0.0..=100.0).contains(&parsed.write_percent) {
+            return Err("--write-percent must be between 0 and 100".to_owned());
+        }
+        Ok(parsed)
+    }
+
+    pub fn stress_config(&self) -> StressConfig {
+        StressConfig {
+            cage_size: self.cage_size,
+            readers: self.readers,
+            writers: self.writers,
+            mixed: self.mixed,
+            write_percent: self.write_percent,
+            slots: self.slots,
+            duration: self.duration.unwrap_or(StressConfig::default().duration),
+            flush: self.flush,
+            cage: self.cage,
+            reclaimer: self.reclaimer,
+            quiescent_every: self.quiescent_every,
+            forgetful: self.forgetful,
+            seed: self.seed,
+            watchdog: self.watchdog,
+            background_reclaim: self.background_reclaim,
+            padded: self.padded,
+            cas_writes: self.cas_writes,
+            record: self.trace.is_some(),
+            pin_cores: self.pin_cores,
+            memory_order: self.memory_order,
+            chaos: self.chaos.then(Chaos::default),
+        }
+    }
+
+    pub fn stall_config(&self) -> StallConfig {
+        StallConfig {
+            cage_size: self.cage_size,
+            writers: self.writers,
+            reclaimer: self.reclaimer,
+            stall: self.stall,
+            shards: self.shards,
+            seed: self.seed,
+            ..StallConfig::default()
+        }
+    }
+
+    pub fn numa_config(&self) -> NumaConfig {
+        NumaConfig {
+            placement: self.placement,
+            ..NumaConfig::default()
+        }
+    }
+
+    pub fn tui_config(&self) -> TuiConfig {
+        let defaults = TuiConfig::default();
+        TuiConfig {
+            cage_size: self.cage_size,
+            readers: self.readers,
+            writers: self.writers,
+            reclaimer: self.reclaimer,
+            stall: self.stall,
+            duration: self.duration.unwrap_or(defaults.duration),
+            seed: self.seed,
+            ..defaults
+        }
+    }
+}
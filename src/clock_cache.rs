@@ -0,0 +1,196 @@
+//! A small concurrent cache using the CLOCK eviction policy.
+//!
+//! Entries live in a fixed ring of `Atomic` slots.  Each entry has a
+//! "referenced" bit that's set whenever it's read.  To make room, the clock
+//! hand sweeps around the ring, clearing referenced bits, and evicts the
+//! first entry that hasn't been used since the last sweep.
+//!
+//! Evicted entries may still be in the hands of readers, so they go to the
+//! epoch collector.  Each entry remembers when it was evicted, and when it's
+//! finally dropped, the time it lingered is added to the cache's stats.
+
+use crossbeam::epoch::{self, pin, Atomic, Guard, Owned, Shared};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long evicted entries waited between eviction and destruction.
+#[derive(Debug, Default)]
+pub struct LingerStats {
+    count: AtomicU64,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl LingerStats {
+    fn record(&self, linger: Duration) {
+        let nanos = linger.as_nanos() as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// The number of evicted entries that have been destroyed so far.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::from_nanos(0),
+            n => Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed) / n),
+        }
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed))
+    }
+}
+
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    referenced: AtomicBool,
+    evicted_at: Mutex<Option<Instant>>,
+    stats: Arc<LingerStats>,
+}
+
+impl<K, V> Drop for Entry<K, V> {
+    fn drop(&mut self) {
+        if let Some(evicted_at) = *self.evicted_at.get_mut().unwrap() {
+            self.stats.record(evicted_at.elapsed());
+        }
+    }
+}
+
+/// A fixed-capacity concurrent cache with CLOCK eviction.
+///
+/// This is a playground cache: lookups scan every slot, and two threads
+/// inserting the same key at the same time can both succeed.
+pub struct ClockCache<K, V> {
+    slots: Vec<Atomic<Entry<K, V>>>,
+    hand: AtomicUsize,
+    evictions: AtomicU64,
+    stats: Arc<LingerStats>,
+}
+
+impl<K, V> ClockCache<K, V>
+where
+    K: Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new(capacity: usize) -> ClockCache<K, V> {
+        assert!(capacity > 0, "ClockCache needs at least one slot");
+        ClockCache {
+            slots: (0..capacity).map(|_| Atomic::null()).collect(),
+            hand: AtomicUsize::new(0),
+            evictions: AtomicU64::new(0),
+            stats: Arc::new(LingerStats::default()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn find<'g>(&self, key: &K, guard: &'g Guard) -> Option<(usize, Shared<'g, Entry<K, V>>)> {
+        self.slots.iter().enumerate().find_map(|(ii, slot)| {
+            let shared = slot.load(Ordering::SeqCst, guard);
+            match unsafe{shared.as_ref()} {
+                Some(e) if e.key == *key => Some((ii, shared)),
+                _ => None,
+            }
+        })
+    }
+
+    /// Look up `key`, marking its entry as recently used.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let guard = &pin();
+        let (_, shared) = self.find(key, guard)?;
+        let e = unsafe{shared.deref()};
+        e.referenced.store(true, Ordering::Relaxed);
+        Some(e.value.clone())
+    }
+
+    // Hand an entry that was just unlinked to the collector.
+    fn retire(&self, old: Shared<'_, Entry<K, V>>, guard: &Guard) {
+        let e = unsafe{old.deref()};
+        *e.evicted_at.lock().unwrap() = Some(Instant::now());
+        unsafe {
+            guard.defer_destroy(old);
+        }
+    }
+
+    /// Add or update an entry, evicting something if the cache is full.
+    pub fn insert(&self, key: K, value: V) {
+        let guard = &pin();
+        let mut new = Owned::new(Entry {
+            key,
+            value,
+            referenced: AtomicBool::new(false),
+            evicted_at: Mutex::new(None),
+            stats: self.stats.clone(),
+        });
+
+        // If the key is already here, replace that entry.
+        if let Some((ii, current)) = self.find(&new.key, guard) {
+            match self.slots[ii].compare_and_set(current, new, Ordering::SeqCst, guard) {
+                Ok(_) => {
+                    self.retire(current, guard);
+                    return;
+                }
+                Err(e) => new = e.new,
+            }
+        }
+
+        // Sweep the clock hand until we find a slot we can take.
+        loop {
+            let ii = self.hand.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+            let slot = &self.slots[ii];
+            let current = slot.load(Ordering::SeqCst, guard);
+
+            if let Some(e) = unsafe{current.as_ref()} {
+                // Recently used entries get a second chance.
+                if e.referenced.swap(false, Ordering::Relaxed) {
+                    continue;
+                }
+            }
+
+            match slot.compare_and_set(current, new, Ordering::SeqCst, guard) {
+                Ok(_) => {
+                    if !current.is_null() {
+                        self.evictions.fetch_add(1, Ordering::Relaxed);
+                        self.retire(current, guard);
+                    }
+                    return;
+                }
+                Err(e) => new = e.new,
+            }
+        }
+    }
+
+    /// The number of entries evicted to make room for new ones.
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    /// Stats about how long evicted or replaced entries lingered before
+    /// being destroyed.
+    pub fn linger_stats(&self) -> &LingerStats {
+        &self.stats
+    }
+}
+
+impl<K, V> Drop for ClockCache<K, V> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            for slot in &self.slots {
+                let shared = slot.load(Ordering::Relaxed, guard);
+                if !shared.is_null() {
+                    drop(shared.into_owned());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! A sorted doubly linked list (a set), with lock-free reads in both
+//! directions and locking writers.
+//!
+//! In a singly linked list, a node is garbage as soon as its predecessor
+//! has been swung past it: nobody who pins after that can find it.  Here a
+//! node is also the target of its successor's `prev`, so unlinking it takes
+//! both neighbours.  Each writer locks the nodes it changes, left to right,
+//! which means in key order, so two writers can't deadlock.  A remove locks
+//! the predecessor, the node and the successor, checks that none of them
+//! has been removed and that they're still next to each other, marks the
+//! node, and swings both neighbours' links past it.
+//!
+//! Only then is the node handed to the epoch collector.  Deferring it any
+//! earlier, with the successor's `prev` still pointing at it, would let a
+//! reader that pins later walk backwards into it after it's been freed.
+//!
+//! Readers take no locks, and may be standing on a node while it's
+//! removed.  Its links are left alone once it's marked, so they still lead
+//! to nodes that were in the list when it was removed, which the reader's
+//! guard keeps alive.  A reader skips marked nodes when collecting keys,
+//! but walks through them.
+
+use crossbeam::epoch::{self, pin, Atomic, Guard, Owned, Shared};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+struct Node<K> {
+    // `None` only for the two sentinels.
+    key: Option<K>,
+    prev: Atomic<Node<K>>,
+    next: Atomic<Node<K>>,
+    // Set, under the lock, just before the node is unlinked.
+    marked: AtomicBool,
+    lock: Mutex<()>,
+}
+
+impl<K> Node<K> {
+    fn new(key: Option<K>) -> Node<K> {
+        Node {
+            key,
+            prev: Atomic::null(),
+            next: Atomic::null(),
+            marked: AtomicBool::new(false),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        // Nothing panics while holding a node's lock, but if it did the
+        // links would still be consistent.
+        self.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_marked(&self) -> bool {
+        self.marked.load(Ordering::SeqCst)
+    }
+}
+
+/// A sorted set with links both ways, with removed nodes reclaimed by
+/// `crossbeam::epoch`.
+///
+/// `remove` unlinks a node both ways under the locks and then defers its
+/// destruction, so its key may outlive the remover's thread; keys have to
+/// be `Send + 'static`.
+pub struct DoublyLinkedList<K> {
+    head: Atomic<Node<K>>,
+    tail: Atomic<Node<K>>,
+}
+
+impl<K: Ord + Send + 'static> Default for DoublyLinkedList<K> {
+    fn default() -> Self {
+        DoublyLinkedList::new()
+    }
+}
+
+impl<K: Ord + Send + 'static> DoublyLinkedList<K> {
+    pub fn new() -> DoublyLinkedList<K> {
+        let guard = unsafe{epoch::unprotected()};
+        let head = Owned::new(Node::new(None)).into_shared(guard);
+        let tail = Owned::new(Node::new(None)).into_shared(guard);
+        unsafe {
+            head.deref().next.store(tail, Ordering::Relaxed);
+            tail.deref().prev.store(head, Ordering::Relaxed);
+        }
+        DoublyLinkedList {
+            head: Atomic::from(head),
+            tail: Atomic::from(tail),
+        }
+    }
+
+    // Find the last node with a key below `key`, and the node after it,
+    // which is either the tail or the first node whose key is >= `key`.
+    // Takes no locks, so either of them may be removed by the time we look.
+    fn find<'g>(&self, key: &K, guard: &'g Guard) -> (&'g Node<K>, Shared<'g, Node<K>>) {
+        let tail = self.tail.load(Ordering::SeqCst, guard);
+        let mut pred = unsafe{self.head.load(Ordering::SeqCst, guard).deref()};
+        let mut curr = pred.next.load(Ordering::SeqCst, guard);
+        while curr != tail {
+            let c = unsafe{curr.deref()};
+            if c.key.as_ref() >= Some(key) {
+                break;
+            }
+            pred = c;
+            curr = c.next.load(Ordering::SeqCst, guard);
+        }
+        (pred, curr)
+    }
+
+    // With both locked: whether `pred` and `curr` are still in the list,
+    // next to each other.
+    fn adjacent(pred: &Node<K>, curr: Shared<'_, Node<K>>, guard: &Guard) -> bool {
+        let c = unsafe{curr.deref()};
+        !pred.is_marked() && !c.is_marked() && pred.next.load(Ordering::SeqCst, guard) == curr
+    }
+
+    /// Add `key` to the set.  Returns `false` if it was already present.
+    pub fn insert(&self, key: K) -> bool {
+        let guard = &pin();
+        let tail = self.tail.load(Ordering::SeqCst, guard);
+        let node = Owned::new(Node::new(Some(key)));
+
+        loop {
+            let (pred, curr) = self.find(node.key.as_ref().unwrap(), guard);
+            let c = unsafe{curr.deref()};
+            let _locks = (pred.lock(), c.lock());
+            if !Self::adjacent(pred, curr, guard) {
+                continue;
+            }
+            if curr != tail && c.key == node.key {
+                return false;
+            }
+
+            // Nobody can see `node` yet, so its own links can be plain
+            // stores.  Backwards readers find it before forwards ones do,
+            // which is fine: it's complete either way.
+            node.prev.store(Shared::from(pred as *const Node<K>), Ordering::Relaxed);
+            node.next.store(curr, Ordering::Relaxed);
+            let node = node.into_shared(guard);
+            c.prev.store(node, Ordering::SeqCst);
+            pred.next.store(node, Ordering::SeqCst);
+            return true;
+        }
+    }
+
+    /// Remove `key` from the set.  Returns `false` if it wasn't present.
+    pub fn remove(&self, key: &K) -> bool {
+        let guard = &pin();
+        let tail = self.tail.load(Ordering::SeqCst, guard);
+
+        loop {
+            let (pred, curr) = self.find(key, guard);
+            let c = unsafe{curr.deref()};
+            let pred_lock = pred.lock();
+            let curr_lock = c.lock();
+            if !Self::adjacent(pred, curr, guard) {
+                continue;
+            }
+            if curr == tail || c.key.as_ref() != Some(key) {
+                return false;
+            }
+
+            // With `curr` locked, nobody can insert or remove next to it, so
+            // its successor stays put and can't be marked.  We still lock it,
+            // because its `prev` is one of the links we change.
+            let succ = c.next.load(Ordering::SeqCst, guard);
+            let s = unsafe{succ.deref()};
+            let succ_lock = s.lock();
+
+            c.marked.store(true, Ordering::SeqCst);
+            pred.next.store(succ, Ordering::SeqCst);
+            s.prev.store(Shared::from(pred as *const Node<K>), Ordering::SeqCst);
+            drop((succ_lock, curr_lock, pred_lock));
+
+            // Neither neighbour points at `curr` any more, so nobody who
+            // pins from now on can reach it, whichever way they walk.
+            unsafe {
+                guard.defer_destroy(curr);
+            }
+            return true;
+        }
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        let guard = &pin();
+        let tail = self.tail.load(Ordering::SeqCst, guard);
+        let (_, curr) = self.find(key, guard);
+        if curr == tail {
+            return false;
+        }
+        let c = unsafe{curr.deref()};
+        c.key.as_ref() == Some(key) && !c.is_marked()
+    }
+
+    /// Copy out every key that isn't marked, walking forwards from the
+    /// head under one guard.
+    pub fn to_vec(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let guard = &pin();
+        Self::walk(&self.head, &self.tail, |n| &n.next, guard)
+    }
+
+    /// Like `to_vec`, but walking backwards from the tail, so the keys come
+    /// out largest first.
+    pub fn to_vec_rev(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let guard = &pin();
+        Self::walk(&self.tail, &self.head, |n| &n.prev, guard)
+    }
+
+    // Collect the keys from `from` to `to`, exclusive, following `link`.
+    fn walk<F>(
+        from: &Atomic<Node<K>>,
+        to: &Atomic<Node<K>>,
+        link: F,
+        guard: &Guard,
+    ) -> Vec<K>
+    where
+        K: Clone,
+        F: Fn(&Node<K>) -> &Atomic<Node<K>>,
+    {
+        let end = to.load(Ordering::SeqCst, guard);
+        let start = unsafe{from.load(Ordering::SeqCst, guard).deref()};
+        let mut keys = Vec::new();
+        let mut curr = link(start).load(Ordering::SeqCst, guard);
+        while curr != end {
+            let c = unsafe{curr.deref()};
+            if !c.is_marked() {
+                keys.extend(c.key.clone());
+            }
+            curr = link(c).load(Ordering::SeqCst, guard);
+        }
+        keys
+    }
+}
+
+impl<K> Drop for DoublyLinkedList<K> {
+    fn drop(&mut self) {
+        // Every node still linked in, and both sentinels, get freed now.
+        // Removed nodes were already handed to the collector.
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut node = self.head.load(Ordering::Relaxed, guard);
+            while !node.is_null() {
+                let owned = node.into_owned();
+                node = owned.next.load(Ordering::Relaxed, guard);
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Values that keep count of their own construction and destruction, for
+//! tests to make assertions about.
+//!
+//! `Canary` prints as it goes, and its counts are shared by the whole
+//! process, so a test can't tell its own canaries from those of a test
+//! running next to it.  A `DropTracker` keeps the books for just the values
+//! it hands out, each with an id of its own, so a test can ask exactly which
+//! values are still alive, and catch one that's dropped twice, or read after
+//! it was dropped.
+//!
+//! `Tracked` values are `Send + Sync`, and hold nothing but the id and the
+//! books, so they'll go in any of the data structures here.  The ordered
+//! ones want keys, so `TrackedKey` wraps a number and a `Tracked` value
+//! that goes with it.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Hands out `Tracked` values, and counts how many times each one has been
+/// dropped.  Clones share the same books.
+#[derive(Clone, Default)]
+pub struct DropTracker {
+    // How many times each id has been dropped, indexed by id.
+    drops: Arc<Mutex<Vec<usize>>>,
+}
+
+impl DropTracker {
+    pub fn new() -> DropTracker {
+        DropTracker::default()
+    }
+
+    // A panic while some other value is being dropped mustn't make the
+    // books unreadable.
+    fn books(&self) -> MutexGuard<'_, Vec<usize>> {
+        self.drops.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Make a new value, with the next id.
+    pub fn track(&self) -> Tracked {
+        let mut drops = self.books();
+        drops.push(0);
+        Tracked {
+            id: drops.len() - 1,
+            tracker: self.clone(),
+        }
+    }
+
+    /// How many values have been made.
+    pub fn created(&self) -> usize {
+        self.books().len()
+    }
+
+    /// How many drops there have been, counting a double drop twice.
+    pub fn dropped(&self) -> usize {
+        self.books().iter().sum()
+    }
+
+    /// How many values haven't been dropped yet.
+    pub fn alive(&self) -> usize {
+        self.books().iter().filter(|&&d| d == 0).count()
+    }
+
+    /// How many times the value with this id has been dropped.
+    pub fn drops(&self, id: usize) -> usize {
+        self.books()[id]
+    }
+
+    /// The ids of the values that haven't been dropped yet.
+    pub fn alive_ids(&self) -> Vec<usize> {
+        self.ids_where(|d| d == 0)
+    }
+
+    /// The ids of the values that have been dropped more than once.
+    pub fn dropped_twice(&self) -> Vec<usize> {
+        self.ids_where(|d| d > 1)
+    }
+
+    fn ids_where(&self, f: impl Fn(usize) -> bool) -> Vec<usize> {
+        self.books()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &d)| f(d))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Panic unless every value that was ever made has been dropped exactly
+    /// once.
+    pub fn assert_all_dropped(&self) {
+        let (alive, twice) = (self.alive_ids(), self.dropped_twice());
+        assert!(
+            alive.is_empty() && twice.is_empty(),
+            "never dropped: {:?}, dropped twice: {:?}",
+            alive,
+            twice
+        );
+    }
+}
+
+impl fmt::Debug for DropTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropTracker")
+            .field("created", &self.created())
+            .field("alive", &self.alive())
+            .field("dropped_twice", &self.dropped_twice())
+            .finish()
+    }
+}
+
+/// A value made by a `DropTracker`, which tells it when it's dropped.
+pub struct Tracked {
+    id: usize,
+    tracker: DropTracker,
+}
+
+impl Tracked {
+    /// This value's id; the first value a tracker makes is 0.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Whether this value has already been dropped, which means whoever is
+    /// asking is looking at freed memory.
+    pub fn is_dropped(&self) -> bool {
+        self.tracker.drops(self.id) > 0
+    }
+
+    /// Like `Canary::validate`: panic if this value has already been
+    /// dropped, and otherwise return its id.
+    pub fn validate(&self) -> usize {
+        assert!(!self.is_dropped(), "use after drop of tracked value {}", self.id);
+        self.id
+    }
+
+    /// The tracker that made this value.
+    pub fn tracker(&self) -> &DropTracker {
+        &self.tracker
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.tracker.books()[self.id] += 1;
+    }
+}
+
+impl fmt::Debug for Tracked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tracked({})", self.id)
+    }
+}
+
+impl fmt::Display for Tracked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tracked value {}", self.id)
+    }
+}
+
+/// A `u64` key that tells its tracker when it's dropped, for the ordered
+/// data structures, where that's when its node is freed.
+///
+/// Keys compare by number alone.  A key made by `lookup` has no tracked
+/// value, so it can be handed to `contains` or `remove` without showing up
+/// in the books, and a clone is one of those.
+pub struct TrackedKey {
+    key: u64,
+    tracked: Option<Tracked>,
+}
+
+impl TrackedKey {
+    /// Make a key that `tracker` keeps the books for.
+    pub fn new(key: u64, tracker: &DropTracker) -> TrackedKey {
+        TrackedKey {
+            key,
+            tracked: Some(tracker.track()),
+        }
+    }
+
+    /// Make a key to look up `key` with, which isn't tracked.
+    pub fn lookup(key: u64) -> TrackedKey {
+        TrackedKey { key, tracked: None }
+    }
+
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    /// The numbers in `keys`, in order, for comparing against.
+    pub fn keys(keys: &[TrackedKey]) -> Vec<u64> {
+        keys.iter().map(TrackedKey::key).collect()
+    }
+}
+
+impl PartialEq for TrackedKey {
+    fn eq(&self, other: &TrackedKey) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for TrackedKey {}
+
+impl PartialOrd for TrackedKey {
+    fn partial_cmp(&self, other: &TrackedKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TrackedKey {
+    fn cmp(&self, other: &TrackedKey) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl Clone for TrackedKey {
+    fn clone(&self) -> TrackedKey {
+        TrackedKey::lookup(self.key)
+    }
+}
+
+impl fmt::Debug for TrackedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.tracked {
+            Some(tracked) => write!(f, "TrackedKey({}, {:?})", self.key, tracked),
+            None => write!(f, "TrackedKey({})", self.key),
+        }
+    }
+}
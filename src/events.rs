@@ -0,0 +1,77 @@
+//! Structured reclamation events, for tools and tests that want to watch
+//! values being retired and destroyed without parsing stdout.
+//!
+//! Call `subscribe` to get a channel.  From then on, every value a
+//! `BirdCage` retires (through any reclaimer) produces a `Deferred` event,
+//! and a `Reclaimed` event with the same id once it has been destroyed.
+//! Values retired while nobody is subscribed produce no events at all, and
+//! cost nothing more than one atomic load.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static SUBSCRIBERS: Mutex<Vec<Sender<Event>>> = Mutex::new(Vec::new());
+// Whether `SUBSCRIBERS` might be non-empty, so the common case can skip
+// the lock.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Something that happened to a retired value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A value was retired, and will be destroyed once that's safe.
+    Deferred { id: u64 },
+    /// The value retired as `id` was destroyed, `delay` after it was
+    /// retired.
+    Reclaimed { id: u64, delay: Duration },
+}
+
+/// Start receiving events.  Dropping the receiver unsubscribes.
+pub fn subscribe() -> Receiver<Event> {
+    let (tx, rx) = mpsc::channel();
+    SUBSCRIBERS.lock().unwrap().push(tx);
+    ACTIVE.store(true, Ordering::SeqCst);
+    rx
+}
+
+fn emit(event: Event) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    subscribers.retain(|tx| tx.send(event).is_ok());
+    if subscribers.is_empty() {
+        ACTIVE.store(false, Ordering::SeqCst);
+    }
+}
+
+// A batch of retired values that subscribers were told about.
+pub(crate) struct Tracker {
+    first_id: u64,
+    retired_at: Instant,
+}
+
+impl Tracker {
+    // Announce that `n` values were just deferred, or return `None` if
+    // nobody's listening.
+    pub(crate) fn deferred(n: usize) -> Option<Tracker> {
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return None;
+        }
+        let first_id = NEXT_ID.fetch_add(n as u64, Ordering::Relaxed);
+        for id in first_id..first_id + n as u64 {
+            emit(Event::Deferred { id });
+        }
+        Some(Tracker {
+            first_id,
+            retired_at: Instant::now(),
+        })
+    }
+
+    // Announce that the `k`th value of the batch has been destroyed.
+    pub(crate) fn reclaimed(&self, k: u64) {
+        emit(Event::Reclaimed {
+            id: self.first_id + k,
+            delay: self.retired_at.elapsed(),
+        });
+    }
+}
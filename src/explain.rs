@@ -0,0 +1,117 @@
+//! Teaching mode: say what the epoch reclaimer is doing, and why memory
+//! is or isn't being freed, as it happens.
+//!
+//! With `set_enabled(true)`, the `Epoch` reclaimer prints a line whenever
+//! a thread pins, defers a value, flushes, or frees something.
+//!
+//! crossbeam keeps the epoch numbers themselves private, so the
+//! explanations are in terms of what can be seen from outside: which pins
+//! make crossbeam try to advance the epoch, how full each thread's bag of
+//! deferred functions is, and how long a freed value waited.  The counts
+//! mirror crossbeam 0.8's own bookkeeping (a bag of 64, a collection
+//! attempt every 128 pins), but they're kept alongside it rather than read
+//! from it, so pins and defers that bypass `Epoch` aren't counted, and a
+//! thread's bag going to the global queue when the thread exits isn't
+//! announced.
+
+use crate::reclaim;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// crossbeam-epoch's thread-local bag holds this many deferred functions
+// before it's sealed and pushed onto the global queue.
+const BAG_SIZE: usize = 64;
+// And every this many pins, it tries to advance the epoch and collect.
+const PINS_BETWEEN_COLLECT: u64 = 128;
+
+thread_local! {
+    static PINS: Cell<u64> = const { Cell::new(0) };
+    static BAG: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Turn the explanations on or off, for every thread.
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn say(what: &str) {
+    let t = thread::current();
+    match t.name() {
+        Some(name) => println!("    epoch ({}): {}", name, what),
+        None => println!("    epoch ({:?}): {}", t.id(), what),
+    }
+}
+
+// The current thread is about to pin, and wasn't pinned already.
+pub(crate) fn pinning() {
+    let count = PINS.with(|p| {
+        let count = p.get();
+        p.set(count + 1);
+        count
+    });
+    let left = PINS_BETWEEN_COLLECT - count % PINS_BETWEEN_COLLECT;
+    if left == PINS_BETWEEN_COLLECT {
+        say("pinned, and since this is one in every 128 pins, tried to advance the epoch and free old garbage");
+    } else {
+        say(&format!(
+            "pinned; nothing retired from now on can be freed until this unpins ({} pins to go until the next collection attempt)",
+            left
+        ));
+    }
+}
+
+// The current thread deferred a function that frees `n` values.
+pub(crate) fn deferring(n: usize) {
+    let items = if n == 1 {
+        "1 value".to_owned()
+    } else {
+        format!("{} values", n)
+    };
+    let in_bag = BAG.with(|b| b.get());
+    if in_bag == BAG_SIZE {
+        BAG.with(|b| b.set(1));
+        say(&format!(
+            "deferred {}; the bag was full, so its 64 went to the global queue, to be freed once the epoch advances twice",
+            items
+        ));
+    } else {
+        BAG.with(|b| b.set(in_bag + 1));
+        say(&format!(
+            "deferred {}, since a pinned reader might still have it; {}/{} in this thread's bag, which isn't shared until it's full or flushed ({} pending in all)",
+            items,
+            in_bag + 1,
+            BAG_SIZE,
+            reclaim::pending()
+        ));
+    }
+}
+
+// The current thread flushed.
+pub(crate) fn flushing() {
+    let in_bag = BAG.with(|b| b.replace(0));
+    say(&format!(
+        "flushed {} deferred functions from this thread's bag to the global queue, then tried to advance the epoch and free old garbage",
+        in_bag
+    ));
+}
+
+// A deferred function retired at `retired_at` is about to run.
+pub(crate) fn freeing(retired_at: Instant) {
+    say(&format!(
+        "freeing a value retired {:?} ago: the epoch has advanced twice since, so nobody pinned back then still is",
+        round(retired_at.elapsed())
+    ));
+}
+
+// Durations to the microsecond are plenty here.
+fn round(d: Duration) -> Duration {
+    Duration::from_micros(d.as_micros() as u64)
+}
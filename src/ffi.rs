@@ -0,0 +1,187 @@
+//! A C interface to a `BirdCage` of canaries, so the cage can be driven
+//! (and abused) from C test harnesses.
+//!
+//! Build it as a shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`, and
+//! declare the functions with `ffi/birdcage.h`; `ffi/harness.c` is an
+//! example.
+//!
+//! Every function pins and unpins for itself, so C code needs no pinning
+//! hygiene to be safe, whatever thread it calls from.  crossbeam registers
+//! a thread with the collector the first time it pins, and unregisters it
+//! from the thread's TLS destructors, which run for threads started with
+//! `pthread_create` just as for Rust's own.  What C code *can* do is hold a
+//! pin with `birdcage_pin`: then nothing retired from then on is freed, by
+//! any thread, until the pin is released, and `birdcage_take` on the
+//! pinned thread waits forever.  A thread that exits (or is cancelled)
+//! while still pinned never releases it at all.  Guards belong to the
+//! thread that made them, so `birdcage_unpin` from any other thread is
+//! refused rather than allowed to corrupt crossbeam's bookkeeping.
+
+use crate::reclaim::{force_reclaim, Epoch, Reclaimer};
+use crate::{BirdCage, Canary};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::thread::{self, ThreadId};
+
+/// The call worked, and the slot had a canary in it.
+pub const BIRDCAGE_OK: c_int = 0;
+/// The call worked, but the slot was empty.
+pub const BIRDCAGE_EMPTY: c_int = 1;
+/// The slot number was out of range, or a pointer was null.
+pub const BIRDCAGE_BAD_ARGUMENT: c_int = -1;
+/// The guard was made by some other thread, and can't be released here.
+pub const BIRDCAGE_WRONG_THREAD: c_int = -2;
+
+// What the canaries print as their context.
+const CTX: &str = "C";
+
+/// A pin held on behalf of C code, and the thread that holds it.
+pub struct BirdcageGuard {
+    _guard: <Epoch as Reclaimer>::Guard,
+    thread: ThreadId,
+}
+
+// Turn the C arguments into a cage reference and an in-range slot.
+unsafe fn checked<'a>(cage: *const BirdCage<Canary>, n: usize) -> Option<&'a BirdCage<Canary>> {
+    cage.as_ref().filter(|cage| n < cage.len())
+}
+
+/// Make a cage of `size` canaries, which print as they're dropped.
+#[no_mangle]
+pub extern "C" fn birdcage_new(size: usize) -> *mut BirdCage<Canary> {
+    Box::into_raw(Box::new(BirdCage::new(size)))
+}
+
+/// Read the canary in slot `n`.
+///
+/// # Safety
+///
+/// `cage` must be null or come from `birdcage_new`, and not be destroyed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn birdcage_access(cage: *const BirdCage<Canary>, n: usize) -> c_int {
+    match checked(cage, n) {
+        Some(cage) => {
+            let found = cage.with_slot(n, |c| println!("[{}] accessing {}", CTX, c));
+            found.map_or(BIRDCAGE_EMPTY, |_| BIRDCAGE_OK)
+        }
+        None => BIRDCAGE_BAD_ARGUMENT,
+    }
+}
+
+/// Put a new canary called `name` into slot `n`, and retire the old one.
+///
+/// A null `name` makes a canary called "Cuckoo".
+///
+/// # Safety
+///
+/// `cage` must be as for `birdcage_access`, and `name` must be null or a
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn birdcage_replace(
+    cage: *const BirdCage<Canary>,
+    n: usize,
+    name: *const c_char,
+) -> c_int {
+    let cage = match checked(cage, n) {
+        Some(cage) => cage,
+        None => return BIRDCAGE_BAD_ARGUMENT,
+    };
+    let name = if name.is_null() {
+        "Cuckoo".into()
+    } else {
+        CStr::from_ptr(name).to_string_lossy()
+    };
+    match cage.replace(n, CTX, Canary::new(&name)) {
+        Ok(()) => BIRDCAGE_OK,
+        Err(_) => BIRDCAGE_BAD_ARGUMENT,
+    }
+}
+
+/// Empty slot `n`, and wait until its canary is safe to drop, then drop it.
+///
+/// This waits forever if any thread, including this one, holds a pin.
+///
+/// # Safety
+///
+/// `cage` must be as for `birdcage_access`.
+#[no_mangle]
+pub unsafe extern "C" fn birdcage_take(cage: *const BirdCage<Canary>, n: usize) -> c_int {
+    match checked(cage, n) {
+        Some(cage) => match cage.take(n).map(|taken| taken.wait()) {
+            Ok(Some(c)) => {
+                println!("[{}] took {}", CTX, c);
+                BIRDCAGE_OK
+            }
+            Ok(None) => BIRDCAGE_EMPTY,
+            Err(_) => BIRDCAGE_BAD_ARGUMENT,
+        },
+        None => BIRDCAGE_BAD_ARGUMENT,
+    }
+}
+
+/// Hand this thread's retired canaries to the collector, and free whatever
+/// it can.
+#[no_mangle]
+pub extern "C" fn birdcage_flush() {
+    Epoch::flush(&Epoch::pin());
+}
+
+/// Flush until every retired canary is freed, and return 1 if that worked,
+/// or 0 if some pin is holding them up.
+#[no_mangle]
+pub extern "C" fn birdcage_reclaim() -> c_int {
+    force_reclaim::<Epoch>() as c_int
+}
+
+/// The number of canaries that haven't been dropped yet, in cages or
+/// waiting to be reclaimed.
+#[no_mangle]
+pub extern "C" fn birdcage_alive() -> usize {
+    Canary::alive()
+}
+
+/// Pin the current thread until `birdcage_unpin`.
+#[no_mangle]
+pub extern "C" fn birdcage_pin() -> *mut BirdcageGuard {
+    Box::into_raw(Box::new(BirdcageGuard {
+        _guard: Epoch::pin(),
+        thread: thread::current().id(),
+    }))
+}
+
+/// Release a pin from `birdcage_pin`.
+///
+/// Only the thread that made the guard can release it; from any other
+/// thread, this returns `BIRDCAGE_WRONG_THREAD` and the guard stays valid.
+///
+/// # Safety
+///
+/// `guard` must be null or come from `birdcage_pin`, and not be released
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn birdcage_unpin(guard: *mut BirdcageGuard) -> c_int {
+    match guard.as_ref() {
+        Some(g) if g.thread != thread::current().id() => BIRDCAGE_WRONG_THREAD,
+        Some(_) => {
+            drop(Box::from_raw(guard));
+            BIRDCAGE_OK
+        }
+        None => BIRDCAGE_BAD_ARGUMENT,
+    }
+}
+
+/// Drop a cage and the canaries still in it.  Canaries it retired earlier
+/// are still up to the collector.
+///
+/// # Safety
+///
+/// `cage` must be null or come from `birdcage_new`, and no other thread may
+/// be using it.  It can't be used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn birdcage_destroy(cage: *mut BirdCage<Canary>) {
+    if !cage.is_null() {
+        drop(Box::from_raw(cage));
+    }
+}
@@ -0,0 +1,116 @@
+//! How often a `BirdCage` asks its reclaimer to flush after a replace.
+//!
+//! Flushing pushes this thread's deferred garbage out where it can be
+//! collected, and tries to collect.  Doing it on every replace keeps the
+//! garbage down but costs throughput; never doing it lets garbage pile up
+//! in thread-local bags.  The policies in between trade one for the other.
+
+use crate::cli::parse_duration;
+use std::cell::Cell;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// When to flush after retiring a replaced value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Leave it to the reclaimer.
+    Never,
+    /// After every replace.
+    EveryOp,
+    /// After every `n`th replace on this thread.
+    EveryN(u64),
+    /// After a replace, if this thread hasn't flushed for at least this long.
+    EveryDuration(Duration),
+}
+
+thread_local! {
+    // Shared by every cage on the thread, which is close enough: the thing
+    // being flushed is the thread's garbage, not the cage's.
+    static SINCE_FLUSH: Cell<u64> = const { Cell::new(0) };
+    static LAST_FLUSH: Cell<Option<Instant>> = const { Cell::new(None) };
+    // For chaos runs: whether to skip the flush after the next retire, and
+    // how many have been skipped.
+    static SKIP_NEXT: Cell<bool> = const { Cell::new(false) };
+    static SKIPPED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Don't flush after the next retire on this thread, even if it's due.
+pub(crate) fn skip_next_flush() {
+    SKIP_NEXT.with(|skip| skip.set(true));
+}
+
+/// How many flushes `skip_next_flush` has stopped on this thread.
+pub(crate) fn skipped_flushes() -> u64 {
+    SKIPPED.with(Cell::get)
+}
+
+impl FlushPolicy {
+    /// Note one more retire on this thread, and say whether it's time to
+    /// flush.
+    pub fn should_flush(self) -> bool {
+        let due = self.due();
+        if SKIP_NEXT.with(|skip| skip.replace(false)) && due {
+            SKIPPED.with(|skipped| skipped.set(skipped.get() + 1));
+            return false;
+        }
+        due
+    }
+
+    fn due(self) -> bool {
+        match self {
+            FlushPolicy::Never => false,
+            FlushPolicy::EveryOp => true,
+            FlushPolicy::EveryN(n) => SINCE_FLUSH.with(|since| {
+                let count = since.get() + 1;
+                let due = count >= n;
+                since.set(if due { 0 } else { count });
+                due
+            }),
+            FlushPolicy::EveryDuration(d) => LAST_FLUSH.with(|last| {
+                let now = Instant::now();
+                match last.get() {
+                    Some(t) if now.duration_since(t) < d => false,
+                    _ => {
+                        last.set(Some(now));
+                        true
+                    }
+                }
+            }),
+        }
+    }
+}
+
+impl fmt::Display for FlushPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlushPolicy::Never => write!(f, "never"),
+            FlushPolicy::EveryOp => write!(f, "every-op"),
+            FlushPolicy::EveryN(n) => write!(f, "every:{}", n),
+            FlushPolicy::EveryDuration(d) => write!(f, "every:{}ms", d.as_secs_f64() * 1000.0),
+        }
+    }
+}
+
+impl FromStr for FlushPolicy {
+    type Err = String;
+
+    /// Accepts `never`, `every-op`, `every:N` for every N replaces, or
+    /// `every:T` with a unit (like `every:5ms`) for a time interval.
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "never" => return Ok(FlushPolicy::Never),
+            "every-op" => return Ok(FlushPolicy::EveryOp),
+            _ => {}
+        }
+        let bad = || format!("unknown flush policy: {:?}", s);
+        let arg = s.strip_prefix("every:").ok_or_else(bad)?;
+        if let Ok(n) = arg.parse::<u64>() {
+            return match n {
+                0 => Err(bad()),
+                n => Ok(FlushPolicy::EveryN(n)),
+            };
+        }
+        parse_duration(arg).map(FlushPolicy::EveryDuration)
+    }
+}
@@ -0,0 +1,99 @@
+//! Decode arbitrary bytes into a few threads' worth of cage operations and
+//! run them at once, with canary validation on.  This is the body of the
+//! `ops` fuzz target in `fuzz/`, kept here so that ordinary tests can run
+//! it on fixed inputs.
+//!
+//! The first byte picks the number of threads; after that, each pair of
+//! bytes is one operation: which thread runs it and what it does, then
+//! which slot.  A read of a dropped canary panics, and so does any canary
+//! that's still alive once the cage is gone.  Build the target with
+//! `cargo fuzz run ops` (which turns on ASAN) to catch the reads that
+//! validation misses because the memory was already reused.
+
+use crate::reclaim::{force_reclaim, Epoch};
+use crate::{BirdCage, Cage, Canary};
+use std::thread;
+
+const MAX_THREADS: usize = 3;
+const SLOTS: usize = 4;
+
+/// One operation, on one slot of the cage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Get(usize),
+    Put(usize),
+    Take(usize),
+    Update(usize),
+    Flush,
+}
+
+/// Split `data` into each thread's operations, in the order they run.
+pub fn decode(data: &[u8]) -> Vec<Vec<Op>> {
+    let (threads, rest) = match data.split_first() {
+        Some((&b, rest)) => (b as usize % MAX_THREADS + 1, rest),
+        None => return Vec::new(),
+    };
+    let mut per_thread = vec![Vec::new(); threads];
+    for pair in rest.chunks_exact(2) {
+        let (what, n) = (pair[0] as usize, pair[1] as usize % SLOTS);
+        let op = match what / threads % 5 {
+            0 => Op::Get(n),
+            1 => Op::Put(n),
+            2 => Op::Take(n),
+            3 => Op::Update(n),
+            _ => Op::Flush,
+        };
+        per_thread[what % threads].push(op);
+    }
+    per_thread
+}
+
+fn run_thread(cage: &BirdCage<Canary>, ops: &[Op]) {
+    for &op in ops {
+        match op {
+            Op::Get(n) => {
+                let _ = cage.with_slot(n, |c| c.validate());
+            }
+            Op::Put(n) => cage.put(n, Canary::silent("put")),
+            Op::Take(n) => {
+                // Whatever comes out of this has to be alive, too.
+                if let Some(c) = cage.take(n).unwrap().wait() {
+                    c.validate();
+                }
+            }
+            Op::Update(n) => {
+                // An empty slot fails the update, which is fine.
+                let _ = cage.update(n, |old| {
+                    old.validate();
+                    Canary::silent("update")
+                });
+            }
+            Op::Flush => cage.flush(),
+        }
+    }
+    // This thread's leftover garbage only reaches the global queue when
+    // its thread-locals are torn down, which can be after the scope has
+    // been joined, and then the final reclaim would miss it.
+    cage.flush();
+}
+
+/// Decode `data` and run it, one thread per list of operations, on a
+/// cage of canaries.  Panics if a canary is read after it's dropped, or
+/// is never dropped at all.
+pub fn run(data: &[u8]) {
+    Canary::set_validation(true);
+    let per_thread = decode(data);
+    let alive_before = Canary::alive();
+
+    let cage = BirdCage::from_fn(SLOTS, |_| Canary::silent("initial"));
+    thread::scope(|s| {
+        for ops in &per_thread {
+            let cage = &cage;
+            s.spawn(move || run_thread(cage, ops));
+        }
+    });
+    drop(cage);
+
+    assert!(force_reclaim::<Epoch>(), "reclamation stalled");
+    assert_eq!(Canary::alive(), alive_before, "a canary was never dropped");
+}
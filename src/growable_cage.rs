@@ -0,0 +1,289 @@
+use crate::cage::{Cage, CageError};
+use crate::reclaim::{self, Epoch, Reclaimer};
+use crossbeam::epoch::{Atomic, Guard, Owned, Shared};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+// A slot that has been copied into the next table is marked with this tag,
+// and nothing is ever written to it again.
+const MOVED: usize = 1;
+
+// Values are boxed in this so that their pointers always have a spare low
+// bit for `MOVED`, whatever `T`'s own alignment is.
+#[repr(align(2))]
+struct Entry<T>(T);
+
+struct Table<T> {
+    slots: Box<[Atomic<Entry<T>>]>,
+    // The table that replaced this one, once a grow has started.
+    next: Atomic<Table<T>>,
+}
+
+impl<T> Table<T> {
+    fn new(slots: Box<[Atomic<Entry<T>>]>) -> Table<T> {
+        Table {
+            slots,
+            next: Atomic::null(),
+        }
+    }
+}
+
+/// A `BirdCage` that can `grow` while other threads are using it.
+///
+/// The slots live in a table behind one more epoch-managed pointer.
+/// Growing builds a bigger table, copies the slots over one at a time, and
+/// then swaps the new table in, deferring the old one to the epoch like
+/// any other garbage, since readers may still be looking at it.
+///
+/// Readers never wait.  Each slot in the old table is marked as moved as
+/// soon as it's been copied, and whoever finds a moved slot follows the old
+/// table to the new one.  That's also why writers use compare-and-swap
+/// rather than a plain swap: a swap would write over the mark, and the
+/// value would be lost in a table nobody looks at any more.
+///
+/// Following a table to the next one is only safe because an epoch guard
+/// protects everything that was reachable when it was pinned, so this only
+/// works with `Epoch`.  A hazard pointer protects one pointer at a time,
+/// and re-checking that the old table still points at the new one proves
+/// nothing, because it always will.
+pub struct GrowableBirdCage<T> {
+    table: Atomic<Table<T>>,
+    // Only one grow at a time.
+    growing: Mutex<()>,
+}
+
+impl<T: Send + 'static> GrowableBirdCage<T> {
+    /// Create a cage with `size` slots, filling slot `n` with `f(n)`.
+    pub fn from_fn<F>(size: usize, mut f: F) -> GrowableBirdCage<T>
+    where
+        F: FnMut(usize) -> T,
+    {
+        let slots = (0..size).map(|ii| Atomic::new(Entry(f(ii)))).collect();
+        GrowableBirdCage {
+            table: Atomic::new(Table::new(slots)),
+            growing: Mutex::new(()),
+        }
+    }
+
+    /// Create a cage with `size` empty slots.
+    pub fn empty(size: usize) -> GrowableBirdCage<T> {
+        GrowableBirdCage {
+            table: Atomic::new(Table::new(empty_slots(size))),
+            growing: Mutex::new(()),
+        }
+    }
+
+    /// The number of slots in the cage right now.
+    pub fn len(&self) -> usize {
+        let guard = &Epoch::pin();
+        unsafe{self.table.load(Ordering::SeqCst, guard).deref()}.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Start a protected section, for use with `get`.
+    pub fn pin(&self) -> Guard {
+        Epoch::pin()
+    }
+
+    /// Get a reference to the value in slot `n`.
+    ///
+    /// The reference is valid for as long as `guard` is alive, even if the
+    /// cage grows in the meantime.
+    pub fn get<'g>(&self, n: usize, guard: &'g Guard) -> Result<&'g T, CageError> {
+        let mut table = self.table.load(Ordering::SeqCst, guard);
+        let len = unsafe{table.deref()}.slots.len();
+        if n >= len {
+            return Err(CageError::IndexOutOfBounds { index: n, len });
+        }
+        loop {
+            let t = unsafe{table.deref()};
+            let p = t.slots[n].load(Ordering::SeqCst, guard);
+            if p.tag() != MOVED {
+                return unsafe{p.as_ref()}
+                    .map(|e| &e.0)
+                    .ok_or(CageError::SlotEmpty { index: n });
+            }
+            table = t.next.load(Ordering::SeqCst, guard);
+        }
+    }
+
+    /// Pin, load slot `n`, and hand the value to `f`, returning whatever
+    /// `f` returns.
+    pub fn with_slot<F, R2>(&self, n: usize, f: F) -> Result<R2, CageError>
+    where
+        F: FnOnce(&T) -> R2,
+    {
+        let guard = &Epoch::pin();
+        self.get(n, guard).map(f)
+    }
+
+    /// Put `value` into slot `n`, show the old value to `removed`, and
+    /// schedule the old value for destruction.
+    pub fn replace_with<F>(&self, n: usize, value: T, removed: F) -> Result<(), CageError>
+    where
+        F: FnOnce(&T),
+    {
+        let guard = &Epoch::pin();
+        let new = Owned::new(Entry(value)).into_shared(guard);
+        let old = match self.swap(n, new, guard) {
+            Ok(old) => old,
+            Err(e) => {
+                // Nobody else ever saw it.
+                drop(unsafe{new.into_owned()});
+                return Err(e);
+            }
+        };
+        if let Some(e) = unsafe{old.as_ref()} {
+            removed(&e.0);
+            self.retire(old, guard);
+        }
+        Ok(())
+    }
+
+    /// Empty slot `n`, scheduling whatever was there for destruction.
+    ///
+    /// Fails if there's no such slot, or it was already empty.
+    pub fn remove(&self, n: usize) -> Result<(), CageError> {
+        let guard = &Epoch::pin();
+        let old = self.swap(n, Shared::null(), guard)?;
+        if old.is_null() {
+            return Err(CageError::SlotEmpty { index: n });
+        }
+        self.retire(old, guard);
+        Ok(())
+    }
+
+    /// Make the cage `new_size` slots long, keeping every value where it
+    /// is, and leaving the new slots empty.  Returns `false` if it already
+    /// had at least that many.
+    ///
+    /// Readers and writers can carry on throughout.  Another `grow` waits
+    /// for this one to finish.
+    pub fn grow(&self, new_size: usize) -> bool {
+        let _growing = self.growing.lock().unwrap();
+        let guard = &Epoch::pin();
+        let old = self.table.load(Ordering::SeqCst, guard);
+        let old_t = unsafe{old.deref()};
+        if new_size <= old_t.slots.len() {
+            return false;
+        }
+        let new = Owned::new(Table::new(empty_slots(new_size))).into_shared(guard);
+        let new_t = unsafe{new.deref()};
+        old_t.next.store(new, Ordering::SeqCst);
+
+        for (n, slot) in old_t.slots.iter().enumerate() {
+            let mut current = slot.load(Ordering::SeqCst, guard);
+            loop {
+                // Nobody goes looking in the new table's slot until the old
+                // one is marked, so this can't race with anything.
+                new_t.slots[n].store(current, Ordering::SeqCst);
+                match slot.compare_and_set(current, Shared::null().with_tag(MOVED), Ordering::SeqCst, guard) {
+                    Ok(_) => break,
+                    Err(e) => current = e.current,
+                }
+            }
+        }
+
+        self.table.store(new, Ordering::SeqCst);
+        // Every slot in the old table is marked, so it owns no values, but
+        // readers may still be following it to the new one.
+        unsafe {
+            guard.defer_destroy(old);
+        }
+        true
+    }
+
+    // Put `new` into slot `n`, in whichever table it lives in now, and
+    // return what was there.  Tables only get bigger, so if the first one
+    // has the slot, so do all the ones after it.
+    fn swap<'g>(
+        &self,
+        n: usize,
+        new: Shared<'g, Entry<T>>,
+        guard: &'g Guard,
+    ) -> Result<Shared<'g, Entry<T>>, CageError> {
+        let mut table = self.table.load(Ordering::SeqCst, guard);
+        let len = unsafe{table.deref()}.slots.len();
+        if n >= len {
+            return Err(CageError::IndexOutOfBounds { index: n, len });
+        }
+        loop {
+            let t = unsafe{table.deref()};
+            let slot = &t.slots[n];
+            let mut current = slot.load(Ordering::SeqCst, guard);
+            while current.tag() != MOVED {
+                match slot.compare_and_set(current, new, Ordering::SeqCst, guard) {
+                    Ok(_) => return Ok(current),
+                    Err(e) => current = e.current,
+                }
+            }
+            table = t.next.load(Ordering::SeqCst, guard);
+        }
+    }
+
+    // Schedule destruction of a value that we just unlinked from a slot.
+    fn retire(&self, old: Shared<'_, Entry<T>>, guard: &Guard) {
+        // crossbeam's `Owned` is a `Box`, and we're the only ones retiring
+        // this value.
+        unsafe {
+            Epoch::retire_with(guard, old.as_raw() as *mut Entry<T>, reclaim::counted(drop));
+        }
+    }
+}
+
+fn empty_slots<T>(size: usize) -> Box<[Atomic<Entry<T>>]> {
+    (0..size).map(|_| Atomic::null()).collect()
+}
+
+impl<T: Send + Sync + 'static> Cage<T> for GrowableBirdCage<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn with_slot<F, R2>(&self, n: usize, f: F) -> Option<R2>
+    where
+        F: FnOnce(&T) -> R2,
+    {
+        self.with_slot(n, f).ok()
+    }
+
+    // The trait can't report a bad slot, so this panics on one, like
+    // indexing a slice would.
+    fn put_with<F>(&self, n: usize, value: T, removed: F)
+    where
+        F: FnOnce(&T),
+    {
+        if let Err(e) = self.replace_with(n, value, removed) {
+            panic!("{}", e);
+        }
+    }
+
+    fn flush(&self) {
+        Epoch::flush(&Epoch::pin());
+    }
+
+    fn force_reclaim(&self) -> bool {
+        reclaim::force_reclaim::<Epoch>()
+    }
+}
+
+impl<T> Drop for GrowableBirdCage<T> {
+    fn drop(&mut self) {
+        // With `&mut self`, no grow is under way, so the current table holds
+        // every value and none of its slots are marked.  Older tables were
+        // deferred when they were replaced.
+        unsafe {
+            let guard = crossbeam::epoch::unprotected();
+            let table = self.table.load(Ordering::Relaxed, guard).into_owned();
+            for slot in table.slots.iter() {
+                let p = slot.load(Ordering::Relaxed, guard);
+                if !p.is_null() {
+                    drop(p.into_owned());
+                }
+            }
+        }
+    }
+}
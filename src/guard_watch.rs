@@ -0,0 +1,84 @@
+//! Catching guards that are held too long, such as across an `.await`.
+//!
+//! A pinned guard held across an `.await` stays pinned for however long
+//! the task is suspended, which can be forever, and nothing retired in the
+//! meantime can be freed.  crossbeam's `Guard` isn't `Send`, so a
+//! work-stealing runtime won't let a task do that, but a single-threaded
+//! one will.
+//!
+//! The best defence is `BirdCage::with_pinned`: the guard only exists
+//! inside a synchronous closure, so there's nowhere to put an `.await`.
+//! When a guard has to live longer, `BirdCage::pin_watched` returns a
+//! `Watched` one, which prints a warning when it's dropped if it was held
+//! longer than `limit()`.
+
+use std::ops::Deref;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// The limit in nanoseconds, or zero for no limit.
+static LIMIT: AtomicU64 = AtomicU64::new(100_000_000);
+static WARNINGS: AtomicU64 = AtomicU64::new(0);
+
+/// Warn about guards held longer than `limit`, or never, if it's zero.
+/// The default is 100ms.
+pub fn set_limit(limit: Duration) {
+    LIMIT.store(limit.as_nanos() as u64, Ordering::Relaxed);
+}
+
+pub fn limit() -> Duration {
+    Duration::from_nanos(LIMIT.load(Ordering::Relaxed))
+}
+
+/// How many warnings have been printed so far.
+pub fn warnings() -> u64 {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+/// A guard that remembers where and when it was pinned, and complains if
+/// it's held too long.
+pub struct Watched<G> {
+    guard: G,
+    since: Instant,
+    at: &'static Location<'static>,
+}
+
+impl<G> Watched<G> {
+    /// Start watching `guard`, which was pinned just now by our caller.
+    #[track_caller]
+    pub fn new(guard: G) -> Watched<G> {
+        Watched {
+            guard,
+            since: Instant::now(),
+            at: Location::caller(),
+        }
+    }
+
+    /// How long the guard has been held.
+    pub fn held(&self) -> Duration {
+        self.since.elapsed()
+    }
+}
+
+impl<G> Deref for Watched<G> {
+    type Target = G;
+
+    fn deref(&self) -> &G {
+        &self.guard
+    }
+}
+
+impl<G> Drop for Watched<G> {
+    fn drop(&mut self) {
+        let limit = limit();
+        let held = self.held();
+        if limit != Duration::ZERO && held > limit {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+            eprintln!(
+                "warning: the guard pinned at {} was held for {:?}, longer than {:?}; is it being held across an .await?",
+                self.at, held, limit
+            );
+        }
+    }
+}
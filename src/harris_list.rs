@@ -0,0 +1,188 @@
+//! A lock-free sorted linked list (a set), using Harris's algorithm.
+//!
+//! Removing a node happens in two steps.  First the node is logically
+//! deleted by setting the tag bit on its own `next` pointer; once that's
+//! done nobody can link a new node after it.  Then it's physically unlinked
+//! by swinging its predecessor past it.  Any thread that runs into a marked
+//! node while searching will try to finish the unlinking, and whichever
+//! thread succeeds hands the node to the epoch collector.
+
+use crossbeam::epoch::{self, pin, Atomic, Guard, Owned, Shared};
+use std::sync::atomic::Ordering;
+
+// The tag on a node's `next` pointer that means "this node is deleted".
+const DELETED: usize = 1;
+
+struct Node<K> {
+    key: K,
+    next: Atomic<Node<K>>,
+}
+
+/// A lock-free sorted set, with removed nodes reclaimed by `crossbeam::epoch`.
+///
+/// `remove` hands the unlinked node to the collector, which drops its key
+/// whenever and wherever it gets around to it, so keys have to be
+/// `Send + 'static`.
+pub struct HarrisList<K> {
+    head: Atomic<Node<K>>,
+}
+
+impl<K: Ord + Send + 'static> Default for HarrisList<K> {
+    fn default() -> Self {
+        HarrisList::new()
+    }
+}
+
+impl<K: Ord + Send + 'static> HarrisList<K> {
+    pub fn new() -> HarrisList<K> {
+        HarrisList {
+            head: Atomic::null(),
+        }
+    }
+
+    // Find the first node whose key is >= `key`, and the link pointing to it.
+    // Any deleted nodes we walk past are unlinked along the way.
+    fn find<'g>(
+        &'g self,
+        key: &K,
+        guard: &'g Guard,
+    ) -> (&'g Atomic<Node<K>>, Shared<'g, Node<K>>) {
+        'retry: loop {
+            let mut prev = &self.head;
+            let mut curr = prev.load(Ordering::SeqCst, guard);
+
+            loop {
+                let c = match unsafe{curr.as_ref()} {
+                    Some(c) => c,
+                    None => return (prev, curr),
+                };
+                let next = c.next.load(Ordering::SeqCst, guard);
+
+                if next.tag() == DELETED {
+                    // `curr` is logically deleted.  Try to unlink it; if `prev`
+                    // changed under us, start over from the head.
+                    let next = next.with_tag(0);
+                    match prev.compare_and_set(curr, next, Ordering::SeqCst, guard) {
+                        Ok(_) => {
+                            unsafe {
+                                guard.defer_destroy(curr);
+                            }
+                            curr = next;
+                            continue;
+                        }
+                        Err(_) => continue 'retry,
+                    }
+                }
+
+                if c.key >= *key {
+                    return (prev, curr);
+                }
+                prev = &c.next;
+                curr = next;
+            }
+        }
+    }
+
+    /// Add `key` to the set.  Returns `false` if it was already present.
+    pub fn insert(&self, key: K) -> bool {
+        let guard = &pin();
+        let mut node = Owned::new(Node {
+            key,
+            next: Atomic::null(),
+        });
+
+        loop {
+            let (prev, curr) = self.find(&node.key, guard);
+            if let Some(c) = unsafe{curr.as_ref()} {
+                if c.key == node.key {
+                    return false;
+                }
+            }
+
+            node.next.store(curr, Ordering::Relaxed);
+            match prev.compare_and_set(curr, node, Ordering::SeqCst, guard) {
+                Ok(_) => return true,
+                Err(e) => node = e.new,
+            }
+        }
+    }
+
+    /// Remove `key` from the set.  Returns `false` if it wasn't present.
+    pub fn remove(&self, key: &K) -> bool {
+        let guard = &pin();
+
+        loop {
+            let (prev, curr) = self.find(key, guard);
+            let c = match unsafe{curr.as_ref()} {
+                Some(c) if c.key == *key => c,
+                _ => return false,
+            };
+
+            // Step one: mark the node as deleted.  If its `next` changed, or
+            // someone else marked it first, go around again.
+            let next = c.next.load(Ordering::SeqCst, guard);
+            if next.tag() == DELETED {
+                continue;
+            }
+            if c.next
+                .compare_and_set(next, next.with_tag(DELETED), Ordering::SeqCst, guard)
+                .is_err()
+            {
+                continue;
+            }
+
+            // Step two: unlink it.  If that fails, a search will clean it up.
+            match prev.compare_and_set(curr, next, Ordering::SeqCst, guard) {
+                Ok(_) => unsafe {
+                    guard.defer_destroy(curr);
+                },
+                Err(_) => {
+                    self.find(key, guard);
+                }
+            }
+            return true;
+        }
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        let guard = &pin();
+        let (_, curr) = self.find(key, guard);
+        match unsafe{curr.as_ref()} {
+            Some(c) => c.key == *key,
+            None => false,
+        }
+    }
+
+    /// Copy out every key that isn't deleted, in order, under one guard.
+    pub fn to_vec(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let guard = &pin();
+        let mut keys = Vec::new();
+        let mut curr = self.head.load(Ordering::SeqCst, guard);
+
+        while let Some(c) = unsafe{curr.as_ref()} {
+            let next = c.next.load(Ordering::SeqCst, guard);
+            if next.tag() != DELETED {
+                keys.push(c.key.clone());
+            }
+            curr = next.with_tag(0);
+        }
+        keys
+    }
+}
+
+impl<K> Drop for HarrisList<K> {
+    fn drop(&mut self) {
+        // Every node still linked into the list gets freed now, marked or not.
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut node = self.head.load(Ordering::Relaxed, guard);
+            while !node.is_null() {
+                let owned = node.into_owned();
+                node = owned.next.load(Ordering::Relaxed, guard).with_tag(0);
+            }
+        }
+    }
+}
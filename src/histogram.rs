@@ -0,0 +1,203 @@
+//! A small fixed-size latency histogram.
+//!
+//! Values are bucketed log-linearly: each power of two is split into
+//! `SUB_BUCKETS` equal pieces, so any recorded value is reported to within
+//! about 6% no matter how large it is.  That's plenty for telling 50ns from
+//! 50µs, and merging two histograms is just adding up their buckets.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const SUB_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+/// Counts of `u64` values (usually nanoseconds).
+#[derive(Clone)]
+pub struct Histogram {
+    counts: Box<[u64; BUCKETS]>,
+    total: u64,
+    sum: u128,
+    max: u64,
+}
+
+fn bucket(v: u64) -> usize {
+    if v < SUB_BUCKETS as u64 {
+        return v as usize;
+    }
+    // Keep the top SUB_BITS + 1 bits.
+    let shift = 63 - v.leading_zeros() - SUB_BITS;
+    let top = (v >> shift) as usize;
+    (shift as usize + 1) * SUB_BUCKETS + (top - SUB_BUCKETS)
+}
+
+// The largest value that lands in bucket `b`.
+fn upper_bound(b: usize) -> u64 {
+    if b < SUB_BUCKETS {
+        return b as u64;
+    }
+    let shift = (b / SUB_BUCKETS - 1) as u32;
+    let top = (SUB_BUCKETS + b % SUB_BUCKETS) as u64;
+    ((top + 1) << shift).wrapping_sub(1)
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram {
+            counts: Box::new([0; BUCKETS]),
+            total: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+
+    pub fn record(&mut self, v: u64) {
+        self.counts[bucket(v)] += 1;
+        self.total += 1;
+        self.sum += u128::from(v);
+        self.max = self.max.max(v);
+    }
+
+    /// Record a duration, in nanoseconds.
+    pub fn record_duration(&mut self, d: Duration) {
+        self.record(d.as_nanos().min(u128::from(u64::MAX)) as u64);
+    }
+
+    /// Add everything recorded in `other` to this histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c += o;
+        }
+        self.total += other.total;
+        self.sum += other.sum;
+        self.max = self.max.max(other.max);
+    }
+
+    /// The number of recorded values.
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.total as f64
+    }
+
+    /// The value below which a fraction `q` (0.0 to 1.0) of the recorded
+    /// values fall, rounded up to the edge of its bucket.  Zero if nothing
+    /// was recorded.
+    pub fn quantile(&self, q: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((q * self.total as f64).ceil() as u64).clamp(1, self.total);
+        let mut seen = 0;
+        for (b, &c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                return upper_bound(b).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// A `Histogram` that any number of threads can record into at once,
+/// without locking.  Take a `snapshot` to read it.
+pub struct AtomicHistogram {
+    counts: [AtomicU64; BUCKETS],
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl AtomicHistogram {
+    pub const fn new() -> AtomicHistogram {
+        AtomicHistogram {
+            counts: [const { AtomicU64::new(0) }; BUCKETS],
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, v: u64) {
+        self.counts[bucket(v)].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(v, Ordering::Relaxed);
+        self.max.fetch_max(v, Ordering::Relaxed);
+    }
+
+    /// Record a duration, in nanoseconds.
+    pub fn record_duration(&self, d: Duration) {
+        self.record(d.as_nanos().min(u128::from(u64::MAX)) as u64);
+    }
+
+    /// Copy out everything recorded so far.  Values recorded while this
+    /// runs may or may not be included.
+    pub fn snapshot(&self) -> Histogram {
+        let mut h = Histogram::new();
+        for (c, a) in h.counts.iter_mut().zip(self.counts.iter()) {
+            *c = a.load(Ordering::Relaxed);
+        }
+        h.total = h.counts.iter().sum();
+        h.sum = u128::from(self.sum.load(Ordering::Relaxed));
+        h.max = self.max.load(Ordering::Relaxed);
+        h
+    }
+
+    /// Forget everything recorded so far.
+    pub fn reset(&self) {
+        for c in self.counts.iter() {
+            c.store(0, Ordering::Relaxed);
+        }
+        self.sum.store(0, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for AtomicHistogram {
+    fn default() -> Self {
+        AtomicHistogram::new()
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+impl fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Histogram")
+            .field("count", &self.total)
+            .field("mean", &self.mean())
+            .field("max", &self.max)
+            .finish()
+    }
+}
+
+/// Prints the count and a few percentiles, treating values as nanoseconds.
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ns = |v: u64| Duration::from_nanos(v);
+        write!(
+            f,
+            "n={} p50 {:?}, p99 {:?}, p99.9 {:?}, max {:?}",
+            self.total,
+            ns(self.quantile(0.5)),
+            ns(self.quantile(0.99)),
+            ns(self.quantile(0.999)),
+            ns(self.max)
+        )
+    }
+}
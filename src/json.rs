@@ -0,0 +1,288 @@
+//! Just enough JSON for the results export and cage snapshots, since
+//! serde isn't available here.
+
+use crate::histogram::Histogram;
+use std::fmt::Write;
+
+/// Something that can be written out as a JSON value.
+pub(crate) trait ToJson {
+    fn write_json(&self, out: &mut String);
+}
+
+/// Builds a JSON object one field at a time.
+pub(crate) struct Object {
+    buf: String,
+    empty: bool,
+}
+
+impl Object {
+    pub(crate) fn new() -> Object {
+        Object {
+            buf: String::from("{"),
+            empty: true,
+        }
+    }
+
+    pub(crate) fn field<V: ToJson + ?Sized>(&mut self, key: &str, value: &V) -> &mut Object {
+        if !self.empty {
+            self.buf.push(',');
+        }
+        self.empty = false;
+        key.write_json(&mut self.buf);
+        self.buf.push(':');
+        value.write_json(&mut self.buf);
+        self
+    }
+
+    pub(crate) fn finish(&mut self) -> String {
+        self.buf.push('}');
+        std::mem::take(&mut self.buf)
+    }
+}
+
+/// An already-serialized value, for nesting objects.
+pub(crate) struct Raw(pub(crate) String);
+
+impl ToJson for Raw {
+    fn write_json(&self, out: &mut String) {
+        out.push_str(&self.0);
+    }
+}
+
+impl ToJson for str {
+    fn write_json(&self, out: &mut String) {
+        out.push('"');
+        for ch in self.chars() {
+            match ch {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if (c as u32) < 0x20 => {
+                    let _ = write!(out, "\\u{:04x}", c as u32);
+                }
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+}
+
+impl ToJson for String {
+    fn write_json(&self, out: &mut String) {
+        self.as_str().write_json(out);
+    }
+}
+
+impl ToJson for bool {
+    fn write_json(&self, out: &mut String) {
+        out.push_str(if *self { "true" } else { "false" });
+    }
+}
+
+macro_rules! json_integer {
+    ($($t:ty),*) => {
+        $(impl ToJson for $t {
+            fn write_json(&self, out: &mut String) {
+                let _ = write!(out, "{}", self);
+            }
+        })*
+    };
+}
+
+json_integer!(u64, usize, u128);
+
+impl ToJson for f64 {
+    fn write_json(&self, out: &mut String) {
+        // JSON has no NaN or infinity.
+        if self.is_finite() {
+            let _ = write!(out, "{}", self);
+        } else {
+            out.push_str("null");
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Some(v) => v.write_json(out),
+            None => out.push_str("null"),
+        }
+    }
+}
+
+/// A summary rather than every bucket: the count, mean, a few percentiles
+/// and the max.
+impl ToJson for Histogram {
+    fn write_json(&self, out: &mut String) {
+        let summary = Object::new()
+            .field("count", &self.count())
+            .field("mean", &self.mean())
+            .field("p50", &self.quantile(0.5))
+            .field("p90", &self.quantile(0.9))
+            .field("p99", &self.quantile(0.99))
+            .field("p999", &self.quantile(0.999))
+            .field("max", &self.max())
+            .finish();
+        out.push_str(&summary);
+    }
+}
+
+/// A parsed JSON value.  Numbers are kept as `f64`, as JavaScript would.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The value of `key`, if this is an object that has one.
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a whole JSON document.
+pub(crate) fn parse(text: &str) -> Result<Value, String> {
+    let mut p = Parser {
+        text,
+        chars: text.char_indices().peekable(),
+    };
+    let value = p.value()?;
+    p.skip_whitespace();
+    match p.chars.next() {
+        None => Ok(value),
+        Some((at, _)) => Err(format!("unexpected {:?} at byte {}", &text[at..], at)),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, want: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some((_, c)) if c == want => Ok(()),
+            Some((at, c)) => Err(format!("expected {:?} at byte {}, found {:?}", want, at, c)),
+            None => Err(format!("expected {:?}, found the end", want)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        let (at, c) = *self.chars.peek().ok_or("expected a value, found the end")?;
+        match c {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => self.string().map(Value::String),
+            't' => self.word("true", Value::Bool(true)),
+            'f' => self.word("false", Value::Bool(false)),
+            'n' => self.word("null", Value::Null),
+            '-' | '0'..='9' => self.number(),
+            _ => Err(format!("unexpected {:?} at byte {}", c, at)),
+        }
+    }
+
+    fn word(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for want in word.chars() {
+            self.expect(want)?;
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.chars.peek().map_or(self.text.len(), |&(at, _)| at);
+        while self
+            .chars
+            .next_if(|(_, c)| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+            .is_some()
+        {}
+        let end = self.chars.peek().map_or(self.text.len(), |&(at, _)| at);
+        let number = &self.text[start..end];
+        number
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| format!("bad number {:?} at byte {}", number, start))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next().ok_or("unterminated string")? {
+                (_, '"') => return Ok(s),
+                (_, '\\') => match self.chars.next().ok_or("unterminated string")? {
+                    (_, '"') => s.push('"'),
+                    (_, '\\') => s.push('\\'),
+                    (_, '/') => s.push('/'),
+                    (_, 'b') => s.push('\u{8}'),
+                    (_, 'f') => s.push('\u{c}'),
+                    (_, 'n') => s.push('\n'),
+                    (_, 'r') => s.push('\r'),
+                    (_, 't') => s.push('\t'),
+                    (at, 'u') => {
+                        let hex: String = (0..4).filter_map(|_| self.chars.next()).map(|(_, c)| c).collect();
+                        // Surrogate pairs aren't worth it here.
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        s.push(c.ok_or_else(|| format!("bad escape at byte {}", at))?);
+                    }
+                    (at, c) => return Err(format!("bad escape {:?} at byte {}", c, at)),
+                },
+                (_, c) => s.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|&(_, c)| c == ']').is_some() {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            if self.chars.next_if(|&(_, c)| c == ']').is_some() {
+                return Ok(Value::Array(items));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|&(_, c)| c == '}').is_some() {
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.chars.next_if(|&(_, c)| c == '}').is_some() {
+                return Ok(Value::Object(fields));
+            }
+            self.expect(',')?;
+        }
+    }
+}
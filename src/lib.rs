@@ -0,0 +1,94 @@
+//! A playground for learning how `crossbeam::epoch` manages memory.
+//!
+//! The interesting parts live in [`BirdCage`], a fixed-size array of
+//! epoch-managed slots, and [`Canary`], an object that announces its own
+//! destruction so we can watch the deferred work happen.
+//!
+//! Each demo is its own binary, sharing this library's instrumentation:
+//! `cargo run --bin birdcage` (or `stack`, `queue`, `list`, `deque`,
+//! `cache`, `rcu`, `tasks`, ...) runs one data structure with canaries in
+//! it, `stress` runs for hours checking invariants, and `bench` compares
+//! the cages and reclaimers.  The default binary, `epoch_playground`, is the
+//! birdcage with every knob on the command line.
+//!
+//! Without the default `std` feature, the crate is `no_std` and only the
+//! core structures are left: `TreiberStack`, `MsQueue` and
+//! `PrivateBirdCage`.  There's no default collector then, so they take
+//! guards from a `Collector` you make yourself.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// Everything in here needs std.
+macro_rules! std_only {
+    ($($item:item)*) => {
+        $(
+            #[cfg(feature = "std")]
+            $item
+        )*
+    };
+}
+
+mod cage;
+pub mod ms_queue;
+mod private_cage;
+pub mod treiber_stack;
+
+std_only! {
+    pub mod affinity;
+    mod arc_cage;
+    mod birdcage;
+    pub mod bucket_map;
+    mod canary;
+    pub mod chaos;
+    pub mod chase_lev;
+    pub mod cli;
+    pub mod clock_cache;
+    pub mod counting_alloc;
+    pub mod doubly_linked_list;
+    pub mod drop_tracker;
+    pub mod events;
+    pub mod explain;
+    #[cfg(feature = "ffi")]
+    pub mod ffi;
+    mod flush_policy;
+    pub mod fuzz_ops;
+    pub mod guard_watch;
+    mod growable_cage;
+    pub mod harris_list;
+    pub mod histogram;
+    mod json;
+    mod lock_cage;
+    pub mod malloc_stats;
+    mod memory_order;
+    pub mod metrics;
+    pub mod numa;
+    pub mod observer;
+    pub mod probe;
+    pub mod reclaim;
+    pub mod repl;
+    pub mod scenario;
+    mod sharded_cage;
+    pub mod skiplist;
+    pub mod slab;
+    mod slots;
+    pub mod stall;
+    pub mod stress;
+    pub mod trace;
+    pub mod tui;
+    pub mod workload;
+    pub mod yield_points;
+
+    pub use arc_cage::ArcCage;
+    pub use birdcage::{BirdCage, CasOutcome, Drain, Iter, SlotId, Taken};
+    pub use canary::{Canary, ReclaimObserver};
+    pub use flush_policy::FlushPolicy;
+    pub use growable_cage::GrowableBirdCage;
+    pub use lock_cage::LockCage;
+    pub use memory_order::MemoryOrder;
+    pub use sharded_cage::{ShardHandle, ShardStats, ShardedBirdCage};
+}
+
+pub use cage::{Cage, CageError, Contention};
+pub use private_cage::PrivateBirdCage;
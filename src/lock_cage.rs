@@ -0,0 +1,78 @@
+//! The trivially-correct baseline: every slot is a `RwLock`.
+//!
+//! Replaced values are dropped on the spot, since the write lock guarantees
+//! nobody is reading them.  This is what every other cage is trying to beat
+//! on performance, and what they should agree with on behavior.
+
+use crate::cage::{Cage, CageError};
+use std::sync::RwLock;
+
+/// A fixed-size collection of slots, each protected by a `RwLock`.
+pub struct LockCage<T> {
+    c: Vec<RwLock<T>>,
+}
+
+impl<T> LockCage<T> {
+    /// Create a cage with `size` slots, filling slot `n` with `f(n)`.
+    pub fn from_fn<F>(size: usize, mut f: F) -> LockCage<T>
+    where
+        F: FnMut(usize) -> T,
+    {
+        LockCage {
+            c: (0..size).map(|ii| RwLock::new(f(ii))).collect(),
+        }
+    }
+
+    /// The number of slots in the cage.
+    pub fn len(&self) -> usize {
+        self.c.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.c.is_empty()
+    }
+
+    // Slot `n`, if there is one.  Slots are never empty.
+    fn slot(&self, n: usize) -> Result<&RwLock<T>, CageError> {
+        self.c.get(n).ok_or(CageError::IndexOutOfBounds {
+            index: n,
+            len: self.c.len(),
+        })
+    }
+
+    /// Hand the value in slot `n` to `f`, returning whatever `f` returns.
+    pub fn with_slot<F, R>(&self, n: usize, f: F) -> Result<R, CageError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        Ok(f(&self.slot(n)?.read().unwrap()))
+    }
+
+    /// Put `new_c` into slot `n`, returning the old value.
+    pub fn swap(&self, n: usize, new_c: T) -> Result<T, CageError> {
+        Ok(std::mem::replace(&mut *self.slot(n)?.write().unwrap(), new_c))
+    }
+}
+
+impl<T: Send + Sync> Cage<T> for LockCage<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn with_slot<F, R>(&self, n: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.with_slot(n, f).ok()
+    }
+
+    // The trait can't report a bad slot, so this panics on one, like
+    // indexing a slice would.
+    fn put_with<F>(&self, n: usize, value: T, removed: F)
+    where
+        F: FnOnce(&T),
+    {
+        let old = self.swap(n, value).unwrap_or_else(|e| panic!("{}", e));
+        removed(&old);
+    }
+}
@@ -1,130 +1,499 @@
-use crossbeam::epoch::{pin, Atomic, Owned};
-use std::sync::atomic::Ordering;
-use std::sync::Arc;
-use std::thread;
+use epoch_playground::cli::{Args, Mode, OutputFormat, USAGE};
+use epoch_playground::counting_alloc::{self, AllocStats, CountingAlloc};
+use epoch_playground::explain;
+use epoch_playground::malloc_stats;
+use epoch_playground::numa;
+use epoch_playground::observer::Observer;
+use epoch_playground::probe;
+use epoch_playground::reclaim::{self, Epoch};
+use epoch_playground::repl;
+use epoch_playground::scenario::{self, Scenario};
+use epoch_playground::stall;
+use epoch_playground::stress::{self, ReclaimerKind};
+use epoch_playground::trace::{self, Trace};
+use epoch_playground::tui;
+use epoch_playground::workload::{self, ThreadRng};
+use epoch_playground::{BirdCage, Canary, PrivateBirdCage};
 use rand::Rng;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
-struct Canary {
-    name: String,
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+// How long each demo thread keeps going: a fixed number of iterations, or
+// until the main thread flips the stop flag.
+#[derive(Clone)]
+struct Limit {
+    iterations: Option<usize>,
+    stop: Arc<AtomicBool>,
 }
 
-impl Canary {
-    fn new(name: &str) -> Canary {
-        Canary {
-            name: name.to_owned(),
+impl Limit {
+    fn new(args: &Args) -> Limit {
+        Limit {
+            iterations: match args.duration {
+                Some(_) => None,
+                None => Some(args.iterations),
+            },
+            stop: Arc::new(AtomicBool::new(false)),
         }
     }
-}
 
-impl Drop for Canary {
-    fn drop(&mut self) {
-        println!("{}: dropped", self.name);
+    fn keep_going(&self, n: usize) -> bool {
+        !self.stop.load(Ordering::Relaxed) && self.iterations.is_none_or(|i| n < i)
     }
 }
 
-struct BirdCage {
-    c: Vec<Atomic<Canary>>,
+// What a demo run did, for the final report.
+struct Summary {
+    ops: usize,
+    elapsed: Duration,
+    created: usize,
+    dropped: usize,
+    memory: Option<AllocStats>,
 }
 
-impl BirdCage {
-    fn new(size: usize) -> BirdCage {
-        let mut bc = BirdCage {
-            c: Vec::with_capacity(size),
-        };
-        for ii in 0..size {
-            let name = format!("Canary {}", ii);
-            bc.c.push(Atomic::new(Canary::new(&name)));
+impl Summary {
+    fn print(&self) {
+        let secs = self.elapsed.as_secs_f64();
+        println!(
+            "{} ops in {:.2}s ({:.0} ops/sec)",
+            self.ops,
+            secs,
+            self.ops as f64 / secs
+        );
+        println!("canaries created: {}", self.created);
+        println!("canaries dropped: {}", self.dropped);
+        if let Some(m) = self.memory {
+            println!(
+                "memory: peak {} KiB, end {} KiB",
+                m.peak_bytes / 1024,
+                m.live_bytes / 1024
+            );
         }
-        bc
     }
+}
 
-    fn access(&self, n: usize, ctx: &str) {
-        let guard = &pin();
-        let shared = self.c[n].load(Ordering::SeqCst, guard);
-        let c: &Canary = unsafe{shared.as_ref()}.unwrap();
-        println!("[{}] accessing {}", ctx, c.name);
+// Let the workers run until they're done (or until the duration is up),
+// then collect the number of operations they did.
+fn finish(args: &Args, limit: &Limit, start: Instant, handles: Vec<JoinHandle<usize>>) -> usize {
+    if let Some(duration) = args.duration {
+        thread::sleep(duration.saturating_sub(start.elapsed()));
+        limit.stop.store(true, Ordering::Relaxed);
     }
+    handles.into_iter().map(|h| h.join().unwrap()).sum()
+}
 
-    fn replace(&self, n: usize, ctx: &str, new_c: Canary) {
-        println!("[{}] put {} into slot {}", ctx, new_c.name, n);
-
-        let guard = &pin();
-
-        // swap() will only accept a Shared or Owned, so let's make one of those.
-        // There are multiple ways to write this code but Owned seems to signal
-        // my intent (because at this point I'm the sole owner.)
-        let owned_new_c = Owned::new(new_c);
+// Increase the iterations or thread count (see --help) to see how threads
+// interact, and how much deferred work will be buffered before items start
+// getting dropped.
+fn worker(birdcage: &BirdCage<Canary>, id: usize, limit: Limit, mut rng: ThreadRng) -> usize {
+    let bc_size = birdcage.len();
+    let my_name = format!("thread {}", id);
+    let mut n = 0;
 
-        // We are stealing whatever Canary happens to be present in this
-        // location, and substituting a new one.
-        let stolen_c = self.c[n].swap(owned_new_c, Ordering::SeqCst, guard);
-        let c: &Canary = unsafe{stolen_c.as_ref()}.unwrap();
-        println!("[{}] removed {}", ctx, c.name);
+    while limit.keep_going(n) {
+        // read-only access of a random element
+        let pick1 = rng.gen_range(0, bc_size);
+        if let Err(e) = birdcage.access(pick1, &my_name) {
+            println!("[{}] {}", my_name, e);
+        }
 
-        // Now schedule the stolen canary for deallocation.
-        // This is equivalent to defer() with a closure that drops the value.
-        unsafe {
-            guard.defer_destroy(stolen_c);
+        // replace a random element with a new one.
+        let c = Canary::new(&format!("{} Cuckoo {}", my_name, n));
+        let pick2 = rng.gen_range(0, bc_size);
+        if let Err(e) = birdcage.replace(pick2, &my_name, c) {
+            println!("[{}] {}", my_name, e);
         }
+        n += 1;
+    }
+    println!("{} exiting", my_name);
+    n * 2
+}
 
-        // Uncomment this to see the deferred function run sooner.
-        // Otherwise, the default Collector will wait until a bunch of
-        // deferred actions have accumulated (~256 in crossbeam 0.7.3).
+fn demo_main(args: &Args, seed: u64) -> (usize, Duration) {
+    // Increase the cage size to see how much deferred work gets buffered.
+    let birdcage = Arc::new(BirdCage::new(args.cage_size).with_flush_policy(args.flush));
+    let limit = Limit::new(args);
+    let mut thread_handles = Vec::new();
 
-        //guard.flush();
+    let start = Instant::now();
+    for thread_id in 0..args.threads {
+        let local_id = thread_id;
+        let local_birdcage = birdcage.clone();
+        let local_limit = limit.clone();
+        let rng = workload::thread_rng(seed, thread_id as u64);
+        let handle = thread::spawn(move ||
+            worker(local_birdcage.as_ref(), local_id, local_limit, rng)
+        );
+        thread_handles.push(handle);
     }
-}
 
-// Increase these numbers to see how threads interact, and how much
-// deferred work will be buffered before items start getting dropped.
-const ITERATIONS: usize = 100;
-const BIRDCAGE_SIZE: usize = 10;
-const NUM_THREADS: usize = 10;
+    let ops = finish(args, &limit, start, thread_handles);
+    (ops, start.elapsed())
+}
 
-fn worker(birdcage: &BirdCage, id: usize) {
-    let bc_size = birdcage.c.len();
+fn private_worker(
+    birdcage: &PrivateBirdCage<Canary>,
+    id: usize,
+    limit: Limit,
+    mut rng: ThreadRng,
+) -> usize {
+    let bc_size = birdcage.len();
     let my_name = format!("thread {}", id);
-    let mut rng = rand::thread_rng();
+    let handle = birdcage.register();
+    let mut n = 0;
 
-    for n in 0..ITERATIONS {
-        // read-only access of a random element
+    while limit.keep_going(n) {
         let pick1 = rng.gen_range(0, bc_size);
-        birdcage.access(pick1, &my_name);
+        if let Err(e) = birdcage.access(&handle, pick1, &my_name) {
+            println!("[{}] {}", my_name, e);
+        }
 
-        // replace a random element with a new one.
         let c = Canary::new(&format!("{} Cuckoo {}", my_name, n));
         let pick2 = rng.gen_range(0, bc_size);
-        birdcage.replace(pick2, &my_name, c);
+        if let Err(e) = birdcage.replace(&handle, pick2, &my_name, c) {
+            println!("[{}] {}", my_name, e);
+        }
+        n += 1;
     }
     println!("{} exiting", my_name);
+    n * 2
 }
 
+fn private_main(args: &Args, seed: u64) -> (usize, Duration) {
+    let birdcage = Arc::new(PrivateBirdCage::new(args.cage_size));
+    let limit = Limit::new(args);
+    let mut thread_handles = Vec::new();
 
-fn main() {
-    // Increase this number to see how much deferred work gets buffered.
-    let birdcage = Arc::new(BirdCage::new(BIRDCAGE_SIZE));
+    let start = Instant::now();
+    for thread_id in 0..args.threads {
+        let local_birdcage = birdcage.clone();
+        let local_limit = limit.clone();
+        let rng = workload::thread_rng(seed, thread_id as u64);
+        let handle = thread::spawn(move ||
+            private_worker(local_birdcage.as_ref(), thread_id, local_limit, rng)
+        );
+        thread_handles.push(handle);
+    }
+
+    let ops = finish(args, &limit, start, thread_handles);
+    let elapsed = start.elapsed();
+
+    // Every thread's LocalHandle is gone, so this drops the collector and
+    // all of its garbage along with the cage.
+    println!("dropping the cage");
+    drop(birdcage);
+    println!("cage dropped");
+    (ops, elapsed)
+}
+
+// Whose turn it is in a step-through run.  The main thread sets it to a
+// thread's id, and that thread sets it back to `None` once it has done
+// one operation.
+#[derive(Default)]
+struct Baton {
+    turn: Mutex<Option<usize>>,
+    changed: Condvar,
+    stop: AtomicBool,
+}
+
+impl Baton {
+    fn set(&self, turn: Option<usize>) {
+        *self.turn.lock().unwrap() = turn;
+        self.changed.notify_all();
+    }
+
+    // Wait until it's `turn`, returning false if the run is stopping.
+    fn wait_for(&self, turn: Option<usize>) -> bool {
+        let mut current = self.turn.lock().unwrap();
+        while *current != turn && !self.stop.load(Ordering::Relaxed) {
+            current = self.changed.wait(current).unwrap();
+        }
+        !self.stop.load(Ordering::Relaxed)
+    }
+
+    fn stop(&self) {
+        let _turn = self.turn.lock().unwrap();
+        self.stop.store(true, Ordering::Relaxed);
+        self.changed.notify_all();
+    }
+}
+
+// The demo's worker, but one operation per turn: an access, then a
+// replace, and so on.
+fn step_worker(birdcage: &BirdCage<Canary>, id: usize, baton: &Baton, mut rng: ThreadRng) {
+    let bc_size = birdcage.len();
+    let my_name = format!("thread {}", id);
+    let mut n = 0;
+
+    while baton.wait_for(Some(id)) {
+        let pick = rng.gen_range(0, bc_size);
+        if n % 2 == 0 {
+            if let Err(e) = birdcage.access(pick, &my_name) {
+                println!("[{}] {}", my_name, e);
+            }
+        } else {
+            let c = Canary::new(&format!("{} Cuckoo {}", my_name, n / 2));
+            if let Err(e) = birdcage.replace(pick, &my_name, c) {
+                println!("[{}] {}", my_name, e);
+            }
+        }
+        n += 1;
+        baton.set(None);
+    }
+}
+
+// Wait before the next step: for `delay`, or until Enter is pressed.
+// Returns false if the user asked to quit.
+fn pause(delay: Option<Duration>, input: &mut impl BufRead) -> bool {
+    if let Some(delay) = delay {
+        thread::sleep(delay);
+        return true;
+    }
+    println!("-- Enter for the next step, q to quit --");
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        // Without a terminal there's nobody to wait for, so run to the end.
+        Ok(0) | Err(_) => true,
+        Ok(_) => line.trim() != "q",
+    }
+}
+
+// Like the demo, but the threads take turns, one operation at a time, in
+// the same order every run, and the cage is printed after each one.
+fn step_main(args: &Args, seed: u64) -> (usize, Duration) {
+    let birdcage = Arc::new(BirdCage::new(args.cage_size).with_flush_policy(args.flush));
+    let baton = Arc::new(Baton::default());
     let mut thread_handles = Vec::new();
 
-    for thread_id in 0..NUM_THREADS {
-        let local_id = thread_id;
+    let start = Instant::now();
+    for thread_id in 0..args.threads {
         let local_birdcage = birdcage.clone();
+        let local_baton = baton.clone();
+        let rng = workload::thread_rng(seed, thread_id as u64);
         let handle = thread::spawn(move ||
-            worker(local_birdcage.as_ref(), local_id)
+            step_worker(local_birdcage.as_ref(), thread_id, &local_baton, rng)
         );
         thread_handles.push(handle);
     }
 
-    for handle in thread_handles {
-        handle.join().unwrap();
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut ops = 0;
+    for step in 0..args.threads * args.iterations * 2 {
+        println!("step {}:", step + 1);
+        baton.set(Some(step % args.threads));
+        baton.wait_for(None);
+        ops += 1;
+        birdcage.dump();
+        if !pause(args.step_delay, &mut input) {
+            break;
+        }
+    }
+    baton.stop();
+    for h in thread_handles {
+        h.join().unwrap();
+    }
+    (ops, start.elapsed())
+}
+
+fn stop_observer(observer: Option<Observer>) {
+    if let Some(observer) = observer {
+        let seen = observer.stop();
+        let max_lag = seen.iter().map(|o| o.lag()).max().unwrap_or(0);
+        eprintln!("observer: {} probes, max lag {}", seen.len(), max_lag);
+    }
+}
+
+fn exit_on_error(path: &Path, result: io::Result<()>) {
+    if let Err(e) = result {
+        eprintln!("can't write {}: {}", path.display(), e);
+        process::exit(1);
     }
+}
 
-    // This seems pretty hacky.  To force any deferred work to run, we need the epoch
-    // to move forward two times.  The magic number two is due to the inner workings
-    // of the global epoch counter.
-    // I wish there was a way to say "destroy all the remaining garbage from _this_
-    // data structure," but the epoch counter, Collector, and deferred work are
-    // global, not per data structure.
-    pin().flush();
-    pin().flush();
+// Report whether every canary ever created has been dropped, and exit with
+// an error if not and --check-leaks was given.
+fn check_leaks(args: &Args) {
+    let created = Canary::created();
+    let alive = Canary::alive();
+    if alive == 0 {
+        if args.output == OutputFormat::Text {
+            println!("all {} canaries dropped", created);
+        }
+        return;
+    }
+    eprintln!("leak: {} of {} canaries were never dropped", alive, created);
+    if args.check_leaks {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    Canary::set_validation(args.validate);
+    Canary::set_payload_bytes(args.payload_bytes);
+    explain::set_enabled(args.explain);
+
+    // Print the seed up front, so a run that crashes can still be replayed.
+    let seed = args.seed.unwrap_or_else(workload::random_seed);
+    match args.mode {
+        Mode::Demo | Mode::Private | Mode::Step => println!("seed: {}", seed),
+        Mode::Stress
+        | Mode::Stall
+        | Mode::Tui
+        | Mode::Repl
+        | Mode::Replay
+        | Mode::Scenario
+        | Mode::Numa
+        | Mode::Probe
+        | Mode::Help => {}
+    }
+
+    // The demo's cage always uses epochs.  The private cage has its own
+    // collector, which the observer can't see.
+    let observed = match args.mode {
+        Mode::Stress | Mode::Stall | Mode::Tui | Mode::Replay => args.reclaimer,
+        _ => ReclaimerKind::Epoch,
+    };
+    let observer = args.observe.map(|every| Observer::start_kind(observed, every, true));
+    // Stops, and prints a last reading, whichever way main returns.
+    let _malloc_stats = args.malloc_stats.map(malloc_stats::Reporter::start);
+
+    counting_alloc::reset_peak();
+    let created_before = Canary::created();
+    let dropped_before = Canary::dropped();
+    let (ops, elapsed) = match args.mode {
+        Mode::Demo => demo_main(&args, seed),
+        Mode::Private => private_main(&args, seed),
+        Mode::Step => step_main(&args, seed),
+        Mode::Stress => {
+            let config = stress::StressConfig {
+                seed: Some(seed),
+                ..args.stress_config()
+            };
+            let report = stress::run(&config);
+            match args.output {
+                OutputFormat::Text => println!("{}", report),
+                OutputFormat::Json => println!("{}", report.to_json()),
+            }
+            if let Some(path) = &args.csv {
+                exit_on_error(path, report.append_csv(path));
+            }
+            if let Some(path) = &args.timeline {
+                exit_on_error(path, report.write_timeline(path));
+            }
+            if let (Some(path), Some(trace)) = (&args.trace, &report.trace) {
+                exit_on_error(path, trace.save(path));
+            }
+            stop_observer(observer);
+            check_leaks(&args);
+            return;
+        }
+        Mode::Stall => {
+            let config = stall::StallConfig {
+                seed: Some(seed),
+                ..args.stall_config()
+            };
+            println!("{}", stall::run(&config));
+            stop_observer(observer);
+            check_leaks(&args);
+            return;
+        }
+        Mode::Tui => {
+            let config = tui::TuiConfig {
+                seed: Some(seed),
+                ..args.tui_config()
+            };
+            tui::run(&config);
+            stop_observer(observer);
+            check_leaks(&args);
+            return;
+        }
+        Mode::Repl => {
+            repl::run(args.cage_size, io::stdin().lock());
+            reclaim::force_reclaim::<Epoch>();
+            stop_observer(observer);
+            check_leaks(&args);
+            return;
+        }
+        Mode::Replay => {
+            // Args::parse makes sure there's a trace to replay.
+            let path = args.trace.as_ref().unwrap();
+            let trace = Trace::load(path).unwrap_or_else(|e| {
+                eprintln!("can't read {}: {}", path.display(), e);
+                process::exit(1);
+            });
+            println!("{}", trace::replay(&trace, args.reclaimer));
+            stop_observer(observer);
+            check_leaks(&args);
+            return;
+        }
+        Mode::Scenario => {
+            // Args::parse only picks this mode along with a file.
+            let path = args.scenario.as_ref().unwrap();
+            let loaded = Scenario::load(path).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(2);
+            });
+            let config = Scenario {
+                seed: args.seed.or(loaded.seed),
+                duration: args.duration.unwrap_or(loaded.duration),
+                ..loaded
+            };
+            let report = scenario::run(&config);
+            println!("{}", report);
+            if let Some(path) = &args.timeline {
+                exit_on_error(path, report.write_timeline(path));
+            }
+            stop_observer(observer);
+            check_leaks(&args);
+            return;
+        }
+        Mode::Numa => {
+            println!("{}", numa::run(&args.numa_config()));
+            stop_observer(observer);
+            check_leaks(&args);
+            return;
+        }
+        Mode::Probe => {
+            println!("{}", probe::run(probe::DEFAULT_DEFERRALS));
+            stop_observer(observer);
+            return;
+        }
+        Mode::Help => {
+            print!("{}", USAGE);
+            return;
+        }
+    };
+
+    // There's no way to say "destroy all the remaining garbage from _this_
+    // data structure," because the epoch counter, Collector, and deferred
+    // work are global, not per data structure.  So drain all of it.
+    reclaim::force_reclaim::<Epoch>();
+
+    Summary {
+        ops,
+        elapsed,
+        created: Canary::created() - created_before,
+        dropped: Canary::dropped() - dropped_before,
+        memory: counting_alloc::stats(),
+    }
+    .print();
+    stop_observer(observer);
+    check_leaks(&args);
 }
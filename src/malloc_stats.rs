@@ -0,0 +1,130 @@
+//! The allocator's own view of the heap, printed every so often during a
+//! long run, to see whether deferred frees leave it fragmented.
+//!
+//! `CountingAlloc` counts the bytes the program asked for; this asks
+//! glibc's malloc how much it's holding on to for them, through
+//! `mallinfo2`, and the kernel how much of that is resident.  jemalloc
+//! would say more, but it isn't available here.  Off glibc, `stats` is
+//! always `None`.
+//!
+//! Garbage that's freed in bursts, when an epoch finally advances, tends to
+//! leave holes that malloc keeps for later rather than giving back, so
+//! fragmentation climbs after a stall even once the garbage is gone.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// One reading of malloc's statistics, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MallocStats {
+    /// In use by the program.
+    pub allocated: usize,
+    /// Free, but kept by malloc for reuse.
+    pub free: usize,
+    /// Obtained from the system, in the heap and in separately mapped
+    /// chunks; the sum of the other two plus malloc's own overhead.
+    pub mapped: usize,
+    /// Mapped memory that's actually in RAM, for the whole process.
+    pub resident: usize,
+}
+
+impl MallocStats {
+    /// The fraction of malloc's memory that's free rather than in use.
+    pub fn fragmentation(&self) -> f64 {
+        let held = self.allocated + self.free;
+        if held == 0 {
+            0.0
+        } else {
+            self.free as f64 / held as f64
+        }
+    }
+}
+
+impl fmt::Display for MallocStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "allocated {} KiB, free {} KiB, mapped {} KiB, resident {} KiB, fragmentation {:.1}%",
+            self.allocated / 1024,
+            self.free / 1024,
+            self.mapped / 1024,
+            self.resident / 1024,
+            self.fragmentation() * 100.0
+        )
+    }
+}
+
+/// Ask malloc how things stand, if it's glibc's.
+pub fn stats() -> Option<MallocStats> {
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    {
+        let info = unsafe{libc::mallinfo2()};
+        Some(MallocStats {
+            allocated: info.uordblks + info.hblkhd,
+            free: info.fordblks,
+            mapped: info.arena + info.hblkhd,
+            resident: resident().unwrap_or(0),
+        })
+    }
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    {
+        None
+    }
+}
+
+// The process's resident set size, from /proc.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn resident() -> Option<usize> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: usize = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe{libc::sysconf(libc::_SC_PAGESIZE)};
+    Some(pages * page_size.max(0) as usize)
+}
+
+/// A thread that prints `stats` to stderr every so often, until it's
+/// dropped.
+pub struct Reporter {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+    /// Start printing every `interval`.  If malloc can't say, this prints
+    /// that once and does nothing else.
+    pub fn start(interval: Duration) -> Reporter {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = if stats().is_none() {
+            eprintln!("malloc: no statistics from this allocator");
+            None
+        } else {
+            let stop = stop.clone();
+            Some(thread::spawn(move || report(interval, &stop)))
+        };
+        Reporter { stop, handle }
+    }
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+            if let Some(s) = stats() {
+                eprintln!("malloc at exit: {}", s);
+            }
+        }
+    }
+}
+
+fn report(interval: Duration, stop: &AtomicBool) {
+    let start = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(interval);
+        if let Some(s) = stats() {
+            eprintln!("malloc {:>8.1}ms: {}", start.elapsed().as_secs_f64() * 1000.0, s);
+        }
+    }
+}
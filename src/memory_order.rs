@@ -0,0 +1,92 @@
+//! Which atomic orderings a `BirdCage` uses on its slots.
+//!
+//! Everything used to be `SeqCst`, which is the easy way to be sure, but
+//! nothing about a slot needs a single total order: a writer has to publish
+//! a new value before anyone can see its pointer (`Release`), and whoever
+//! loads a pointer, or swaps one out to retire it, has to see the value it
+//! points to (`Acquire`).  `AcqRel` is that, and nothing more.
+//!
+//! What the reclaimers do with their own bookkeeping is up to them.
+//! Epochs and QSBR load slots with whatever ordering they're given, since
+//! keeping the value alive is up to the pin, not the load.  Hazard
+//! pointers protect with `SeqCst` loads whatever they're given, because
+//! publishing a hazard and then checking the slot again is a store followed
+//! by a load, and only `SeqCst` keeps those in order.  The retiring side
+//! has the same problem the other way round (unlink, then load the
+//! hazards), but the slot swaps still take this ordering: the hazard scan
+//! starts with a `SeqCst` fence instead, so an `AcqRel` unlink can't slip
+//! past it.
+//!
+//! Don't expect much difference on x86, where every load is already an
+//! acquire and every swap is already `SeqCst`; `cargo bench -- acqrel`
+//! shows what it's worth on the machine at hand.  There are no loom tests
+//! for the weaker orderings, since loom isn't available here, so the
+//! argument above is the whole case for them.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+
+/// How strongly a `BirdCage` orders its slot operations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryOrder {
+    /// `SeqCst` everywhere.  This is the default.
+    #[default]
+    SeqCst,
+    /// `Acquire` loads, `AcqRel` swaps and compare-and-swaps, and `Relaxed`
+    /// where a pointer is only compared and never followed.
+    AcqRel,
+}
+
+impl MemoryOrder {
+    /// For loading a pointer that's going to be followed.
+    pub fn load(self) -> Ordering {
+        match self {
+            MemoryOrder::SeqCst => Ordering::SeqCst,
+            MemoryOrder::AcqRel => Ordering::Acquire,
+        }
+    }
+
+    /// For loading a pointer that's only going to be compared, as a
+    /// `SlotId`.
+    pub fn peek(self) -> Ordering {
+        match self {
+            MemoryOrder::SeqCst => Ordering::SeqCst,
+            MemoryOrder::AcqRel => Ordering::Relaxed,
+        }
+    }
+
+    /// For swaps, and compare-and-swaps that succeed.
+    pub fn swap(self) -> Ordering {
+        match self {
+            MemoryOrder::SeqCst => Ordering::SeqCst,
+            MemoryOrder::AcqRel => Ordering::AcqRel,
+        }
+    }
+
+    /// For compare-and-swaps that fail, which are only loads.
+    pub fn failure(self) -> Ordering {
+        self.load()
+    }
+}
+
+impl fmt::Display for MemoryOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryOrder::SeqCst => write!(f, "seqcst"),
+            MemoryOrder::AcqRel => write!(f, "acqrel"),
+        }
+    }
+}
+
+impl FromStr for MemoryOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "seqcst" => Ok(MemoryOrder::SeqCst),
+            "acqrel" => Ok(MemoryOrder::AcqRel),
+            _ => Err(format!("unknown memory order: {:?}", s)),
+        }
+    }
+}
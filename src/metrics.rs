@@ -0,0 +1,174 @@
+//! Prometheus-style metrics for long runs, so they can be graphed while
+//! they go.
+//!
+//! `Metrics::serve` answers every HTTP request with the current values in
+//! Prometheus' text format, which is enough for Prometheus to scrape.
+//! `Metrics::write_textfile` writes the same thing to a file instead, for
+//! node_exporter's textfile collector.
+
+use crate::histogram::AtomicHistogram;
+use crate::reclaim::{self, Reclaimer};
+use crate::Canary;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Counters and gauges for one run.  Share it with an `Arc`.
+///
+/// The canary and pending-garbage numbers are read from their global
+/// counters whenever the metrics are rendered; the rest are fed in by the
+/// run.
+#[derive(Default)]
+pub struct Metrics {
+    ops: AtomicU64,
+    probes_issued: AtomicU64,
+    probes_reclaimed: Arc<AtomicU64>,
+    guard_hold: AtomicHistogram,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Count `n` more operations.
+    pub fn add_ops(&self, n: u64) {
+        self.ops.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// The number of operations counted so far.
+    pub fn ops(&self) -> u64 {
+        self.ops.load(Ordering::Relaxed)
+    }
+
+    /// Record how long a guard was held.
+    pub fn record_guard_hold(&self, d: Duration) {
+        self.guard_hold.record_duration(d);
+    }
+
+    /// Retire a probe through `R` and flush.
+    ///
+    /// The epoch itself isn't visible from outside crossbeam, so this is
+    /// how advances get counted: a probe can only be destroyed once the
+    /// epoch has moved on, so calling this regularly and watching
+    /// `probes_reclaimed` keep up with `probes_issued` shows the epoch
+    /// advancing.
+    pub fn probe<R: Reclaimer>(&self) {
+        {
+            let guard = R::pin();
+            let probe = Box::into_raw(Box::new(()));
+            let reclaimed = self.probes_reclaimed.clone();
+            // Nobody else ever sees the probe, so it's trivially unreachable.
+            unsafe {
+                R::retire_with(&guard, probe, move |_| {
+                    reclaimed.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            self.probes_issued.fetch_add(1, Ordering::Relaxed);
+            R::flush(&guard);
+        }
+        R::quiescent();
+    }
+
+    /// The current values, in Prometheus' text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let name = format!("epoch_playground_{}", name);
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        let load = |a: &AtomicU64| a.load(Ordering::Relaxed).to_string();
+
+        metric("ops_total", "counter", "Cage operations completed.", load(&self.ops));
+        metric(
+            "canaries_created_total",
+            "counter",
+            "Canaries created.",
+            Canary::created().to_string(),
+        );
+        metric(
+            "canaries_dropped_total",
+            "counter",
+            "Canaries dropped.",
+            Canary::dropped().to_string(),
+        );
+        metric(
+            "pending_garbage",
+            "gauge",
+            "Values retired whose destruction hasn't run yet.",
+            reclaim::pending().to_string(),
+        );
+        metric(
+            "probes_issued_total",
+            "counter",
+            "Probes retired to watch the epoch advance.",
+            load(&self.probes_issued),
+        );
+        metric(
+            "probes_reclaimed_total",
+            "counter",
+            "Probes destroyed, each one after an epoch advance.",
+            load(&self.probes_reclaimed),
+        );
+
+        // A summary, in seconds.
+        let h = self.guard_hold.snapshot();
+        let name = "epoch_playground_guard_hold_seconds";
+        let secs = |ns: f64| ns / 1e9;
+        let _ = writeln!(out, "# HELP {} How long operations held their guard.", name);
+        let _ = writeln!(out, "# TYPE {} summary", name);
+        for q in &[0.5, 0.99, 0.999] {
+            let v = secs(h.quantile(*q) as f64);
+            let _ = writeln!(out, "{}{{quantile=\"{}\"}} {}", name, q, v);
+        }
+        let _ = writeln!(out, "{}_sum {}", name, secs(h.mean() * h.count() as f64));
+        let _ = writeln!(out, "{}_count {}", name, h.count());
+        out
+    }
+
+    /// Write the current values to `path`.  The file is replaced in one
+    /// step, so a collector never reads half of it.
+    pub fn write_textfile(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.render())?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Answer HTTP requests on `addr` with the current values, from a
+    /// background thread, and return the address actually bound (useful
+    /// with port 0).
+    ///
+    /// Every request gets the metrics, whatever its path.
+    pub fn serve<A: ToSocketAddrs>(self: &Arc<Self>, addr: A) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        let metrics = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                // One bad client shouldn't stop the others.
+                let _ = stream.and_then(|mut s| {
+                    let mut request = [0; 1024];
+                    let _ = s.read(&mut request)?;
+                    let body = metrics.render();
+                    write!(
+                        s,
+                        "HTTP/1.0 200 OK\r\n\
+                         Content-Type: text/plain; version=0.0.4\r\n\
+                         Content-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                });
+            }
+        });
+        Ok(local)
+    }
+}
@@ -0,0 +1,153 @@
+//! A lock-free multi-producer, multi-consumer Michael–Scott queue.
+//!
+//! The queue always has a sentinel node at the head.  When a consumer pops,
+//! the old sentinel is unlinked and the node holding the popped value becomes
+//! the new sentinel.  Another thread may have loaded the old sentinel just
+//! before it was unlinked, and may still be following its `next` pointer,
+//! so unlinked nodes are handed to the epoch collector instead of being
+//! freed on the spot.
+//!
+//! The `_with` methods take a guard, which can come from any `Collector`;
+//! the plain ones pin the default collector, which needs std.
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::Ordering;
+#[cfg(feature = "std")]
+use crossbeam::epoch::pin;
+use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared};
+
+struct Node<T> {
+    // Uninitialized in the sentinel, and moved out when a node is popped.
+    data: MaybeUninit<T>,
+    next: Atomic<Node<T>>,
+}
+
+/// A lock-free FIFO queue, with unlinked nodes reclaimed by `crossbeam::epoch`.
+pub struct MsQueue<T> {
+    head: Atomic<Node<T>>,
+    tail: Atomic<Node<T>>,
+}
+
+impl<T> Default for MsQueue<T> {
+    fn default() -> Self {
+        MsQueue::new()
+    }
+}
+
+impl<T> MsQueue<T> {
+    pub fn new() -> MsQueue<T> {
+        let q = MsQueue {
+            head: Atomic::null(),
+            tail: Atomic::null(),
+        };
+        let sentinel = Owned::new(Node {
+            data: MaybeUninit::uninit(),
+            next: Atomic::null(),
+        });
+        // Nobody else can see the queue yet.
+        unsafe {
+            let guard = epoch::unprotected();
+            let sentinel = sentinel.into_shared(guard);
+            q.head.store(sentinel, Ordering::Relaxed);
+            q.tail.store(sentinel, Ordering::Relaxed);
+        }
+        q
+    }
+
+    #[cfg(feature = "std")]
+    pub fn push(&self, value: T) {
+        self.push_with(value, &pin());
+    }
+
+    #[cfg(feature = "std")]
+    pub fn pop(&self) -> Option<T> {
+        self.pop_with(&pin())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn is_empty(&self) -> bool {
+        self.is_empty_with(&pin())
+    }
+
+    /// Like `push`, under a guard the caller already has.
+    ///
+    /// Every guard used with one queue has to come from the same collector.
+    pub fn push_with(&self, value: T, guard: &Guard) {
+        let new = Owned::new(Node {
+            data: MaybeUninit::new(value),
+            next: Atomic::null(),
+        })
+        .into_shared(guard);
+
+        loop {
+            let tail = self.tail.load(Ordering::SeqCst, guard);
+            // The tail is never null, and never freed while we're pinned.
+            let t = unsafe{tail.deref()};
+            let next = t.next.load(Ordering::SeqCst, guard);
+
+            if !next.is_null() {
+                // The tail is lagging behind; help move it along and retry.
+                let _ = self.tail.compare_and_set(tail, next, Ordering::SeqCst, guard);
+                continue;
+            }
+
+            if t.next
+                .compare_and_set(Shared::null(), new, Ordering::SeqCst, guard)
+                .is_ok()
+            {
+                // If this fails, someone else already helped us.
+                let _ = self.tail.compare_and_set(tail, new, Ordering::SeqCst, guard);
+                return;
+            }
+        }
+    }
+
+    /// Like `pop`, under a guard the caller already has.
+    pub fn pop_with(&self, guard: &Guard) -> Option<T> {
+        loop {
+            let head = self.head.load(Ordering::SeqCst, guard);
+            let h = unsafe{head.deref()};
+            let next = h.next.load(Ordering::SeqCst, guard);
+            let n = unsafe{next.as_ref()}?;
+
+            if self
+                .head
+                .compare_and_set(head, next, Ordering::SeqCst, guard)
+                .is_ok()
+            {
+                // Don't let the tail point at a node we're about to retire.
+                let tail = self.tail.load(Ordering::SeqCst, guard);
+                if head == tail {
+                    let _ = self.tail.compare_and_set(tail, next, Ordering::SeqCst, guard);
+                }
+
+                // `next` is the new sentinel, and we're the only thread that
+                // gets to move its data out.  The old sentinel might still be
+                // in use by another thread, so it is destroyed later.
+                unsafe {
+                    guard.defer_destroy(head);
+                    return Some(n.data.as_ptr().read());
+                }
+            }
+        }
+    }
+
+    pub fn is_empty_with(&self, guard: &Guard) -> bool {
+        let head = self.head.load(Ordering::SeqCst, guard);
+        unsafe{head.deref()}.next.load(Ordering::SeqCst, guard).is_null()
+    }
+}
+
+impl<T> Drop for MsQueue<T> {
+    fn drop(&mut self) {
+        // We have `&mut self`, so nobody else can be looking at the queue.
+        unsafe {
+            let guard = epoch::unprotected();
+            while self.pop_with(guard).is_some() {}
+
+            // Only the sentinel is left, and its data is uninitialized.
+            let sentinel = self.head.load(Ordering::Relaxed, guard);
+            drop(sentinel.into_owned());
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! What it costs when canaries live on one NUMA node and are read, or
+//! dropped, from another.
+//!
+//! For every pair of nodes, a thread on the first node fills a cage with
+//! canaries, and then a thread on the second node reads them all a few
+//! times, and retires them all and reclaims them, so the deferred drops
+//! run on the second node too.  Comparing the off-diagonal pairs with the
+//! local ones gives the cross-node penalty.  With `Placement::Interleave`,
+//! the canaries are spread over every node instead, and only the node
+//! doing the reading and dropping varies.
+//!
+//! Placement uses the allocating thread's memory policy, set with
+//! `set_mempolicy`, which decides where pages go when they're first
+//! touched.  That's best-effort: memory the allocator already had lying
+//! around stays where it was.  The nodes come from sysfs, so all of this
+//! needs Linux; elsewhere there's one node, and no policy is set.
+
+use crate::affinity;
+use crate::reclaim::{force_reclaim, Epoch};
+use crate::{BirdCage, Canary};
+use std::fmt;
+use std::hint::black_box;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Where a run's canaries are allocated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// All on one node, trying each node in turn.
+    Bind,
+    /// Spread page by page over every node.
+    Interleave,
+}
+
+impl FromStr for Placement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "bind" => Ok(Placement::Bind),
+            "interleave" => Ok(Placement::Interleave),
+            _ => Err(format!("unknown placement: {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Placement::Bind => "bind",
+            Placement::Interleave => "interleave",
+        })
+    }
+}
+
+/// A NUMA node, and the cores on it that this process may use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub id: usize,
+    pub cpus: Vec<usize>,
+}
+
+/// The nodes with at least one core this process may use.
+///
+/// Without sysfs (or off Linux) that's one node 0 with every core.
+pub fn nodes() -> Vec<Node> {
+    let allowed = affinity::cores();
+    let mut nodes = Vec::new();
+    #[cfg(target_os = "linux")]
+    if let Ok(dir) = std::fs::read_dir("/sys/devices/system/node") {
+        for entry in dir.flatten() {
+            let name = entry.file_name();
+            let id = match name.to_str().and_then(|n| n.strip_prefix("node")) {
+                Some(id) => match id.parse() {
+                    Ok(id) => id,
+                    Err(_) => continue,
+                },
+                None => continue,
+            };
+            let list = std::fs::read_to_string(entry.path().join("cpulist")).unwrap_or_default();
+            let cpus: Vec<usize> = parse_cpu_list(&list)
+                .into_iter()
+                .filter(|cpu| allowed.contains(cpu))
+                .collect();
+            if !cpus.is_empty() {
+                nodes.push(Node { id, cpus });
+            }
+        }
+    }
+    if nodes.is_empty() {
+        nodes.push(Node {
+            id: 0,
+            cpus: allowed,
+        });
+    }
+    nodes.sort_by_key(|n| n.id);
+    nodes
+}
+
+// Read a sysfs list like "0-3,8,10-11".  Anything unreadable is skipped.
+fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        let (first, last) = match part.split_once('-') {
+            Some((a, b)) => (a.parse::<usize>(), b.parse()),
+            None => (part.parse(), part.parse()),
+        };
+        if let (Ok(first), Ok(last)) = (first, last) {
+            cpus.extend(first..=last);
+        }
+    }
+    cpus
+}
+
+// Make the calling thread's new pages come from `nodes`: all from the one
+// node for `Bind`, or spread over them for `Interleave`.  Returns whether
+// the kernel agreed.
+fn set_memory_policy(placement: Placement, nodes: &[usize]) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        // From <numaif.h>.
+        const MPOL_BIND: libc::c_long = 2;
+        const MPOL_INTERLEAVE: libc::c_long = 3;
+        let mode = match placement {
+            Placement::Bind => MPOL_BIND,
+            Placement::Interleave => MPOL_INTERLEAVE,
+        };
+        let bits = libc::c_ulong::BITS as usize;
+        let max = nodes.iter().max().map_or(0, |&id| id + 1);
+        let mut mask = vec![0 as libc::c_ulong; max.div_ceil(bits).max(1)];
+        for &id in nodes {
+            mask[id / bits] |= 1 << (id % bits);
+        }
+        // The kernel wants one more than the number of bits it should read.
+        let maxnode = (mask.len() * bits + 1) as libc::c_ulong;
+        let set = unsafe{libc::syscall(libc::SYS_set_mempolicy, mode, mask.as_ptr(), maxnode)};
+        set == 0
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (placement, nodes);
+        false
+    }
+}
+
+/// How a NUMA run should be set up.
+#[derive(Clone, Debug)]
+pub struct NumaConfig {
+    /// How many canaries each pair of nodes works on.
+    pub canaries: usize,
+    /// How many times the reading thread goes over all of them.
+    pub passes: usize,
+    pub placement: Placement,
+}
+
+impl Default for NumaConfig {
+    fn default() -> Self {
+        NumaConfig {
+            canaries: 100_000,
+            passes: 4,
+            placement: Placement::Bind,
+        }
+    }
+}
+
+/// The cost of working on canaries from one node that were allocated on
+/// another (or, for `alloc_node: None`, interleaved over all of them).
+#[derive(Clone, Copy, Debug)]
+pub struct Cell {
+    pub alloc_node: Option<usize>,
+    pub run_node: usize,
+    /// Whether the kernel accepted the memory policy.  If not, the canaries
+    /// are wherever the allocator put them.
+    pub placed: bool,
+    /// The mean time to read one canary.
+    pub read: Duration,
+    /// The mean time to reclaim and drop one canary.
+    pub drop: Duration,
+}
+
+impl Cell {
+    pub fn is_local(&self) -> bool {
+        self.alloc_node == Some(self.run_node)
+    }
+}
+
+/// What happened during a NUMA run.
+#[derive(Clone, Debug)]
+pub struct NumaReport {
+    pub config: NumaConfig,
+    pub nodes: Vec<Node>,
+    pub cells: Vec<Cell>,
+}
+
+impl NumaReport {
+    /// How much slower cross-node reads and drops were than local ones, as
+    /// ratios of their means, or `None` if there was nothing to compare.
+    pub fn penalty(&self) -> Option<(f64, f64)> {
+        let mean = |local: bool, f: fn(&Cell) -> Duration| {
+            let times: Vec<f64> = self
+                .cells
+                .iter()
+                .filter(|c| c.alloc_node.is_some() && c.is_local() == local)
+                .map(|c| f(c).as_secs_f64())
+                .collect();
+            times.iter().sum::<f64>() / times.len() as f64
+        };
+        if self.config.placement != Placement::Bind || self.nodes.len() < 2 {
+            return None;
+        }
+        Some((
+            mean(false, |c| c.read) / mean(true, |c| c.read),
+            mean(false, |c| c.drop) / mean(true, |c| c.drop),
+        ))
+    }
+}
+
+impl fmt::Display for NumaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "numa: {} node{}, {} canaries, {} read passes, {} placement",
+            self.nodes.len(),
+            if self.nodes.len() == 1 { "" } else { "s" },
+            self.config.canaries,
+            self.config.passes,
+            self.config.placement
+        )?;
+        writeln!(f, "{:<12}{:>8}{:>12}{:>12}", "allocated", "run", "read", "drop")?;
+        for c in &self.cells {
+            let alloc = match c.alloc_node {
+                Some(node) => format!("node {}", node),
+                None => "interleaved".to_owned(),
+            };
+            write!(
+                f,
+                "{:<12}{:>8}{:>12}{:>12}",
+                alloc,
+                format!("node {}", c.run_node),
+                format!("{:.1?}", c.read),
+                format!("{:.1?}", c.drop)
+            )?;
+            writeln!(f, "{}", if c.placed { "" } else { "  (policy not applied)" })?;
+        }
+        match self.penalty() {
+            Some((read, drop)) => write!(
+                f,
+                "cross-node penalty: reads {:.2}x, drops {:.2}x",
+                read, drop
+            ),
+            None if self.nodes.len() < 2 => {
+                write!(f, "only one node, so there's no cross-node penalty to measure")
+            }
+            None => write!(f, "interleaved, so there's no local case to compare with"),
+        }
+    }
+}
+
+/// Run the NUMA experiment described by `config`.
+pub fn run(config: &NumaConfig) -> NumaReport {
+    let nodes = nodes();
+    let ids: Vec<usize> = nodes.iter().map(|n| n.id).collect();
+    let mut cells = Vec::new();
+    for run_on in &nodes {
+        match config.placement {
+            Placement::Bind => {
+                for alloc_on in &nodes {
+                    cells.push(run_cell(config, Some(alloc_on), &[alloc_on.id], run_on));
+                }
+            }
+            Placement::Interleave => cells.push(run_cell(config, None, &ids, run_on)),
+        }
+    }
+    NumaReport {
+        config: config.clone(),
+        nodes,
+        cells,
+    }
+}
+
+fn run_cell(config: &NumaConfig, alloc_on: Option<&Node>, policy: &[usize], run_on: &Node) -> Cell {
+    let n = config.canaries;
+    let placement = config.placement;
+    let alloc_cpu = alloc_on.unwrap_or(run_on).cpus[0];
+    let (cage, placed) = thread::scope(|s| {
+        s.spawn(|| {
+            affinity::pin_current(alloc_cpu);
+            let placed = set_memory_policy(placement, policy);
+            let cage = BirdCage::<Canary, Epoch>::from_fn(n, |ii| {
+                Canary::silent(&format!("Canary {}", ii))
+            });
+            (cage, placed)
+        })
+        .join()
+        .unwrap()
+    });
+
+    let (read, drop) = thread::scope(|s| {
+        s.spawn(|| {
+            affinity::pin_current(run_on.cpus[0]);
+            let start = Instant::now();
+            {
+                let guard = &cage.pin();
+                for _ in 0..config.passes {
+                    for ii in 0..n {
+                        black_box(cage.get(ii, guard).ok().map(|c| c.generation()));
+                    }
+                }
+            }
+            let read = start.elapsed() / (n * config.passes).max(1) as u32;
+
+            // Stay pinned while retiring, so nothing is freed until we're
+            // ready to time it.
+            let guard = cage.pin();
+            for ii in 0..n {
+                let _ = cage.remove(ii);
+            }
+            drop(guard);
+            let start = Instant::now();
+            force_reclaim::<Epoch>();
+            (read, start.elapsed() / n.max(1) as u32)
+        })
+        .join()
+        .unwrap()
+    });
+
+    Cell {
+        alloc_node: alloc_on.map(|node| node.id),
+        run_node: run_on.id,
+        placed,
+        read,
+        drop,
+    }
+}
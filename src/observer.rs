@@ -0,0 +1,114 @@
+//! A background thread that shows whether reclamation is making progress.
+//!
+//! crossbeam-epoch doesn't expose the global epoch, so the observer watches
+//! it indirectly: every tick it retires a small numbered probe through the
+//! reclaimer and flushes.  The probe's destructor records its number, so
+//! "issued" minus "completed" says how many ticks' worth of garbage are
+//! stuck behind the epoch.  When the epoch advances, the lag stays at a
+//! tick or two; when some thread stays pinned, the lag grows by one every
+//! tick until it lets go.
+
+use crate::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use crate::stress::ReclaimerKind;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// One observation.
+#[derive(Clone, Copy, Debug)]
+pub struct Observation {
+    /// How long after the observer started this was taken.
+    pub at: Duration,
+    /// The number of probes retired so far.
+    pub issued: u64,
+    /// The highest-numbered probe that has been destroyed.
+    pub completed: u64,
+    /// `reclaim::pending` at the time.
+    pub pending: usize,
+}
+
+impl Observation {
+    /// How many probes are still waiting to be destroyed.
+    pub fn lag(&self) -> u64 {
+        self.issued.saturating_sub(self.completed)
+    }
+}
+
+/// A running observer thread.  Call `stop` to get what it saw.
+pub struct Observer {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Vec<Observation>>,
+}
+
+impl Observer {
+    /// Start observing reclaimer `R`, taking an observation every
+    /// `interval`.  If `print` is set, each one is also printed to stderr
+    /// as it's taken.
+    pub fn start<R: Reclaimer>(interval: Duration, print: bool) -> Observer {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let handle = thread::spawn(move || observe::<R>(interval, print, &thread_stop));
+        Observer { stop, handle }
+    }
+
+    /// Like `start`, with the reclaimer picked at run time.
+    pub fn start_kind(kind: ReclaimerKind, interval: Duration, print: bool) -> Observer {
+        match kind {
+            ReclaimerKind::Epoch => Observer::start::<Epoch>(interval, print),
+            ReclaimerKind::Hazard => Observer::start::<HazardPointers>(interval, print),
+            ReclaimerKind::Qsbr => Observer::start::<Qsbr>(interval, print),
+        }
+    }
+
+    /// Stop the observer and return everything it saw, in order.
+    pub fn stop(self) -> Vec<Observation> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().unwrap()
+    }
+}
+
+fn observe<R: Reclaimer>(interval: Duration, print: bool, stop: &AtomicBool) -> Vec<Observation> {
+    let completed = Arc::new(AtomicU64::new(0));
+    let start = Instant::now();
+    let mut observations = Vec::new();
+    let mut issued = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(interval);
+        issued += 1;
+        {
+            let guard = R::pin();
+            let probe = Box::into_raw(Box::new(issued));
+            let completed = completed.clone();
+            // Nobody else ever sees the probe, so it's trivially unreachable.
+            unsafe {
+                R::retire_with(&guard, probe, move |n| {
+                    completed.fetch_max(*n, Ordering::Relaxed);
+                });
+            }
+            R::flush(&guard);
+        }
+        // We hold nothing now; without this QSBR would wait on us forever.
+        R::quiescent();
+
+        let o = Observation {
+            at: start.elapsed(),
+            issued,
+            completed: completed.load(Ordering::Relaxed),
+            pending: reclaim::pending(),
+        };
+        if print {
+            eprintln!(
+                "observer {:>8.1}ms: probe {} done {} (lag {}), pending {}",
+                o.at.as_secs_f64() * 1000.0,
+                o.issued,
+                o.completed,
+                o.lag(),
+                o.pending
+            );
+        }
+        observations.push(o);
+    }
+    observations
+}
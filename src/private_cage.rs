@@ -0,0 +1,183 @@
+use crate::cage::CageError;
+#[cfg(feature = "std")]
+use crate::Canary;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::fmt::Display;
+use core::sync::atomic::Ordering;
+use crossbeam::epoch::{self, Atomic, Collector, Guard, LocalHandle, Owned};
+
+/// A `BirdCage` variant that owns its own `Collector`.
+///
+/// The epoch counter and deferred garbage belong to this structure alone,
+/// rather than being shared with everything else in the process.  Each
+/// thread that wants to touch the cage must `register()` to get a
+/// `LocalHandle`, and pass that handle to every operation.
+///
+/// When the cage and every handle it gave out have been dropped, the
+/// collector is dropped too, and all the garbage that was deferred through
+/// it gets destroyed right then.  No flushing hacks required.
+///
+/// Since it brings its own collector, this is the cage that works without
+/// std, using `put` and `with_slot`; the printing `access` and `replace`
+/// need std.
+///
+/// A slot that's out of range or empty is reported as a `CageError`.
+pub struct PrivateBirdCage<T> {
+    c: Vec<Atomic<T>>,
+    collector: Collector,
+}
+
+#[cfg(feature = "std")]
+impl PrivateBirdCage<Canary> {
+    /// Create a cage full of canaries named "Canary 0", "Canary 1", ...
+    pub fn new(size: usize) -> PrivateBirdCage<Canary> {
+        PrivateBirdCage::from_fn(size, |ii| Canary::new(&format!("Canary {}", ii)))
+    }
+}
+
+impl<T: Send + 'static> PrivateBirdCage<T> {
+    /// Create a cage with `size` slots, filling slot `n` with `f(n)`.
+    pub fn from_fn<F>(size: usize, mut f: F) -> PrivateBirdCage<T>
+    where
+        F: FnMut(usize) -> T,
+    {
+        PrivateBirdCage {
+            c: (0..size).map(|ii| Atomic::new(f(ii))).collect(),
+            collector: Collector::new(),
+        }
+    }
+
+    /// Get a handle for the current thread.
+    pub fn register(&self) -> LocalHandle {
+        self.collector.register()
+    }
+
+    /// The number of slots in the cage.
+    pub fn len(&self) -> usize {
+        self.c.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.c.is_empty()
+    }
+
+    // Using a guard from some other collector would let our garbage be
+    // reclaimed while our readers are still looking at it.
+    fn pin(&self, handle: &LocalHandle) -> Guard {
+        assert!(
+            handle.collector() == &self.collector,
+            "LocalHandle belongs to a different collector"
+        );
+        handle.pin()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn access(&self, handle: &LocalHandle, n: usize, ctx: &str) -> Result<(), CageError>
+    where
+        T: Display,
+    {
+        self.with_slot(handle, n, |c| println!("[{}] accessing {}", ctx, c))
+    }
+
+    // Slot `n`, if there is one.
+    fn slot(&self, n: usize) -> Result<&Atomic<T>, CageError> {
+        self.c.get(n).ok_or(CageError::IndexOutOfBounds {
+            index: n,
+            len: self.c.len(),
+        })
+    }
+
+    /// Pin `handle`, load slot `n`, and hand the value to `f`, returning
+    /// whatever `f` returns.
+    pub fn with_slot<F, R>(&self, handle: &LocalHandle, n: usize, f: F) -> Result<R, CageError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let slot = self.slot(n)?;
+        let guard = &self.pin(handle);
+        let shared = slot.load(Ordering::SeqCst, guard);
+        unsafe{shared.as_ref()}.map(f).ok_or(CageError::SlotEmpty { index: n })
+    }
+
+    #[cfg(feature = "std")]
+    pub fn replace(
+        &self,
+        handle: &LocalHandle,
+        n: usize,
+        ctx: &str,
+        new_c: T,
+    ) -> Result<(), CageError>
+    where
+        T: Display,
+    {
+        self.slot(n)?;
+        println!("[{}] put {} into slot {}", ctx, new_c, n);
+        self.put_with(handle, n, new_c, |c| println!("[{}] removed {}", ctx, c))
+    }
+
+    /// Put `new_c` into slot `n`, and destroy the old value once nobody
+    /// registered with this cage can be looking at it.
+    pub fn put(&self, handle: &LocalHandle, n: usize, new_c: T) -> Result<(), CageError> {
+        self.put_with(handle, n, new_c, |_| {})
+    }
+
+    /// Like `put`, showing the old value (if there was one) to `removed`
+    /// first.
+    pub fn put_with<F: FnOnce(&T)>(
+        &self,
+        handle: &LocalHandle,
+        n: usize,
+        new_c: T,
+        removed: F,
+    ) -> Result<(), CageError> {
+        let slot = self.slot(n)?;
+        let guard = &self.pin(handle);
+        let stolen_c = slot.swap(Owned::new(new_c), Ordering::SeqCst, guard);
+        if let Some(c) = unsafe{stolen_c.as_ref()} {
+            removed(c);
+            // This garbage goes into our collector, not the global one.
+            unsafe {
+                guard.defer_destroy(stolen_c);
+            }
+        }
+        Ok(())
+    }
+
+    /// Put `new_c` into slot `n` and hand back the old value right away.
+    ///
+    /// Having `&mut self` means no other thread can be looking at the cage,
+    /// so there's nothing to defer, the collector isn't involved at all,
+    /// and nothing is left over for it to clean up later.  That makes this
+    /// the way to set up or tear down a cage from a single thread.
+    pub fn replace_mut(&mut self, n: usize, new_c: T) -> Result<Option<T>, CageError> {
+        let slot = self.slot(n)?;
+        unsafe {
+            let guard = epoch::unprotected();
+            let stolen_c = slot.swap(Owned::new(new_c), Ordering::Relaxed, guard);
+            if stolen_c.is_null() {
+                Ok(None)
+            } else {
+                Ok(Some(*stolen_c.into_owned().into_box()))
+            }
+        }
+    }
+}
+
+impl<T> Drop for PrivateBirdCage<T> {
+    fn drop(&mut self) {
+        // We have `&mut self`, so nobody else can be looking at the slots.
+        // The values that are still in the cage can be destroyed right away.
+        unsafe {
+            let guard = epoch::unprotected();
+            for slot in &self.c {
+                let shared = slot.load(Ordering::Relaxed, guard);
+                if !shared.is_null() {
+                    drop(shared.into_owned());
+                }
+            }
+        }
+        // Our collector is dropped after this, which destroys the deferred
+        // garbage once the last LocalHandle is gone too.
+    }
+}
@@ -0,0 +1,114 @@
+//! How much deferred work piles up before crossbeam gets around to running
+//! any of it, measured rather than remembered.
+//!
+//! One thread defers one value at a time, pinning afresh for each, the way
+//! `BirdCage::put` does when it doesn't flush, and after each one checks
+//! how many values have been dropped.  It uses a `Collector` of its own,
+//! which works exactly like the default one but has no other threads to
+//! wait for, so what shows up is crossbeam's own batching: deferred work
+//! sits in a thread-local bag until the bag fills up, and collection is
+//! only attempted every so many pins.
+
+use crate::drop_tracker::DropTracker;
+use crossbeam::epoch::Collector;
+use std::fmt;
+
+/// The number of deferrals `run` makes by default.
+pub const DEFAULT_DEFERRALS: usize = 10_000;
+
+/// One time the collector ran some of our deferred work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Collection {
+    /// How many deferrals had been made when it happened.
+    pub after: usize,
+    /// How many values it dropped.
+    pub dropped: usize,
+}
+
+/// What the probe saw.
+#[derive(Clone, Debug)]
+pub struct ProbeReport {
+    pub deferrals: usize,
+    pub collections: Vec<Collection>,
+    /// What was still waiting at the end, and was only dropped when the
+    /// collector went away.
+    pub left_over: usize,
+}
+
+impl ProbeReport {
+    /// How many deferrals accumulated before anything was dropped.
+    pub fn first_collection(&self) -> Option<usize> {
+        self.collections.first().map(|c| c.after)
+    }
+
+    /// The most common number of values dropped at once, after the first
+    /// collection.
+    pub fn usual_batch(&self) -> Option<usize> {
+        most_common(self.collections.iter().skip(1).map(|c| c.dropped))
+    }
+
+    /// The most common number of deferrals between one collection and the
+    /// next.
+    pub fn usual_gap(&self) -> Option<usize> {
+        most_common(self.collections.windows(2).map(|w| w[1].after - w[0].after))
+    }
+}
+
+fn most_common(values: impl Iterator<Item = usize>) -> Option<usize> {
+    let mut values: Vec<usize> = values.collect();
+    values.sort_unstable();
+    values
+        .chunk_by(|a, b| a == b)
+        .max_by_key(|run| run.len())
+        .map(|run| run[0])
+}
+
+impl fmt::Display for ProbeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} deferrals, one per pin, never flushed", self.deferrals)?;
+        match self.first_collection() {
+            Some(n) => writeln!(f, "first values dropped after {} deferrals", n)?,
+            None => writeln!(f, "nothing was dropped until the collector went away")?,
+        }
+        if let Some(gap) = self.usual_gap() {
+            writeln!(f, "then usually every {} deferrals", gap)?;
+        }
+        if let Some(batch) = self.usual_batch() {
+            writeln!(f, "usually {} values at a time", batch)?;
+        }
+        write!(
+            f,
+            "{} collections; {} values left over at the end",
+            self.collections.len(),
+            self.left_over
+        )
+    }
+}
+
+/// Defer `deferrals` values, one per pin, and record when they're dropped.
+pub fn run(deferrals: usize) -> ProbeReport {
+    let tracker = DropTracker::new();
+    let collector = Collector::new();
+    let handle = collector.register();
+    let mut collections = Vec::new();
+    let mut seen = 0;
+    for ii in 1..=deferrals {
+        let value = tracker.track();
+        handle.pin().defer(move || drop(value));
+        let dropped = tracker.dropped();
+        if dropped > seen {
+            collections.push(Collection {
+                after: ii,
+                dropped: dropped - seen,
+            });
+            seen = dropped;
+        }
+    }
+    drop(handle);
+    drop(collector);
+    ProbeReport {
+        deferrals,
+        collections,
+        left_over: tracker.dropped() - seen,
+    }
+}
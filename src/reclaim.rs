@@ -0,0 +1,240 @@
+//! Pluggable memory reclamation schemes.
+//!
+//! A `Reclaimer` knows how to protect a pointer that was loaded from a
+//! shared slot, and how to retire a pointer that has been unlinked so that
+//! it's only destroyed once nobody can be using it.  `BirdCage` is generic
+//! over this, so the same workload can be run under different schemes.
+
+use crate::events::Tracker;
+use crate::Canary;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+mod epoch;
+mod hazard;
+mod qsbr;
+
+pub use self::epoch::Epoch;
+pub use self::hazard::{HazardGuard, HazardPointers};
+pub use self::qsbr::{Qsbr, QsbrGuard};
+
+/// A memory reclamation scheme.
+///
+/// Implementations are zero-sized marker types; all of their state is
+/// global or per-thread.
+pub trait Reclaimer: Send + Sync + 'static {
+    /// Keeps protected pointers valid for as long as it's alive.
+    type Guard;
+
+    /// A short name, for reports and command line options.
+    const NAME: &'static str;
+
+    /// Start a protected section on the current thread.
+    fn pin() -> Self::Guard;
+
+    /// Load the pointer in `slot`, and make sure whatever it points to isn't
+    /// destroyed before `guard` is dropped.
+    fn protect<T>(slot: &AtomicPtr<T>, guard: &Self::Guard) -> *mut T;
+
+    /// Like `protect`, but load the pointer with `order`, if the scheme
+    /// allows it.  Schemes that need `SeqCst` to keep their own promises
+    /// ignore `order`, which is what this does by default.
+    fn protect_ordered<T>(slot: &AtomicPtr<T>, guard: &Self::Guard, order: Ordering) -> *mut T {
+        let _ = order;
+        Self::protect(slot, guard)
+    }
+
+    /// Call `f` with ownership of `ptr` once no thread can be using it.
+    ///
+    /// `f` runs on whichever thread gets around to reclaiming it.  Don't let
+    /// it panic: the panic comes out of some unrelated pin or flush, and
+    /// under `Epoch` the rest of the garbage that was reclaimed along with
+    /// it is leaked.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have come from `Box::into_raw`, must no longer be
+    /// reachable by other threads, and must not be retired twice.
+    unsafe fn retire_with<T, F>(guard: &Self::Guard, ptr: *mut T, f: F)
+    where
+        T: Send + 'static,
+        F: FnOnce(Box<T>) + Send + 'static;
+
+    /// Call `f` on each of `ptrs` once no thread can be using any of them.
+    ///
+    /// Schemes that track readers pointer by pointer have to retire each one
+    /// separately, which is what this does by default.  The others can defer
+    /// the whole batch as a single job.
+    ///
+    /// # Safety
+    ///
+    /// The same rules as `retire_with` apply to every pointer.
+    unsafe fn retire_batch_with<T, F>(guard: &Self::Guard, ptrs: Vec<*mut T>, f: F)
+    where
+        T: Send + 'static,
+        F: Fn(Box<T>) + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        for ptr in ptrs {
+            let f = f.clone();
+            Self::retire_with(guard, ptr, move |owned| f(owned));
+        }
+    }
+
+    /// Destroy `ptr` once no thread can be using it.
+    ///
+    /// # Safety
+    ///
+    /// The same rules as `retire_with` apply.
+    unsafe fn retire<T: Send + 'static>(guard: &Self::Guard, ptr: *mut T) {
+        Self::retire_with(guard, ptr, drop)
+    }
+
+    /// Try to get retired garbage destroyed sooner rather than later.
+    fn flush(guard: &Self::Guard);
+
+    /// Announce that this thread isn't holding any protected references.
+    ///
+    /// Only schemes like QSBR need this; for everything else it does nothing.
+    fn quiescent() {}
+
+    /// Let go of everything `guard` protects and start again, so that a
+    /// reader that runs for a long time doesn't hold reclamation back the
+    /// whole time.
+    ///
+    /// Taking the guard by value means no reference loaded under it can be
+    /// used afterwards.  By default this drops it, announces a quiescent
+    /// state, and pins again.
+    fn repin(guard: Self::Guard) -> Self::Guard {
+        drop(guard);
+        Self::quiescent();
+        Self::pin()
+    }
+}
+
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of values a `BirdCage` has retired whose deferred destruction
+/// hasn't run yet, across every reclaimer and thread.
+///
+/// Watching this is the easiest way to see how much garbage each scheme
+/// lets pile up before it gets around to freeing it.
+pub fn pending() -> usize {
+    PENDING.load(Ordering::Relaxed)
+}
+
+// Wrap a deferred function so that `pending` counts its value from now
+// until the function has finished running, and any `events` subscribers
+// hear about both ends.
+pub(crate) fn counted<T, F>(f: F) -> impl FnOnce(Box<T>) + Send + 'static
+where
+    T: Send + 'static,
+    F: FnOnce(Box<T>) + Send + 'static,
+{
+    PENDING.fetch_add(1, Ordering::Relaxed);
+    let tracker = Tracker::deferred(1);
+    move |owned| {
+        f(owned);
+        PENDING.fetch_sub(1, Ordering::Relaxed);
+        if let Some(t) = tracker {
+            t.reclaimed(0);
+        }
+    }
+}
+
+// Like `counted`, for a batch of `n` values that all go through `f`.
+pub(crate) fn counted_each<T, F>(n: usize, f: F) -> impl Fn(Box<T>) + Send + Sync + 'static
+where
+    T: Send + 'static,
+    F: Fn(Box<T>) + Send + Sync + 'static,
+{
+    PENDING.fetch_add(n, Ordering::Relaxed);
+    let tracker = Tracker::deferred(n);
+    let done = AtomicU64::new(0);
+    move |owned| {
+        f(owned);
+        PENDING.fetch_sub(1, Ordering::Relaxed);
+        if let Some(t) = &tracker {
+            t.reclaimed(done.fetch_add(1, Ordering::Relaxed));
+        }
+    }
+}
+
+// How many rounds in a row `force_reclaim` will go without seeing anything
+// destroyed before it decides nothing more is coming.
+const PATIENCE: u32 = 16;
+
+/// Flush `R` over and over until it stops destroying garbage, and say
+/// whether everything this thread had retired is gone.
+///
+/// It retires a small numbered probe and flushes until the probe has been
+/// destroyed, which means everything retired before it is gone too.  If
+/// `pending` went down in the meantime, other garbage was still coming
+/// loose, so it sends another probe after it; once a probe makes it
+/// through with `pending` holding still, it's done.  It always returns,
+/// even if some other thread is holding reclamation up (a pinned epoch
+/// reader, or a QSBR thread that never reaches a quiescent state): after a
+/// run of flushes with nothing destroyed, it gives up and returns `false`.
+///
+/// "Nothing destroyed" means no probe finished, `pending` didn't go down
+/// and no `Canary` was dropped.  Garbage that is none of those (like the
+/// nodes of the lock-free structures) is invisible to it, so a long enough
+/// backlog of only that can make it give up early.
+///
+/// Garbage still sitting in another live thread's local bag isn't reachable
+/// from here.  Only that thread's flush, or its exit, hands it over.
+pub fn force_reclaim<R: Reclaimer>() -> bool {
+    let completed = Arc::new(AtomicU64::new(0));
+    let mut issued = 0;
+    let mut pending_at_issue = 0;
+    let mut last_pending = pending();
+    let mut last_dropped = Canary::dropped();
+    let mut idle = 0;
+
+    while idle < PATIENCE {
+        let done = completed.load(Ordering::Relaxed);
+        let guard = R::pin();
+        if done == issued {
+            if issued > 0 && pending() >= pending_at_issue {
+                return true;
+            }
+            issued += 1;
+            pending_at_issue = pending();
+            let probe = Box::into_raw(Box::new(issued));
+            let completed = completed.clone();
+            // Nobody else ever sees the probe, so it's trivially unreachable.
+            unsafe {
+                R::retire_with(&guard, probe, move |n| {
+                    completed.fetch_max(*n, Ordering::Relaxed);
+                });
+            }
+        }
+        R::flush(&guard);
+        drop(guard);
+        R::quiescent();
+
+        let now_pending = pending();
+        let now_dropped = Canary::dropped();
+        if completed.load(Ordering::Relaxed) > done
+            || now_pending < last_pending
+            || now_dropped > last_dropped
+        {
+            idle = 0;
+        } else {
+            idle += 1;
+            // Whoever is holding things up might need the CPU to get out
+            // of the way, like a thread that's pinned while it exits.
+            thread::yield_now();
+        }
+        last_pending = now_pending;
+        last_dropped = now_dropped;
+    }
+    false
+}
+
+// Raw pointers aren't `Send`, but the ones we retire are owned by whoever
+// runs the deferred function, so it's fine to move them to another thread.
+pub(crate) struct SendPtr<T>(pub(crate) *mut T);
+
+unsafe impl<T: Send> Send for SendPtr<T> {}
@@ -0,0 +1,96 @@
+use super::{Reclaimer, SendPtr};
+use crate::explain;
+use crossbeam::epoch::{self, Guard};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::time::Instant;
+
+/// Epoch-based reclamation, using the default `crossbeam::epoch` collector.
+pub struct Epoch;
+
+impl Reclaimer for Epoch {
+    type Guard = Guard;
+    const NAME: &'static str = "epoch";
+
+    fn pin() -> Guard {
+        if explain::enabled() && !epoch::is_pinned() {
+            explain::pinning();
+        }
+        epoch::pin()
+    }
+
+    fn protect<T>(slot: &AtomicPtr<T>, guard: &Guard) -> *mut T {
+        Self::protect_ordered(slot, guard, Ordering::SeqCst)
+    }
+
+    fn protect_ordered<T>(slot: &AtomicPtr<T>, guard: &Guard, order: Ordering) -> *mut T {
+        // Guards from any other collector (or `unprotected()`) wouldn't keep
+        // our garbage alive, so pointers loaded under them could dangle.
+        assert!(
+            guard.collector() == Some(epoch::default_collector()),
+            "Epoch needs a guard from the default collector"
+        );
+        // Anything we load can't be destroyed until the guard is unpinned.
+        // That's the epoch's job; the ordering only has to make the value
+        // visible along with its pointer, which `Acquire` does.
+        slot.load(order)
+    }
+
+    unsafe fn retire_with<T, F>(guard: &Guard, ptr: *mut T, f: F)
+    where
+        T: Send + 'static,
+        F: FnOnce(Box<T>) + Send + 'static,
+    {
+        let ptr = SendPtr(ptr);
+        if explain::enabled() {
+            explain::deferring(1);
+            let retired_at = Instant::now();
+            guard.defer(move || {
+                explain::freeing(retired_at);
+                f(Box::from_raw(ptr.0))
+            });
+            return;
+        }
+        guard.defer(move || f(Box::from_raw(ptr.0)));
+    }
+
+    unsafe fn retire_batch_with<T, F>(guard: &Guard, ptrs: Vec<*mut T>, f: F)
+    where
+        T: Send + 'static,
+        F: Fn(Box<T>) + Send + Sync + 'static,
+    {
+        // The epoch protects everything at once, so the batch can share
+        // one deferred function.
+        let ptrs: Vec<_> = ptrs.into_iter().map(SendPtr).collect();
+        let retired_at = if explain::enabled() {
+            explain::deferring(ptrs.len());
+            Some(Instant::now())
+        } else {
+            None
+        };
+        guard.defer(move || {
+            if let Some(t) = retired_at {
+                explain::freeing(t);
+            }
+            for ptr in ptrs {
+                f(Box::from_raw(ptr.0));
+            }
+        });
+    }
+
+    fn flush(guard: &Guard) {
+        // The default Collector will wait until a bunch of deferred actions
+        // have accumulated unless we flush.  `epoch_playground probe`
+        // measures how many.
+        if explain::enabled() {
+            explain::flushing();
+        }
+        guard.flush();
+    }
+
+    fn repin(mut guard: Guard) -> Guard {
+        // Unpins and pins again in place, unless the thread is pinned by
+        // some other guard too, in which case it can't help.
+        guard.repin();
+        guard
+    }
+}
@@ -0,0 +1,213 @@
+//! A minimal hazard pointer implementation.
+//!
+//! Every thread publishes the pointers it's about to dereference in a
+//! global list of hazard records.  Retired pointers are kept on a
+//! thread-local list, and every so often the thread scans all the hazard
+//! records and frees whatever nobody has published.
+//!
+//! Compared to epochs, this protects individual pointers rather than whole
+//! time periods, so garbage can be freed as soon as its last reader moves
+//! on, but every protected load costs a store, a fence, and a re-check.
+
+use super::{Reclaimer, SendPtr};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ptr;
+use std::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// Scan once this many pointers have been retired (or twice the number of
+// hazard records, if that's bigger).
+const SCAN_THRESHOLD: usize = 64;
+
+struct HazardRecord {
+    ptr: AtomicPtr<u8>,
+    active: AtomicBool,
+    next: *const HazardRecord,
+}
+
+// Records are never freed, and only ever pushed onto the front of the list.
+static RECORDS: AtomicPtr<HazardRecord> = AtomicPtr::new(ptr::null_mut());
+static NUM_RECORDS: AtomicUsize = AtomicUsize::new(0);
+
+// Retired pointers left behind by threads that have exited.
+static ORPHANS: Mutex<Vec<Retired>> = Mutex::new(Vec::new());
+
+fn records() -> impl Iterator<Item = &'static HazardRecord> {
+    let mut p = RECORDS.load(Ordering::SeqCst) as *const HazardRecord;
+    std::iter::from_fn(move || {
+        let rec = unsafe{p.as_ref()}?;
+        p = rec.next;
+        Some(rec)
+    })
+}
+
+fn acquire_record() -> &'static HazardRecord {
+    // Reuse a record that some exited thread gave back, if there is one.
+    for rec in records() {
+        if !rec.active.load(Ordering::Relaxed)
+            && rec
+                .active
+                .compare_exchange(false, true, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok()
+        {
+            return rec;
+        }
+    }
+
+    let rec = Box::into_raw(Box::new(HazardRecord {
+        ptr: AtomicPtr::new(ptr::null_mut()),
+        active: AtomicBool::new(true),
+        next: ptr::null(),
+    }));
+    let mut head = RECORDS.load(Ordering::SeqCst);
+    loop {
+        unsafe {
+            (*rec).next = head;
+        }
+        match RECORDS.compare_exchange(head, rec, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => break,
+            Err(actual) => head = actual,
+        }
+    }
+    NUM_RECORDS.fetch_add(1, Ordering::Relaxed);
+    unsafe{&*rec}
+}
+
+struct Retired {
+    addr: usize,
+    free: Box<dyn FnOnce() + Send>,
+}
+
+#[derive(Default)]
+struct Local {
+    // Records this thread owns but isn't using right now.
+    free_records: Vec<&'static HazardRecord>,
+    retired: Vec<Retired>,
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        for rec in self.free_records.drain(..) {
+            rec.active.store(false, Ordering::SeqCst);
+        }
+        if !self.retired.is_empty() {
+            ORPHANS.lock().unwrap().append(&mut self.retired);
+        }
+    }
+}
+
+thread_local! {
+    static LOCAL: RefCell<Local> = RefCell::new(Local::default());
+}
+
+// Free everything in `retired` that isn't currently protected.  The frees
+// run after we're done with the list, in case a destructor retires more.
+//
+// The fence keeps the swaps that unlinked these pointers ahead of the
+// hazard loads below.  Callers may have unlinked them with a mere `AcqRel`
+// swap, and without the fence a reader could publish a hazard, re-check its
+// slot and still see the old pointer, while this scan misses the hazard.
+fn scan(retired: &mut Vec<Retired>) -> Vec<Retired> {
+    atomic::fence(Ordering::SeqCst);
+    let hazards: HashSet<usize> = records()
+        .map(|rec| rec.ptr.load(Ordering::SeqCst) as usize)
+        .filter(|&addr| addr != 0)
+        .collect();
+    let (protected, unprotected) = retired
+        .drain(..)
+        .partition(|r| hazards.contains(&r.addr));
+    *retired = protected;
+    unprotected
+}
+
+fn collect(force: bool) {
+    let to_free = LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        let threshold = SCAN_THRESHOLD.max(2 * NUM_RECORDS.load(Ordering::Relaxed));
+        if !force && local.retired.len() < threshold {
+            return Vec::new();
+        }
+        // Adopt whatever exited threads left behind.
+        if let Ok(mut orphans) = ORPHANS.try_lock() {
+            local.retired.append(&mut orphans);
+        }
+        scan(&mut local.retired)
+    });
+    for r in to_free {
+        (r.free)();
+    }
+}
+
+/// Hazard pointer reclamation.
+pub struct HazardPointers;
+
+/// A set of hazard records, one for each pointer protected through it.
+pub struct HazardGuard {
+    records: RefCell<Vec<&'static HazardRecord>>,
+}
+
+impl Drop for HazardGuard {
+    fn drop(&mut self) {
+        let records = self.records.get_mut();
+        for rec in records.iter() {
+            rec.ptr.store(ptr::null_mut(), Ordering::SeqCst);
+        }
+        let _ = LOCAL.try_with(|local| local.borrow_mut().free_records.append(records));
+        // If the thread is exiting, hand the records back to everyone.
+        for rec in records.drain(..) {
+            rec.active.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Reclaimer for HazardPointers {
+    type Guard = HazardGuard;
+    const NAME: &'static str = "hazard";
+
+    fn pin() -> HazardGuard {
+        HazardGuard {
+            records: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn protect<T>(slot: &AtomicPtr<T>, guard: &HazardGuard) -> *mut T {
+        let rec = LOCAL
+            .try_with(|local| local.borrow_mut().free_records.pop())
+            .ok()
+            .flatten()
+            .unwrap_or_else(acquire_record);
+        guard.records.borrow_mut().push(rec);
+
+        // Publish the pointer, then make sure it's still in the slot.  If it
+        // is, nobody can have retired it before seeing our hazard.
+        let mut p = slot.load(Ordering::SeqCst);
+        loop {
+            rec.ptr.store(p as *mut u8, Ordering::SeqCst);
+            let again = slot.load(Ordering::SeqCst);
+            if again == p {
+                return p;
+            }
+            p = again;
+        }
+    }
+
+    unsafe fn retire_with<T, F>(_guard: &HazardGuard, ptr: *mut T, f: F)
+    where
+        T: Send + 'static,
+        F: FnOnce(Box<T>) + Send + 'static,
+    {
+        let addr = ptr as usize;
+        let ptr = SendPtr(ptr);
+        let retired = Retired {
+            addr,
+            free: Box::new(move || f(Box::from_raw(ptr.0))),
+        };
+        LOCAL.with(|local| local.borrow_mut().retired.push(retired));
+        collect(false);
+    }
+
+    fn flush(_guard: &HazardGuard) {
+        collect(true);
+    }
+}
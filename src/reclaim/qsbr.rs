@@ -0,0 +1,239 @@
+//! Quiescent-state-based reclamation.
+//!
+//! Readers don't announce anything when they start reading.  Instead, each
+//! thread periodically calls `Qsbr::quiescent()` at a point where it holds
+//! no references into any shared structure.  Retired pointers are stamped
+//! with a global counter, and once every online thread has announced a
+//! quiescent state since that stamp, they can be freed.
+//!
+//! That makes the read path nearly free, but it moves the burden onto the
+//! application: a thread that forgets to announce quiescence holds up
+//! reclamation for everyone, forever.
+
+use super::{Reclaimer, SendPtr};
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Try to free garbage once this many pointers have been retired.  If most
+// of them turn out to be stuck, wait for the list to double before trying
+// again, so a stalled thread doesn't make every retire a full scan.
+const COLLECT_THRESHOLD: usize = 64;
+
+static COUNTER: AtomicU64 = AtomicU64::new(1);
+
+struct ThreadRecord {
+    // The counter value this thread saw at its last quiescent state.
+    seen: AtomicU64,
+    online: AtomicBool,
+    next: *const ThreadRecord,
+}
+
+// Records are never freed, and only ever pushed onto the front of the list.
+static THREADS: AtomicPtr<ThreadRecord> = AtomicPtr::new(ptr::null_mut());
+
+struct Retired {
+    stamp: u64,
+    free: Box<dyn FnOnce() + Send>,
+}
+
+// Retired pointers left behind by threads that have exited.
+static ORPHANS: Mutex<Vec<Retired>> = Mutex::new(Vec::new());
+
+fn threads() -> impl Iterator<Item = &'static ThreadRecord> {
+    let mut p = THREADS.load(Ordering::SeqCst) as *const ThreadRecord;
+    std::iter::from_fn(move || {
+        let rec = unsafe{p.as_ref()}?;
+        p = rec.next;
+        Some(rec)
+    })
+}
+
+fn register() -> &'static ThreadRecord {
+    // A thread can't be holding references to anything retired before it
+    // came online, so it starts out as if it had just been quiescent.
+    let now = COUNTER.load(Ordering::SeqCst);
+
+    for rec in threads() {
+        if !rec.online.load(Ordering::Relaxed) {
+            rec.seen.store(now, Ordering::SeqCst);
+            if rec
+                .online
+                .compare_exchange(false, true, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok()
+            {
+                return rec;
+            }
+        }
+    }
+
+    let rec = Box::into_raw(Box::new(ThreadRecord {
+        seen: AtomicU64::new(now),
+        online: AtomicBool::new(true),
+        next: ptr::null(),
+    }));
+    let mut head = THREADS.load(Ordering::SeqCst);
+    loop {
+        unsafe {
+            (*rec).next = head;
+        }
+        match THREADS.compare_exchange(head, rec, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => break,
+            Err(actual) => head = actual,
+        }
+    }
+    unsafe{&*rec}
+}
+
+struct Local {
+    record: &'static ThreadRecord,
+    // How many guards this thread has alive right now.
+    depth: Cell<usize>,
+    retired: RefCell<Vec<Retired>>,
+    next_collect: Cell<usize>,
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        self.record.online.store(false, Ordering::SeqCst);
+        let retired = self.retired.get_mut();
+        if !retired.is_empty() {
+            ORPHANS.lock().unwrap().append(retired);
+        }
+    }
+}
+
+thread_local! {
+    static LOCAL: Local = Local {
+        record: register(),
+        depth: Cell::new(0),
+        retired: RefCell::new(Vec::new()),
+        next_collect: Cell::new(COLLECT_THRESHOLD),
+    };
+}
+
+// Free everything that every online thread has been quiescent since.
+fn collect(local: &Local) {
+    let safe_below = threads()
+        .filter(|rec| rec.online.load(Ordering::SeqCst))
+        .map(|rec| rec.seen.load(Ordering::SeqCst))
+        .min()
+        .unwrap_or(u64::MAX);
+
+    // The frees run after we're done with the list, in case a destructor
+    // retires more.
+    let to_free: Vec<Retired> = {
+        let mut retired = local.retired.borrow_mut();
+        if let Ok(mut orphans) = ORPHANS.try_lock() {
+            retired.append(&mut orphans);
+        }
+        let (free, keep): (Vec<_>, Vec<_>) =
+            retired.drain(..).partition(|r| r.stamp < safe_below);
+        *retired = keep;
+        local
+            .next_collect
+            .set(COLLECT_THRESHOLD.max(2 * retired.len()));
+        free
+    };
+    for r in to_free {
+        (r.free)();
+    }
+}
+
+// Stamp `free` and add it to this thread's list, collecting if the list
+// has grown enough.  A batch counts as one entry.
+fn push_retired(free: Box<dyn FnOnce() + Send>) {
+    let retired = Retired {
+        stamp: COUNTER.fetch_add(1, Ordering::SeqCst),
+        free,
+    };
+    LOCAL.with(|local| {
+        let len = {
+            let mut list = local.retired.borrow_mut();
+            list.push(retired);
+            list.len()
+        };
+        if len >= local.next_collect.get() {
+            collect(local);
+        }
+    });
+}
+
+/// Quiescent-state-based reclamation.
+pub struct Qsbr;
+
+/// A QSBR guard doesn't publish anything; it only stops this thread from
+/// announcing a quiescent state while references may still be in use.
+pub struct QsbrGuard {
+    // The depth count is per-thread, so the guard must stay on this thread.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for QsbrGuard {
+    fn drop(&mut self) {
+        let _ = LOCAL.try_with(|local| local.depth.set(local.depth.get() - 1));
+    }
+}
+
+impl Reclaimer for Qsbr {
+    type Guard = QsbrGuard;
+    const NAME: &'static str = "qsbr";
+
+    fn pin() -> QsbrGuard {
+        LOCAL.with(|local| local.depth.set(local.depth.get() + 1));
+        QsbrGuard {
+            _not_send: PhantomData,
+        }
+    }
+
+    fn protect<T>(slot: &AtomicPtr<T>, _guard: &QsbrGuard) -> *mut T {
+        slot.load(Ordering::SeqCst)
+    }
+
+    fn protect_ordered<T>(slot: &AtomicPtr<T>, _guard: &QsbrGuard, order: Ordering) -> *mut T {
+        slot.load(order)
+    }
+
+    unsafe fn retire_with<T, F>(_guard: &QsbrGuard, ptr: *mut T, f: F)
+    where
+        T: Send + 'static,
+        F: FnOnce(Box<T>) + Send + 'static,
+    {
+        let ptr = SendPtr(ptr);
+        push_retired(Box::new(move || f(Box::from_raw(ptr.0))));
+    }
+
+    unsafe fn retire_batch_with<T, F>(_guard: &QsbrGuard, ptrs: Vec<*mut T>, f: F)
+    where
+        T: Send + 'static,
+        F: Fn(Box<T>) + Send + Sync + 'static,
+    {
+        // Every pointer gets the same stamp anyway, so one entry will do.
+        let ptrs: Vec<_> = ptrs.into_iter().map(SendPtr).collect();
+        push_retired(Box::new(move || {
+            for ptr in ptrs {
+                f(Box::from_raw(ptr.0));
+            }
+        }));
+    }
+
+    fn flush(_guard: &QsbrGuard) {
+        LOCAL.with(collect);
+    }
+
+    /// Announce a quiescent state, unless this thread still has a guard
+    /// alive (in which case it isn't really quiescent, and nothing happens).
+    fn quiescent() {
+        LOCAL.with(|local| {
+            if local.depth.get() == 0 {
+                local
+                    .record
+                    .seen
+                    .store(COUNTER.load(Ordering::SeqCst), Ordering::SeqCst);
+                collect(local);
+            }
+        });
+    }
+}
@@ -0,0 +1,164 @@
+//! Drive an epoch `BirdCage` by hand, one command at a time, and watch
+//! exactly when the deferred drops fire.
+//!
+//! Every canary is verbose, so a drop prints the moment it happens, in
+//! between the commands.  `pin` holds a guard until the matching `unpin`,
+//! and while one is held, `access` and `replace` run under it instead of
+//! pinning for themselves.  Try replacing a slot while pinned and then
+//! flushing: nothing gets dropped until you unpin (and flush a couple more
+//! times, since the epoch has to advance twice).
+//!
+//! Commands are read from any `BufRead`, so a what-if can be saved in a
+//! file and piped in.
+
+use crate::reclaim::{self, Epoch, Reclaimer};
+use crate::{BirdCage, Cage, Canary};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+commands:
+    access N        print the canary in slot N
+    replace N NAME  put a new canary called NAME into slot N
+    take N          empty slot N
+    pin             start holding a guard (pins can nest)
+    unpin           drop the most recent guard
+    flush           push this thread's garbage along and try to collect
+    stats           canaries created, dropped and alive; values pending
+    help            print this message
+    quit            leave (end of input works too)
+";
+
+enum Command {
+    Access(usize),
+    Replace(usize, String),
+    Take(usize),
+    Pin,
+    Unpin,
+    Flush,
+    Stats,
+    Help,
+    Quit,
+}
+
+fn parse(line: &str, cage_size: usize) -> Result<Option<Command>, String> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some(word) => word,
+        None => return Ok(None),
+    };
+    let mut slot = || -> Result<usize, String> {
+        let word = words.next().ok_or_else(|| format!("{} needs a slot", command))?;
+        match word.parse() {
+            Ok(n) if n < cage_size => Ok(n),
+            _ => Err(format!("bad slot {:?}: there are {} slots", word, cage_size)),
+        }
+    };
+    let parsed = match command {
+        "access" => Command::Access(slot()?),
+        "replace" => {
+            let n = slot()?;
+            let name: Vec<&str> = words.by_ref().collect();
+            if name.is_empty() {
+                return Err("replace needs a name".to_owned());
+            }
+            Command::Replace(n, name.join(" "))
+        }
+        "take" => Command::Take(slot()?),
+        "pin" => Command::Pin,
+        "unpin" => Command::Unpin,
+        "flush" => Command::Flush,
+        "stats" => Command::Stats,
+        "help" => Command::Help,
+        "quit" | "exit" => Command::Quit,
+        _ => return Err(format!("unknown command {:?}; try help", command)),
+    };
+    match words.next() {
+        Some(extra) => Err(format!("unexpected {:?} after {}", extra, command)),
+        None => Ok(Some(parsed)),
+    }
+}
+
+/// Read commands from `input` until it runs out or says `quit`, running
+/// each against a cage of `cage_size` canaries.
+pub fn run<B: BufRead>(cage_size: usize, input: B) {
+    let birdcage: BirdCage<Canary> =
+        BirdCage::from_fn(cage_size, |ii| Canary::new(&format!("Canary {}", ii)));
+    let mut guards: Vec<<Epoch as Reclaimer>::Guard> = Vec::new();
+    print!("{}", HELP);
+
+    let mut lines = input.lines();
+    loop {
+        print!("{}> ", "pinned ".repeat(guards.len()));
+        let _ = io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let command = match parse(&line, cage_size) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        match command {
+            Command::Access(n) => {
+                let accessed = match guards.last() {
+                    Some(guard) => birdcage.access_with(n, "repl", guard),
+                    None => birdcage.access(n, "repl"),
+                };
+                if let Err(e) = accessed {
+                    println!("[repl] {}", e);
+                }
+            }
+            Command::Replace(n, name) => {
+                let c = Canary::new(&name);
+                let replaced = match guards.last() {
+                    Some(guard) => birdcage.replace_with(n, "repl", c, guard),
+                    None => birdcage.replace(n, "repl", c),
+                };
+                if let Err(e) = replaced {
+                    println!("[repl] {}", e);
+                }
+            }
+            Command::Take(n) => match birdcage.take(n) {
+                // Dropping the `Taken` means the value is dropped by the
+                // reclaimer, whenever it gets around to it.
+                Ok(taken) => {
+                    drop(taken);
+                    println!("[repl] took slot {} ({} pending)", n, reclaim::pending());
+                }
+                Err(e) => println!("[repl] {}", e),
+            },
+            Command::Pin => {
+                guards.push(birdcage.pin());
+                println!("pinned ({} guards held)", guards.len());
+            }
+            Command::Unpin => match guards.pop() {
+                Some(guard) => {
+                    drop(guard);
+                    println!("unpinned ({} guards held)", guards.len());
+                }
+                None => println!("not pinned"),
+            },
+            Command::Flush => {
+                birdcage.flush();
+                println!("flushed ({} pending)", reclaim::pending());
+            }
+            Command::Stats => println!(
+                "{} canaries created, {} dropped, {} alive; {} values pending; {} guards held",
+                Canary::created(),
+                Canary::dropped(),
+                Canary::alive(),
+                reclaim::pending(),
+                guards.len()
+            ),
+            Command::Help => print!("{}", HELP),
+            Command::Quit => break,
+        }
+    }
+    println!();
+    // Let go of everything, so the cleanup afterwards can see it all.
+    drop(guards);
+}
@@ -0,0 +1,710 @@
+//! Workloads described in a file, so a library of named experiments can
+//! live next to the code and be run with `--scenario FILE`.
+//!
+//! A scenario is written in a small subset of TOML: `key = value` lines,
+//! with strings, numbers, booleans and arrays (which may span lines), plus
+//! one `[[thread]]` table for each kind of thread.  For example:
+//!
+//! ```toml
+//! name = "stalled reader"
+//! slots = 10
+//! duration = "2s"
+//! reclaimer = "epoch"      # or hazard, qsbr
+//! flush = "never"          # any --flush-policy
+//! distribution = "uniform" # any --slots
+//!
+//! [[thread]]
+//! name = "writer"
+//! count = 4
+//! write_percent = 100
+//!
+//! [[thread]]
+//! name = "sleepy reader"
+//! script = ["pin 500ms", "sleep 100ms"]
+//! ```
+//!
+//! A thread either has a `script`, which it runs over and over until the
+//! scenario's time is up, or a `write_percent`, and then picks random
+//! accesses and replaces in that ratio.  Either kind can have a `pause`
+//! between operations.  Script steps are:
+//!
+//! - `access` or `access N`: read a random slot, or slot N
+//! - `replace` or `replace N`: replace a random slot, or slot N
+//! - `flush`: flush this thread's garbage
+//! - `sleep T`: do nothing for T
+//! - `pin T`: pin, read a slot, and stay pinned for T
+//! - `hold T`: pin, and keep reading slots for T without unpinning, like a
+//!   reader walking a big structure
+//! - `hold T repin T2`: the same, but calling `repin` every T2, the usual
+//!   way to let the epoch advance under a long reader; compare
+//!   `scenarios/long-reader.toml` with `scenarios/long-reader-repin.toml`
+
+use crate::cli::parse_duration;
+use crate::counting_alloc;
+use crate::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use crate::stress::{self, ReclaimerKind, Sample};
+use crate::tui;
+use crate::workload::{self, Generator, Op, SlotDistribution};
+use crate::{BirdCage, Cage, Canary, FlushPolicy};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+// How many bars the report's garbage curve has.
+const CURVE_WIDTH: usize = 60;
+// How often a `hold` step reads a slot.  Reading flat out would starve the
+// writers on a small machine.
+const HOLD_READ_INTERVAL: Duration = Duration::from_millis(1);
+
+/// One step of a thread's script.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    /// Read a slot: the given one, or a random one.
+    Access(Option<usize>),
+    /// Replace a slot: the given one, or a random one.
+    Replace(Option<usize>),
+    Flush,
+    Sleep(Duration),
+    /// Pin, read a slot, and stay pinned this long.
+    Pin(Duration),
+    /// Pin, and keep reading slots this long, repinning as often as the
+    /// second duration says, if at all.
+    Hold(Duration, Option<Duration>),
+}
+
+/// What a thread does.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Work {
+    /// Run these steps over and over.
+    Script(Vec<Step>),
+    /// Random operations, this percentage of them replaces.
+    Mix { write_percent: f64 },
+}
+
+/// One kind of thread in a scenario.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThreadSpec {
+    pub name: String,
+    /// How many threads of this kind to run.
+    pub count: usize,
+    pub work: Work,
+    /// How long to wait after each operation.
+    pub pause: Duration,
+}
+
+/// A workload, as read from a scenario file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub cage_size: usize,
+    pub duration: Duration,
+    pub reclaimer: ReclaimerKind,
+    pub flush: FlushPolicy,
+    pub slots: SlotDistribution,
+    pub seed: Option<u64>,
+    pub threads: Vec<ThreadSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "a string",
+            Value::Int(_) => "an integer",
+            Value::Float(_) => "a number",
+            Value::Bool(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+// A table's keys, with the line each one was on.
+type Table = Vec<(String, Value, usize)>;
+
+// Cut a comment off the end of a line, leaving any `#` inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (ii, ch) in line.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..ii],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Parse one value from the front of `s`, returning it and the rest.
+fn parse_value(s: &str) -> Result<(Value, &str), String> {
+    let s = s.trim_start();
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((ii, ch)) = chars.next() {
+            match ch {
+                '"' => return Ok((Value::Str(out), &rest[ii + 1..])),
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => out.push('\n'),
+                    Some((_, 't')) => out.push('\t'),
+                    Some((_, c @ ('"' | '\\'))) => out.push(c),
+                    _ => return Err("bad escape in string".to_owned()),
+                },
+                c => out.push(c),
+            }
+        }
+        return Err("unterminated string".to_owned());
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected , or ] in array".to_owned());
+            }
+        }
+    }
+    let end = s.find(|c: char| c == ',' || c == ']' || c.is_whitespace()).unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+    let value = match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => {
+            let digits = word.replace('_', "");
+            if let Ok(n) = digits.parse() {
+                Value::Int(n)
+            } else if let Ok(x) = digits.parse() {
+                Value::Float(x)
+            } else {
+                return Err(format!("can't parse value {:?}", word));
+            }
+        }
+    };
+    Ok((value, rest))
+}
+
+// Split the file into the top-level table and the `[[thread]]` tables.
+fn parse_tables(text: &str) -> Result<(Table, Vec<(Table, usize)>), String> {
+    let mut top = Table::new();
+    let mut threads: Vec<(Table, usize)> = Vec::new();
+    let mut lines = text.lines().enumerate();
+
+    while let Some((ii, line)) = lines.next() {
+        let line_no = ii + 1;
+        let err = |e: String| format!("line {}: {}", line_no, e);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "[[thread]]" {
+            threads.push((Table::new(), line_no));
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(err(format!("unknown table {}", line)));
+        }
+        let (key, mut value) = match line.split_once('=') {
+            Some((k, v)) => (k.trim().to_owned(), v.trim().to_owned()),
+            None => return Err(err("expected key = value".to_owned())),
+        };
+        // An array can go on over several lines.
+        while value.starts_with('[') && parse_value(&value).is_err() {
+            match lines.next() {
+                Some((_, more)) => {
+                    value.push(' ');
+                    value.push_str(strip_comment(more).trim());
+                }
+                None => break,
+            }
+        }
+        let (parsed, rest) = parse_value(&value).map_err(err)?;
+        if !rest.trim().is_empty() {
+            return Err(err(format!("unexpected {:?} after the value", rest.trim())));
+        }
+        let table = match threads.last_mut() {
+            Some((t, _)) => t,
+            None => &mut top,
+        };
+        if table.iter().any(|(k, _, _)| *k == key) {
+            return Err(err(format!("{} is set twice", key)));
+        }
+        table.push((key, parsed, line_no));
+    }
+    Ok((top, threads))
+}
+
+// Takes typed values out of a table, and complains about leftovers.
+struct Fields {
+    table: Table,
+}
+
+impl Fields {
+    fn take(&mut self, key: &str) -> Option<(Value, usize)> {
+        let ii = self.table.iter().position(|(k, _, _)| k == key)?;
+        let (_, v, line) = self.table.remove(ii);
+        Some((v, line))
+    }
+
+    fn string(&mut self, key: &str) -> Result<Option<String>, String> {
+        match self.take(key) {
+            None => Ok(None),
+            Some((Value::Str(s), _)) => Ok(Some(s)),
+            Some((v, line)) => Err(wrong_type(key, "a string", &v, line)),
+        }
+    }
+
+    // A string value that parses with `FromStr`.
+    fn parsed<T: FromStr<Err = String>>(&mut self, key: &str) -> Result<Option<T>, String> {
+        let line = self.table.iter().find(|(k, _, _)| k == key).map(|e| e.2);
+        match self.string(key)? {
+            None => Ok(None),
+            Some(s) => s
+                .parse()
+                .map(Some)
+                .map_err(|e| format!("line {}: {}", line.unwrap_or(0), e)),
+        }
+    }
+
+    fn duration(&mut self, key: &str) -> Result<Option<Duration>, String> {
+        let line = self.table.iter().find(|(k, _, _)| k == key).map(|e| e.2);
+        match self.string(key)? {
+            None => Ok(None),
+            Some(s) => parse_duration(&s)
+                .map(Some)
+                .map_err(|e| format!("line {}: {}", line.unwrap_or(0), e)),
+        }
+    }
+
+    fn count(&mut self, key: &str) -> Result<Option<usize>, String> {
+        match self.take(key) {
+            None => Ok(None),
+            Some((Value::Int(n), _)) if n >= 0 => Ok(Some(n as usize)),
+            Some((v, line)) => Err(wrong_type(key, "a count", &v, line)),
+        }
+    }
+
+    fn number(&mut self, key: &str) -> Result<Option<f64>, String> {
+        match self.take(key) {
+            None => Ok(None),
+            Some((Value::Int(n), _)) => Ok(Some(n as f64)),
+            Some((Value::Float(x), _)) => Ok(Some(x)),
+            Some((v, line)) => Err(wrong_type(key, "a number", &v, line)),
+        }
+    }
+
+    fn strings(&mut self, key: &str) -> Result<Option<(Vec<String>, usize)>, String> {
+        match self.take(key) {
+            None => Ok(None),
+            Some((Value::Array(items), line)) => {
+                let strings = items
+                    .into_iter()
+                    .map(|v| match v {
+                        Value::Str(s) => Ok(s),
+                        v => Err(wrong_type(key, "an array of strings", &v, line)),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Some((strings, line)))
+            }
+            Some((v, line)) => Err(wrong_type(key, "an array of strings", &v, line)),
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self.table.first() {
+            None => Ok(()),
+            Some((k, _, line)) => Err(format!("line {}: unknown key {}", line, k)),
+        }
+    }
+}
+
+fn wrong_type(key: &str, wanted: &str, got: &Value, line: usize) -> String {
+    format!("line {}: {} should be {}, not {}", line, key, wanted, got.describe())
+}
+
+impl FromStr for Step {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let slot = |word: &str| {
+            word.parse()
+                .map_err(|_| format!("bad slot {:?} in step {:?}", word, s))
+        };
+        match words[..] {
+            ["access"] => Ok(Step::Access(None)),
+            ["access", n] => Ok(Step::Access(Some(slot(n)?))),
+            ["replace"] => Ok(Step::Replace(None)),
+            ["replace", n] => Ok(Step::Replace(Some(slot(n)?))),
+            ["flush"] => Ok(Step::Flush),
+            ["sleep", t] => Ok(Step::Sleep(parse_duration(t)?)),
+            ["pin", t] => Ok(Step::Pin(parse_duration(t)?)),
+            ["hold", t] => Ok(Step::Hold(parse_duration(t)?, None)),
+            ["hold", t, "repin", every] => {
+                Ok(Step::Hold(parse_duration(t)?, Some(parse_duration(every)?)))
+            }
+            _ => Err(format!("unknown step {:?}", s)),
+        }
+    }
+}
+
+impl Scenario {
+    /// Read a scenario file.
+    pub fn load(path: &Path) -> Result<Scenario, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Scenario::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parse the text of a scenario file.
+    pub fn parse(text: &str) -> Result<Scenario, String> {
+        let (top, thread_tables) = parse_tables(text)?;
+        let mut top = Fields { table: top };
+        let scenario = Scenario {
+            name: top.string("name")?.unwrap_or_else(|| "unnamed".to_owned()),
+            cage_size: top.count("slots")?.unwrap_or(10),
+            duration: top.duration("duration")?.unwrap_or(Duration::from_secs(2)),
+            reclaimer: top.parsed("reclaimer")?.unwrap_or(ReclaimerKind::Epoch),
+            flush: top.parsed("flush")?.unwrap_or(FlushPolicy::Never),
+            slots: top.parsed("distribution")?.unwrap_or(SlotDistribution::Uniform),
+            seed: top.count("seed")?.map(|s| s as u64),
+            threads: thread_tables
+                .into_iter()
+                .map(|(table, line)| ThreadSpec::parse(table, line))
+                .collect::<Result<_, _>>()?,
+        };
+        top.finish()?;
+
+        if scenario.cage_size == 0 {
+            return Err("slots must be at least 1".to_owned());
+        }
+        if scenario.threads.is_empty() {
+            return Err("a scenario needs at least one [[thread]]".to_owned());
+        }
+        for spec in &scenario.threads {
+            if let Work::Script(steps) = &spec.work {
+                for step in steps {
+                    if let Step::Access(Some(n)) | Step::Replace(Some(n)) = step {
+                        if *n >= scenario.cage_size {
+                            return Err(format!(
+                                "thread {:?} uses slot {}, but there are only {} slots",
+                                spec.name, n, scenario.cage_size
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(scenario)
+    }
+}
+
+impl ThreadSpec {
+    fn parse(table: Table, line: usize) -> Result<ThreadSpec, String> {
+        let mut fields = Fields { table };
+        let name = fields.string("name")?.unwrap_or_else(|| "thread".to_owned());
+        let count = fields.count("count")?.unwrap_or(1);
+        let pause = fields.duration("pause")?.unwrap_or(Duration::ZERO);
+        let script = fields.strings("script")?;
+        let write_percent = fields.number("write_percent")?;
+        fields.finish()?;
+
+        let work = match (script, write_percent) {
+            (Some(_), Some(_)) => {
+                return Err(format!("line {}: a thread has a script or a write_percent, not both", line))
+            }
+            (Some((steps, line)), None) => {
+                let steps: Vec<Step> = steps
+                    .iter()
+                    .map(|s| s.parse().map_err(|e| format!("line {}: {}", line, e)))
+                    .collect::<Result<_, _>>()?;
+                if steps.is_empty() {
+                    return Err(format!("line {}: the script is empty", line));
+                }
+                Work::Script(steps)
+            }
+            (None, Some(p)) if (0.0..=100.0).contains(&p) => Work::Mix { write_percent: p },
+            (None, Some(_)) => {
+                return Err(format!("line {}: write_percent must be between 0 and 100", line))
+            }
+            (None, None) => {
+                return Err(format!("line {}: a thread needs a script or a write_percent", line))
+            }
+        };
+        Ok(ThreadSpec {
+            name,
+            count,
+            work,
+            pause,
+        })
+    }
+}
+
+/// What a scenario run did.
+#[derive(Clone, Debug)]
+pub struct ScenarioReport {
+    pub name: String,
+    pub backend: &'static str,
+    pub seed: u64,
+    pub elapsed: Duration,
+    /// Each kind of thread's name, and how many operations all the threads
+    /// of that kind did between them.  Sleeps count as operations.
+    pub ops: Vec<(String, u64)>,
+    /// The most canaries alive beyond the ones in the cage, at any sample.
+    pub peak_garbage: usize,
+    pub mean_garbage: f64,
+    pub peak_pending: usize,
+    /// The most memory in use at once, if `CountingAlloc` is installed.
+    pub peak_bytes: Option<usize>,
+    /// Garbage and pending counts, sampled every millisecond.
+    pub timeline: Vec<Sample>,
+}
+
+impl ScenarioReport {
+    /// Write the garbage samples to `path` as CSV, one row per sample.
+    pub fn write_timeline(&self, path: &Path) -> io::Result<()> {
+        stress::write_timeline(&self.timeline, path)
+    }
+}
+
+impl fmt::Display for ScenarioReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({}, seed {}): {:.2}s",
+            self.name,
+            self.backend,
+            self.seed,
+            self.elapsed.as_secs_f64()
+        )?;
+        for (name, ops) in &self.ops {
+            writeln!(f, "  {:<20} {} ops", name, ops)?;
+        }
+        write!(
+            f,
+            "garbage: peak {}, mean {:.1}; peak pending {}",
+            self.peak_garbage, self.mean_garbage, self.peak_pending
+        )?;
+        if let Some(peak) = self.peak_bytes {
+            write!(f, "; memory peak {} KiB", peak / 1024)?;
+        }
+        // The worst of each stretch of samples, so short spikes still show.
+        let per_bar = self.timeline.len().div_ceil(CURVE_WIDTH).max(1);
+        let curve = self
+            .timeline
+            .chunks(per_bar)
+            .map(|c| c.iter().map(|s| s.garbage).max().unwrap_or(0));
+        write!(f, "\n  garbage over time: {}", tui::sparkline(curve))
+    }
+}
+
+/// Run `scenario`.
+pub fn run(scenario: &Scenario) -> ScenarioReport {
+    match scenario.reclaimer {
+        ReclaimerKind::Epoch => run_with::<Epoch>(scenario),
+        ReclaimerKind::Hazard => run_with::<HazardPointers>(scenario),
+        ReclaimerKind::Qsbr => run_with::<Qsbr>(scenario),
+    }
+}
+
+/// Run `scenario` with a specific `Reclaimer`, ignoring
+/// `scenario.reclaimer`.
+pub fn run_with<R: Reclaimer>(scenario: &Scenario) -> ScenarioReport {
+    let birdcage = BirdCage::<Canary, R>::from_fn(scenario.cage_size, |ii| {
+        Canary::silent(&format!("Canary {}", ii))
+    })
+    .with_flush_policy(scenario.flush);
+    let seed = scenario.seed.unwrap_or_else(workload::random_seed);
+    let stop = AtomicBool::new(false);
+    let ops: Vec<AtomicU64> = scenario.threads.iter().map(|_| AtomicU64::new(0)).collect();
+    let alive_before = Canary::alive();
+    counting_alloc::reset_peak();
+    let mut samples = Vec::new();
+
+    let start = Instant::now();
+    thread::scope(|s| {
+        let (birdcage, stop) = (&birdcage, &stop);
+        let mut stream = 0;
+        for (spec, ops) in scenario.threads.iter().zip(&ops) {
+            for id in 0..spec.count {
+                let rng = workload::thread_rng(seed, stream);
+                stream += 1;
+                let gen = match spec.work {
+                    Work::Mix { write_percent } => {
+                        Generator::new(scenario.slots, scenario.cage_size, write_percent)
+                    }
+                    Work::Script(_) => Generator::new(scenario.slots, scenario.cage_size, 0.0),
+                };
+                s.spawn(move || {
+                    let name = format!("{} {}", spec.name, id);
+                    let worker = Worker {
+                        birdcage,
+                        name,
+                        gen,
+                        rng,
+                        stop,
+                        pause: spec.pause,
+                    };
+                    ops.fetch_add(worker.run(&spec.work), Ordering::Relaxed);
+                });
+            }
+        }
+
+        while start.elapsed() < scenario.duration {
+            thread::sleep(SAMPLE_INTERVAL);
+            samples.push(Sample {
+                at: start.elapsed(),
+                garbage: Canary::alive().saturating_sub(alive_before),
+                pending: reclaim::pending(),
+            });
+        }
+        stop.store(true, Ordering::Relaxed);
+    });
+    let elapsed = start.elapsed();
+    let peak_bytes = counting_alloc::stats().map(|m| m.peak_bytes);
+    drop(birdcage);
+    reclaim::force_reclaim::<R>();
+
+    ScenarioReport {
+        name: scenario.name.clone(),
+        backend: R::NAME,
+        seed,
+        elapsed,
+        ops: scenario
+            .threads
+            .iter()
+            .zip(&ops)
+            .map(|(spec, n)| (spec.name.clone(), n.load(Ordering::Relaxed)))
+            .collect(),
+        peak_garbage: samples.iter().map(|s| s.garbage).max().unwrap_or(0),
+        mean_garbage: samples.iter().map(|s| s.garbage).sum::<usize>() as f64
+            / samples.len().max(1) as f64,
+        peak_pending: samples.iter().map(|s| s.pending).max().unwrap_or(0),
+        peak_bytes,
+        timeline: samples,
+    }
+}
+
+// One running thread of a scenario.
+struct Worker<'a, R: Reclaimer> {
+    birdcage: &'a BirdCage<Canary, R>,
+    name: String,
+    gen: Generator,
+    rng: workload::ThreadRng,
+    stop: &'a AtomicBool,
+    pause: Duration,
+}
+
+impl<R: Reclaimer> Worker<'_, R> {
+    // Keep working until told to stop, and return how many operations that
+    // took.
+    fn run(mut self, work: &Work) -> u64 {
+        let mut ops = 0;
+        'outer: while !self.stopped() {
+            match work {
+                Work::Script(steps) => {
+                    for step in steps {
+                        if self.stopped() {
+                            break 'outer;
+                        }
+                        self.step(*step);
+                        ops += 1;
+                    }
+                }
+                Work::Mix { .. } => {
+                    let step = match self.gen.next_op(&mut self.rng) {
+                        Op::Access(n) => Step::Access(Some(n)),
+                        Op::Replace(n) => Step::Replace(Some(n)),
+                    };
+                    self.step(step);
+                    ops += 1;
+                }
+            }
+        }
+        // Hand the leftovers over before the scope ends, so the final
+        // reclaim sees them.
+        self.birdcage.flush();
+        ops
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    fn step(&mut self, step: Step) {
+        let birdcage = self.birdcage;
+        match step {
+            Step::Access(n) => {
+                let n = n.unwrap_or_else(|| self.gen.slot(&mut self.rng));
+                let _ = birdcage.with_slot(n, |c| assert!(!c.name().is_empty()));
+            }
+            Step::Replace(n) => {
+                let n = n.unwrap_or_else(|| self.gen.slot(&mut self.rng));
+                birdcage.put(n, Canary::silent(&format!("{} Cuckoo", self.name)));
+            }
+            Step::Flush => birdcage.flush(),
+            Step::Sleep(d) => self.sleep(d),
+            Step::Pin(d) => {
+                let guard = birdcage.pin();
+                let n = self.gen.slot(&mut self.rng);
+                let _ = birdcage.get(n, &guard);
+                self.sleep(d);
+            }
+            Step::Hold(d, repin) => self.hold(d, repin),
+        }
+        birdcage.quiescent();
+        if !self.pause.is_zero() {
+            self.sleep(self.pause);
+        }
+    }
+
+    // Stay pinned for `d`, reading as we go, and repinning every `repin`.
+    fn hold(&mut self, d: Duration, repin: Option<Duration>) {
+        let birdcage = self.birdcage;
+        let start = Instant::now();
+        let mut guard = birdcage.pin();
+        let mut pinned_at = start;
+        while !self.stopped() && start.elapsed() < d {
+            let n = self.gen.slot(&mut self.rng);
+            if let Ok(c) = birdcage.get(n, &guard) {
+                assert!(!c.name().is_empty());
+            }
+            thread::sleep(HOLD_READ_INTERVAL);
+            if repin.is_some_and(|every| pinned_at.elapsed() >= every) {
+                // Nothing we read before this is protected any more.
+                guard = R::repin(guard);
+                pinned_at = Instant::now();
+            }
+        }
+    }
+
+    // Sleep for `d`, waking early if the scenario is over.
+    fn sleep(&self, d: Duration) {
+        let start = Instant::now();
+        while !self.stopped() && start.elapsed() < d {
+            thread::sleep(Duration::from_millis(10).min(d));
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use crate::cage::CageError;
+use crate::reclaim::SendPtr;
+use crossbeam::epoch::{self, Atomic, Collector, Guard, LocalHandle, Owned};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A `BirdCage` split into shards, each with its own `Collector`.
+///
+/// Slot `n` lives in shard `n % shards`.  A thread only pins the shard it
+/// is touching, so a reader that stays pinned in one shard holds up that
+/// shard's garbage and nobody else's, where with a single global collector
+/// it would hold up everything.
+///
+/// Like `PrivateBirdCage`, each thread has to `register()` first, and pass
+/// the `ShardHandle` it gets to every operation.
+pub struct ShardedBirdCage<T> {
+    shards: Vec<Shard<T>>,
+    len: usize,
+}
+
+struct Shard<T> {
+    c: Vec<Atomic<T>>,
+    collector: Collector,
+    counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    retired: AtomicU64,
+    destroyed: AtomicU64,
+}
+
+/// One thread's handles into every shard of a `ShardedBirdCage`.
+pub struct ShardHandle {
+    handles: Vec<LocalHandle>,
+}
+
+/// How much garbage one shard has seen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShardStats {
+    /// Values replaced so far.
+    pub retired: u64,
+    /// Replaced values that have been destroyed.
+    pub destroyed: u64,
+}
+
+impl ShardStats {
+    /// Replaced values still waiting on this shard's collector.
+    pub fn pending(&self) -> u64 {
+        self.retired.saturating_sub(self.destroyed)
+    }
+}
+
+impl<T: Send + 'static> ShardedBirdCage<T> {
+    /// Create a cage with `size` slots spread over `shards` shards, filling
+    /// slot `n` with `f(n)`.
+    ///
+    /// # Panics
+    ///
+    /// If `shards` is zero.
+    pub fn from_fn<F>(size: usize, shards: usize, mut f: F) -> ShardedBirdCage<T>
+    where
+        F: FnMut(usize) -> T,
+    {
+        assert!(shards > 0, "a ShardedBirdCage needs at least one shard");
+        let mut cage = ShardedBirdCage {
+            shards: (0..shards)
+                .map(|_| Shard {
+                    c: Vec::new(),
+                    collector: Collector::new(),
+                    counters: Arc::default(),
+                })
+                .collect(),
+            len: size,
+        };
+        for n in 0..size {
+            cage.shards[n % shards].c.push(Atomic::new(f(n)));
+        }
+        cage
+    }
+
+    /// Get this thread's handles, one per shard.
+    pub fn register(&self) -> ShardHandle {
+        ShardHandle {
+            handles: self.shards.iter().map(|s| s.collector.register()).collect(),
+        }
+    }
+
+    /// The number of slots in the cage.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Which shard slot `n` lives in.
+    pub fn shard_of(&self, n: usize) -> usize {
+        n % self.shards.len()
+    }
+
+    /// Pin shard `shard` only.  References into that shard's slots stay
+    /// valid while the guard is alive; the other shards carry on.
+    pub fn pin_shard(&self, handle: &ShardHandle, shard: usize) -> Guard {
+        let handle = &handle.handles[shard];
+        // A guard from some other collector wouldn't protect anything.
+        assert!(
+            handle.collector() == &self.shards[shard].collector,
+            "ShardHandle belongs to a different cage"
+        );
+        handle.pin()
+    }
+
+    // Slot `n` and the shard it lives in, if there is one.
+    fn slot(&self, n: usize) -> Result<(&Shard<T>, &Atomic<T>), CageError> {
+        if n >= self.len {
+            return Err(CageError::IndexOutOfBounds { index: n, len: self.len });
+        }
+        let shard = &self.shards[self.shard_of(n)];
+        Ok((shard, &shard.c[n / self.shards.len()]))
+    }
+
+    /// Get a reference to the value in slot `n`.  `guard` must have come
+    /// from `pin_shard` for the slot's shard.
+    pub fn get<'g>(&self, n: usize, guard: &'g Guard) -> Result<&'g T, CageError> {
+        let (shard, slot) = self.slot(n)?;
+        assert!(
+            guard.collector() == Some(&shard.collector),
+            "guard is for a different shard"
+        );
+        let shared = slot.load(Ordering::SeqCst, guard);
+        unsafe{shared.as_ref()}.ok_or(CageError::SlotEmpty { index: n })
+    }
+
+    /// Pin the shard holding slot `n`, and hand the value to `f`.
+    pub fn with_slot<F, R>(&self, handle: &ShardHandle, n: usize, f: F) -> Result<R, CageError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.slot(n)?;
+        let guard = &self.pin_shard(handle, self.shard_of(n));
+        self.get(n, guard).map(f)
+    }
+
+    /// Put `new_c` into slot `n`, and retire the old value into the slot's
+    /// shard.
+    pub fn replace(&self, handle: &ShardHandle, n: usize, new_c: T) -> Result<(), CageError> {
+        let (shard, slot) = self.slot(n)?;
+        let guard = &self.pin_shard(handle, self.shard_of(n));
+        let stolen_c = slot.swap(Owned::new(new_c), Ordering::SeqCst, guard);
+        if stolen_c.is_null() {
+            return Ok(());
+        }
+        shard.counters.retired.fetch_add(1, Ordering::Relaxed);
+        let counters = shard.counters.clone();
+        // We unlinked it, so we're the only ones retiring it, and this
+        // garbage goes into this shard's collector only, which is why it's
+        // counted in the shard's stats and not the global `pending`.
+        let ptr = SendPtr(stolen_c.as_raw() as *mut T);
+        guard.defer(move || {
+            drop(unsafe{Box::from_raw(ptr.0)});
+            counters.destroyed.fetch_add(1, Ordering::Relaxed);
+        });
+        Ok(())
+    }
+
+    /// Flush this thread's garbage in every shard.
+    pub fn flush(&self, handle: &ShardHandle) {
+        for shard in 0..self.shards.len() {
+            self.pin_shard(handle, shard).flush();
+        }
+    }
+
+    /// How much garbage each shard has seen, in shard order.
+    pub fn stats(&self) -> Vec<ShardStats> {
+        self.shards
+            .iter()
+            .map(|s| ShardStats {
+                retired: s.counters.retired.load(Ordering::Relaxed),
+                destroyed: s.counters.destroyed.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+impl<T> Drop for ShardedBirdCage<T> {
+    fn drop(&mut self) {
+        // We have `&mut self`, so nobody else can be looking at the slots.
+        // The values that are still in the cage can be destroyed right away.
+        unsafe {
+            let guard = epoch::unprotected();
+            for slot in self.shards.iter().flat_map(|s| &s.c) {
+                let shared = slot.load(Ordering::Relaxed, guard);
+                if !shared.is_null() {
+                    drop(shared.into_owned());
+                }
+            }
+        }
+        // Each collector destroys its garbage once its last handle is gone.
+    }
+}
@@ -0,0 +1,286 @@
+//! A simplified lock-free skiplist (a sorted set).
+//!
+//! Each node has a tower of `next` pointers, and may be linked into several
+//! levels at once.  Removing a node marks every level of its tower (top
+//! down, using the tag bit like the Harris list), and then searches unlink
+//! it one level at a time.  That means a node may be unlinked from level 3
+//! by one thread and from level 0 by another, and it's only garbage once
+//! the last of those has happened.
+//!
+//! To know when that is, each node counts how many levels it's currently
+//! linked into, plus one for the thread that's inserting it.  Whoever drops
+//! that count to zero hands the node to the epoch collector.
+
+use crossbeam::epoch::{self, pin, Atomic, Guard, Owned, Shared};
+use rand::Rng;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const MAX_HEIGHT: usize = 16;
+
+// The tag on a tower pointer that means "this node is deleted at this level".
+const DELETED: usize = 1;
+
+type Tower<K> = [Atomic<Node<K>>; MAX_HEIGHT];
+
+struct Node<K> {
+    key: K,
+    height: usize,
+    refs: AtomicUsize,
+    next: Tower<K>,
+}
+
+/// A lock-free sorted set, with removed nodes reclaimed by `crossbeam::epoch`.
+///
+/// A removed node goes to the collector only once the last level it was
+/// linked into lets go of it, which may be some other thread's search, so
+/// keys have to be `Send + 'static`.
+pub struct SkipList<K> {
+    head: Tower<K>,
+}
+
+// A random height, where each extra level is half as likely as the last.
+fn random_height() -> usize {
+    let mut rng = rand::thread_rng();
+    let mut height = 1;
+    while height < MAX_HEIGHT && rng.gen::<bool>() {
+        height += 1;
+    }
+    height
+}
+
+struct Position<'g, K> {
+    preds: [&'g Atomic<Node<K>>; MAX_HEIGHT],
+    succs: [Shared<'g, Node<K>>; MAX_HEIGHT],
+}
+
+impl<K: Ord + Send + 'static> Default for SkipList<K> {
+    fn default() -> Self {
+        SkipList::new()
+    }
+}
+
+impl<K: Ord + Send + 'static> SkipList<K> {
+    pub fn new() -> SkipList<K> {
+        SkipList {
+            head: Default::default(),
+        }
+    }
+
+    // Drop one reference to a node; the last one out schedules destruction.
+    fn release(&self, node: Shared<'_, Node<K>>, guard: &Guard) {
+        if unsafe{node.deref()}.refs.fetch_sub(1, Ordering::SeqCst) == 1 {
+            unsafe {
+                guard.defer_destroy(node);
+            }
+        }
+    }
+
+    // At every level, find the last node with a key < `key` and the node
+    // after it.  Marked nodes found along the way are unlinked.
+    fn find<'g>(&'g self, key: &K, guard: &'g Guard) -> Position<'g, K> {
+        'retry: loop {
+            let mut pos = Position {
+                preds: [&self.head[0]; MAX_HEIGHT],
+                succs: [Shared::null(); MAX_HEIGHT],
+            };
+            let mut tower = &self.head;
+
+            for level in (0..MAX_HEIGHT).rev() {
+                let mut pred = &tower[level];
+                let mut curr = pred.load(Ordering::SeqCst, guard);
+                if curr.tag() == DELETED {
+                    // Our predecessor is being deleted under us.
+                    continue 'retry;
+                }
+
+                while let Some(c) = unsafe{curr.as_ref()} {
+                    let succ = c.next[level].load(Ordering::SeqCst, guard);
+
+                    if succ.tag() == DELETED {
+                        let succ = succ.with_tag(0);
+                        match pred.compare_and_set(curr, succ, Ordering::SeqCst, guard) {
+                            Ok(_) => {
+                                self.release(curr, guard);
+                                curr = succ;
+                                continue;
+                            }
+                            Err(_) => continue 'retry,
+                        }
+                    }
+
+                    if c.key >= *key {
+                        break;
+                    }
+                    tower = &c.next;
+                    pred = &c.next[level];
+                    curr = succ;
+                }
+
+                pos.preds[level] = pred;
+                pos.succs[level] = curr;
+            }
+            return pos;
+        }
+    }
+
+    /// Add `key` to the set.  Returns `false` if it was already present.
+    pub fn insert(&self, key: K) -> bool {
+        let guard = &pin();
+        let height = random_height();
+        let mut node = Owned::new(Node {
+            key,
+            height,
+            refs: AtomicUsize::new(0),
+            next: Default::default(),
+        });
+
+        // Link in the bottom level first; that's what decides membership.
+        let (mut pos, shared) = loop {
+            let pos = self.find(&node.key, guard);
+            if let Some(s) = unsafe{pos.succs[0].as_ref()} {
+                if s.key == node.key {
+                    return false;
+                }
+            }
+
+            // One reference for level 0, and one for us while we finish.
+            node.refs.store(2, Ordering::Relaxed);
+            node.next[0].store(pos.succs[0], Ordering::Relaxed);
+            match pos.preds[0].compare_and_set(pos.succs[0], node, Ordering::SeqCst, guard) {
+                Ok(shared) => break (pos, shared),
+                Err(e) => node = e.new,
+            }
+        };
+        let n = unsafe{shared.deref()};
+
+        // Now build the rest of the tower, giving up if someone removes us.
+        'levels: for level in 1..height {
+            loop {
+                let next = n.next[level].load(Ordering::SeqCst, guard);
+                if next.tag() == DELETED {
+                    break 'levels;
+                }
+                if next != pos.succs[level]
+                    && n.next[level]
+                        .compare_and_set(next, pos.succs[level], Ordering::SeqCst, guard)
+                        .is_err()
+                {
+                    // The only other thing that changes our pointers is marking.
+                    break 'levels;
+                }
+
+                n.refs.fetch_add(1, Ordering::SeqCst);
+                if pos.preds[level]
+                    .compare_and_set(pos.succs[level], shared, Ordering::SeqCst, guard)
+                    .is_ok()
+                {
+                    break;
+                }
+                // We still hold our own reference, so this can't reach zero.
+                n.refs.fetch_sub(1, Ordering::SeqCst);
+
+                pos = self.find(&n.key, guard);
+                if pos.succs[0] != shared {
+                    // We've already been removed from the bottom level.
+                    break 'levels;
+                }
+            }
+        }
+
+        self.release(shared, guard);
+        true
+    }
+
+    /// Remove `key` from the set.  Returns `false` if it wasn't present.
+    pub fn remove(&self, key: &K) -> bool {
+        let guard = &pin();
+        let pos = self.find(key, guard);
+        let n = match unsafe{pos.succs[0].as_ref()} {
+            Some(n) if n.key == *key => n,
+            _ => return false,
+        };
+
+        // Mark the upper levels, top down.  It doesn't matter who wins these.
+        for level in (1..n.height).rev() {
+            loop {
+                let next = n.next[level].load(Ordering::SeqCst, guard);
+                if next.tag() == DELETED
+                    || n.next[level]
+                        .compare_and_set(next, next.with_tag(DELETED), Ordering::SeqCst, guard)
+                        .is_ok()
+                {
+                    break;
+                }
+            }
+        }
+
+        // Whoever marks the bottom level is the one who removed the key.
+        loop {
+            let next = n.next[0].load(Ordering::SeqCst, guard);
+            if next.tag() == DELETED {
+                return false;
+            }
+            if n.next[0]
+                .compare_and_set(next, next.with_tag(DELETED), Ordering::SeqCst, guard)
+                .is_ok()
+            {
+                break;
+            }
+        }
+
+        // Let a search do the unlinking.
+        self.find(key, guard);
+        true
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        let guard = &pin();
+        let pos = self.find(key, guard);
+        match unsafe{pos.succs[0].as_ref()} {
+            Some(n) => n.key == *key,
+            None => false,
+        }
+    }
+
+    /// Copy out every key that isn't deleted, in order, under one guard.
+    pub fn to_vec(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let guard = &pin();
+        let mut keys = Vec::new();
+        let mut curr = self.head[0].load(Ordering::SeqCst, guard);
+
+        while let Some(c) = unsafe{curr.as_ref()} {
+            let next = c.next[0].load(Ordering::SeqCst, guard);
+            if next.tag() != DELETED {
+                keys.push(c.key.clone());
+            }
+            curr = next.with_tag(0);
+        }
+        keys
+    }
+}
+
+impl<K> Drop for SkipList<K> {
+    fn drop(&mut self) {
+        // A marked node can still be linked into some upper level after it's
+        // gone from the bottom, so collect everything reachable from any
+        // level.  Anything reachable hasn't been handed to the collector.
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut nodes = HashSet::new();
+            for level in 0..MAX_HEIGHT {
+                let mut curr = self.head[level].load(Ordering::Relaxed, guard);
+                while let Some(c) = curr.as_ref() {
+                    nodes.insert(curr.as_raw());
+                    curr = c.next[level].load(Ordering::Relaxed, guard).with_tag(0);
+                }
+            }
+            for node in nodes {
+                drop(Shared::from(node).into_owned());
+            }
+        }
+    }
+}
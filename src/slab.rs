@@ -0,0 +1,110 @@
+//! A concurrent slab, where a freed slot only becomes reusable after the
+//! epoch has advanced.
+//!
+//! Reusing memory too early is the usual epoch problem, but reusing an
+//! *index* too early is just as bad: a reader that looked up index 7 a
+//! moment ago could suddenly find someone else's value there.  So removing
+//! a value does two deferred things: destroy the value, and put its index
+//! back on the free list.  A thread that's still pinned since before the
+//! removal can never see that index handed out again.
+
+use crate::treiber_stack::TreiberStack;
+use crossbeam::epoch::{self, pin, Atomic, Owned, Shared};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A fixed-capacity concurrent slab of `T`, addressed by index.
+pub struct Slab<T> {
+    slots: Vec<Atomic<T>>,
+    free: Arc<TreiberStack<usize>>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl<T: Send + Sync + 'static> Slab<T> {
+    pub fn new(capacity: usize) -> Slab<T> {
+        let free = TreiberStack::new();
+        // Push in reverse, so the first insert gets index 0.
+        for ii in (0..capacity).rev() {
+            free.push(ii);
+        }
+        Slab {
+            slots: (0..capacity).map(|_| Atomic::null()).collect(),
+            free: Arc::new(free),
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Store `value` in a free slot and return its index.
+    ///
+    /// If there are no free slots (including when freed slots are still
+    /// waiting for the epoch to advance), the value is handed back.
+    pub fn insert(&self, value: T) -> Result<usize, T> {
+        let ii = match self.free.pop() {
+            Some(ii) => ii,
+            None => return Err(value),
+        };
+        // Nobody else can have this index, so a plain store is fine.
+        self.slots[ii].store(Owned::new(value), Ordering::SeqCst);
+        Ok(ii)
+    }
+
+    /// Hand the value at index `ii` to `f`, if there is one.
+    pub fn with<F, R>(&self, ii: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let guard = &pin();
+        let shared = self.slots.get(ii)?.load(Ordering::SeqCst, guard);
+        unsafe{shared.as_ref()}.map(f)
+    }
+
+    /// Remove the value at index `ii`.  The value is destroyed, and the
+    /// index becomes reusable, once the epoch has advanced.
+    pub fn remove(&self, ii: usize) -> bool {
+        let slot = match self.slots.get(ii) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let guard = &pin();
+        let old = slot.swap(Shared::null(), Ordering::SeqCst, guard);
+        if old.is_null() {
+            return false;
+        }
+
+        unsafe {
+            guard.defer_destroy(old);
+        }
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let free = self.free.clone();
+        let pending = self.pending.clone();
+        guard.defer(move || {
+            free.push(ii);
+            pending.fetch_sub(1, Ordering::SeqCst);
+        });
+        true
+    }
+
+    /// The number of removed slots that are waiting for the epoch to advance
+    /// before they can be reused.
+    pub fn pending_reuse(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+}
+
+impl<T> Drop for Slab<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            for slot in &self.slots {
+                let shared = slot.load(Ordering::Relaxed, guard);
+                if !shared.is_null() {
+                    drop(shared.into_owned());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! The array of atomic pointers behind a `BirdCage`, either packed tightly
+//! or with each slot on its own cache line.
+//!
+//! Packed slots share cache lines with their neighbours, so two threads
+//! writing to different slots can still fight over the same line.  That
+//! false sharing shows up in every contention measurement, which is why
+//! the padded layout exists.
+
+use crossbeam::utils::CachePadded;
+use std::ops::Index;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+pub(crate) enum Slots<T> {
+    Packed(Box<[AtomicPtr<T>]>),
+    Padded(Box<[CachePadded<AtomicPtr<T>>]>),
+}
+
+impl<T> Slots<T> {
+    /// Lay out `ptrs` one per slot, padded or not.
+    pub(crate) fn new<I>(ptrs: I, padded: bool) -> Slots<T>
+    where
+        I: IntoIterator<Item = *mut T>,
+    {
+        let ptrs = ptrs.into_iter().map(AtomicPtr::new);
+        if padded {
+            Slots::Padded(ptrs.map(CachePadded::new).collect())
+        } else {
+            Slots::Packed(ptrs.collect())
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Slots::Packed(s) => s.len(),
+            Slots::Padded(s) => s.len(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn is_padded(&self) -> bool {
+        match self {
+            Slots::Packed(_) => false,
+            Slots::Padded(_) => true,
+        }
+    }
+
+    /// Copy the pointers into a new layout.  The caller owns whatever they
+    /// point to, and must make sure only one of the two copies is used.
+    pub(crate) fn relayout(&self, padded: bool) -> Slots<T> {
+        let ptrs = (0..self.len()).map(|n| self[n].load(Ordering::SeqCst));
+        Slots::new(ptrs, padded)
+    }
+}
+
+impl<T> Index<usize> for Slots<T> {
+    type Output = AtomicPtr<T>;
+
+    fn index(&self, n: usize) -> &AtomicPtr<T> {
+        match self {
+            Slots::Packed(s) => &s[n],
+            Slots::Padded(s) => &s[n],
+        }
+    }
+}
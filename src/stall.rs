@@ -0,0 +1,286 @@
+//! What happens to garbage when one reader stays pinned.
+//!
+//! Writers replace canaries the whole time.  After a short warm-up, one
+//! reader pins, reads a slot, and then goes to sleep without unpinning.
+//! Under epochs (and QSBR) nothing retired after that point can be freed
+//! until the reader wakes up, so garbage and memory grow for as long as it
+//! sleeps.  Once it lets go, we measure how long it takes for the garbage
+//! to get back down to what it was during the warm-up.
+
+use crate::counting_alloc;
+use crate::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use crate::stress::ReclaimerKind;
+use crate::workload;
+use crate::{BirdCage, Canary, ShardedBirdCage};
+use rand::Rng;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+const WARM_UP: Duration = Duration::from_millis(200);
+
+/// How a stalled-reader run should be set up.
+#[derive(Clone, Debug)]
+pub struct StallConfig {
+    pub cage_size: usize,
+    pub writers: usize,
+    pub reclaimer: ReclaimerKind,
+    /// How long the reader stays pinned.
+    pub stall: Duration,
+    /// How long to wait for garbage to recover after the reader unpins
+    /// before giving up.
+    pub recovery_limit: Duration,
+    /// The seed for the writers' random number generators, or `None` to
+    /// pick one.
+    pub seed: Option<u64>,
+    /// If nonzero, use a `ShardedBirdCage` with this many shards instead of
+    /// a `BirdCage`.  The reader only pins the first shard.
+    pub shards: usize,
+}
+
+impl Default for StallConfig {
+    fn default() -> Self {
+        StallConfig {
+            cage_size: 10,
+            writers: 4,
+            reclaimer: ReclaimerKind::Epoch,
+            stall: Duration::from_secs(1),
+            recovery_limit: Duration::from_secs(5),
+            seed: None,
+            shards: 0,
+        }
+    }
+}
+
+/// What happened during a stalled-reader run.
+#[derive(Clone, Debug)]
+pub struct StallReport {
+    pub config: StallConfig,
+    /// The seed the run actually used.
+    pub seed: u64,
+    /// The most garbage seen during the warm-up, before the stall.  This is
+    /// the level garbage needs to get back to.
+    pub baseline_garbage: usize,
+    /// Garbage at the moment the reader unpinned.
+    pub garbage_at_unpin: usize,
+    /// For a sharded run, each shard's pending garbage at the moment the
+    /// reader unpinned.  Empty otherwise.
+    pub shard_pending_at_unpin: Vec<u64>,
+    pub peak_garbage: usize,
+    /// The allocator's peak during the run, if `CountingAlloc` is installed.
+    pub peak_bytes: Option<usize>,
+    /// How long after unpinning garbage got back to the baseline, or `None`
+    /// if it didn't within `recovery_limit`.
+    pub recovery: Option<Duration>,
+    pub writes: u64,
+}
+
+impl fmt::Display for StallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.config.shards > 0 {
+            write!(f, "epoch, {} shards", self.config.shards)?;
+        } else {
+            write!(f, "{}", self.config.reclaimer.name())?;
+        }
+        writeln!(
+            f,
+            ": {} writers, {} slots, reader stalled for {:?}",
+            self.config.writers, self.config.cage_size, self.config.stall
+        )?;
+        writeln!(f, "seed: {}", self.seed)?;
+        writeln!(f, "writes: {}", self.writes)?;
+        writeln!(f, "garbage before the stall: {}", self.baseline_garbage)?;
+        writeln!(f, "garbage at unpin:         {}", self.garbage_at_unpin)?;
+        for (shard, pending) in self.shard_pending_at_unpin.iter().enumerate() {
+            writeln!(f, "  shard {} at unpin:       {}", shard, pending)?;
+        }
+        writeln!(f, "peak garbage:             {}", self.peak_garbage)?;
+        if let Some(bytes) = self.peak_bytes {
+            writeln!(f, "peak memory:              {} KiB", bytes / 1024)?;
+        }
+        match self.recovery {
+            Some(d) => write!(f, "recovered {:?} after unpin", d),
+            None => write!(
+                f,
+                "didn't recover within {:?} of unpin",
+                self.config.recovery_limit
+            ),
+        }
+    }
+}
+
+/// Run the stalled-reader scenario described by `config`.
+pub fn run(config: &StallConfig) -> StallReport {
+    if config.shards > 0 {
+        return run_sharded(config);
+    }
+    match config.reclaimer {
+        ReclaimerKind::Epoch => run_with::<Epoch>(config),
+        ReclaimerKind::Hazard => run_with::<HazardPointers>(config),
+        ReclaimerKind::Qsbr => run_with::<Qsbr>(config),
+    }
+}
+
+/// Run the scenario with a specific `Reclaimer`, ignoring
+/// `config.reclaimer` and `config.shards`.
+pub fn run_with<R: Reclaimer>(config: &StallConfig) -> StallReport {
+    let birdcage: BirdCage<Canary, R> = BirdCage::from_fn(config.cage_size, |ii| {
+        Canary::silent(&format!("Canary {}", ii))
+    });
+    let seed = config.seed.unwrap_or_else(workload::random_seed);
+
+    let report = drive(
+        config,
+        seed,
+        |id, stop| {
+            let mut rng = workload::thread_rng(seed, id as u64);
+            let mut count: u64 = 0;
+            while !stop.load(Ordering::Relaxed) {
+                let c = Canary::silent(&format!("writer {} Cuckoo {}", id, count));
+                birdcage.swap_and_destroy(rng.gen_range(0, birdcage.len()), c, |_| {}).unwrap();
+                count += 1;
+                if count.is_multiple_of(64) {
+                    R::quiescent();
+                }
+            }
+            count
+        },
+        || {
+            let guard = birdcage.pin();
+            assert!(birdcage.get(0, &guard).is_ok());
+            thread::sleep(config.stall);
+            drop(guard);
+            R::quiescent();
+        },
+        Vec::new,
+    );
+    reclaim::force_reclaim::<R>();
+    report
+}
+
+/// Run the scenario on a `ShardedBirdCage` with `config.shards` shards,
+/// ignoring `config.reclaimer`.  The reader pins only shard 0.
+pub fn run_sharded(config: &StallConfig) -> StallReport {
+    let birdcage = ShardedBirdCage::from_fn(config.cage_size, config.shards.max(1), |ii| {
+        Canary::silent(&format!("Canary {}", ii))
+    });
+    let seed = config.seed.unwrap_or_else(workload::random_seed);
+
+    drive(
+        config,
+        seed,
+        |id, stop| {
+            let handle = birdcage.register();
+            let mut rng = workload::thread_rng(seed, id as u64);
+            let mut count: u64 = 0;
+            while !stop.load(Ordering::Relaxed) {
+                let c = Canary::silent(&format!("writer {} Cuckoo {}", id, count));
+                birdcage.replace(&handle, rng.gen_range(0, birdcage.len()), c).unwrap();
+                count += 1;
+            }
+            count
+        },
+        || {
+            let handle = birdcage.register();
+            let guard = birdcage.pin_shard(&handle, 0);
+            assert!(birdcage.get(0, &guard).is_ok());
+            thread::sleep(config.stall);
+        },
+        || birdcage.stats().iter().map(|s| s.pending()).collect(),
+    )
+    // Dropping the cage here takes its collectors, and their garbage, with
+    // it.
+}
+
+// Everything but the cage: start the writers, stall a reader once they've
+// warmed up, and watch the garbage.  `writer` runs one writer thread until
+// `stop` is set and returns how many writes it did; `stall` pins, sleeps
+// and unpins; `shard_pending` reports each shard's pending garbage.
+fn drive<W, S, P>(
+    config: &StallConfig,
+    seed: u64,
+    writer: W,
+    stall: S,
+    shard_pending: P,
+) -> StallReport
+where
+    W: Fn(usize, &AtomicBool) -> u64 + Sync,
+    S: FnOnce() + Send,
+    P: Fn() -> Vec<u64>,
+{
+    let created_before = Canary::created() - config.cage_size;
+    let dropped_before = Canary::dropped();
+    let garbage = || {
+        let alive = (Canary::created() - created_before) - (Canary::dropped() - dropped_before);
+        alive.saturating_sub(config.cage_size)
+    };
+    counting_alloc::reset_peak();
+
+    // Sample garbage until `done` says to stop, returning the peak.
+    let sample_until = |done: &mut dyn FnMut(usize) -> bool| {
+        let mut peak = 0;
+        loop {
+            thread::sleep(SAMPLE_INTERVAL);
+            let g = garbage();
+            peak = peak.max(g);
+            if done(g) {
+                return peak;
+            }
+        }
+    };
+
+    let stop = AtomicBool::new(false);
+    let unpinned = AtomicBool::new(false);
+    thread::scope(|s| {
+        let writers: Vec<_> = (0..config.writers)
+            .map(|id| {
+                let (writer, stop) = (&writer, &stop);
+                s.spawn(move || writer(id, stop))
+            })
+            .collect();
+
+        let start = Instant::now();
+        let baseline_garbage = sample_until(&mut |_| start.elapsed() >= WARM_UP);
+
+        let reader = s.spawn(|| {
+            stall();
+            unpinned.store(true, Ordering::SeqCst);
+        });
+
+        let mut garbage_at_unpin = 0;
+        let mut pending_at_unpin = Vec::new();
+        let stall_peak = sample_until(&mut |g| {
+            garbage_at_unpin = g;
+            pending_at_unpin = shard_pending();
+            unpinned.load(Ordering::SeqCst)
+        });
+        reader.join().unwrap();
+
+        let unpinned_at = Instant::now();
+        let mut recovery = None;
+        let recovery_peak = sample_until(&mut |g| {
+            if g <= baseline_garbage {
+                recovery = Some(unpinned_at.elapsed());
+                return true;
+            }
+            unpinned_at.elapsed() >= config.recovery_limit
+        });
+
+        stop.store(true, Ordering::Relaxed);
+        let writes = writers.into_iter().map(|h| h.join().unwrap()).sum();
+
+        StallReport {
+            config: config.clone(),
+            seed,
+            baseline_garbage,
+            garbage_at_unpin,
+            shard_pending_at_unpin: pending_at_unpin,
+            peak_garbage: baseline_garbage.max(stall_peak).max(recovery_peak),
+            peak_bytes: counting_alloc::stats().map(|m| m.peak_bytes),
+            recovery,
+            writes,
+        }
+    })
+}
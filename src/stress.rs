@@ -0,0 +1,949 @@
+//! A multi-threaded workload that hammers one cage from separate reader and
+//! writer threads.
+
+use crate::affinity;
+use crate::chaos::{Chaos, ChaosStats, Gremlin};
+use crate::counting_alloc::{self, AllocStats};
+use crate::histogram::Histogram;
+use crate::json::{Object, Raw};
+use crate::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use crate::trace::{Recorder, Trace, TraceOp};
+use crate::workload::{self, Generator, Op, SlotDistribution, ThreadRng};
+use crate::{ArcCage, BirdCage, Cage, Canary, Contention, FlushPolicy, LockCage, MemoryOrder};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the main thread samples the amount of unreclaimed garbage.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+
+// How often the watchdog looks at the pending garbage.
+const WATCHDOG_INTERVAL: Duration = Duration::from_micros(500);
+
+// Each thread's gremlin draws from stream `CHAOS_STREAM + n`, well clear
+// of the streams the threads themselves use.
+const CHAOS_STREAM: u64 = 1 << 32;
+
+// Each thread times one of every this many reads (and writes), so reading
+// the clock doesn't swamp the operations being measured.
+const LATENCY_EVERY: u64 = 16;
+
+/// Which `Reclaimer` a stress run should use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReclaimerKind {
+    Epoch,
+    Hazard,
+    Qsbr,
+}
+
+impl ReclaimerKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ReclaimerKind::Epoch => Epoch::NAME,
+            ReclaimerKind::Hazard => HazardPointers::NAME,
+            ReclaimerKind::Qsbr => Qsbr::NAME,
+        }
+    }
+}
+
+impl FromStr for ReclaimerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "epoch" => Ok(ReclaimerKind::Epoch),
+            "hazard" => Ok(ReclaimerKind::Hazard),
+            "qsbr" => Ok(ReclaimerKind::Qsbr),
+            _ => Err(format!("unknown reclaimer: {:?}", s)),
+        }
+    }
+}
+
+/// Which cage a stress run should use.
+///
+/// Only `BirdCage` pays attention to the reclaimer; the other two are
+/// baselines that don't defer anything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CageKind {
+    BirdCage,
+    Arc,
+    Lock,
+}
+
+impl CageKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CageKind::BirdCage => "birdcage",
+            CageKind::Arc => "arc",
+            CageKind::Lock => "rwlock",
+        }
+    }
+}
+
+impl FromStr for CageKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "birdcage" => Ok(CageKind::BirdCage),
+            "arc" => Ok(CageKind::Arc),
+            "rwlock" => Ok(CageKind::Lock),
+            _ => Err(format!("unknown cage: {:?}", s)),
+        }
+    }
+}
+
+/// How a stress run should be set up.
+#[derive(Clone, Debug)]
+pub struct StressConfig {
+    pub cage_size: usize,
+    pub readers: usize,
+    pub writers: usize,
+    /// Threads that both access and replace, according to `write_percent`.
+    pub mixed: usize,
+    /// The percentage of a mixed thread's operations that are replaces.
+    pub write_percent: f64,
+    /// How every thread picks which slot to use.
+    pub slots: SlotDistribution,
+    pub duration: Duration,
+    pub flush: FlushPolicy,
+    pub cage: CageKind,
+    pub reclaimer: ReclaimerKind,
+    /// How many operations each thread does between quiescent states
+    /// (only QSBR cares).  Zero means never.
+    pub quiescent_every: u64,
+    /// If set, reader 0 never announces a quiescent state.
+    pub forgetful: bool,
+    /// The seed for every thread's random number generator, or `None` to
+    /// pick one.  The report records the seed that was used.
+    pub seed: Option<u64>,
+    /// If set, a watchdog thread forces a flush whenever more than this
+    /// many values are waiting on the reclaimer.
+    pub watchdog: Option<usize>,
+    /// If set, a background thread flushes the reclaimer this often, so the
+    /// application threads don't have to.
+    ///
+    /// Only the epoch backend really benefits: its garbage ends up in a
+    /// global queue anyone can collect, while hazard pointers and QSBR keep
+    /// each thread's garbage to itself, and only that thread can free it.
+    pub background_reclaim: Option<Duration>,
+    /// Whether a `BirdCage` gives each slot its own cache line.
+    pub padded: bool,
+    /// Whether writes go through `Cage::put_cas_with`, a CAS loop with
+    /// backoff, instead of a plain swap.
+    pub cas_writes: bool,
+    /// Whether to record every operation, for `StressReport::trace`.
+    pub record: bool,
+    /// Whether to pin every reader, writer and mixer to a core of its own
+    /// (taking turns, if there are more threads than cores), and count
+    /// their operations per core.
+    pub pin_cores: bool,
+    /// How strongly a `BirdCage` orders its slot operations.
+    pub memory_order: MemoryOrder,
+    /// If set, every thread misbehaves now and then, as described in
+    /// `chaos`.
+    pub chaos: Option<Chaos>,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        StressConfig {
+            cage_size: 10,
+            readers: 4,
+            writers: 4,
+            mixed: 0,
+            write_percent: 5.0,
+            slots: SlotDistribution::Uniform,
+            duration: Duration::from_secs(5),
+            flush: FlushPolicy::Never,
+            cage: CageKind::BirdCage,
+            reclaimer: ReclaimerKind::Epoch,
+            quiescent_every: 64,
+            forgetful: false,
+            seed: None,
+            watchdog: None,
+            background_reclaim: None,
+            padded: false,
+            cas_writes: false,
+            record: false,
+            pin_cores: false,
+            memory_order: MemoryOrder::SeqCst,
+            chaos: None,
+        }
+    }
+}
+
+/// What happened during a stress run.
+#[derive(Clone, Debug)]
+pub struct StressReport {
+    pub config: StressConfig,
+    /// The seed the run actually used, to replay it with `--seed`.
+    pub seed: u64,
+    pub elapsed: Duration,
+    pub reads: u64,
+    pub writes: u64,
+    /// A sample of read latencies, in nanoseconds.
+    pub read_latency: Histogram,
+    /// A sample of write latencies, in nanoseconds.
+    pub write_latency: Histogram,
+    /// How long each replaced canary waited to be dropped, in nanoseconds.
+    /// Canaries still waiting at the end of the run aren't included.
+    pub reclaim_delay: Histogram,
+    /// The allocator's counters at the start of the run, if `CountingAlloc`
+    /// is installed.
+    pub memory_before: Option<AllocStats>,
+    /// The allocator's counters after the final flush.  The peak covers the
+    /// whole run.
+    pub memory_after: Option<AllocStats>,
+    pub created: usize,
+    pub dropped: usize,
+    /// The most replaced-but-not-yet-dropped canaries seen at once.
+    pub peak_garbage: usize,
+    /// The average number of replaced-but-not-yet-dropped canaries.
+    pub mean_garbage: f64,
+    /// The most values waiting on the reclaimer at once, from
+    /// `reclaim::pending`.  Always zero for the baseline cages.
+    pub peak_pending: usize,
+    /// Every garbage sample taken during the run, in order.
+    pub timeline: Vec<Sample>,
+    /// How many times the watchdog checked, and how many of those it had
+    /// to force a flush, if it was running.
+    pub watchdog: Option<WatchdogStats>,
+    /// How many times the background reclaimer flushed, if it was running.
+    pub background_flushes: Option<u64>,
+    /// How often the writers' CAS loops had to go around again, if
+    /// `cas_writes` was set.
+    pub contention: Option<Contention>,
+    /// Every operation of the run, if `record` was set.  Chaos isn't
+    /// recorded, so replaying the trace runs it cleanly.
+    pub trace: Option<Trace>,
+    /// What the chaos did, if `chaos` was set.
+    pub chaos: Option<ChaosStats>,
+    /// The work done on each core, in core order, if `pin_cores` was set.
+    /// Threads that couldn't be pinned aren't counted.
+    pub per_core: Vec<CoreStats>,
+}
+
+/// What the threads pinned to one core did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoreStats {
+    pub core: usize,
+    pub threads: usize,
+    pub ops: u64,
+}
+
+/// What the garbage watchdog did during a run.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WatchdogStats {
+    pub checks: u64,
+    pub interventions: u64,
+}
+
+/// One sample of how much garbage there was, partway through a run.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    /// How far into the run the sample was taken.
+    pub at: Duration,
+    /// Replaced-but-not-yet-dropped canaries.
+    pub garbage: usize,
+    /// Values retired but not yet destroyed, from `reclaim::pending`.
+    pub pending: usize,
+}
+
+/// Write `samples` to `path` as CSV, one row per sample.
+pub fn write_timeline(samples: &[Sample], path: &Path) -> io::Result<()> {
+    let mut out = io::BufWriter::new(File::create(path)?);
+    writeln!(out, "elapsed_ms,garbage,pending")?;
+    for s in samples {
+        writeln!(
+            out,
+            "{:.3},{},{}",
+            s.at.as_secs_f64() * 1000.0,
+            s.garbage,
+            s.pending
+        )?;
+    }
+    out.flush()
+}
+
+impl StressReport {
+    /// A short name for what was measured: the reclaimer for a `BirdCage`,
+    /// or the cage itself for the baselines.
+    pub fn backend(&self) -> &'static str {
+        match self.config.cage {
+            CageKind::BirdCage => self.config.reclaimer.name(),
+            cage => cage.name(),
+        }
+    }
+
+    /// An estimate of how long a replaced canary waits before it's dropped,
+    /// from Little's law: mean garbage = write rate * mean delay.
+    pub fn mean_reclaim_delay(&self) -> Duration {
+        let write_rate = self.writes as f64 / self.elapsed.as_secs_f64();
+        if write_rate == 0.0 {
+            return Duration::from_secs(0);
+        }
+        Duration::from_secs_f64(self.mean_garbage / write_rate)
+    }
+
+    /// The column names for `csv_row`.
+    pub const CSV_HEADER: &'static str = "backend,cage,reclaimer,cage_size,readers,writers,\
+        mixed,write_percent,slots,flush,seed,elapsed_secs,reads,writes,ops_per_sec,\
+        read_p50_ns,read_p99_ns,write_p50_ns,write_p99_ns,peak_garbage,mean_garbage,\
+        mean_reclaim_delay_ns,reclaim_p50_ns,reclaim_p99_ns,reclaim_max_ns,start_bytes,peak_bytes,end_bytes,\
+        created,dropped,watchdog_interventions,background_reclaim_ms,padded,\
+        cas_retries,cas_spurious";
+
+    /// This report as one line of CSV, without a trailing newline.  The
+    /// memory columns are blank if `CountingAlloc` isn't installed.
+    pub fn csv_row(&self) -> String {
+        let blank = |v: Option<usize>| v.map(|v| v.to_string()).unwrap_or_default();
+        let c = &self.config;
+        let secs = self.elapsed.as_secs_f64();
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{:.3},{},{},{:.0},{},{},{},{},{},{:.1},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.backend(),
+            c.cage.name(),
+            c.reclaimer.name(),
+            c.cage_size,
+            c.readers,
+            c.writers,
+            c.mixed,
+            c.write_percent,
+            c.slots,
+            c.flush,
+            self.seed,
+            secs,
+            self.reads,
+            self.writes,
+            (self.reads + self.writes) as f64 / secs,
+            self.read_latency.quantile(0.5),
+            self.read_latency.quantile(0.99),
+            self.write_latency.quantile(0.5),
+            self.write_latency.quantile(0.99),
+            self.peak_garbage,
+            self.mean_garbage,
+            self.mean_reclaim_delay().as_nanos(),
+            self.reclaim_delay.quantile(0.5),
+            self.reclaim_delay.quantile(0.99),
+            self.reclaim_delay.max(),
+            blank(self.memory_before.map(|m| m.live_bytes)),
+            blank(self.memory_after.map(|m| m.peak_bytes)),
+            blank(self.memory_after.map(|m| m.live_bytes)),
+            self.created,
+            self.dropped,
+            self.watchdog
+                .map(|w| w.interventions.to_string())
+                .unwrap_or_default(),
+            c.background_reclaim
+                .map(|d| (d.as_secs_f64() * 1000.0).to_string())
+                .unwrap_or_default(),
+            c.padded,
+            self.contention
+                .map(|c| c.retries.to_string())
+                .unwrap_or_default(),
+            self.contention
+                .map(|c| c.spurious.to_string())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// This report, including the whole configuration, as a JSON document.
+    /// Latencies are in nanoseconds.
+    pub fn to_json(&self) -> String {
+        let c = &self.config;
+        let config = Object::new()
+            .field("cage", c.cage.name())
+            .field("reclaimer", c.reclaimer.name())
+            .field("cage_size", &c.cage_size)
+            .field("readers", &c.readers)
+            .field("writers", &c.writers)
+            .field("mixed", &c.mixed)
+            .field("write_percent", &c.write_percent)
+            .field("slots", &c.slots.to_string())
+            .field("duration_secs", &c.duration.as_secs_f64())
+            .field("flush", &c.flush.to_string())
+            .field("quiescent_every", &c.quiescent_every)
+            .field("forgetful", &c.forgetful)
+            // The seed the run used, whether it was asked for or picked.
+            .field("seed", &self.seed)
+            .field("padded", &c.padded)
+            .field("cas_writes", &c.cas_writes)
+            .field("pin_cores", &c.pin_cores)
+            .field("memory_order", &c.memory_order.to_string())
+            .field("chaos", &c.chaos.is_some())
+            .field(
+                "background_reclaim_ms",
+                &c.background_reclaim.map(|d| d.as_secs_f64() * 1000.0),
+            )
+            .finish();
+        let per_core: Vec<String> = self
+            .per_core
+            .iter()
+            .map(|c| {
+                Object::new()
+                    .field("core", &c.core)
+                    .field("threads", &c.threads)
+                    .field("ops", &c.ops)
+                    .finish()
+            })
+            .collect();
+        Object::new()
+            .field("config", &Raw(config))
+            .field("elapsed_secs", &self.elapsed.as_secs_f64())
+            .field("reads", &self.reads)
+            .field("writes", &self.writes)
+            .field(
+                "ops_per_sec",
+                &((self.reads + self.writes) as f64 / self.elapsed.as_secs_f64()),
+            )
+            .field("per_core", &Raw(format!("[{}]", per_core.join(","))))
+            .field("read_latency_ns", &self.read_latency)
+            .field("write_latency_ns", &self.write_latency)
+            .field("peak_garbage", &self.peak_garbage)
+            .field("mean_garbage", &self.mean_garbage)
+            .field("peak_pending", &self.peak_pending)
+            .field("watchdog_checks", &self.watchdog.map(|w| w.checks))
+            .field("watchdog_interventions", &self.watchdog.map(|w| w.interventions))
+            .field("background_flushes", &self.background_flushes)
+            .field("cas_retries", &self.contention.map(|c| c.retries))
+            .field("cas_spurious", &self.contention.map(|c| c.spurious))
+            .field("chaos_holds", &self.chaos.map(|c| c.holds))
+            .field("chaos_held_ms", &self.chaos.map(|c| c.held.as_secs_f64() * 1000.0))
+            .field("chaos_delays", &self.chaos.map(|c| c.delays))
+            .field("chaos_delayed_ms", &self.chaos.map(|c| c.delayed.as_secs_f64() * 1000.0))
+            .field("chaos_skipped_flushes", &self.chaos.map(|c| c.skipped_flushes))
+            .field("mean_reclaim_delay_ns", &self.mean_reclaim_delay().as_nanos())
+            .field("reclaim_delay_ns", &self.reclaim_delay)
+            .field("start_bytes", &self.memory_before.map(|m| m.live_bytes))
+            .field("peak_bytes", &self.memory_after.map(|m| m.peak_bytes))
+            .field("end_bytes", &self.memory_after.map(|m| m.live_bytes))
+            .field("created", &self.created)
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+
+    /// Write the garbage samples to `path` as CSV, one row per sample.
+    pub fn write_timeline(&self, path: &Path) -> io::Result<()> {
+        write_timeline(&self.timeline, path)
+    }
+
+    /// Append this report to the CSV file at `path`, writing the header
+    /// first if the file is new or empty.
+    pub fn append_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", Self::CSV_HEADER)?;
+        }
+        writeln!(file, "{}", self.csv_row())
+    }
+}
+
+impl fmt::Display for StressReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.elapsed.as_secs_f64();
+        let ops = self.reads + self.writes;
+        write!(
+            f,
+            "{}: {} readers, {} writers",
+            self.backend(),
+            self.config.readers,
+            self.config.writers,
+        )?;
+        if self.config.mixed > 0 {
+            write!(
+                f,
+                ", {} mixed ({}% writes)",
+                self.config.mixed, self.config.write_percent
+            )?;
+        }
+        writeln!(
+            f,
+            ", {} {}slots ({}), {:.2}s",
+            self.config.cage_size,
+            if self.config.padded { "padded " } else { "" },
+            self.config.slots,
+            secs
+        )?;
+        if self.config.memory_order != MemoryOrder::SeqCst {
+            writeln!(f, "memory order: {}", self.config.memory_order)?;
+        }
+        writeln!(f, "seed:    {}", self.seed)?;
+        writeln!(f, "reads:   {}", self.reads)?;
+        writeln!(f, "writes:  {}", self.writes)?;
+        writeln!(f, "ops/sec: {:.0}", ops as f64 / secs)?;
+        for c in &self.per_core {
+            writeln!(
+                f,
+                "  core {}: {:.0} ops/sec from {} thread{}",
+                c.core,
+                c.ops as f64 / secs,
+                c.threads,
+                if c.threads == 1 { "" } else { "s" }
+            )?;
+        }
+        writeln!(f, "read latency:  {}", self.read_latency)?;
+        writeln!(f, "write latency: {}", self.write_latency)?;
+        writeln!(f, "garbage: peak {}, mean {:.1}", self.peak_garbage, self.mean_garbage)?;
+        writeln!(f, "pending: peak {}", self.peak_pending)?;
+        if let Some(w) = self.watchdog {
+            writeln!(
+                f,
+                "watchdog: flushed {} times in {} checks",
+                w.interventions, w.checks
+            )?;
+        }
+        if let (Some(every), Some(flushes)) =
+            (self.config.background_reclaim, self.background_flushes)
+        {
+            writeln!(f, "background reclaimer: flushed {} times, every {:?}", flushes, every)?;
+        }
+        if let Some(c) = self.contention {
+            writeln!(
+                f,
+                "CAS writes: {} retries after backing off, {} spurious failures",
+                c.retries, c.spurious
+            )?;
+        }
+        if let Some(c) = self.chaos {
+            writeln!(f, "chaos: {}", c)?;
+        }
+        writeln!(f, "mean reclaim delay: {:?}", self.mean_reclaim_delay())?;
+        writeln!(f, "reclaim delay: {}", self.reclaim_delay)?;
+        if let (Some(before), Some(after)) = (self.memory_before, self.memory_after) {
+            writeln!(
+                f,
+                "memory: start {} KiB, peak {} KiB, end {} KiB",
+                before.live_bytes / 1024,
+                after.peak_bytes / 1024,
+                after.live_bytes / 1024
+            )?;
+        }
+        writeln!(f, "canaries created: {}", self.created)?;
+        writeln!(f, "canaries dropped: {}", self.dropped)?;
+        write!(f, "canaries alive:   {}", self.created - self.dropped)
+    }
+}
+
+fn read<C: Cage<Canary>>(birdcage: &C, pick: usize) {
+    birdcage.with_slot(pick, |c| {
+        // Touch the data so the read can't be optimized away.
+        assert!(!c.name().is_empty());
+    });
+}
+
+// Run `f`, timing it if this is the `count`th op and `count` is a multiple
+// of LATENCY_EVERY.
+fn timed<F: FnOnce()>(count: u64, latency: &mut Histogram, f: F) {
+    if count.is_multiple_of(LATENCY_EVERY) {
+        let start = Instant::now();
+        f();
+        latency.record_duration(start.elapsed());
+    } else {
+        f();
+    }
+}
+
+// What one thread did.
+#[derive(Default)]
+struct ThreadStats {
+    reads: u64,
+    writes: u64,
+    read_latency: Histogram,
+    write_latency: Histogram,
+    contention: Contention,
+    // Operations between quiescent states (zero means never).
+    quiescent_every: u64,
+    recorder: Option<Recorder>,
+    // The core this thread is pinned to, if it is.
+    core: Option<usize>,
+    gremlin: Option<Gremlin>,
+    chaos: ChaosStats,
+}
+
+impl ThreadStats {
+    fn new(
+        quiescent_every: u64,
+        recorder: Option<Recorder>,
+        gremlin: Option<Gremlin>,
+    ) -> ThreadStats {
+        ThreadStats {
+            quiescent_every,
+            recorder,
+            gremlin,
+            ..ThreadStats::default()
+        }
+    }
+
+    // Pin the calling thread to `core`, if there is one, and remember it if
+    // that worked.
+    fn pin_to(mut self, core: Option<usize>) -> ThreadStats {
+        self.core = core.filter(|&core| affinity::pin_current(core));
+        self
+    }
+
+    fn ops(&self) -> u64 {
+        self.reads + self.writes
+    }
+
+    fn record(&mut self, op: TraceOp) {
+        if let Some(r) = &mut self.recorder {
+            r.record(op);
+        }
+    }
+
+    // Give the gremlin, if there is one, its chance to hold a guard.
+    fn misbehave<C: Cage<Canary>>(&mut self, cage: &C) {
+        if let Some(g) = &mut self.gremlin {
+            g.before_op(cage);
+        }
+    }
+
+    // Called on the thread itself once it's done, since some of what the
+    // gremlin did was counted there.
+    fn finish(mut self) -> ThreadStats {
+        if let Some(g) = self.gremlin.take() {
+            self.chaos = g.finish();
+        }
+        self
+    }
+
+    // Announce a quiescent state every `quiescent_every` operations.
+    fn checkpoint<C: Cage<Canary>>(&mut self, cage: &C) {
+        let every = self.quiescent_every;
+        if every != 0 && self.ops().is_multiple_of(every) {
+            self.record(TraceOp::Quiescent);
+            cage.quiescent();
+        }
+    }
+
+    fn read<C: Cage<Canary>>(&mut self, birdcage: &C, pick: usize) {
+        self.record(TraceOp::Access(pick));
+        timed(self.reads, &mut self.read_latency, || read(birdcage, pick));
+        self.reads += 1;
+    }
+
+    fn write<C: Cage<Canary>>(&mut self, birdcage: &C, pick: usize, c: Canary, cas: bool) {
+        self.record(TraceOp::Replace(pick));
+        let delay = self.gremlin.as_mut().and_then(Gremlin::before_replace);
+        let removed = move |old: &Canary| {
+            if let Some(delay) = delay {
+                thread::sleep(delay);
+            }
+            Canary::mark_retired(old);
+        };
+        let contention = &mut self.contention;
+        timed(self.writes, &mut self.write_latency, || {
+            if cas {
+                contention.merge(&birdcage.put_cas_with(pick, c, removed));
+            } else {
+                birdcage.put_with(pick, c, removed);
+            }
+        });
+        self.writes += 1;
+    }
+
+    fn merge(&mut self, other: &ThreadStats) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+        self.read_latency.merge(&other.read_latency);
+        self.write_latency.merge(&other.write_latency);
+        self.contention.merge(&other.contention);
+        self.chaos.merge(&other.chaos);
+    }
+}
+
+// Force a flush whenever the pending garbage goes over `threshold`.
+fn watchdog<C: Cage<Canary>>(birdcage: &C, threshold: usize, stop: &AtomicBool) -> WatchdogStats {
+    let mut stats = WatchdogStats::default();
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(WATCHDOG_INTERVAL);
+        stats.checks += 1;
+        if reclaim::pending() > threshold {
+            birdcage.flush();
+            stats.interventions += 1;
+        }
+    }
+    stats
+}
+
+// Flush every `interval` until told to stop, returning how many times.
+fn background_reclaimer<C: Cage<Canary>>(
+    birdcage: &C,
+    interval: Duration,
+    stop: &AtomicBool,
+) -> u64 {
+    let mut flushes = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(interval);
+        birdcage.flush();
+        flushes += 1;
+    }
+    flushes
+}
+
+fn reader<C: Cage<Canary>>(
+    birdcage: &C,
+    gen: &Generator,
+    mut rng: ThreadRng,
+    mut stats: ThreadStats,
+    stop: &AtomicBool,
+) -> ThreadStats {
+    while !stop.load(Ordering::Relaxed) {
+        stats.misbehave(birdcage);
+        stats.read(birdcage, gen.slot(&mut rng));
+        stats.checkpoint(birdcage);
+    }
+    stats.finish()
+}
+
+fn writer<C: Cage<Canary>>(
+    birdcage: &C,
+    gen: &Generator,
+    mut rng: ThreadRng,
+    mut stats: ThreadStats,
+    stop: &AtomicBool,
+    id: usize,
+    cas: bool,
+) -> ThreadStats {
+    while !stop.load(Ordering::Relaxed) {
+        stats.misbehave(birdcage);
+        let c = Canary::silent(&format!("writer {} Cuckoo {}", id, stats.writes));
+        stats.write(birdcage, gen.slot(&mut rng), c, cas);
+        stats.checkpoint(birdcage);
+    }
+    stats.finish()
+}
+
+fn mixer<C: Cage<Canary>>(
+    birdcage: &C,
+    gen: &Generator,
+    mut rng: ThreadRng,
+    mut stats: ThreadStats,
+    stop: &AtomicBool,
+    id: usize,
+    cas: bool,
+) -> ThreadStats {
+    while !stop.load(Ordering::Relaxed) {
+        stats.misbehave(birdcage);
+        match gen.next_op(&mut rng) {
+            Op::Access(pick) => stats.read(birdcage, pick),
+            Op::Replace(pick) => {
+                let c = Canary::silent(&format!("mixer {} Cuckoo {}", id, stats.writes));
+                stats.write(birdcage, pick, c, cas);
+            }
+        }
+        stats.checkpoint(birdcage);
+    }
+    stats.finish()
+}
+
+/// Run the stress workload described by `config`.
+///
+/// The canary counts and reclaim delays in the report are global, so they
+/// also include any canaries created or dropped by other code running at
+/// the same time.
+pub fn run(config: &StressConfig) -> StressReport {
+    let fill = |ii| Canary::silent(&format!("Canary {}", ii));
+    match config.cage {
+        CageKind::BirdCage => match config.reclaimer {
+            ReclaimerKind::Epoch => run_with::<Epoch>(config),
+            ReclaimerKind::Hazard => run_with::<HazardPointers>(config),
+            ReclaimerKind::Qsbr => run_with::<Qsbr>(config),
+        },
+        CageKind::Arc => run_on(config, ArcCage::from_fn(config.cage_size, fill)),
+        CageKind::Lock => run_on(config, LockCage::from_fn(config.cage_size, fill)),
+    }
+}
+
+/// Run the stress workload on a `BirdCage` with a specific `Reclaimer`,
+/// ignoring `config.cage` and `config.reclaimer`.
+pub fn run_with<R: Reclaimer>(config: &StressConfig) -> StressReport {
+    let birdcage = BirdCage::<Canary, R>::from_fn(config.cage_size, |ii| {
+        Canary::silent(&format!("Canary {}", ii))
+    });
+    let birdcage = birdcage
+        .with_flush_policy(config.flush)
+        .with_padded_slots(config.padded)
+        .with_memory_order(config.memory_order);
+    run_on(config, birdcage)
+}
+
+/// Run the stress workload on an already-filled cage, ignoring
+/// `config.cage` and `config.reclaimer`.
+///
+/// The cage should hold `config.cage_size` canaries and nothing else, so
+/// that anything beyond that counts as garbage.
+pub fn run_on<C: Cage<Canary> + 'static>(config: &StressConfig, birdcage: C) -> StressReport {
+    let created_before = Canary::created() - birdcage.len();
+    Canary::reset_reclaim_delays();
+    counting_alloc::reset_peak();
+    let memory_before = counting_alloc::stats();
+    let dropped_before = Canary::dropped();
+    let garbage = || {
+        let alive = (Canary::created() - created_before) - (Canary::dropped() - dropped_before);
+        alive.saturating_sub(config.cage_size)
+    };
+
+    let birdcage = Arc::new(birdcage);
+    let stop = Arc::new(AtomicBool::new(false));
+    let gen = Generator::new(config.slots, config.cage_size, config.write_percent);
+    let seed = config.seed.unwrap_or_else(workload::random_seed);
+    let start = Instant::now();
+    // Readers, then writers, then mixers each get the next stream, which
+    // is also their thread number in a recorded trace.
+    let mut streams = 0..;
+    let cores = if config.pin_cores {
+        affinity::cores()
+    } else {
+        Vec::new()
+    };
+    let mut next_thread = || {
+        let stream = streams.next().unwrap();
+        let recorder = if config.record {
+            Some(Recorder::new(stream as usize, start))
+        } else {
+            None
+        };
+        let core = cores.get(stream as usize % cores.len().max(1)).copied();
+        let gremlin = config
+            .chaos
+            .map(|chaos| Gremlin::new(chaos, workload::thread_rng(seed, CHAOS_STREAM + stream)));
+        (workload::thread_rng(seed, stream), recorder, core, gremlin)
+    };
+    let mut readers = Vec::new();
+    let mut writers = Vec::new();
+    let mut mixers = Vec::new();
+
+    for id in 0..config.readers {
+        let birdcage = birdcage.clone();
+        let gen = gen.clone();
+        let (rng, recorder, core, gremlin) = next_thread();
+        let stop = stop.clone();
+        let every = if config.forgetful && id == 0 {
+            0
+        } else {
+            config.quiescent_every
+        };
+        let stats = ThreadStats::new(every, recorder, gremlin);
+        readers.push(thread::spawn(move || {
+            reader(&*birdcage, &gen, rng, stats.pin_to(core), &stop)
+        }));
+    }
+    for id in 0..config.writers {
+        let birdcage = birdcage.clone();
+        let gen = gen.clone();
+        let (rng, recorder, core, gremlin) = next_thread();
+        let stop = stop.clone();
+        let stats = ThreadStats::new(config.quiescent_every, recorder, gremlin);
+        let cas = config.cas_writes;
+        writers.push(thread::spawn(move || {
+            writer(&*birdcage, &gen, rng, stats.pin_to(core), &stop, id, cas)
+        }));
+    }
+    for id in 0..config.mixed {
+        let birdcage = birdcage.clone();
+        let gen = gen.clone();
+        let (rng, recorder, core, gremlin) = next_thread();
+        let stop = stop.clone();
+        let stats = ThreadStats::new(config.quiescent_every, recorder, gremlin);
+        let cas = config.cas_writes;
+        mixers.push(thread::spawn(move || {
+            mixer(&*birdcage, &gen, rng, stats.pin_to(core), &stop, id, cas)
+        }));
+    }
+
+    let watchdog = config.watchdog.map(|threshold| {
+        let birdcage = birdcage.clone();
+        let stop = stop.clone();
+        thread::spawn(move || watchdog(&*birdcage, threshold, &stop))
+    });
+
+    let background = config.background_reclaim.map(|interval| {
+        let birdcage = birdcage.clone();
+        let stop = stop.clone();
+        thread::spawn(move || background_reclaimer(&*birdcage, interval, &stop))
+    });
+
+    let mut timeline = Vec::new();
+    while start.elapsed() < config.duration {
+        thread::sleep(SAMPLE_INTERVAL);
+        timeline.push(Sample {
+            at: start.elapsed(),
+            garbage: garbage(),
+            pending: reclaim::pending(),
+        });
+    }
+    let total_garbage: usize = timeline.iter().map(|s| s.garbage).sum();
+    stop.store(true, Ordering::Relaxed);
+
+    let mut stats = ThreadStats::default();
+    let mut recorders = Vec::new();
+    let mut per_core = BTreeMap::new();
+    for h in readers.into_iter().chain(writers).chain(mixers) {
+        let mut thread_stats = h.join().unwrap();
+        recorders.extend(thread_stats.recorder.take());
+        if let Some(core) = thread_stats.core {
+            let c = per_core.entry(core).or_insert(CoreStats {
+                core,
+                threads: 0,
+                ops: 0,
+            });
+            c.threads += 1;
+            c.ops += thread_stats.ops();
+        }
+        stats.merge(&thread_stats);
+    }
+    let watchdog = watchdog.map(|h| h.join().unwrap());
+    let background_flushes = background.map(|h| h.join().unwrap());
+    let elapsed = start.elapsed();
+
+    // Give the deferred work a chance to run before we count drops.
+    birdcage.force_reclaim();
+
+    StressReport {
+        config: config.clone(),
+        seed,
+        elapsed,
+        reads: stats.reads,
+        writes: stats.writes,
+        read_latency: stats.read_latency,
+        write_latency: stats.write_latency,
+        reclaim_delay: Canary::reclaim_delays(),
+        memory_before,
+        memory_after: counting_alloc::stats(),
+        created: Canary::created() - created_before,
+        dropped: Canary::dropped() - dropped_before,
+        peak_garbage: timeline.iter().map(|s| s.garbage).max().unwrap_or(0),
+        mean_garbage: total_garbage as f64 / timeline.len().max(1) as f64,
+        peak_pending: timeline.iter().map(|s| s.pending).max().unwrap_or(0),
+        timeline,
+        watchdog,
+        background_flushes,
+        contention: if config.cas_writes {
+            Some(stats.contention)
+        } else {
+            None
+        },
+        trace: if config.record {
+            Some(Trace::from_recorders(config.cage_size, recorders))
+        } else {
+            None
+        },
+        per_core: per_core.into_values().collect(),
+        chaos: if config.chaos.is_some() {
+            Some(stats.chaos)
+        } else {
+            None
+        },
+    }
+}
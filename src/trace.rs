@@ -0,0 +1,312 @@
+//! Recording a stress run's operations, and playing them back later.
+//!
+//! A recorded stress run (`StressConfig::record`) notes every access,
+//! replace and quiescent state, with the thread that did it and when.
+//! `replay` runs the same operations again on a fresh cage, one thread per
+//! recorded thread, in the same order and with the same gaps between
+//! them, and samples the garbage as it goes.  Replay can't reproduce how
+//! the threads' operations overlapped to the nanosecond, but it does
+//! reproduce which operations started before which, so a run that piled up
+//! garbage in an interesting way can be saved, shared, and looked at
+//! again, even with a different reclaimer.
+//!
+//! Traces are plain text, one operation per line, and every operation of
+//! every thread gets a line, so keep recorded runs short: a few seconds
+//! of stress run is millions of lines.
+
+use crate::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use crate::stress::{ReclaimerKind, Sample};
+use crate::{BirdCage, Cage, Canary};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const HEADER: &str = "# epoch_playground trace";
+
+// How often replay samples the pending garbage, as stress runs do.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+
+// How far ahead of its time an operation is allowed to sleep, rather than
+// spin.  Sleeping can overshoot by about this much.
+const SLEEP_SLACK: Duration = Duration::from_millis(1);
+
+/// One recorded cage operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceOp {
+    Access(usize),
+    Replace(usize),
+    Quiescent,
+}
+
+/// A `TraceOp`, with which thread did it and when, relative to the start
+/// of the run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub at: Duration,
+    pub thread: usize,
+    pub op: TraceOp,
+}
+
+/// Every operation of a run, in the order they started.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trace {
+    /// The number of slots in the cage the trace was recorded on.
+    pub cage_size: usize,
+    /// The number of threads; every `Entry::thread` is less than this.
+    pub threads: usize,
+    pub entries: Vec<Entry>,
+}
+
+/// Notes one thread's operations as it goes.
+#[derive(Debug)]
+pub struct Recorder {
+    thread: usize,
+    start: Instant,
+    entries: Vec<Entry>,
+}
+
+impl Recorder {
+    /// Start recording for `thread`, with times measured from `start`.
+    pub fn new(thread: usize, start: Instant) -> Recorder {
+        Recorder {
+            thread,
+            start,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Note that this thread is about to do `op`.
+    pub fn record(&mut self, op: TraceOp) {
+        self.entries.push(Entry {
+            at: self.start.elapsed(),
+            thread: self.thread,
+            op,
+        });
+    }
+}
+
+impl Trace {
+    /// Put several threads' recordings together into one trace.
+    pub fn from_recorders<I>(cage_size: usize, recorders: I) -> Trace
+    where
+        I: IntoIterator<Item = Recorder>,
+    {
+        let mut trace = Trace {
+            cage_size,
+            ..Trace::default()
+        };
+        for r in recorders {
+            trace.threads = trace.threads.max(r.thread + 1);
+            trace.entries.extend(r.entries);
+        }
+        // Stable, so a thread's own operations never change places.
+        trace.entries.sort_by_key(|e| e.at);
+        trace
+    }
+
+    /// Write the trace to `path`, replacing whatever was there.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}", HEADER)?;
+        writeln!(out, "slots {}", self.cage_size)?;
+        writeln!(out, "threads {}", self.threads)?;
+        for e in &self.entries {
+            let at = e.at.as_nanos();
+            match e.op {
+                TraceOp::Access(n) => writeln!(out, "{} {} access {}", at, e.thread, n)?,
+                TraceOp::Replace(n) => writeln!(out, "{} {} replace {}", at, e.thread, n)?,
+                TraceOp::Quiescent => writeln!(out, "{} {} quiescent", at, e.thread)?,
+            }
+        }
+        out.flush()
+    }
+
+    /// Read a trace written by `save`.
+    pub fn load(path: &Path) -> io::Result<Trace> {
+        let invalid = |line: usize, what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), line, what),
+            )
+        };
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let mut next_line = || lines.next().unwrap_or_else(|| Ok(String::new()));
+        if next_line()? != HEADER {
+            return Err(invalid(1, "not a trace"));
+        }
+        let mut header = |line: usize, key: &str| -> io::Result<usize> {
+            next_line()?
+                .strip_prefix(key)
+                .and_then(|v| v.trim().parse().ok())
+                .ok_or_else(|| invalid(line, &format!("expected {:?}", key)))
+        };
+        let cage_size = header(2, "slots")?;
+        let threads = header(3, "threads")?;
+
+        let mut trace = Trace {
+            cage_size,
+            threads,
+            entries: Vec::new(),
+        };
+        for (ii, text) in lines.enumerate() {
+            let text = text?;
+            let line = ii + 4;
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let number = |word: &str| word.parse::<u64>().ok();
+            let slot = |word: &str| number(word).map(|n| n as usize).filter(|&n| n < cage_size);
+            let op = match words[..] {
+                [_, _, "access", n] => slot(n).map(TraceOp::Access),
+                [_, _, "replace", n] => slot(n).map(TraceOp::Replace),
+                [_, _, "quiescent"] => Some(TraceOp::Quiescent),
+                _ => None,
+            };
+            let at = words.first().and_then(|w| number(w));
+            let thread = words.get(1).and_then(|w| number(w)).map(|t| t as usize);
+            match (at, thread, op) {
+                (Some(at), Some(thread), Some(op)) if thread < threads => {
+                    trace.entries.push(Entry {
+                        at: Duration::from_nanos(at),
+                        thread,
+                        op,
+                    });
+                }
+                _ => return Err(invalid(line, "bad operation")),
+            }
+        }
+        Ok(trace)
+    }
+
+    /// How long the recorded run went on for.
+    pub fn duration(&self) -> Duration {
+        self.entries.last().map_or(Duration::ZERO, |e| e.at)
+    }
+}
+
+/// What happened during a replay.
+#[derive(Clone, Debug)]
+pub struct ReplayReport {
+    pub elapsed: Duration,
+    /// The trace's running time, for comparison with `elapsed`.
+    pub recorded: Duration,
+    pub ops: usize,
+    /// The most values waiting on the reclaimer at once.
+    pub peak_pending: usize,
+    /// Every sample taken during the replay, in order.  `garbage` counts
+    /// live canaries beyond the ones in the cage, as in a stress run.
+    pub timeline: Vec<Sample>,
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "replayed {} ops in {:.2}s (recorded in {:.2}s)",
+            self.ops,
+            self.elapsed.as_secs_f64(),
+            self.recorded.as_secs_f64()
+        )?;
+        let peak_garbage = self.timeline.iter().map(|s| s.garbage).max().unwrap_or(0);
+        write!(
+            f,
+            "peak garbage: {} canaries, peak pending: {}",
+            peak_garbage, self.peak_pending
+        )
+    }
+}
+
+/// Replay `trace` on a fresh `BirdCage` using `reclaimer`.
+pub fn replay(trace: &Trace, reclaimer: ReclaimerKind) -> ReplayReport {
+    match reclaimer {
+        ReclaimerKind::Epoch => replay_with::<Epoch>(trace),
+        ReclaimerKind::Hazard => replay_with::<HazardPointers>(trace),
+        ReclaimerKind::Qsbr => replay_with::<Qsbr>(trace),
+    }
+}
+
+/// Replay `trace` on a fresh `BirdCage` with a specific `Reclaimer`.
+pub fn replay_with<R: Reclaimer>(trace: &Trace) -> ReplayReport {
+    let birdcage = BirdCage::<Canary, R>::from_fn(trace.cage_size, |ii| {
+        Canary::silent(&format!("Canary {}", ii))
+    });
+    replay_on(trace, &birdcage)
+}
+
+/// Replay `trace` on an already-filled cage, which needs at least
+/// `trace.cage_size` slots.
+pub fn replay_on<C: Cage<Canary>>(trace: &Trace, cage: &C) -> ReplayReport {
+    assert!(cage.len() >= trace.cage_size, "the cage is too small for this trace");
+    let alive_before = Canary::alive();
+    // Which entry gets to start next.  Every thread waits for its own
+    // entries' turns, so they start in the recorded order.
+    let next = AtomicUsize::new(0);
+    let mut timeline = Vec::new();
+
+    let start = Instant::now();
+    thread::scope(|s| {
+        let (next, start) = (&next, &start);
+        for thread in 0..trace.threads {
+            s.spawn(move || {
+                let mine = trace.entries.iter().enumerate().filter(|(_, e)| e.thread == thread);
+                for (turn, e) in mine {
+                    while next.load(Ordering::Acquire) != turn {
+                        thread::yield_now();
+                    }
+                    wait_until(*start + e.at);
+                    next.store(turn + 1, Ordering::Release);
+                    match e.op {
+                        TraceOp::Access(n) => {
+                            cage.with_slot(n, |c| assert!(!c.name().is_empty()));
+                        }
+                        TraceOp::Replace(n) => {
+                            let c = Canary::silent(&format!("thread {} Cuckoo", thread));
+                            cage.put_with(n, c, Canary::mark_retired);
+                        }
+                        TraceOp::Quiescent => cage.quiescent(),
+                    }
+                }
+                // Hand the leftovers over before the scope ends, so the
+                // final reclaim sees them.
+                cage.flush();
+            });
+        }
+
+        while next.load(Ordering::Relaxed) < trace.entries.len() {
+            thread::sleep(SAMPLE_INTERVAL);
+            timeline.push(Sample {
+                at: start.elapsed(),
+                garbage: Canary::alive().saturating_sub(alive_before),
+                pending: reclaim::pending(),
+            });
+        }
+    });
+    let elapsed = start.elapsed();
+    cage.force_reclaim();
+
+    ReplayReport {
+        elapsed,
+        recorded: trace.duration(),
+        ops: trace.entries.len(),
+        peak_pending: timeline.iter().map(|s| s.pending).max().unwrap_or(0),
+        timeline,
+    }
+}
+
+// Sleep until shortly before `deadline`, then spin the rest of the way.
+fn wait_until(deadline: Instant) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+        let left = deadline - now;
+        if left > SLEEP_SLACK {
+            thread::sleep(left - SLEEP_SLACK);
+        } else {
+            thread::yield_now();
+        }
+    }
+}
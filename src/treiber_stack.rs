@@ -0,0 +1,120 @@
+//! A lock-free Treiber stack.
+//!
+//! Unlike the `BirdCage`, whose slots are independent, the stack's nodes
+//! point at each other.  A popped node may still be in use by another thread
+//! that loaded it as `head` a moment ago, so it has to be destroyed later.
+//!
+//! The `_with` methods take a guard, which can come from any `Collector`;
+//! the plain ones pin the default collector, which needs std.
+
+use core::mem::ManuallyDrop;
+use core::ptr;
+use core::sync::atomic::Ordering;
+#[cfg(feature = "std")]
+use crossbeam::epoch::pin;
+use crossbeam::epoch::{self, Atomic, Guard, Owned};
+
+struct Node<T> {
+    // The data is moved out by `pop`, so the node itself must not drop it.
+    data: ManuallyDrop<T>,
+    next: Atomic<Node<T>>,
+}
+
+/// A lock-free stack, with popped nodes reclaimed by `crossbeam::epoch`.
+pub struct TreiberStack<T> {
+    head: Atomic<Node<T>>,
+}
+
+impl<T> Default for TreiberStack<T> {
+    fn default() -> Self {
+        TreiberStack::new()
+    }
+}
+
+impl<T> TreiberStack<T> {
+    pub fn new() -> TreiberStack<T> {
+        TreiberStack {
+            head: Atomic::null(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn push(&self, value: T) {
+        self.push_with(value, &pin());
+    }
+
+    #[cfg(feature = "std")]
+    pub fn pop(&self) -> Option<T> {
+        self.pop_with(&pin())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn is_empty(&self) -> bool {
+        self.is_empty_with(&pin())
+    }
+
+    /// Like `push`, under a guard the caller already has.
+    ///
+    /// Every guard used with one stack has to come from the same collector.
+    pub fn push_with(&self, value: T, guard: &Guard) {
+        let mut node = Owned::new(Node {
+            data: ManuallyDrop::new(value),
+            next: Atomic::null(),
+        });
+
+        loop {
+            let head = self.head.load(Ordering::SeqCst, guard);
+            node.next.store(head, Ordering::Relaxed);
+
+            match self.head.compare_and_set(head, node, Ordering::SeqCst, guard) {
+                Ok(_) => return,
+                // Someone else got there first; try again on top of them.
+                Err(e) => node = e.new,
+            }
+        }
+    }
+
+    /// Like `pop`, under a guard the caller already has.
+    pub fn pop_with(&self, guard: &Guard) -> Option<T> {
+        loop {
+            let head = self.head.load(Ordering::SeqCst, guard);
+            let h = unsafe{head.as_ref()}?;
+            let next = h.next.load(Ordering::SeqCst, guard);
+
+            if self
+                .head
+                .compare_and_set(head, next, Ordering::SeqCst, guard)
+                .is_ok()
+            {
+                // We unlinked the node, so we're the only one who may take the
+                // data.  Other threads can still be reading `next`, though, so
+                // the node's memory has to wait for the epoch to advance.
+                unsafe {
+                    let data = ptr::read(&*h.data);
+                    guard.defer_destroy(head);
+                    return Some(data);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty_with(&self, guard: &Guard) -> bool {
+        self.head.load(Ordering::SeqCst, guard).is_null()
+    }
+}
+
+impl<T> Drop for TreiberStack<T> {
+    fn drop(&mut self) {
+        // Nobody else can see the stack any more, so we can walk it and
+        // free every node (and its data) immediately.
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut node = self.head.load(Ordering::Relaxed, guard);
+            while !node.is_null() {
+                let mut owned = node.into_owned();
+                node = owned.next.load(Ordering::Relaxed, guard);
+                ManuallyDrop::drop(&mut owned.data);
+            }
+        }
+    }
+}
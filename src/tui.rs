@@ -0,0 +1,300 @@
+//! A live dashboard: the stall scenario, redrawn in the terminal a few
+//! times a second instead of summed up at the end.
+//!
+//! Readers and writers hammer the cage, while a sleepy reader keeps
+//! pinning, sleeping for `stall`, and letting go.  Each frame shows every
+//! thread's op rate and how long it held its guards, the pending garbage
+//! (with a little history), and whether the epoch is moving.
+//!
+//! crossbeam-epoch doesn't expose the global epoch, so the dashboard does
+//! what the observer does: it retires a probe every frame and watches for
+//! the probes to be destroyed.  While the sleepy reader is pinned they
+//! stop coming back, and the epoch shows as stalled.
+//!
+//! The drawing is plain ANSI escapes, so it needs a terminal that
+//! understands them, but nothing else.
+
+use crate::counting_alloc;
+use crate::histogram::AtomicHistogram;
+use crate::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use crate::stress::ReclaimerKind;
+use crate::workload;
+use crate::{BirdCage, Cage, Canary};
+use rand::Rng;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Readers and writers time one of every this many operations.  The
+// sleepy reader times all of them, since there are so few.
+const TIME_EVERY: u64 = 16;
+// How many frames of pending-garbage history to draw.
+const HISTORY: usize = 60;
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// How a dashboard run should be set up.
+#[derive(Clone, Debug)]
+pub struct TuiConfig {
+    pub cage_size: usize,
+    pub readers: usize,
+    pub writers: usize,
+    pub reclaimer: ReclaimerKind,
+    /// How long the sleepy reader stays pinned each time, and then how
+    /// long it stays away before pinning again.
+    pub stall: Duration,
+    /// How long to run.
+    pub duration: Duration,
+    /// How often to redraw.
+    pub frame: Duration,
+    pub seed: Option<u64>,
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        TuiConfig {
+            cage_size: 10,
+            readers: 2,
+            writers: 2,
+            reclaimer: ReclaimerKind::Epoch,
+            stall: Duration::from_secs(1),
+            duration: Duration::from_secs(30),
+            frame: Duration::from_millis(250),
+            seed: None,
+        }
+    }
+}
+
+// What one thread has been up to, shared with the drawing thread.
+struct Panel {
+    name: String,
+    time_every: u64,
+    ops: AtomicU64,
+    hold: AtomicHistogram,
+    pinned: AtomicBool,
+}
+
+impl Panel {
+    fn new(name: String, time_every: u64) -> Panel {
+        Panel {
+            name,
+            time_every,
+            ops: AtomicU64::new(0),
+            hold: AtomicHistogram::new(),
+            pinned: AtomicBool::new(false),
+        }
+    }
+
+    // Count one operation, timing it now and then.  Every operation pins
+    // for exactly as long as it runs.
+    fn op<F: FnOnce()>(&self, f: F) {
+        let n = self.ops.fetch_add(1, Ordering::Relaxed);
+        if n.is_multiple_of(self.time_every) {
+            let start = Instant::now();
+            f();
+            self.hold.record_duration(start.elapsed());
+        } else {
+            f();
+        }
+    }
+}
+
+/// Run the dashboard described by `config`, drawing to stdout.
+pub fn run(config: &TuiConfig) {
+    match config.reclaimer {
+        ReclaimerKind::Epoch => run_with::<Epoch>(config),
+        ReclaimerKind::Hazard => run_with::<HazardPointers>(config),
+        ReclaimerKind::Qsbr => run_with::<Qsbr>(config),
+    }
+}
+
+/// Run the dashboard with a specific `Reclaimer`, ignoring
+/// `config.reclaimer`.
+pub fn run_with<R: Reclaimer>(config: &TuiConfig) {
+    let birdcage: BirdCage<Canary, R> = BirdCage::from_fn(config.cage_size, |ii| {
+        Canary::silent(&format!("Canary {}", ii))
+    });
+    let seed = config.seed.unwrap_or_else(workload::random_seed);
+    let stop = AtomicBool::new(false);
+    let panels: Vec<Panel> = (0..config.readers)
+        .map(|id| Panel::new(format!("reader {}", id), TIME_EVERY))
+        .chain((0..config.writers).map(|id| Panel::new(format!("writer {}", id), TIME_EVERY)))
+        .chain(Some(Panel::new("sleepy reader".to_owned(), 1)))
+        .collect();
+    let (readers, rest) = panels.split_at(config.readers);
+    let (writers, sleepy) = rest.split_at(config.writers);
+
+    thread::scope(|s| {
+        let (birdcage, stop) = (&birdcage, &stop);
+        for (id, panel) in readers.iter().enumerate() {
+            s.spawn(move || {
+                let mut rng = workload::thread_rng(seed, id as u64);
+                while !stop.load(Ordering::Relaxed) {
+                    let n = rng.gen_range(0, birdcage.len());
+                    panel.op(|| {
+                        let _ = birdcage.with_slot(n, |c| assert!(!c.name().is_empty()));
+                    });
+                    birdcage.quiescent();
+                }
+            });
+        }
+        for (id, panel) in writers.iter().enumerate() {
+            s.spawn(move || {
+                let stream = (config.readers + id) as u64;
+                let mut rng = workload::thread_rng(seed, stream);
+                while !stop.load(Ordering::Relaxed) {
+                    let n = rng.gen_range(0, birdcage.len());
+                    let c = Canary::silent(&format!("writer {} Cuckoo", id));
+                    panel.op(|| birdcage.put(n, c));
+                    birdcage.quiescent();
+                }
+            });
+        }
+        let panel = &sleepy[0];
+        s.spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                panel.op(|| {
+                    let guard = birdcage.pin();
+                    assert!(birdcage.get(0, &guard).is_ok());
+                    panel.pinned.store(true, Ordering::Relaxed);
+                    sleep_unless_stopped(config.stall, stop);
+                    panel.pinned.store(false, Ordering::Relaxed);
+                });
+                birdcage.quiescent();
+                sleep_unless_stopped(config.stall, stop);
+            }
+        });
+
+        draw::<R>(config, &panels, stop);
+        stop.store(true, Ordering::Relaxed);
+    });
+    reclaim::force_reclaim::<R>();
+}
+
+// Sleep for `d`, waking early if `stop` gets set.
+fn sleep_unless_stopped(d: Duration, stop: &AtomicBool) {
+    let start = Instant::now();
+    while !stop.load(Ordering::Relaxed) && start.elapsed() < d {
+        thread::sleep(Duration::from_millis(10).min(d));
+    }
+}
+
+// Redraw every frame until the run is over.
+fn draw<R: Reclaimer>(config: &TuiConfig, panels: &[Panel], stop: &AtomicBool) {
+    let start = Instant::now();
+    let completed = Arc::new(AtomicU64::new(0));
+    let mut issued = 0;
+    let mut stalled_since: Option<Instant> = None;
+    let mut history = VecDeque::with_capacity(HISTORY);
+    let mut last_ops: Vec<u64> = vec![0; panels.len()];
+    let mut last_frame = Instant::now();
+
+    while start.elapsed() < config.duration && !stop.load(Ordering::Relaxed) {
+        thread::sleep(config.frame);
+
+        // The probe from last frame should be gone by now, if the epoch
+        // moved at all.
+        let done = completed.load(Ordering::Relaxed);
+        if done == issued {
+            stalled_since = None;
+        } else if stalled_since.is_none() {
+            stalled_since = Some(last_frame);
+        }
+        issued += 1;
+        {
+            let guard = R::pin();
+            let probe = Box::into_raw(Box::new(issued));
+            let completed = completed.clone();
+            // Nobody else ever sees the probe, so it's trivially unreachable.
+            unsafe {
+                R::retire_with(&guard, probe, move |n| {
+                    completed.fetch_max(*n, Ordering::Relaxed);
+                });
+            }
+            R::flush(&guard);
+        }
+        R::quiescent();
+
+        let pending = reclaim::pending();
+        if history.len() == HISTORY {
+            history.pop_front();
+        }
+        history.push_back(pending);
+
+        let secs = last_frame.elapsed().as_secs_f64();
+        last_frame = Instant::now();
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}: {} readers, {} writers, sleepy reader pins for {:?}    {:.1}s / {:?}",
+            R::NAME,
+            config.readers,
+            config.writers,
+            config.stall,
+            start.elapsed().as_secs_f64(),
+            config.duration
+        );
+        let _ = writeln!(out);
+        let _ = match stalled_since {
+            None => writeln!(out, "epoch:   advancing (probe {} of {} back)", done, issued),
+            Some(t) => writeln!(
+                out,
+                "epoch:   STALLED for {:.1}s (probe {} of {} back)",
+                t.elapsed().as_secs_f64(),
+                done,
+                issued
+            ),
+        };
+        let _ = writeln!(out, "pending: {:<10} {}", pending, sparkline(history.iter().copied()));
+        if let Some(m) = counting_alloc::stats() {
+            let _ = writeln!(out, "memory:  {} KiB live", m.live_bytes / 1024);
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "{:<16}{:>12}{:>12}{:>12}{:>12}",
+            "thread", "ops/s", "hold p50", "hold p99", "hold max"
+        );
+        for (panel, last) in panels.iter().zip(last_ops.iter_mut()) {
+            let ops = panel.ops.load(Ordering::Relaxed);
+            let rate = (ops - *last) as f64 / secs;
+            *last = ops;
+            let hold = panel.hold.snapshot();
+            panel.hold.reset();
+            let ns = |v: u64| format!("{:?}", Duration::from_nanos(v));
+            let _ = write!(
+                out,
+                "{:<16}{:>12.0}{:>12}{:>12}{:>12}",
+                panel.name,
+                rate,
+                ns(hold.quantile(0.5)),
+                ns(hold.quantile(0.99)),
+                ns(hold.max())
+            );
+            if panel.pinned.load(Ordering::Relaxed) {
+                let _ = write!(out, "   pinned");
+            }
+            let _ = writeln!(out);
+        }
+
+        // Home the cursor and clear the screen, then draw the frame.
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let _ = write!(stdout, "\x1b[H\x1b[2J{}", out);
+        let _ = stdout.flush();
+    }
+}
+
+// A bar per value, scaled to the largest one.
+pub(crate) fn sparkline<I>(values: I) -> String
+where
+    I: IntoIterator<Item = usize>,
+    I::IntoIter: Clone,
+{
+    let values = values.into_iter();
+    let max = values.clone().max().unwrap_or(0).max(1);
+    values.map(|v| BARS[v * (BARS.len() - 1) / max]).collect()
+}
@@ -0,0 +1,141 @@
+//! Generators for the slot indices and access/replace mix used by the
+//! stress workload.
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt;
+use std::str::FromStr;
+
+/// The random number generator every randomized mode uses.
+pub type ThreadRng = ChaCha8Rng;
+
+/// A deterministic generator for thread number `thread` of a run seeded
+/// with `seed`.
+///
+/// Each thread gets its own ChaCha stream, so a run with the same seed and
+/// thread counts replays the same sequence of operations on every thread.
+/// (How those threads interleave is still up to the scheduler.)
+pub fn thread_rng(seed: u64, thread: u64) -> ThreadRng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(thread);
+    rng
+}
+
+/// Pick a seed for a run that wasn't given one.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// How slots are picked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlotDistribution {
+    /// Every slot is equally likely.
+    Uniform,
+    /// Slot `k` is picked with probability proportional to `1 / (k + 1)^s`,
+    /// so the low-numbered slots are hot.
+    Zipf(f64),
+}
+
+impl SlotDistribution {
+    /// The skew used for a plain `zipf`, which is the usual YCSB value.
+    pub const DEFAULT_ZIPF: f64 = 0.99;
+}
+
+impl fmt::Display for SlotDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlotDistribution::Uniform => write!(f, "uniform"),
+            SlotDistribution::Zipf(s) => write!(f, "zipf:{}", s),
+        }
+    }
+}
+
+impl FromStr for SlotDistribution {
+    type Err = String;
+
+    /// Accepts `uniform`, `zipf`, or `zipf:S` for a specific skew `S`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let bad = || format!("unknown slot distribution: {:?}", s);
+        match s {
+            "uniform" => Ok(SlotDistribution::Uniform),
+            "zipf" => Ok(SlotDistribution::Zipf(Self::DEFAULT_ZIPF)),
+            _ => {
+                let skew = s.strip_prefix("zipf:").ok_or_else(bad)?;
+                match skew.parse::<f64>() {
+                    Ok(skew) if skew >= 0.0 && skew.is_finite() => {
+                        Ok(SlotDistribution::Zipf(skew))
+                    }
+                    _ => Err(bad()),
+                }
+            }
+        }
+    }
+}
+
+/// One operation for a mixed workload thread to carry out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Access(usize),
+    Replace(usize),
+}
+
+/// Picks slots, and optionally operations, for one thread.
+///
+/// Building one walks every slot for the zipfian case, so each thread
+/// should build its own up front rather than one per operation.
+#[derive(Clone, Debug)]
+pub struct Generator {
+    len: usize,
+    write_percent: f64,
+    // The cumulative distribution over slots; empty means uniform.
+    cdf: Vec<f64>,
+}
+
+impl Generator {
+    /// A generator over `len` slots that makes `write_percent` percent of
+    /// its operations replaces.
+    pub fn new(slots: SlotDistribution, len: usize, write_percent: f64) -> Generator {
+        assert!(len > 0, "can't pick from zero slots");
+        let cdf = match slots {
+            SlotDistribution::Uniform => Vec::new(),
+            SlotDistribution::Zipf(s) => {
+                let mut total = 0.0;
+                let mut cdf: Vec<f64> = (0..len)
+                    .map(|k| {
+                        total += 1.0 / ((k + 1) as f64).powf(s);
+                        total
+                    })
+                    .collect();
+                for p in &mut cdf {
+                    *p /= total;
+                }
+                cdf
+            }
+        };
+        Generator {
+            len,
+            write_percent,
+            cdf,
+        }
+    }
+
+    /// Pick a slot.
+    pub fn slot<R: Rng>(&self, rng: &mut R) -> usize {
+        if self.cdf.is_empty() {
+            return rng.gen_range(0, self.len);
+        }
+        let x: f64 = rng.gen();
+        // Rounding can leave the last entry a hair under 1.0.
+        self.cdf.partition_point(|&p| p <= x).min(self.len - 1)
+    }
+
+    /// Pick an operation and the slot it applies to.
+    pub fn next_op<R: Rng>(&self, rng: &mut R) -> Op {
+        let slot = self.slot(rng);
+        if rng.gen::<f64>() * 100.0 < self.write_percent {
+            Op::Replace(slot)
+        } else {
+            Op::Access(slot)
+        }
+    }
+}
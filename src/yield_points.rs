@@ -0,0 +1,261 @@
+//! Hooks at the interesting points of `BirdCage`'s operations, where a
+//! thread can be made to yield or sleep, to provoke interleavings that
+//! almost never happen on their own.
+//!
+//! The hooks are compiled in with the `yield-points` feature, and do
+//! nothing at all without it:
+//!
+//! - `AfterLoad`: a slot's pointer has just been loaded and protected, and
+//!   nothing has been done with it yet.
+//! - `BeforeCas`: a compare-and-swap is about to try to replace what was
+//!   loaded, so a write from another thread here makes it fail.
+//! - `AfterDefer`: an old value has just been handed to the reclaimer.
+//!
+//! What a thread does at each hook comes from a `Schedule`: its seed, the
+//! thread's number and how many hooks the thread has hit so far decide, so
+//! the same seed makes the same threads stop at the same places every time.
+//! Specific steps can be pinned down with `Schedule::at`, to replay one
+//! interleaving on purpose.  Unlike loom this explores nothing by itself,
+//! and the OS scheduler still has a say, but it runs the real code at full
+//! speed, with the real reclaimers.
+//!
+//! ```ignore
+//! // Thread 0 stops for 10ms at its second hook, and nowhere else.
+//! let pause = Action::Sleep(Duration::from_millis(10));
+//! yield_points::set_schedule(Some(Schedule::new(7).quiet().at(0, 1, pause)));
+//! // ...then, on the thread that should stop:
+//! yield_points::set_thread(0);
+//! ```
+
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Where in an operation a hook is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Point {
+    AfterLoad,
+    BeforeCas,
+    AfterDefer,
+}
+
+impl Point {
+    /// Every point, in the order they come in an update.
+    pub const ALL: [Point; 3] = [Point::AfterLoad, Point::BeforeCas, Point::AfterDefer];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Point::AfterLoad => "after-load",
+            Point::BeforeCas => "before-cas",
+            Point::AfterDefer => "after-defer",
+        }
+    }
+}
+
+impl FromStr for Point {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Point::ALL
+            .iter()
+            .copied()
+            .find(|p| p.name() == s)
+            .ok_or_else(|| format!("unknown yield point: {:?}", s))
+    }
+}
+
+/// What a thread does when it hits a hook.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Yield,
+    Sleep(Duration),
+}
+
+/// Decides what every thread does at every hook.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    pub seed: u64,
+    /// The points that act; threads carry straight on at the others,
+    /// though they still count as steps.
+    pub points: Vec<Point>,
+    /// How often a hook yields, in percent.
+    pub yield_percent: u32,
+    /// How often a hook sleeps instead, in percent.
+    pub sleep_percent: u32,
+    /// The longest sleep; each is uniform up to this.
+    pub max_sleep: Duration,
+    // (thread, step, action) for steps that don't leave it to chance.
+    fixed: Vec<(u64, u64, Action)>,
+}
+
+impl Schedule {
+    /// A schedule that yields at one hook in five, and sleeps for up to
+    /// 100µs at one in twenty, everywhere.
+    pub fn new(seed: u64) -> Schedule {
+        Schedule {
+            seed,
+            points: Point::ALL.to_vec(),
+            yield_percent: 20,
+            sleep_percent: 5,
+            max_sleep: Duration::from_micros(100),
+            fixed: Vec::new(),
+        }
+    }
+
+    /// This schedule with chance taken out: nothing happens at any hook
+    /// except the ones set with `at`.
+    pub fn quiet(self) -> Schedule {
+        Schedule {
+            yield_percent: 0,
+            sleep_percent: 0,
+            ..self
+        }
+    }
+
+    /// This schedule, acting only at `points`.
+    pub fn only_at(self, points: &[Point]) -> Schedule {
+        Schedule {
+            points: points.to_vec(),
+            ..self
+        }
+    }
+
+    /// Make thread `thread` do `action` at its `step`th hook (counting
+    /// from zero), whatever the seed would have it do.
+    pub fn at(mut self, thread: u64, step: u64, action: Action) -> Schedule {
+        self.fixed.retain(|&(t, s, _)| (t, s) != (thread, step));
+        self.fixed.push((thread, step, action));
+        self
+    }
+
+    /// What thread `thread` does when its `step`th hook is at `point`.
+    pub fn action(&self, thread: u64, step: u64, point: Point) -> Action {
+        let fixed = self.fixed.iter().find(|&&(t, s, _)| (t, s) == (thread, step));
+        if let Some(&(_, _, action)) = fixed {
+            return action;
+        }
+        if !self.points.contains(&point) {
+            return Action::Continue;
+        }
+        let roll = mix(mix(mix(self.seed) ^ thread) ^ step);
+        let percent = (roll % 100) as u32;
+        if percent < self.yield_percent {
+            Action::Yield
+        } else if percent < self.yield_percent + self.sleep_percent {
+            let nanos = self.max_sleep.as_nanos() as u64;
+            Action::Sleep(Duration::from_nanos((roll >> 32) % nanos.max(1)))
+        } else {
+            Action::Continue
+        }
+    }
+}
+
+// SplitMix64's finalizer: a cheap, well-mixed hash of one word.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Whether the hooks were compiled in.
+pub fn enabled() -> bool {
+    cfg!(feature = "yield-points")
+}
+
+/// The hook itself.  Without the `yield-points` feature it's empty.
+#[inline(always)]
+pub fn hit(point: Point) {
+    #[cfg(feature = "yield-points")]
+    hooks::hit(point);
+    #[cfg(not(feature = "yield-points"))]
+    let _ = point;
+}
+
+/// Use `schedule` for every thread from now on, or stop acting at the
+/// hooks with `None`.  Does nothing without the `yield-points` feature.
+pub fn set_schedule(schedule: Option<Schedule>) {
+    #[cfg(feature = "yield-points")]
+    hooks::set_schedule(schedule);
+    #[cfg(not(feature = "yield-points"))]
+    drop(schedule);
+}
+
+/// Give the calling thread number `thread` in the schedule, and start its
+/// step count again from zero.
+///
+/// Threads that never call this are numbered from 2^32 up, in the order
+/// they first hit a hook, which isn't reproducible.
+pub fn set_thread(thread: u64) {
+    #[cfg(feature = "yield-points")]
+    hooks::set_thread(thread);
+    #[cfg(not(feature = "yield-points"))]
+    let _ = thread;
+}
+
+/// How many hooks the calling thread has hit since its step count last
+/// started again.  Always zero without the `yield-points` feature.
+pub fn steps() -> u64 {
+    #[cfg(feature = "yield-points")]
+    return hooks::steps();
+    #[cfg(not(feature = "yield-points"))]
+    0
+}
+
+#[cfg(feature = "yield-points")]
+mod hooks {
+    use super::{Action, Point, Schedule};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::RwLock;
+    use std::thread;
+
+    static ACTIVE: AtomicBool = AtomicBool::new(false);
+    static SCHEDULE: RwLock<Option<Schedule>> = RwLock::new(None);
+    static NEXT_THREAD: AtomicU64 = AtomicU64::new(1 << 32);
+
+    thread_local! {
+        static THREAD: Cell<Option<u64>> = const { Cell::new(None) };
+        static STEP: Cell<u64> = const { Cell::new(0) };
+    }
+
+    pub(super) fn hit(point: Point) {
+        let step = STEP.with(|s| s.replace(s.get() + 1));
+        // The fast path, for when nobody is scheduling anything.
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+        let thread = THREAD.with(|t| match t.get() {
+            Some(thread) => thread,
+            None => {
+                let thread = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+                t.set(Some(thread));
+                thread
+            }
+        });
+        let action = match &*SCHEDULE.read().unwrap_or_else(|e| e.into_inner()) {
+            Some(schedule) => schedule.action(thread, step, point),
+            None => Action::Continue,
+        };
+        match action {
+            Action::Continue => {}
+            Action::Yield => thread::yield_now(),
+            Action::Sleep(d) => thread::sleep(d),
+        }
+    }
+
+    pub(super) fn set_schedule(schedule: Option<Schedule>) {
+        let active = schedule.is_some();
+        *SCHEDULE.write().unwrap_or_else(|e| e.into_inner()) = schedule;
+        ACTIVE.store(active, Ordering::Relaxed);
+    }
+
+    pub(super) fn set_thread(thread: u64) {
+        THREAD.with(|t| t.set(Some(thread)));
+        STEP.with(|s| s.set(0));
+    }
+
+    pub(super) fn steps() -> u64 {
+        STEP.with(Cell::get)
+    }
+}
@@ -0,0 +1,27 @@
+use epoch_playground::affinity;
+use epoch_playground::stress::{self, StressConfig};
+use std::time::Duration;
+
+#[test]
+fn pinned_runs_count_ops_per_core() {
+    let cores = affinity::cores();
+    assert!(!cores.is_empty());
+
+    let config = StressConfig {
+        readers: 2,
+        writers: 1,
+        duration: Duration::from_millis(20),
+        pin_cores: true,
+        ..StressConfig::default()
+    };
+    let report = stress::run(&config);
+    let counted: u64 = report.per_core.iter().map(|c| c.ops).sum();
+    assert!(counted <= report.reads + report.writes);
+    assert!(report.per_core.windows(2).all(|w| w[0].core < w[1].core));
+    assert!(report.per_core.iter().all(|c| cores.contains(&c.core)));
+    if cfg!(target_os = "linux") {
+        let threads: usize = report.per_core.iter().map(|c| c.threads).sum();
+        assert_eq!(threads, 3);
+        assert_eq!(counted, report.reads + report.writes);
+    }
+}
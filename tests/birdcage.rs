@@ -0,0 +1,502 @@
+use epoch_playground::drop_tracker::{DropTracker, Tracked};
+use epoch_playground::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use epoch_playground::{
+    ArcCage, BirdCage, Cage, CageError, Canary, LockCage, MemoryOrder, ReclaimObserver,
+    ShardedBirdCage,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[test]
+fn drop_reclaims_remaining_values() {
+    let tracker = DropTracker::new();
+    let birdcage: BirdCage<_> = BirdCage::from_fn(10, |_| tracker.track());
+    assert_eq!(tracker.dropped(), 0);
+
+    drop(birdcage);
+    assert_eq!(tracker.dropped(), 10);
+}
+
+#[test]
+fn drop_skips_empty_slots() {
+    let tracker = DropTracker::new();
+    let birdcage: BirdCage<_> = BirdCage::from_fn(3, |_| tracker.track());
+    let taken = birdcage.take(1).unwrap().wait().unwrap();
+    assert_eq!(tracker.dropped(), 0);
+
+    drop(birdcage);
+    assert_eq!(tracker.dropped(), 2);
+    drop(taken);
+    assert_eq!(tracker.dropped(), 3);
+}
+
+#[test]
+fn taken_values_arrive_under_qsbr() {
+    let tracker = DropTracker::new();
+    let birdcage: BirdCage<_, Qsbr> = BirdCage::from_fn(2, |_| tracker.track());
+    // Nothing under QSBR is freed until this thread has been quiescent, so
+    // `wait` has to say so itself.
+    let taken = birdcage.take(1).unwrap().wait().unwrap();
+    assert_eq!(taken.id(), 1);
+    assert!(!taken.is_dropped());
+    drop(taken);
+    drop(birdcage);
+    tracker.assert_all_dropped();
+}
+
+#[test]
+fn single_threaded_use_leaves_nothing_behind() {
+    let tracker = DropTracker::new();
+    let birdcage: BirdCage<_> = BirdCage::from_fn(3, |_| tracker.track());
+    assert!(birdcage.get(0, &birdcage.pin()).is_ok());
+    birdcage.put(0, tracker.track());
+    drop(birdcage.take(1).unwrap().wait().unwrap());
+
+    assert!(reclaim::force_reclaim::<Epoch>());
+    assert_eq!(tracker.dropped(), 2);
+    drop(birdcage);
+    assert_eq!(tracker.dropped(), 4);
+}
+
+#[test]
+fn replace_mut_hands_back_the_old_value() {
+    let tracker = DropTracker::new();
+    let mut birdcage: BirdCage<_> = BirdCage::from_fn(2, |_| tracker.track());
+    let old = birdcage.replace_mut(0, tracker.track()).unwrap().unwrap();
+    assert_eq!(tracker.dropped(), 0);
+    drop(old);
+    assert_eq!(tracker.dropped(), 1);
+
+    let mut empty: BirdCage<_> = BirdCage::empty(1);
+    assert!(empty.replace_mut(0, tracker.track()).unwrap().is_none());
+    drop(empty);
+    drop(birdcage);
+    assert_eq!(tracker.dropped(), 4);
+}
+
+fn force_reclaim_drops_replaced_values<R: Reclaimer>() {
+    let tracker = DropTracker::new();
+    let birdcage: BirdCage<_, R> = BirdCage::from_fn(4, |_| tracker.track());
+    for n in 0..100 {
+        birdcage.put(n % 4, tracker.track());
+    }
+
+    assert!(reclaim::force_reclaim::<R>());
+    assert_eq!(tracker.dropped(), 100);
+}
+
+#[test]
+fn force_reclaim_drains_epoch() {
+    force_reclaim_drops_replaced_values::<Epoch>();
+}
+
+#[test]
+fn force_reclaim_drains_hazard_pointers() {
+    force_reclaim_drops_replaced_values::<HazardPointers>();
+}
+
+#[test]
+fn force_reclaim_drains_qsbr() {
+    force_reclaim_drops_replaced_values::<Qsbr>();
+}
+
+#[test]
+fn concurrent_updates_are_not_lost() {
+    let birdcage: Arc<BirdCage<u64>> = Arc::new(BirdCage::from_fn(2, |_| 0));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let birdcage = birdcage.clone();
+            std::thread::spawn(move || {
+                for ii in 0..1000 {
+                    birdcage.update(ii % 2, |n| n + 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    let guard = &birdcage.pin();
+    assert_eq!(birdcage.iter(guard).sum::<u64>(), 4000);
+}
+
+// x86 hides most ordering mistakes, but on weaker hardware a wrong one could
+// lose an update, or hand `update` a value that isn't all there yet.
+fn acqrel_updates_are_not_lost<R: Reclaimer>() {
+    let birdcage: Arc<BirdCage<u64, R>> =
+        Arc::new(BirdCage::from_fn(2, |_| 0).with_memory_order(MemoryOrder::AcqRel));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let birdcage = birdcage.clone();
+            std::thread::spawn(move || {
+                for ii in 0..1000 {
+                    birdcage.update(ii % 2, |n| n + 1).unwrap();
+                    R::quiescent();
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    let guard = &birdcage.pin();
+    assert_eq!(birdcage.iter(guard).sum::<u64>(), 4000);
+}
+
+#[test]
+fn acqrel_updates_under_epoch() {
+    acqrel_updates_are_not_lost::<Epoch>();
+}
+
+#[test]
+fn acqrel_updates_under_hazard_pointers() {
+    acqrel_updates_are_not_lost::<HazardPointers>();
+}
+
+#[test]
+fn acqrel_updates_under_qsbr() {
+    acqrel_updates_are_not_lost::<Qsbr>();
+}
+
+#[test]
+fn backoff_replaces_see_every_value_once() {
+    let birdcage: Arc<BirdCage<u64>> = Arc::new(BirdCage::from_fn(1, |_| 0));
+    let handles: Vec<_> = (1..=4)
+        .map(|id| {
+            let birdcage = birdcage.clone();
+            std::thread::spawn(move || {
+                let mut removed = Vec::new();
+                for ii in 0..1000 {
+                    birdcage.replace_backoff(0, id * 1000 + ii, |&old| removed.push(old)).unwrap();
+                }
+                removed
+            })
+        })
+        .collect();
+    let mut seen: Vec<u64> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+    seen.extend(birdcage.get(0, &birdcage.pin()).ok());
+
+    // Every value went in once and came out once, except the last one in.
+    seen.sort_unstable();
+    let expected: Vec<u64> = std::iter::once(0)
+        .chain((1..=4).flat_map(|id| (0..1000).map(move |ii| id * 1000 + ii)))
+        .collect();
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn update_skips_empty_slots() {
+    let birdcage: BirdCage<u64> = BirdCage::empty(1);
+    assert_eq!(birdcage.update(0, |n| n + 1), Err(CageError::SlotEmpty { index: 0 }));
+    assert!(birdcage.get(0, &birdcage.pin()).is_err());
+}
+
+fn replace_all_retires_every_old_value<R: Reclaimer>() {
+    let tracker = DropTracker::new();
+    let birdcage: BirdCage<_, R> = BirdCage::from_fn(4, |_| tracker.track());
+    assert_eq!(birdcage.replace_all((0..4).map(|_| tracker.track())), 4);
+
+    assert!(reclaim::force_reclaim::<R>());
+    assert_eq!(tracker.dropped(), 4);
+}
+
+#[test]
+fn replace_all_batches_under_epoch() {
+    replace_all_retires_every_old_value::<Epoch>();
+}
+
+#[test]
+fn replace_all_falls_back_under_hazard_pointers() {
+    replace_all_retires_every_old_value::<HazardPointers>();
+}
+
+#[test]
+fn swap_slots_exchanges_values() {
+    let birdcage: BirdCage<u64> = BirdCage::from_fn(3, |n| n as u64);
+    birdcage.swap_slots(0, 2).unwrap();
+    birdcage.swap_slots(1, 1).unwrap();
+    assert_eq!(birdcage.snapshot(), [Some(2), Some(1), Some(0)]);
+
+    assert!(birdcage.remove(1).is_ok());
+    birdcage.swap_slots(1, 2).unwrap();
+    assert_eq!(birdcage.snapshot(), [Some(2), Some(0), None]);
+}
+
+// What a reader can see of a swap in progress.  Slot `i` is emptied first,
+// and the values are never in two slots at once, but two separate loads
+// can still see the same value twice.  A write that races with a swap is
+// either swapped along with everything else or overwritten and retired,
+// but either way nothing leaks and nothing is dropped twice.
+#[test]
+fn swap_slots_is_not_atomic() {
+    const WRITES: usize = 1000;
+    let tracker = DropTracker::new();
+    let birdcage: BirdCage<_> = BirdCage::from_fn(2, |_| tracker.track());
+    let done = AtomicUsize::new(0);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for _ in 0..10_000 {
+                birdcage.swap_slots(0, 1).unwrap();
+            }
+            birdcage.flush();
+            done.fetch_add(1, Ordering::SeqCst);
+        });
+        s.spawn(|| {
+            for _ in 0..WRITES {
+                birdcage.put(0, tracker.track());
+            }
+            // Otherwise this thread's garbage might not be handed over
+            // until after the scope ends.
+            birdcage.flush();
+            done.fetch_add(1, Ordering::SeqCst);
+        });
+        while done.load(Ordering::SeqCst) < 2 {
+            let guard = &birdcage.pin();
+            // Slot 0 looks empty whenever a swap is halfway done, as often
+            // as the scheduler likes, but whatever it does hold is alive.
+            if let Ok(t) = birdcage.get(0, guard) {
+                t.validate();
+            }
+            // `j` always holds one value or the other.
+            assert!(birdcage.get(1, guard).is_ok());
+        }
+    });
+
+    // Every write pushed exactly one value out of the cage.
+    assert!(reclaim::force_reclaim::<Epoch>());
+    assert_eq!(tracker.dropped(), WRITES);
+    assert_eq!(birdcage.iter(&birdcage.pin()).count(), 2);
+}
+
+fn clear_waits_for_readers<R: Reclaimer>() {
+    let tracker = DropTracker::new();
+    let birdcage: BirdCage<_, R> = BirdCage::from_fn(8, |_| tracker.track());
+    assert!(birdcage.remove(3).is_ok());
+    let guard = birdcage.pin();
+    let held = birdcage.get(5, &guard).unwrap();
+
+    assert_eq!(birdcage.clear(), 7);
+    assert_eq!(birdcage.clear(), 0);
+    assert!(birdcage.get(5, &guard).is_err());
+    R::flush(&guard);
+    assert!(!held.is_dropped());
+    drop(guard);
+
+    assert!(reclaim::force_reclaim::<R>());
+    assert_eq!(tracker.dropped(), 8);
+    drop(birdcage);
+    assert_eq!(tracker.dropped(), 8);
+}
+
+#[test]
+fn clear_under_epoch() {
+    clear_waits_for_readers::<Epoch>();
+}
+
+#[test]
+fn clear_under_hazard_pointers() {
+    clear_waits_for_readers::<HazardPointers>();
+}
+
+#[test]
+fn clear_under_qsbr() {
+    clear_waits_for_readers::<Qsbr>();
+}
+
+// Readers keep loading while the cage is refilled and drained over and
+// over.  Every value comes out of a drain exactly once, and nothing is
+// dropped until whoever drained it lets go.
+#[test]
+fn drain_hands_over_every_value_once() {
+    const ROUNDS: usize = 50;
+    let tracker = DropTracker::new();
+    let birdcage: BirdCage<Tracked> = BirdCage::empty(4);
+    let done = AtomicUsize::new(0);
+    let mut drained = Vec::new();
+    std::thread::scope(|s| {
+        for _ in 0..2 {
+            s.spawn(|| {
+                while done.load(Ordering::SeqCst) == 0 {
+                    let guard = &birdcage.pin();
+                    for c in birdcage.iter(guard) {
+                        c.validate();
+                    }
+                }
+            });
+        }
+        for _ in 0..ROUNDS {
+            for n in 0..4 {
+                birdcage.put(n, tracker.track());
+            }
+            let drain = birdcage.drain();
+            assert_eq!(drain.len(), 4);
+            drained.extend(drain);
+        }
+        done.store(1, Ordering::SeqCst);
+    });
+
+    assert_eq!(drained.len(), 4 * ROUNDS);
+    assert_eq!(tracker.dropped(), 0);
+    drop(drained);
+    assert_eq!(tracker.dropped(), 4 * ROUNDS);
+    assert_eq!(birdcage.drain().count(), 0);
+}
+
+#[test]
+fn get_cloned_outlives_the_value() {
+    let birdcage: BirdCage<String> = BirdCage::from_fn(2, |n| format!("bird {}", n));
+    let copy = birdcage.get_cloned(1).unwrap();
+    assert!(birdcage.remove(1).is_ok());
+    assert!(reclaim::force_reclaim::<Epoch>());
+    assert_eq!(copy, "bird 1");
+    assert_eq!(birdcage.get_cloned(1), Err(CageError::SlotEmpty { index: 1 }));
+}
+
+#[test]
+fn lookups_report_missing_slots() {
+    let birdcage: BirdCage<u64> = BirdCage::from_fn(2, |n| n as u64);
+    let guard = &birdcage.pin();
+    let out_of_range = CageError::IndexOutOfBounds { index: 2, len: 2 };
+    assert_eq!(birdcage.get(2, guard), Err(out_of_range));
+    assert_eq!(birdcage.with_slot(2, |n| *n), Err(out_of_range));
+    assert_eq!(birdcage.remove(2), Err(out_of_range));
+    assert_eq!(out_of_range.to_string(), "slot 2 is out of range for a cage of 2");
+
+    assert_eq!(birdcage.remove(0), Ok(()));
+    assert_eq!(birdcage.remove(0), Err(CageError::SlotEmpty { index: 0 }));
+    assert_eq!(birdcage.get(0, guard), Err(CageError::SlotEmpty { index: 0 }));
+    assert_eq!(birdcage.get(1, guard), Ok(&1));
+}
+
+#[test]
+fn writes_report_missing_slots() {
+    let tracker = DropTracker::new();
+    let mut birdcage: BirdCage<_> = BirdCage::from_fn(2, |_| tracker.track());
+    let out_of_range = CageError::IndexOutOfBounds { index: 2, len: 2 };
+    let (e, back) = birdcage.insert(2, tracker.track()).unwrap_err();
+    assert_eq!((e, tracker.drops(back.id())), (out_of_range, 0));
+    drop(back);
+    let (e, _) = birdcage.insert(0, tracker.track()).unwrap_err();
+    assert_eq!(e, CageError::SlotOccupied { index: 0 });
+    assert_eq!(birdcage.swap_slots(0, 2), Err(out_of_range));
+    assert_eq!(birdcage.take(2).err(), Some(out_of_range));
+    assert_eq!(birdcage.current_id(2), Err(out_of_range));
+    let id = birdcage.current_id(0).unwrap();
+    assert_eq!(birdcage.replace_if(2, id, tracker.track()).err(), Some(out_of_range));
+    assert_eq!(birdcage.replace_if_weak(2, id, tracker.track()).err(), Some(out_of_range));
+    assert_eq!(birdcage.update(2, |_| unreachable!()), Err(out_of_range));
+    assert_eq!(birdcage.replace_backoff(2, tracker.track(), |_| ()), Err(out_of_range));
+    // The pairs before the bad one still go in.
+    let batch = vec![(1, tracker.track()), (2, tracker.track())];
+    assert_eq!(birdcage.replace_many(batch), Err(out_of_range));
+    assert_eq!(birdcage.replace_mut(2, tracker.track()).err(), Some(out_of_range));
+
+    // Nothing that was turned away went into the cage.
+    assert_eq!(birdcage.current_id(0), Ok(id));
+    drop(birdcage);
+    assert!(reclaim::force_reclaim::<Epoch>());
+    tracker.assert_all_dropped();
+}
+
+#[test]
+fn sharded_lookups_report_missing_slots() {
+    let birdcage = ShardedBirdCage::from_fn(5, 2, |n| n as u64);
+    let handle = birdcage.register();
+    let out_of_range = CageError::IndexOutOfBounds { index: 5, len: 5 };
+    assert_eq!(birdcage.with_slot(&handle, 5, |n| *n), Err(out_of_range));
+    assert_eq!(birdcage.replace(&handle, 5, 0), Err(out_of_range));
+    assert_eq!(birdcage.with_slot(&handle, 4, |n| *n), Ok(4));
+    let guard = birdcage.pin_shard(&handle, 1);
+    assert_eq!(birdcage.get(5, &guard), Err(out_of_range));
+    assert_eq!(birdcage.get(3, &guard), Ok(&3));
+}
+
+#[test]
+fn baseline_cages_report_missing_slots() {
+    let out_of_range = CageError::IndexOutOfBounds { index: 2, len: 2 };
+    let arc = ArcCage::from_fn(2, |n| n);
+    assert_eq!(arc.load(2), Err(out_of_range));
+    assert_eq!(arc.with_slot(2, |n| *n), Err(out_of_range));
+    assert_eq!(arc.swap(2, 7), Err(out_of_range));
+    assert_eq!(arc.swap(1, 7).as_deref(), Ok(&1));
+
+    let lock = LockCage::from_fn(2, |n| n);
+    assert_eq!(lock.with_slot(2, |n| *n), Err(out_of_range));
+    assert_eq!(lock.swap(2, 7), Err(out_of_range));
+    assert_eq!(lock.swap(1, 7), Ok(1));
+    assert_eq!(lock.with_slot(1, |n| *n), Ok(7));
+}
+
+#[test]
+fn debug_shows_every_slot() {
+    let birdcage: BirdCage<u64> = BirdCage::from_fn(3, |n| n as u64 * 10);
+    assert!(birdcage.remove(1).is_ok());
+    let shown = format!("{:?}", birdcage);
+    assert!(shown.starts_with("BirdCage { reclaimer: \"epoch\", order: SeqCst"));
+    assert!(shown.contains("0: 0 at 0x"));
+    assert!(shown.contains("1: empty"));
+    assert!(shown.contains("2: 20 at 0x"));
+    assert!(format!("{:#?}", birdcage).contains("    slots: {\n"));
+}
+
+#[test]
+fn snapshots_restore_into_a_new_cage() {
+    let birdcage: BirdCage<Canary, HazardPointers> =
+        BirdCage::from_fn(3, |n| Canary::silent(&format!("Canary \"{}\"", n)));
+    assert!(birdcage.remove(1).is_ok());
+    let json = birdcage.snapshot_to_json();
+    assert_eq!(
+        json,
+        r#"{"reclaimer":"hazard","slots":[{"name":"Canary \"0\"","silent":true},null,{"name":"Canary \"2\"","silent":true}]}"#
+    );
+
+    let restored: BirdCage<Canary> = BirdCage::restore_from_json(&json).unwrap();
+    assert_eq!(restored.len(), 3);
+    assert_eq!(restored.with_slot(0, |c| c.name().to_owned()), Ok("Canary \"0\"".to_owned()));
+    assert!(restored.with_slot(1, |_| ()).is_err());
+    assert_eq!(restored.snapshot_to_json().replace("epoch", "hazard"), json);
+
+    let loud: BirdCage<Canary> = BirdCage::restore_from_json(r#" { "slots" : [ {"name": "Téa"} ] } "#).unwrap();
+    assert_eq!(loud.with_slot(0, |c| (c.name().to_owned(), c.is_silent())), Ok(("Téa".to_owned(), false)));
+}
+
+#[test]
+fn bad_snapshots_are_rejected() {
+    for (json, error) in [
+        ("", "expected a value, found the end"),
+        ("{}", "a snapshot needs a \"slots\" array"),
+        (r#"{"slots":[1]}"#, "slot 0 should be an object or null"),
+        (r#"{"slots":[null,{}]}"#, "slot 1 needs a \"name\" string"),
+        (r#"{"slots":[{"name":"x","silent":1}]}"#, "slot 0: \"silent\" should be true or false"),
+        (r#"{"slots":[]} x"#, "unexpected \"x\" at byte 13"),
+    ] {
+        let restored = BirdCage::<Canary>::restore_from_json(json);
+        assert_eq!(restored.err().as_deref(), Some(error), "{}", json);
+    }
+}
+
+#[test]
+fn observers_hear_about_every_drop() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let observer: Arc<dyn ReclaimObserver> = Arc::new(move |c: &Canary, delay| {
+        tx.send((c.name().to_owned(), delay)).unwrap();
+    });
+    let observed = |name: &str| Canary::silent(name).with_observer(observer.clone());
+    let birdcage: BirdCage<Canary> = BirdCage::from_fn(2, |n| observed(&format!("old {}", n)));
+
+    birdcage.put_with(0, observed("new 0"), Canary::mark_retired);
+    let guard = birdcage.pin();
+    assert!(rx.try_recv().is_err());
+    drop(guard);
+    assert!(reclaim::force_reclaim::<Epoch>());
+    let (name, delay) = rx.try_recv().unwrap();
+    assert_eq!(name, "old 0");
+    assert!(delay.is_some());
+
+    drop(birdcage);
+    let mut rest: Vec<_> = rx.try_iter().collect();
+    rest.sort();
+    assert_eq!(rest, [("new 0".to_owned(), None), ("old 1".to_owned(), None)]);
+}
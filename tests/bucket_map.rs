@@ -0,0 +1,74 @@
+use epoch_playground::bucket_map::BucketMap;
+use epoch_playground::drop_tracker::{DropTracker, Tracked};
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const THREADS: u64 = 4;
+const KEYS: u64 = 100;
+
+// Buckets are copied on every write, so the copies share their values.  A
+// value is only dropped once the last copy holding it has been reclaimed.
+type Value = Arc<Tracked>;
+
+#[test]
+fn concurrent_writers_leave_the_right_values_and_no_garbage() {
+    let tracker = DropTracker::new();
+    // Few buckets, so writers keep losing their CAS to each other.
+    let map: BucketMap<u64, Value> = BucketMap::new(4);
+    let stop = AtomicBool::new(false);
+
+    let expected: Vec<(u64, usize)> = thread::scope(|s| {
+        let (map, tracker, stop) = (&map, &tracker, &stop);
+        s.spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                for key in 0..THREADS * KEYS {
+                    if let Some(v) = map.get(&key) {
+                        v.validate();
+                    }
+                }
+            }
+        });
+        let writers: Vec<_> = (0..THREADS)
+            .map(|id| {
+                s.spawn(move || {
+                    let keys = id * KEYS..(id + 1) * KEYS;
+                    for key in keys.clone() {
+                        assert!(map.insert(key, Arc::new(tracker.track())).is_none());
+                    }
+                    let mut left = Vec::new();
+                    for key in keys {
+                        if key % 2 == 0 {
+                            let new = Arc::new(tracker.track());
+                            let new_id = new.id();
+                            let old = map.insert(key, new).unwrap();
+                            assert_ne!(old.id(), new_id);
+                        }
+                        if key % 3 == 0 {
+                            assert!(map.remove(&key).is_some());
+                            assert!(map.remove(&key).is_none());
+                        } else {
+                            left.push((key, map.get(&key).unwrap().id()));
+                        }
+                    }
+                    crossbeam::epoch::pin().flush();
+                    left
+                })
+            })
+            .collect();
+        let expected = writers.into_iter().flat_map(|w| w.join().unwrap()).collect();
+        stop.store(true, Ordering::Relaxed);
+        expected
+    });
+
+    assert_eq!(map.len(), expected.len());
+    for &(key, id) in &expected {
+        assert_eq!(map.get(&key).map(|v| v.id()), Some(id));
+    }
+    assert!(!map.contains_key(&3));
+
+    drop(map);
+    assert!(force_reclaim::<Epoch>());
+    tracker.assert_all_dropped();
+}
@@ -0,0 +1,14 @@
+use epoch_playground::Canary;
+
+// The payload size is global, so this test gets a process of its own;
+// canaries made by tests running next to it would pick up its setting.
+#[test]
+fn canaries_carry_the_payload_they_were_made_with() {
+    let light = Canary::silent("light");
+    Canary::set_payload_bytes(4096);
+    let heavy = Canary::silent("heavy");
+    Canary::set_payload_bytes(0);
+    assert_eq!(light.payload_len(), 0);
+    assert_eq!(heavy.payload_len(), 4096);
+    assert!(format!("{:?}", heavy).contains("4096 bytes"));
+}
@@ -0,0 +1,43 @@
+use epoch_playground::chaos::Chaos;
+use epoch_playground::stress::{self, StressConfig};
+use epoch_playground::FlushPolicy;
+use std::time::Duration;
+
+#[test]
+fn chaos_runs_say_what_they_did() {
+    let config = StressConfig {
+        readers: 1,
+        writers: 1,
+        mixed: 1,
+        duration: Duration::from_millis(50),
+        flush: FlushPolicy::EveryOp,
+        seed: Some(3),
+        chaos: Some(Chaos {
+            hold_chance: 0.01,
+            max_hold: Duration::from_micros(500),
+            delay_chance: 0.1,
+            max_delay: Duration::from_micros(50),
+            skip_flush_chance: 0.5,
+        }),
+        ..StressConfig::default()
+    };
+    let report = stress::run(&config);
+    let chaos = report.chaos.expect("the run had chaos");
+    assert!(report.writes > 0);
+    assert!(chaos.holds > 0 && chaos.held > Duration::ZERO, "{}", chaos);
+    assert!(chaos.delays > 0 && chaos.delayed > Duration::ZERO, "{}", chaos);
+    assert!(chaos.skipped_flushes > 0 && chaos.skipped_flushes < report.writes, "{}", chaos);
+    assert!(report.to_string().contains("chaos: held a guard"));
+    assert!(report.to_json().contains("\"chaos_holds\":"));
+
+    let clean = stress::run(&StressConfig {
+        chaos: None,
+        ..config
+    });
+    assert!(clean.chaos.is_none());
+    assert!(!clean.to_string().contains("chaos"));
+    // Each field is written once, in the config if that's where it belongs.
+    let json = clean.to_json();
+    assert_eq!(json.matches("\"seed\":").count(), 1, "{}", json);
+    assert_eq!(json.matches("\"reclaimer\":").count(), 1, "{}", json);
+}
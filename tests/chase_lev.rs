@@ -0,0 +1,68 @@
+use epoch_playground::chase_lev::{Steal, Worker};
+use epoch_playground::drop_tracker::{DropTracker, Tracked};
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+const STEALERS: usize = 3;
+const ITEMS: usize = 5000;
+
+#[test]
+fn every_item_comes_out_exactly_once() {
+    let tracker = DropTracker::new();
+    let worker = Worker::new();
+    let done = AtomicBool::new(false);
+
+    let (mut taken, stolen) = thread::scope(|s| {
+        let thieves: Vec<_> = (0..STEALERS)
+            .map(|_| {
+                let (stealer, done) = (worker.stealer(), &done);
+                s.spawn(move || {
+                    let mut stolen: Vec<Tracked> = Vec::new();
+                    loop {
+                        match stealer.steal() {
+                            Steal::Success(item) => stolen.push(item),
+                            Steal::Retry => {}
+                            Steal::Empty if done.load(Ordering::SeqCst) => break,
+                            Steal::Empty => thread::yield_now(),
+                        }
+                    }
+                    crossbeam::epoch::pin().flush();
+                    stolen
+                })
+            })
+            .collect();
+
+        // The owner pushes in bursts, which makes the buffer grow under the
+        // stealers, and pops some of each burst back off itself.
+        let mut taken = Vec::new();
+        for n in 0..ITEMS {
+            worker.push(tracker.track());
+            if n % 7 == 0 {
+                taken.extend(worker.pop());
+            }
+        }
+        while let Some(item) = worker.pop() {
+            taken.push(item);
+        }
+        done.store(true, Ordering::SeqCst);
+        crossbeam::epoch::pin().flush();
+
+        let stolen: Vec<Tracked> = thieves.into_iter().flat_map(|t| t.join().unwrap()).collect();
+        (taken, stolen)
+    });
+    assert!(worker.is_empty());
+
+    taken.extend(stolen);
+    let mut ids: Vec<usize> = taken.iter().map(|t| t.validate()).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), ITEMS);
+    assert_eq!(taken.len(), ITEMS);
+    assert_eq!(tracker.alive(), ITEMS);
+
+    drop(taken);
+    drop(worker);
+    assert!(force_reclaim::<Epoch>());
+    tracker.assert_all_dropped();
+}
@@ -0,0 +1,41 @@
+use epoch_playground::clock_cache::ClockCache;
+use epoch_playground::drop_tracker::{DropTracker, Tracked};
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use std::sync::Arc;
+
+#[test]
+fn eviction_gives_used_entries_a_second_chance() {
+    let tracker = DropTracker::new();
+    let cache = ClockCache::new(3);
+    let mut ids = Vec::new();
+    for key in 0..3 {
+        let value: Arc<Tracked> = Arc::new(tracker.track());
+        ids.push(value.id());
+        cache.insert(key, value);
+    }
+
+    // The hand is back at key 0, which has just been used, so it clears
+    // that and takes key 1 instead.
+    assert!(cache.get(&0).is_some());
+    cache.insert(3, Arc::new(tracker.track()));
+    assert_eq!(cache.evictions(), 1);
+    assert!(cache.get(&1).is_none());
+    for key in [0, 2, 3] {
+        assert!(cache.get(&key).is_some(), "lost key {}", key);
+    }
+
+    // Replacing a key doesn't evict anything, but the old entry lingers
+    // all the same.
+    cache.insert(2, Arc::new(tracker.track()));
+    assert_eq!(cache.evictions(), 1);
+    assert_ne!(cache.get(&2).unwrap().id(), ids[2]);
+
+    assert!(force_reclaim::<Epoch>());
+    assert_eq!(cache.linger_stats().count(), 2);
+    assert_eq!(tracker.drops(ids[1]), 1);
+    assert_eq!(tracker.drops(ids[2]), 1);
+    assert_eq!(tracker.alive(), 3);
+
+    drop(cache);
+    tracker.assert_all_dropped();
+}
@@ -0,0 +1,82 @@
+use epoch_playground::doubly_linked_list::DoublyLinkedList;
+use epoch_playground::drop_tracker::{DropTracker, TrackedKey};
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use std::sync::atomic::{self, AtomicBool};
+use std::thread;
+
+const THREADS: u64 = 4;
+const KEYS: u64 = 200;
+
+#[test]
+fn both_directions_see_the_same_set() {
+    let list = DoublyLinkedList::new();
+    for n in [5, 1, 3, 9, 7] {
+        assert!(list.insert(n));
+    }
+    assert!(!list.insert(3));
+    assert!(list.remove(&1));
+    assert!(list.remove(&9));
+    assert!(!list.remove(&9));
+    assert!(list.contains(&5) && !list.contains(&1));
+    assert_eq!(list.to_vec(), [3, 5, 7]);
+    assert_eq!(list.to_vec_rev(), [7, 5, 3]);
+}
+
+#[test]
+fn concurrent_writers_leave_both_links_consistent() {
+    let tracker = DropTracker::new();
+    let list = DoublyLinkedList::new();
+    let done = AtomicBool::new(false);
+    thread::scope(|s| {
+        let walker = s.spawn(|| {
+            let mut walks = 0;
+            while !done.load(atomic::Ordering::SeqCst) || walks == 0 {
+                let forwards = TrackedKey::keys(&list.to_vec());
+                assert!(forwards.windows(2).all(|w| w[0] < w[1]));
+                let backwards = TrackedKey::keys(&list.to_vec_rev());
+                assert!(backwards.windows(2).all(|w| w[0] > w[1]));
+                walks += 1;
+            }
+            crossbeam::epoch::pin().flush();
+        });
+        let writers: Vec<_> = (0..THREADS)
+            .map(|id| {
+                let (list, tracker) = (&list, &tracker);
+                s.spawn(move || {
+                    // Every thread has keys of its own, interleaved with
+                    // everybody else's.  It churns the odd ones in and out
+                    // a few times, then removes every other one it put in.
+                    for n in (id..KEYS).step_by(THREADS as usize) {
+                        assert!(list.insert(TrackedKey::new(n, tracker)));
+                    }
+                    for _ in 0..5 {
+                        for n in (id + THREADS..KEYS).step_by(2 * THREADS as usize) {
+                            assert!(list.remove(&TrackedKey::lookup(n)));
+                            assert!(!list.contains(&TrackedKey::lookup(n)));
+                            assert!(list.insert(TrackedKey::new(n, tracker)));
+                        }
+                    }
+                    for n in (id..KEYS).step_by(2 * THREADS as usize) {
+                        assert!(list.remove(&TrackedKey::lookup(n)));
+                    }
+                    crossbeam::epoch::pin().flush();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, atomic::Ordering::SeqCst);
+        walker.join().unwrap();
+    });
+
+    let expected: Vec<u64> = (0..KEYS).filter(|n| n % (2 * THREADS) >= THREADS).collect();
+    assert_eq!(TrackedKey::keys(&list.to_vec()), expected);
+    let mut backwards = TrackedKey::keys(&list.to_vec_rev());
+    backwards.reverse();
+    assert_eq!(backwards, expected);
+
+    drop(list);
+    assert!(force_reclaim::<Epoch>());
+    tracker.assert_all_dropped();
+}
@@ -0,0 +1,37 @@
+use epoch_playground::events::{self, Event};
+use epoch_playground::reclaim::{self, Epoch, HazardPointers, Reclaimer};
+use epoch_playground::{BirdCage, Cage};
+use std::collections::HashSet;
+
+// Replace `replaces` values, reclaim them, and check that every one was
+// announced as deferred and then reclaimed, once each.  Every retirement
+// in this process comes from here, so there's nothing else to filter out.
+fn every_retirement_is_reported<R: Reclaimer>(replaces: usize) {
+    let rx = events::subscribe();
+    let birdcage: BirdCage<u64, R> = BirdCage::from_fn(4, |n| n as u64);
+    for n in 0..replaces {
+        birdcage.put(n % 4, n as u64);
+    }
+    assert_eq!(birdcage.replace_all(0..4), 4);
+    assert!(reclaim::force_reclaim::<R>());
+
+    let (mut deferred, mut reclaimed) = (HashSet::new(), HashSet::new());
+    for event in rx.try_iter() {
+        match event {
+            Event::Deferred { id } => assert!(deferred.insert(id), "deferred {} twice", id),
+            Event::Reclaimed { id, .. } => {
+                assert!(deferred.contains(&id), "reclaimed {} before deferring it", id);
+                assert!(reclaimed.insert(id), "reclaimed {} twice", id);
+            }
+        }
+    }
+    assert_eq!(deferred.len(), replaces + 4);
+    assert_eq!(reclaimed, deferred);
+}
+
+#[test]
+fn events_pair_up() {
+    // One test, so the two runs can't overlap and see each other's events.
+    every_retirement_is_reported::<Epoch>(100);
+    every_retirement_is_reported::<HazardPointers>(100);
+}
@@ -0,0 +1,31 @@
+//! Run with `cargo test --features ffi`.
+#![cfg(feature = "ffi")]
+
+use epoch_playground::ffi::*;
+use std::ptr;
+use std::thread;
+
+#[test]
+fn the_c_interface_checks_its_arguments() {
+    unsafe {
+        let cage = birdcage_new(2);
+        assert_eq!(birdcage_access(cage, 1), BIRDCAGE_OK);
+        assert_eq!(birdcage_access(cage, 2), BIRDCAGE_BAD_ARGUMENT);
+        assert_eq!(birdcage_access(ptr::null(), 0), BIRDCAGE_BAD_ARGUMENT);
+        assert_eq!(birdcage_replace(cage, 0, ptr::null()), BIRDCAGE_OK);
+        assert_eq!(birdcage_replace(cage, 0, b"Finch\0".as_ptr().cast()), BIRDCAGE_OK);
+        assert_eq!(birdcage_take(cage, 1), BIRDCAGE_OK);
+        assert_eq!(birdcage_take(cage, 1), BIRDCAGE_EMPTY);
+        assert_eq!(birdcage_access(cage, 1), BIRDCAGE_EMPTY);
+        assert_eq!(birdcage_unpin(ptr::null_mut()), BIRDCAGE_BAD_ARGUMENT);
+        birdcage_destroy(cage);
+    }
+}
+
+#[test]
+fn guards_are_only_released_by_their_own_thread() {
+    let guard = birdcage_pin() as usize;
+    let elsewhere = thread::spawn(move || unsafe { birdcage_unpin(guard as *mut _) });
+    assert_eq!(elsewhere.join().unwrap(), BIRDCAGE_WRONG_THREAD);
+    assert_eq!(unsafe { birdcage_unpin(guard as *mut _) }, BIRDCAGE_OK);
+}
@@ -0,0 +1,32 @@
+use epoch_playground::fuzz_ops::{self, Op};
+
+#[test]
+fn decode_deals_ops_out_to_threads() {
+    // Two threads; a trailing odd byte is ignored.
+    let data = [1, 0, 0, 5, 1, 7, 6, 8, 3, 2, 3, 9];
+    assert_eq!(
+        fuzz_ops::decode(&data),
+        [
+            vec![Op::Get(0), Op::Flush, Op::Put(3)],
+            vec![Op::Take(1), Op::Update(2)],
+        ]
+    );
+    assert!(fuzz_ops::decode(&[]).is_empty());
+}
+
+#[test]
+fn fixed_inputs_run_clean() {
+    let inputs: [&[u8]; 5] = [
+        &[],
+        &[0],
+        // One thread filling, taking and updating every slot.
+        &[0, 1, 0, 2, 0, 3, 0, 1, 1, 2, 1, 3, 1, 1, 2, 2, 2, 3, 2, 4, 0],
+        // Three threads all working on slot 0.
+        &[2, 3, 0, 4, 0, 5, 0, 6, 0, 7, 0, 8, 0, 9, 0, 10, 0, 11, 0, 12, 0],
+        // Three threads, everything everywhere.
+        &[5, 0, 1, 13, 2, 27, 3, 41, 0, 55, 1, 69, 2, 83, 3, 97, 0, 111, 1, 125, 2],
+    ];
+    for data in inputs {
+        fuzz_ops::run(data);
+    }
+}
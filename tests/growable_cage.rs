@@ -0,0 +1,91 @@
+use epoch_playground::drop_tracker::{DropTracker, Tracked};
+use epoch_playground::reclaim::{self, Epoch};
+use epoch_playground::{Cage, CageError, GrowableBirdCage};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+#[test]
+fn grow_keeps_values_and_adds_empty_slots() {
+    let tracker = DropTracker::new();
+    let cage = GrowableBirdCage::from_fn(3, |_| tracker.track());
+    assert!(cage.grow(5));
+    assert!(!cage.grow(4));
+    assert_eq!(cage.len(), 5);
+
+    let guard = &cage.pin();
+    let values: Vec<_> = (0..5).map(|n| cage.get(n, guard).ok().map(Tracked::id)).collect();
+    assert_eq!(values, [Some(0), Some(1), Some(2), None, None]);
+    assert_eq!(tracker.dropped(), 0);
+    drop(cage);
+    tracker.assert_all_dropped();
+}
+
+#[test]
+fn references_survive_a_grow() {
+    let tracker = DropTracker::new();
+    let cage = GrowableBirdCage::from_fn(2, |_| tracker.track());
+    let guard = cage.pin();
+    let old = cage.get(1, &guard).unwrap();
+    cage.grow(8);
+    cage.replace_with(1, tracker.track(), |c| assert_eq!(c.id(), 1)).unwrap();
+    cage.replace_with(7, tracker.track(), |_| panic!("slot 7 was empty")).unwrap();
+    assert_eq!(old.validate(), 1);
+    drop(guard);
+
+    assert!(reclaim::force_reclaim::<Epoch>());
+    assert_eq!(tracker.alive_ids(), [0, 2, 3]);
+    assert_eq!(cage.remove(7), Ok(()));
+    assert_eq!(cage.remove(7), Err(CageError::SlotEmpty { index: 7 }));
+    let out_of_range = CageError::IndexOutOfBounds { index: 8, len: 8 };
+    assert_eq!(cage.remove(8), Err(out_of_range));
+    assert_eq!(cage.replace_with(8, tracker.track(), |_| ()), Err(out_of_range));
+    drop(cage);
+    assert!(reclaim::force_reclaim::<Epoch>());
+    tracker.assert_all_dropped();
+}
+
+#[test]
+fn no_write_is_lost_while_growing() {
+    const WRITES: usize = 2000;
+    let tracker = DropTracker::new();
+    let cage = GrowableBirdCage::from_fn(4, |_| tracker.track());
+    let done = AtomicBool::new(false);
+    thread::scope(|s| {
+        for n in 0..4 {
+            let (cage, tracker) = (&cage, &tracker);
+            s.spawn(move || {
+                let mut last = n;
+                for _ in 0..WRITES {
+                    let t = tracker.track();
+                    let id = t.id();
+                    cage.replace_with(n, t, |c| assert_eq!(c.id(), last)).unwrap();
+                    last = id;
+                }
+                // This thread's garbage might not be handed over until
+                // after the scope ends.
+                cage.flush();
+            });
+        }
+        s.spawn(|| {
+            while !done.load(Ordering::SeqCst) {
+                for n in 0..4 {
+                    assert!(cage.with_slot(n, Tracked::validate).is_ok());
+                }
+            }
+        });
+        for size in 5..200 {
+            assert!(cage.grow(size));
+        }
+        done.store(true, Ordering::SeqCst);
+    });
+
+    assert_eq!(cage.len(), 199);
+    assert_eq!(tracker.created(), 4 * (WRITES + 1));
+    // A lost write would leave a value that's neither in the cage nor
+    // retired.
+    assert!(reclaim::force_reclaim::<Epoch>());
+    assert_eq!(tracker.alive(), 4);
+    drop(cage);
+    assert!(reclaim::force_reclaim::<Epoch>());
+    tracker.assert_all_dropped();
+}
@@ -0,0 +1,26 @@
+use epoch_playground::guard_watch;
+use epoch_playground::{BirdCage, Canary};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn guards_held_too_long_are_reported() {
+    let cage = BirdCage::<Canary>::from_fn(2, |ii| Canary::silent(&format!("Canary {}", ii)));
+    guard_watch::set_limit(Duration::from_millis(5));
+
+    let name = cage.with_pinned(|guard| cage.get(1, guard).ok().map(|c| c.name().to_owned()));
+    assert_eq!(name.as_deref(), Some("Canary 1"));
+    assert_eq!(guard_watch::warnings(), 0);
+
+    let guard = cage.pin_watched();
+    thread::sleep(Duration::from_millis(10));
+    assert!(cage.get(0, &guard).is_ok());
+    drop(guard);
+    assert_eq!(guard_watch::warnings(), 1);
+
+    guard_watch::set_limit(Duration::ZERO);
+    let guard = cage.pin_watched();
+    thread::sleep(Duration::from_millis(10));
+    drop(guard);
+    assert_eq!(guard_watch::warnings(), 1);
+}
@@ -0,0 +1,61 @@
+use epoch_playground::drop_tracker::{DropTracker, TrackedKey};
+use epoch_playground::harris_list::HarrisList;
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use std::thread;
+
+const THREADS: u64 = 4;
+const KEYS: u64 = 200;
+
+#[test]
+fn keeps_a_sorted_set() {
+    let list = HarrisList::new();
+    for n in [5, 1, 3, 9, 7] {
+        assert!(list.insert(n));
+    }
+    assert!(!list.insert(3));
+    assert!(list.remove(&1));
+    assert!(list.remove(&9));
+    assert!(!list.remove(&9));
+    assert!(list.contains(&5) && !list.contains(&1));
+    assert_eq!(list.to_vec(), [3, 5, 7]);
+}
+
+#[test]
+fn concurrent_writers_free_every_node_once() {
+    let tracker = DropTracker::new();
+    let list = HarrisList::new();
+    thread::scope(|s| {
+        for id in 0..THREADS {
+            let (list, tracker) = (&list, &tracker);
+            s.spawn(move || {
+                // Every thread has keys of its own, interleaved with
+                // everybody else's, so a node marked by one thread is often
+                // unlinked by another's search.  Every other key is churned
+                // in and out a few times, then left out.
+                for n in (id..KEYS).step_by(THREADS as usize) {
+                    assert!(list.insert(TrackedKey::new(n, tracker)));
+                }
+                for _ in 0..5 {
+                    for n in (id..KEYS).step_by(2 * THREADS as usize) {
+                        assert!(list.remove(&TrackedKey::lookup(n)));
+                        assert!(!list.contains(&TrackedKey::lookup(n)));
+                        assert!(list.insert(TrackedKey::new(n, tracker)));
+                    }
+                }
+                for n in (id..KEYS).step_by(2 * THREADS as usize) {
+                    assert!(list.remove(&TrackedKey::lookup(n)));
+                }
+                let kept = TrackedKey::keys(&list.to_vec());
+                assert!(kept.windows(2).all(|w| w[0] < w[1]));
+                crossbeam::epoch::pin().flush();
+            });
+        }
+    });
+
+    let expected: Vec<u64> = (0..KEYS).filter(|n| n % (2 * THREADS) >= THREADS).collect();
+    assert_eq!(TrackedKey::keys(&list.to_vec()), expected);
+
+    drop(list);
+    assert!(force_reclaim::<Epoch>());
+    tracker.assert_all_dropped();
+}
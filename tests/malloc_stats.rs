@@ -0,0 +1,34 @@
+use epoch_playground::malloc_stats::{self, MallocStats};
+use std::hint::black_box;
+
+#[test]
+fn fragmentation_is_the_free_share() {
+    let stats = MallocStats {
+        allocated: 300,
+        free: 100,
+        mapped: 500,
+        resident: 0,
+    };
+    assert_eq!(stats.fragmentation(), 0.25);
+    let empty = MallocStats {
+        allocated: 0,
+        free: 0,
+        mapped: 0,
+        resident: 0,
+    };
+    assert_eq!(empty.fragmentation(), 0.0);
+}
+
+#[test]
+fn glibc_sees_big_allocations() {
+    if !cfg!(all(target_os = "linux", target_env = "gnu")) {
+        assert_eq!(malloc_stats::stats(), None);
+        return;
+    }
+    let before = malloc_stats::stats().unwrap();
+    let big = black_box(vec![1u8; 1 << 24]);
+    let during = malloc_stats::stats().unwrap();
+    assert!(during.allocated >= before.allocated + big.len() / 2);
+    assert!(during.resident > 0);
+    drop(big);
+}
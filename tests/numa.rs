@@ -0,0 +1,28 @@
+use epoch_playground::numa::{self, NumaConfig, Placement};
+use epoch_playground::Canary;
+
+#[test]
+fn every_pair_of_nodes_gets_a_cell() {
+    let nodes = numa::nodes();
+    assert!(!nodes.is_empty());
+    assert!(nodes.iter().all(|n| !n.cpus.is_empty()));
+    let alive_before = Canary::alive();
+
+    for &placement in &[Placement::Bind, Placement::Interleave] {
+        let config = NumaConfig {
+            canaries: 1000,
+            passes: 1,
+            placement,
+        };
+        let report = numa::run(&config);
+        let expected = match placement {
+            Placement::Bind => nodes.len() * nodes.len(),
+            Placement::Interleave => nodes.len(),
+        };
+        assert_eq!(report.cells.len(), expected);
+        assert_eq!(report.cells.iter().any(|c| c.is_local()), placement == Placement::Bind);
+        assert_eq!(report.penalty().is_some(), placement == Placement::Bind && nodes.len() > 1);
+    }
+    // Each cell reclaims its own canaries before the next one starts.
+    assert_eq!(Canary::alive(), alive_before);
+}
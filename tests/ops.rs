@@ -0,0 +1,204 @@
+//! Random sequences of cage operations, checked against a simple model.
+//!
+//! Each case is generated from its own seed, so a failure names the seed
+//! that reproduces it.  There's no shrinking: the sequences are short
+//! enough to read.
+
+use epoch_playground::drop_tracker::{DropTracker, Tracked};
+use epoch_playground::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use epoch_playground::workload;
+use epoch_playground::{BirdCage, Cage, Taken};
+use rand::Rng;
+use std::thread;
+
+const CASES: u64 = 100;
+const MAX_OPS: usize = 64;
+const SLOTS: usize = 4;
+
+// Like `Canary::validate`: reading a value that's been dropped is a bug.
+fn read(t: &Tracked, seed: u64) -> usize {
+    assert!(!t.is_dropped(), "seed {}: read a freed value", seed);
+    t.id()
+}
+
+// Every value that was ever made has been dropped exactly once.
+fn check_all_dropped_once(tracker: &DropTracker, seed: u64) {
+    let (alive, twice) = (tracker.alive_ids(), tracker.dropped_twice());
+    assert!(alive.is_empty(), "seed {}: never dropped: {:?}", seed, alive);
+    assert!(twice.is_empty(), "seed {}: dropped twice: {:?}", seed, twice);
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Get(usize),
+    Put(usize),
+    Take(usize),
+    Update(usize),
+    ReplaceMut(usize),
+}
+
+fn ops<G: Rng>(rng: &mut G, with_mut: bool) -> Vec<Op> {
+    let kinds = if with_mut { 5 } else { 4 };
+    let len = rng.gen_range(0, MAX_OPS + 1);
+    (0..len)
+        .map(|_| {
+            let n = rng.gen_range(0, SLOTS);
+            match rng.gen_range(0, kinds) {
+                0 => Op::Get(n),
+                1 => Op::Put(n),
+                2 => Op::Take(n),
+                3 => Op::Update(n),
+                _ => Op::ReplaceMut(n),
+            }
+        })
+        .collect()
+}
+
+// Run one thread's ops, returning what its takes should eventually produce.
+fn run_shared<R: Reclaimer>(
+    cage: &BirdCage<Tracked, R>,
+    tracker: &DropTracker,
+    ops: &[Op],
+    seed: u64,
+) -> Vec<Taken<Tracked, R>> {
+    let mut taken = Vec::new();
+    for &op in ops {
+        match op {
+            Op::Get(n) => {
+                let _ = cage.with_slot(n, |t| read(t, seed));
+            }
+            Op::Put(n) => cage.put(n, tracker.track()),
+            Op::Take(n) => taken.push(cage.take(n).unwrap()),
+            Op::Update(n) => {
+                // Another thread may have emptied the slot.
+                let _ = cage.update(n, |old| {
+                    read(old, seed);
+                    tracker.track()
+                });
+            }
+            Op::ReplaceMut(_) => unreachable!("needs &mut"),
+        }
+        cage.quiescent();
+    }
+    // Our leftover garbage isn't handed over until our thread-locals are
+    // torn down, which can be after the scope has been joined.
+    cage.flush();
+    taken
+}
+
+// One thread, checking every step against a model of what each slot holds.
+fn sequential<R: Reclaimer>() {
+    for seed in 0..CASES {
+        let mut rng = workload::thread_rng(seed, 0);
+        let ops = ops(&mut rng, true);
+        let tracker = DropTracker::new();
+        let mut cage: BirdCage<_, R> = BirdCage::from_fn(SLOTS, |_| tracker.track());
+        let mut model: Vec<Option<usize>> = (0..SLOTS).map(Some).collect();
+        let mut taken = Vec::new();
+
+        for &op in &ops {
+            match op {
+                Op::Get(n) => {
+                    let got = cage.get(n, &cage.pin()).ok().map(|t| read(t, seed));
+                    assert_eq!(got, model[n], "seed {}: {:?}", seed, op);
+                }
+                Op::Put(n) => {
+                    let t = tracker.track();
+                    model[n] = Some(t.id());
+                    cage.put(n, t);
+                }
+                Op::Take(n) => taken.push((cage.take(n).unwrap(), model[n].take())),
+                Op::Update(n) => {
+                    let mut new_id = None;
+                    let updated = cage.update(n, |old| {
+                        read(old, seed);
+                        let t = tracker.track();
+                        new_id = Some(t.id());
+                        t
+                    });
+                    assert_eq!(updated.is_ok(), model[n].is_some(), "seed {}: {:?}", seed, op);
+                    if updated.is_ok() {
+                        model[n] = new_id;
+                    }
+                }
+                Op::ReplaceMut(n) => {
+                    let t = tracker.track();
+                    let id = t.id();
+                    let old = cage.replace_mut(n, t).unwrap().map(|t| read(&t, seed));
+                    assert_eq!(old, model[n], "seed {}: {:?}", seed, op);
+                    if let Some(old) = old {
+                        assert_eq!(tracker.drops(old), 1, "seed {}: {:?}", seed, op);
+                    }
+                    model[n] = Some(id);
+                }
+            }
+            cage.quiescent();
+        }
+
+        assert!(reclaim::force_reclaim::<R>(), "seed {}: reclaim stalled", seed);
+        for (t, expected) in taken {
+            let got = t.try_get().map(|t| read(&t, seed));
+            assert_eq!(got, expected, "seed {}: wrong value taken", seed);
+        }
+        drop(cage);
+        check_all_dropped_once(&tracker, seed);
+    }
+}
+
+// Two threads at once.  There's no model to compare with, but nothing
+// read may be freed, and everything must be dropped exactly once.
+fn concurrent<R: Reclaimer>() {
+    for seed in 0..CASES {
+        let per_thread: Vec<Vec<Op>> = (0..2)
+            .map(|stream| ops(&mut workload::thread_rng(seed, stream), false))
+            .collect();
+        let tracker = DropTracker::new();
+        let cage: BirdCage<_, R> = BirdCage::from_fn(SLOTS, |_| tracker.track());
+
+        let taken: Vec<_> = thread::scope(|s| {
+            let (cage, tracker) = (&cage, &tracker);
+            let handles: Vec<_> = per_thread
+                .iter()
+                .map(|ops| s.spawn(move || run_shared(cage, tracker, ops, seed)))
+                .collect();
+            handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+        });
+
+        assert!(reclaim::force_reclaim::<R>(), "seed {}: reclaim stalled", seed);
+        for t in taken {
+            drop(t.try_get());
+        }
+        drop(cage);
+        check_all_dropped_once(&tracker, seed);
+    }
+}
+
+#[test]
+fn sequential_ops_match_the_model_under_epoch() {
+    sequential::<Epoch>();
+}
+
+#[test]
+fn sequential_ops_match_the_model_under_hazard_pointers() {
+    sequential::<HazardPointers>();
+}
+
+#[test]
+fn sequential_ops_match_the_model_under_qsbr() {
+    sequential::<Qsbr>();
+}
+
+#[test]
+fn concurrent_ops_drop_everything_once_under_epoch() {
+    concurrent::<Epoch>();
+}
+
+#[test]
+fn concurrent_ops_drop_everything_once_under_hazard_pointers() {
+    concurrent::<HazardPointers>();
+}
+
+#[test]
+fn concurrent_ops_drop_everything_once_under_qsbr() {
+    concurrent::<Qsbr>();
+}
@@ -0,0 +1,74 @@
+//! The core structures, driven the way they have to be without std: every
+//! guard comes from a `Collector` made for the purpose.
+
+use crossbeam::epoch::Collector;
+use epoch_playground::drop_tracker::DropTracker;
+use epoch_playground::ms_queue::MsQueue;
+use epoch_playground::treiber_stack::TreiberStack;
+use epoch_playground::PrivateBirdCage;
+
+#[test]
+fn stack_and_queue_work_under_any_collector() {
+    let collector = Collector::new();
+    let handle = collector.register();
+    let guard = &handle.pin();
+
+    let stack = TreiberStack::new();
+    let queue = MsQueue::new();
+    for n in 0..5 {
+        stack.push_with(n, guard);
+        queue.push_with(n, guard);
+    }
+    let popped: Vec<_> = std::iter::from_fn(|| stack.pop_with(guard)).collect();
+    assert_eq!(popped, [4, 3, 2, 1, 0]);
+    let popped: Vec<_> = std::iter::from_fn(|| queue.pop_with(guard)).collect();
+    assert_eq!(popped, [0, 1, 2, 3, 4]);
+    assert!(stack.is_empty_with(guard));
+    assert!(queue.is_empty_with(guard));
+}
+
+#[test]
+fn put_defers_into_the_private_collector() {
+    let tracker = DropTracker::new();
+    let cage = PrivateBirdCage::from_fn(2, |_| tracker.track());
+    let handle = cage.register();
+    {
+        // While we're pinned, nothing we replace can go.
+        let _guard = handle.pin();
+        cage.put(&handle, 0, tracker.track()).unwrap();
+        cage.put(&handle, 0, tracker.track()).unwrap();
+        assert_eq!(tracker.dropped(), 0);
+    }
+
+    drop(handle);
+    drop(cage);
+    assert_eq!(tracker.dropped(), 4);
+}
+
+#[test]
+fn stack_and_queue_drop_every_value_once() {
+    let tracker = DropTracker::new();
+    let collector = Collector::new();
+    let handle = collector.register();
+    let stack = TreiberStack::new();
+    let queue = MsQueue::new();
+    {
+        let guard = &handle.pin();
+        for _ in 0..5 {
+            stack.push_with(tracker.track(), guard);
+            queue.push_with(tracker.track(), guard);
+        }
+        // Popped values are handed back and dropped here; only the nodes
+        // that held them are deferred.
+        assert_eq!(stack.pop_with(guard).map(|t| t.id()), Some(8));
+        assert_eq!(queue.pop_with(guard).map(|t| t.id()), Some(1));
+        assert_eq!(tracker.alive(), 8);
+    }
+
+    // The rest go with the structures.
+    drop(stack);
+    drop(queue);
+    drop(handle);
+    drop(collector);
+    tracker.assert_all_dropped();
+}
@@ -0,0 +1,143 @@
+//! What happens to deferred work when something panics along the way.
+//!
+//! Two places can go wrong.  A writer can panic after it has swapped a value
+//! out of a slot but before it has retired it, and then nobody will ever
+//! retire it.  Or a deferred destructor can panic while the collector is
+//! running it, and then the rest of that batch of garbage is never run.
+//! Either way memory leaks, nothing is freed twice, and the cage and the
+//! collector carry on working.
+
+use crossbeam::epoch::LocalHandle;
+use epoch_playground::reclaim::{self, Epoch, HazardPointers, Qsbr, Reclaimer};
+use epoch_playground::drop_tracker::{DropTracker, Tracked};
+use epoch_playground::{BirdCage, Cage, PrivateBirdCage};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread;
+
+// Panics when it's dropped, if it was told to.  Its tracked value is
+// dropped either way.
+struct Bomb {
+    _tracked: Tracked,
+    explode: bool,
+}
+
+impl Drop for Bomb {
+    fn drop(&mut self) {
+        if self.explode {
+            panic!("bomb went off in a deferred destructor");
+        }
+    }
+}
+
+fn panic_between_swap_and_retire<R: Reclaimer>() {
+    let tracker = DropTracker::new();
+    let cage = Arc::new(BirdCage::<Tracked, R>::from_fn(1, |_| tracker.track()));
+    let new = tracker.track();
+
+    // `removed` runs after the swap and before the retire, so a panic there
+    // unwinds straight past the retire.
+    let worker = {
+        let cage = cage.clone();
+        thread::spawn(move || {
+            cage.put_with(0, new, |_| panic!("worker died mid-replace"));
+        })
+    };
+    assert!(worker.join().is_err());
+
+    // The new value went in, and the worker's guard was dropped on the way
+    // out, so nothing is stuck waiting on it.
+    assert_eq!(cage.with_slot(0, Tracked::id), Ok(1));
+    assert!(reclaim::force_reclaim::<R>());
+    // The old value was unlinked but never retired: it's leaked for good.
+    assert_eq!(tracker.alive_ids(), [0, 1]);
+
+    // Everything else still works, including reclaiming the value the
+    // panicking worker put in.
+    cage.put(0, tracker.track());
+    cage.flush();
+    assert!(reclaim::force_reclaim::<R>());
+    assert_eq!(tracker.alive_ids(), [0, 2]);
+
+    drop(cage);
+    assert_eq!(tracker.alive_ids(), [0]);
+    assert!(tracker.dropped_twice().is_empty());
+}
+
+#[test]
+fn panic_between_swap_and_retire_leaks_under_epoch() {
+    panic_between_swap_and_retire::<Epoch>();
+}
+
+#[test]
+fn panic_between_swap_and_retire_leaks_under_hazard_pointers() {
+    panic_between_swap_and_retire::<HazardPointers>();
+}
+
+#[test]
+fn panic_between_swap_and_retire_leaks_under_qsbr() {
+    panic_between_swap_and_retire::<Qsbr>();
+}
+
+// Flush until the collector has nothing left to run.  Returns how many of
+// those flushes panicked.
+fn flush_all(handle: &LocalHandle) -> usize {
+    let mut panics = 0;
+    for _ in 0..8 {
+        if panic::catch_unwind(AssertUnwindSafe(|| handle.pin().flush())).is_err() {
+            panics += 1;
+        }
+    }
+    panics
+}
+
+#[test]
+fn panicking_destructor_leaks_the_rest_of_its_batch() {
+    // A private collector, so the panic can't go off in some other test's
+    // thread, the way it would in the global one.
+    let tracker = DropTracker::new();
+    let cage = PrivateBirdCage::from_fn(1, |_| Bomb {
+        _tracked: tracker.track(),
+        explode: false,
+    });
+    let handle = cage.register();
+
+    // Retire ten values under one pin, so they all land in the same batch:
+    // the one the cage started with, and then bombs 0 to 8.  Bomb 4 panics
+    // when it's destroyed.
+    {
+        let _guard = handle.pin();
+        for ii in 0..10 {
+            let bomb = Bomb {
+                _tracked: tracker.track(),
+                explode: ii == 4,
+            };
+            cage.put(&handle, 0, bomb).unwrap();
+        }
+    }
+
+    // The panic comes out of whichever flush happens to run the batch, and
+    // only once: the batch is gone by then.
+    assert_eq!(flush_all(&handle), 1);
+    // The batch runs in order, so everything up to and including bomb 4 was
+    // destroyed, and bombs 5 to 8 are leaked.  Bomb 9 is in the slot.
+    assert_eq!(tracker.alive_ids(), [6, 7, 8, 9, 10]);
+
+    // The collector is still usable, and later garbage is destroyed as usual.
+    for _ in 0..3 {
+        let bomb = Bomb {
+            _tracked: tracker.track(),
+            explode: false,
+        };
+        cage.put(&handle, 0, bomb).unwrap();
+    }
+    assert_eq!(flush_all(&handle), 0);
+    assert_eq!(tracker.alive_ids(), [6, 7, 8, 9, 13]);
+
+    // Dropping the cage destroys the value in the slot, and nothing else
+    // turns up.
+    drop(handle);
+    drop(cage);
+    assert_eq!(tracker.alive_ids(), [6, 7, 8, 9]);
+    assert!(tracker.dropped_twice().is_empty());
+}
@@ -0,0 +1,37 @@
+use epoch_playground::drop_tracker::DropTracker;
+use epoch_playground::{CageError, PrivateBirdCage};
+
+#[test]
+fn dropping_the_cage_destroys_its_garbage() {
+    let tracker = DropTracker::new();
+    let cage = PrivateBirdCage::from_fn(3, |_| tracker.track());
+    let handle = cage.register();
+    for n in 0..10 {
+        cage.replace(&handle, n % 3, "test", tracker.track()).unwrap();
+    }
+    assert_eq!(cage.with_slot(&handle, 0, |_| 1), Ok(1));
+    let out_of_range = CageError::IndexOutOfBounds { index: 3, len: 3 };
+    assert_eq!(cage.with_slot(&handle, 3, |_| 1), Err(out_of_range));
+    assert_eq!(cage.replace(&handle, 3, "test", tracker.track()), Err(out_of_range));
+    assert_eq!(cage.put(&handle, 3, tracker.track()), Err(out_of_range));
+
+    // Nothing outside the cage holds its garbage, so it all goes when the
+    // last handle and the cage do.
+    drop(handle);
+    drop(cage);
+    assert_eq!(tracker.dropped(), 15);
+}
+
+#[test]
+fn replace_mut_skips_the_collector() {
+    let tracker = DropTracker::new();
+    let mut cage = PrivateBirdCage::from_fn(2, |_| tracker.track());
+    drop(cage.replace_mut(1, tracker.track()).unwrap());
+    assert_eq!(tracker.dropped(), 1);
+    let out_of_range = CageError::IndexOutOfBounds { index: 2, len: 2 };
+    assert_eq!(cage.replace_mut(2, tracker.track()).err(), Some(out_of_range));
+    assert_eq!(tracker.dropped(), 2);
+
+    drop(cage);
+    assert_eq!(tracker.dropped(), 4);
+}
@@ -0,0 +1,15 @@
+use epoch_playground::probe;
+
+#[test]
+fn probe_accounts_for_every_deferral() {
+    let report = probe::run(2000);
+    // Deferred work is batched, so the first deferral is never run right
+    // away.
+    let first = report.first_collection().expect("nothing was collected");
+    assert!(first > 1, "{}", report);
+    assert!(report.collections.windows(2).all(|w| w[0].after < w[1].after));
+
+    let collected: usize = report.collections.iter().map(|c| c.dropped).sum();
+    assert_eq!(collected + report.left_over, 2000);
+    assert!(report.usual_gap().is_some(), "{}", report);
+}
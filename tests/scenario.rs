@@ -0,0 +1,106 @@
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::scenario::{self, Scenario, Step, ThreadSpec, Work};
+use epoch_playground::stress::ReclaimerKind;
+use epoch_playground::workload::SlotDistribution;
+use epoch_playground::{Canary, FlushPolicy};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+#[test]
+fn shipped_scenarios_parse() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+    let mut found = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if let Err(e) = Scenario::load(&path) {
+            panic!("{}", e);
+        }
+        found += 1;
+    }
+    assert!(found > 0);
+}
+
+#[test]
+fn scenarios_parse_into_threads() {
+    let text = r#"
+        name = "all # of it"  # the name keeps its hash
+        slots = 4
+        duration = "50ms"
+        flush = "every:2"
+        distribution = "zipf"
+        seed = 7
+
+        [[thread]]
+        name = "scripted"
+        script = [
+            "access", "access 3",   # a comment inside the array
+            "replace 1", "flush",
+            "sleep 1ms", "pin 2ms",
+            "hold 2ms", "hold 3ms repin 1ms",
+        ]
+
+        [[thread]]
+        count = 3
+        write_percent = 12.5
+        pause = "1ms"
+    "#;
+    let expected = Scenario {
+        name: "all # of it".to_owned(),
+        cage_size: 4,
+        duration: Duration::from_millis(50),
+        reclaimer: ReclaimerKind::Epoch,
+        flush: FlushPolicy::EveryN(2),
+        slots: SlotDistribution::Zipf(SlotDistribution::DEFAULT_ZIPF),
+        seed: Some(7),
+        threads: vec![
+            ThreadSpec {
+                name: "scripted".to_owned(),
+                count: 1,
+                work: Work::Script(vec![
+                    Step::Access(None),
+                    Step::Access(Some(3)),
+                    Step::Replace(Some(1)),
+                    Step::Flush,
+                    Step::Sleep(Duration::from_millis(1)),
+                    Step::Pin(Duration::from_millis(2)),
+                    Step::Hold(Duration::from_millis(2), None),
+                    Step::Hold(Duration::from_millis(3), Some(Duration::from_millis(1))),
+                ]),
+                pause: Duration::ZERO,
+            },
+            ThreadSpec {
+                name: "thread".to_owned(),
+                count: 3,
+                work: Work::Mix { write_percent: 12.5 },
+                pause: Duration::from_millis(1),
+            },
+        ],
+    };
+    let parsed = Scenario::parse(text).unwrap();
+    assert_eq!(parsed, expected);
+
+    let report = scenario::run(&parsed);
+    assert_eq!(report.ops.len(), 2);
+    assert!(report.ops.iter().all(|(_, n)| *n > 0), "{}", report);
+    assert!(!report.timeline.is_empty());
+    assert!(force_reclaim::<Epoch>());
+    assert_eq!(Canary::alive(), 0);
+}
+
+#[test]
+fn mistakes_are_reported_with_their_line() {
+    let cases = [
+        ("slots = 4\nslot = 3\n[[thread]]\nwrite_percent = 5\n", "line 2: unknown key slot"),
+        ("[[thread]]\nscript = [\"jump\"]\n", "line 2: unknown step \"jump\""),
+        ("[[thread]]\nscript = [\"hold 1s repin\"]\n", "unknown step \"hold 1s repin\""),
+        ("[[thread]]\ncount = 2\n", "line 1: a thread needs a script or a write_percent"),
+        ("slots = 2\n[[thread]]\nscript = [\"access 2\"]\n", "uses slot 2"),
+        ("duration = 5\n", "line 1: duration should be a string, not an integer"),
+        ("[thread]\n", "line 1: unknown table [thread]"),
+    ];
+    for (text, wanted) in &cases {
+        let err = Scenario::parse(text).unwrap_err();
+        assert!(err.contains(wanted), "{:?} gave {:?}", text, err);
+    }
+}
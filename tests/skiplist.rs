@@ -0,0 +1,108 @@
+use epoch_playground::drop_tracker::{DropTracker, TrackedKey};
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::skiplist::SkipList;
+use std::sync::atomic::{self, AtomicUsize};
+use std::thread;
+
+const THREADS: u64 = 4;
+const KEYS: u64 = 500;
+
+#[test]
+fn keeps_a_sorted_set() {
+    let list = SkipList::new();
+    for n in [5, 1, 3, 9, 7] {
+        assert!(list.insert(n));
+    }
+    assert!(!list.insert(3));
+    assert!(list.remove(&1));
+    assert!(list.remove(&9));
+    assert!(!list.remove(&9));
+    assert!(list.contains(&5) && !list.contains(&1));
+    assert_eq!(list.to_vec(), [3, 5, 7]);
+}
+
+#[test]
+fn concurrent_writers_free_every_tower_once() {
+    let tracker = DropTracker::new();
+    let list = SkipList::new();
+    thread::scope(|s| {
+        for id in 0..THREADS {
+            let (list, tracker) = (&list, &tracker);
+            s.spawn(move || {
+                // Every thread has keys of its own, interleaved with
+                // everybody else's, so towers built by one thread are
+                // unlinked by searches from the others.  It churns every
+                // other key in and out a few times, then leaves it out.
+                for n in (id..KEYS).step_by(THREADS as usize) {
+                    assert!(list.insert(TrackedKey::new(n, tracker)));
+                }
+                for _ in 0..5 {
+                    for n in (id..KEYS).step_by(2 * THREADS as usize) {
+                        assert!(list.remove(&TrackedKey::lookup(n)));
+                        assert!(!list.contains(&TrackedKey::lookup(n)));
+                        assert!(list.insert(TrackedKey::new(n, tracker)));
+                        assert!(list.contains(&TrackedKey::lookup(n)));
+                    }
+                }
+                for n in (id..KEYS).step_by(2 * THREADS as usize) {
+                    assert!(list.remove(&TrackedKey::lookup(n)));
+                }
+                let kept = TrackedKey::keys(&list.to_vec());
+                assert!(kept.windows(2).all(|w| w[0] < w[1]));
+                crossbeam::epoch::pin().flush();
+            });
+        }
+    });
+
+    let expected: Vec<u64> = (0..KEYS).filter(|n| n % (2 * THREADS) >= THREADS).collect();
+    assert_eq!(TrackedKey::keys(&list.to_vec()), expected);
+
+    drop(list);
+    assert!(force_reclaim::<Epoch>());
+    tracker.assert_all_dropped();
+}
+
+#[test]
+fn racing_writers_take_each_key_once() {
+    let tracker = DropTracker::new();
+    let list = SkipList::new();
+    let (inserted, removed) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    for round in 0..10 {
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                let (list, tracker) = (&list, &tracker);
+                let (inserted, removed) = (&inserted, &removed);
+                s.spawn(move || {
+                    // Everybody inserts and then removes the same keys, so
+                    // only one insert and one remove of each can win, and
+                    // every thread's remove comes after its own insert.
+                    let keys = round * 10..round * 10 + KEYS / 5;
+                    for n in keys.clone() {
+                        if list.insert(TrackedKey::new(n, tracker)) {
+                            inserted.fetch_add(1, atomic::Ordering::SeqCst);
+                        }
+                    }
+                    for n in keys {
+                        if list.remove(&TrackedKey::lookup(n)) {
+                            removed.fetch_add(1, atomic::Ordering::SeqCst);
+                        }
+                    }
+                    crossbeam::epoch::pin().flush();
+                });
+            }
+        });
+        assert!(list.to_vec().is_empty());
+        assert_eq!(
+            inserted.load(atomic::Ordering::SeqCst),
+            removed.load(atomic::Ordering::SeqCst)
+        );
+    }
+
+    // A removed tower can still be linked into an upper level, in which
+    // case it's the list's to free rather than the collector's.  Either way
+    // it goes exactly once.
+    assert!(inserted.load(atomic::Ordering::SeqCst) as u64 >= 10 * (KEYS / 5));
+    drop(list);
+    assert!(force_reclaim::<Epoch>());
+    tracker.assert_all_dropped();
+}
@@ -0,0 +1,32 @@
+use epoch_playground::drop_tracker::DropTracker;
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::slab::Slab;
+
+#[test]
+fn a_removed_index_waits_for_the_epoch() {
+    let tracker = DropTracker::new();
+    let slab = Slab::new(3);
+    assert_eq!(slab.insert(tracker.track()).ok(), Some(0));
+    assert_eq!(slab.insert(tracker.track()).ok(), Some(1));
+
+    // While we're still pinned from before the remove, we might still be
+    // looking at index 0, so nobody can be given it.
+    let guard = crossbeam::epoch::pin();
+    let removed = slab.with(0, |t| t.id()).unwrap();
+    assert!(slab.remove(0));
+    assert!(slab.with(0, |_| ()).is_none());
+    assert_eq!(slab.pending_reuse(), 1);
+    assert_eq!(slab.insert(tracker.track()).ok(), Some(2));
+    assert!(slab.insert(tracker.track()).is_err());
+    assert_eq!(tracker.drops(removed), 0);
+    drop(guard);
+
+    assert!(force_reclaim::<Epoch>());
+    assert_eq!(slab.pending_reuse(), 0);
+    assert_eq!(tracker.drops(removed), 1);
+    assert_eq!(slab.insert(tracker.track()).ok(), Some(0));
+    assert!(slab.with(0, |t| t.validate() != removed).unwrap());
+
+    drop(slab);
+    tracker.assert_all_dropped();
+}
@@ -0,0 +1,53 @@
+use epoch_playground::reclaim::{force_reclaim, Epoch};
+use epoch_playground::stress::{self, ReclaimerKind, StressConfig};
+use epoch_playground::trace::{self, Trace, TraceOp};
+use epoch_playground::Canary;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("epoch_playground-{}-{}", process::id(), name))
+}
+
+#[test]
+fn recorded_runs_survive_a_round_trip_and_replay() {
+    let config = StressConfig {
+        readers: 1,
+        writers: 1,
+        duration: Duration::from_millis(20),
+        quiescent_every: 8,
+        record: true,
+        ..StressConfig::default()
+    };
+    let report = stress::run(&config);
+    let recorded = report.trace.expect("the run was recorded");
+    assert_eq!(recorded.threads, 2);
+    assert_eq!(recorded.entries.len() as u64 - quiescents(&recorded), report.reads + report.writes);
+    assert!(recorded.entries.windows(2).all(|w| w[0].at <= w[1].at));
+
+    let path = temp_path("round-trip.trace");
+    recorded.save(&path).unwrap();
+    let loaded = Trace::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, recorded);
+
+    let replayed = trace::replay(&loaded, ReclaimerKind::Epoch);
+    assert_eq!(replayed.ops, recorded.entries.len());
+    assert!(force_reclaim::<Epoch>());
+    assert_eq!(Canary::alive(), 0);
+}
+
+fn quiescents(trace: &Trace) -> u64 {
+    trace.entries.iter().filter(|e| e.op == TraceOp::Quiescent).count() as u64
+}
+
+#[test]
+fn bad_traces_are_rejected() {
+    let path = temp_path("bad.trace");
+    fs::write(&path, "# epoch_playground trace\nslots 4\nthreads 1\n10 0 replace 4\n").unwrap();
+    let err = Trace::load(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert!(err.to_string().ends_with(":4: bad operation"), "{}", err);
+}
@@ -0,0 +1,84 @@
+//! The hooks themselves only run with `cargo test --features yield-points`.
+
+use epoch_playground::yield_points::{Action, Point, Schedule};
+use std::time::Duration;
+
+fn actions(schedule: &Schedule, thread: u64) -> Vec<Action> {
+    (0..200)
+        .map(|step| schedule.action(thread, step, Point::ALL[step as usize % 3]))
+        .collect()
+}
+
+#[test]
+fn schedules_depend_only_on_seed_thread_and_step() {
+    let schedule = Schedule::new(7);
+    let first = actions(&schedule, 0);
+    assert_eq!(first, actions(&Schedule::new(7), 0));
+    assert_ne!(first, actions(&Schedule::new(8), 0));
+    assert_ne!(first, actions(&schedule, 1));
+    assert!(first.contains(&Action::Continue));
+    assert!(first.contains(&Action::Yield));
+    assert!(first.iter().all(|a| match a {
+        Action::Sleep(d) => *d < schedule.max_sleep,
+        _ => true,
+    }));
+
+    let only_cas = Schedule::new(7).only_at(&[Point::BeforeCas]);
+    for (step, action) in actions(&only_cas, 0).into_iter().enumerate() {
+        if step % 3 == 1 {
+            assert_eq!(action, first[step]);
+        } else {
+            assert_eq!(action, Action::Continue);
+        }
+    }
+}
+
+#[test]
+fn fixed_steps_override_the_seed() {
+    let pause = Action::Sleep(Duration::from_millis(5));
+    let quiet = Schedule::new(7).quiet().at(2, 4, Action::Yield).at(2, 4, pause);
+    for thread in 0..4 {
+        for (step, action) in actions(&quiet, thread).into_iter().enumerate() {
+            if (thread, step) == (2, 4) {
+                assert_eq!(action, pause);
+            } else {
+                assert_eq!(action, Action::Continue);
+            }
+        }
+    }
+    assert_eq!("before-cas".parse::<Point>(), Ok(Point::BeforeCas));
+    assert!("during-cas".parse::<Point>().is_err());
+}
+
+// Stop an update between its load and its CAS for long enough that
+// another thread's write lands there, so the CAS has to go around again.
+#[cfg(feature = "yield-points")]
+#[test]
+fn a_pause_before_the_cas_forces_a_retry() {
+    use epoch_playground::yield_points;
+    use epoch_playground::{BirdCage, Cage};
+    use std::thread;
+
+    let cage: BirdCage<u64> = BirdCage::from_fn(1, |_| 1);
+    let pause = Action::Sleep(Duration::from_millis(100));
+    // Thread 0's second hook is the update's first `BeforeCas`.
+    yield_points::set_schedule(Some(Schedule::new(0).quiet().at(0, 1, pause)));
+    let retries = thread::scope(|s| {
+        let updater = s.spawn(|| {
+            yield_points::set_thread(0);
+            let retries = cage.update(0, |v| v + 1);
+            // Load and CAS twice, and one retire.
+            assert_eq!(yield_points::steps(), 5);
+            retries
+        });
+        s.spawn(|| {
+            yield_points::set_thread(1);
+            thread::sleep(Duration::from_millis(20));
+            cage.put(0, 10);
+        });
+        updater.join().unwrap()
+    });
+    yield_points::set_schedule(None);
+    assert_eq!(retries, Ok(1));
+    assert_eq!(cage.get_cloned(0), Ok(11));
+}